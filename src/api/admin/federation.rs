@@ -0,0 +1,63 @@
+use axum::{extract::Path, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::{known_room, AdminUser};
+use crate::{services, Result};
+
+#[derive(Deserialize)]
+pub struct SetRoomFederationRequest {
+	disabled: bool,
+}
+
+/// # `GET /_conduwuit/admin/v1/federation/incoming`
+///
+/// Lists the rooms incoming PDUs are currently handled for, like
+/// `federation incoming-federation`.
+pub async fn incoming_federation_route(_: AdminUser) -> Result<Json<Value>> {
+	let handling = services()
+		.globals
+		.roomid_federationhandletime
+		.read()
+		.await
+		.iter()
+		.map(|(room_id, (event_id, started))| {
+			json!({
+				"room_id": room_id,
+				"event_id": event_id,
+				"elapsed_ms": started.elapsed().as_millis(),
+			})
+		})
+		.collect::<Vec<_>>();
+
+	Ok(Json(json!({
+		"rooms": handling,
+	})))
+}
+
+/// # `PUT /_conduwuit/admin/v1/federation/rooms/{roomId}`
+///
+/// Disables or enables handling incoming federation for a room, like
+/// `federation disable-room` and `federation enable-room`.
+pub async fn set_room_federation_route(
+	AdminUser(sender_user): AdminUser, Path(room_id): Path<String>, Json(body): Json<SetRoomFederationRequest>,
+) -> Result<Json<Value>> {
+	let room_id = known_room(&room_id)?;
+
+	services()
+		.rooms
+		.metadata
+		.disable_room(&room_id, body.disabled)?;
+
+	info!(
+		"{sender_user} {} incoming federation for {room_id} through the admin API",
+		if body.disabled {
+			"disabled"
+		} else {
+			"enabled"
+		}
+	);
+
+	Ok(Json(json!({})))
+}
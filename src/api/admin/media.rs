@@ -0,0 +1,51 @@
+use axum::{extract::Path, Json};
+use ruma::{api::client::error::ErrorKind, OwnedMxcUri};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::AdminUser;
+use crate::{services, Error, Result};
+
+#[derive(Deserialize)]
+pub struct DeletePastRemoteMediaRequest {
+	/// e.g. "5m" to delete remote media of the past 5 minutes
+	duration: String,
+}
+
+/// # `DELETE /_conduwuit/admin/v1/media/{serverName}/{mediaId}`
+///
+/// Deletes a media file and its thumbnails, like `media delete --mxc`.
+pub async fn delete_media_route(
+	AdminUser(sender_user): AdminUser, Path((server_name, media_id)): Path<(String, String)>,
+) -> Result<Json<Value>> {
+	let mxc = OwnedMxcUri::from(format!("mxc://{server_name}/{media_id}"));
+	if !mxc.is_valid() {
+		return Err(Error::BadRequest(ErrorKind::InvalidParam, "Invalid MXC URI."));
+	}
+
+	services().media.delete(mxc.to_string()).await?;
+
+	info!("{sender_user} deleted {mxc} through the admin API");
+
+	Ok(Json(json!({})))
+}
+
+/// # `POST /_conduwuit/admin/v1/media/delete_past_remote`
+///
+/// Deletes remote media cached within the given duration, like
+/// `media delete-past-remote-media`.
+pub async fn delete_past_remote_media_route(
+	AdminUser(sender_user): AdminUser, Json(body): Json<DeletePastRemoteMediaRequest>,
+) -> Result<Json<Value>> {
+	let deleted = services()
+		.media
+		.delete_all_remote_media_at_after_time(body.duration)
+		.await?;
+
+	info!("{sender_user} deleted {deleted} remote media files through the admin API");
+
+	Ok(Json(json!({
+		"deleted": deleted,
+	})))
+}
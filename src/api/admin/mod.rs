@@ -0,0 +1,105 @@
+//! JSON admin API under `/_conduwuit/admin/v1/`, exposing the operations of
+//! the admin room commands to scripts. Every request has to be authenticated
+//! with the access token of a server admin, i.e. a member of the admin room.
+
+mod federation;
+mod media;
+mod rooms;
+mod server;
+mod users;
+
+use axum::{async_trait, extract::FromRequestParts, Json};
+pub use federation::*;
+use http::request::Parts;
+pub use media::*;
+pub use rooms::*;
+use ruma::{
+	api::client::error::ErrorKind, events::room::message::RoomMessageEventContent, OwnedRoomId, OwnedUserId, RoomId,
+	UserId,
+};
+use serde::{Deserialize, Serialize};
+pub use server::*;
+use tracing::info;
+pub use users::*;
+
+use crate::{api::ruma_wrapper::authenticate_user, service::admin::get_room_info, services, Error, Result};
+
+/// A server admin authenticated with their access token
+pub struct AdminUser(pub OwnedUserId);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminUser {
+	type Rejection = Error;
+
+	async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+		let (user_id, _) = authenticate_user(parts).await?;
+
+		if !services().users.is_admin(&user_id)? {
+			return Err(Error::BadRequest(
+				ErrorKind::forbidden(),
+				"Only server admins can use the admin API.",
+			));
+		}
+
+		Ok(Self(user_id))
+	}
+}
+
+#[derive(Serialize)]
+pub struct RoomInfo {
+	room_id: OwnedRoomId,
+	joined_members: u64,
+	name: String,
+}
+
+impl RoomInfo {
+	fn new(room_id: &OwnedRoomId) -> Self {
+		let (room_id, joined_members, name) = get_room_info(room_id);
+
+		Self {
+			room_id,
+			joined_members,
+			name,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+pub struct CommandRequest {
+	command: String,
+}
+
+/// # `POST /_conduwuit/admin/v1/command`
+///
+/// Runs any admin room command, for operations without a dedicated endpoint.
+/// Returns the message the server user would reply with in the admin room.
+pub async fn run_command_route(
+	AdminUser(sender_user): AdminUser, Json(body): Json<CommandRequest>,
+) -> Result<Json<RoomMessageEventContent>> {
+	info!("{sender_user} ran admin command through the admin API: {}", body.command);
+
+	Ok(Json(services().admin.run_command(&body.command).await))
+}
+
+/// Parses a user ID from a path and checks it belongs to an existing local
+/// user.
+fn local_user(user_id: &str) -> Result<OwnedUserId> {
+	let user_id = UserId::parse(user_id).map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid user ID."))?;
+
+	if user_id.server_name() != services().globals.server_name() || !services().users.exists(&user_id)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "User does not exist on this server."));
+	}
+
+	Ok(user_id)
+}
+
+/// Parses a room ID from a path and checks the server knows the room.
+fn known_room(room_id: &str) -> Result<OwnedRoomId> {
+	let room_id = RoomId::parse(room_id).map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid room ID."))?;
+
+	if !services().rooms.metadata.exists(&room_id)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Room is not known to this server."));
+	}
+
+	Ok(room_id)
+}
@@ -0,0 +1,138 @@
+use axum::{extract::Path, Json};
+use http::Uri;
+use ruma::{api::client::error::ErrorKind, OwnedRoomAliasId, OwnedRoomId, OwnedUserId};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{known_room, AdminUser, RoomInfo};
+use crate::{service::admin::PAGE_SIZE, services, Error, Result};
+
+#[derive(Deserialize)]
+pub struct ListRoomsParams {
+	/// Starting at 1
+	page: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct RoomDetails {
+	room_id: OwnedRoomId,
+	name: Option<String>,
+	joined_members: u64,
+	local_members: Vec<OwnedUserId>,
+	aliases: Vec<OwnedRoomAliasId>,
+	public: bool,
+	banned: bool,
+	federation_disabled: bool,
+}
+
+/// # `GET /_conduwuit/admin/v1/rooms`
+///
+/// Lists the rooms the server knows about, largest first, like `rooms list`.
+///
+/// - Paginated with the `page` query parameter, starting at 1
+pub async fn list_rooms_route(_: AdminUser, uri: Uri) -> Result<Json<Value>> {
+	let params: ListRoomsParams = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid query parameters."))?;
+	let page = params.page.unwrap_or(1);
+
+	let mut rooms = services()
+		.rooms
+		.metadata
+		.iter_ids()
+		.filter_map(Result::ok)
+		.map(|room_id| RoomInfo::new(&room_id))
+		.collect::<Vec<_>>();
+	rooms.sort_by_key(|room| room.joined_members);
+	rooms.reverse();
+
+	let total = rooms.len();
+	let (rooms, next_page) = paginate(rooms, page);
+
+	Ok(Json(json!({
+		"rooms": rooms,
+		"total": total,
+		"next_page": next_page,
+	})))
+}
+
+/// The items on a page, starting at 1, and the number of the next page if
+/// there are more items
+fn paginate<T>(items: Vec<T>, page: usize) -> (Vec<T>, Option<usize>) {
+	let next_page = (page.saturating_mul(PAGE_SIZE) < items.len()).then_some(page + 1);
+	let items = items
+		.into_iter()
+		.skip(page.saturating_sub(1).saturating_mul(PAGE_SIZE))
+		.take(PAGE_SIZE)
+		.collect();
+
+	(items, next_page)
+}
+
+/// # `GET /_conduwuit/admin/v1/rooms/{roomId}`
+pub async fn get_room_route(_: AdminUser, Path(room_id): Path<String>) -> Result<Json<RoomDetails>> {
+	let room_id = known_room(&room_id)?;
+
+	let local_members = services()
+		.rooms
+		.state_cache
+		.room_members(&room_id)
+		.filter_map(Result::ok)
+		.filter(|user_id| user_id.server_name() == services().globals.server_name())
+		.collect();
+
+	let aliases = services()
+		.rooms
+		.alias
+		.local_aliases_for_room(&room_id)
+		.filter_map(Result::ok)
+		.collect();
+
+	Ok(Json(RoomDetails {
+		name: services().rooms.state_accessor.get_name(&room_id)?,
+		joined_members: services()
+			.rooms
+			.state_cache
+			.room_joined_count(&room_id)?
+			.unwrap_or(0),
+		local_members,
+		aliases,
+		public: services().rooms.directory.is_public_room(&room_id)?,
+		banned: services().rooms.metadata.is_banned(&room_id)?,
+		federation_disabled: services().rooms.metadata.is_disabled(&room_id)?,
+		room_id,
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{paginate, ListRoomsParams};
+	use crate::service::admin::PAGE_SIZE;
+
+	#[test]
+	fn pages() {
+		let items = (0..PAGE_SIZE * 2 + 1).collect::<Vec<_>>();
+
+		let (first, next_page) = paginate(items.clone(), 1);
+		assert_eq!(first, (0..PAGE_SIZE).collect::<Vec<_>>());
+		assert_eq!(next_page, Some(2));
+
+		let (last, next_page) = paginate(items.clone(), 3);
+		assert_eq!(last, vec![PAGE_SIZE * 2]);
+		assert_eq!(next_page, None);
+
+		let (past_the_end, next_page) = paginate(items, 4);
+		assert!(past_the_end.is_empty());
+		assert_eq!(next_page, None);
+	}
+
+	#[test]
+	fn list_rooms_params() {
+		let params: ListRoomsParams = serde_html_form::from_str("page=2").unwrap();
+		assert_eq!(params.page, Some(2));
+
+		let params: ListRoomsParams = serde_html_form::from_str("").unwrap();
+		assert_eq!(params.page, None);
+
+		assert!(serde_html_form::from_str::<ListRoomsParams>("page=first").is_err());
+	}
+}
@@ -0,0 +1,86 @@
+use axum::Json;
+use ruma::api::client::error::ErrorKind;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::AdminUser;
+use crate::{services, Error, Result};
+
+#[derive(Deserialize)]
+pub struct ClearCachesRequest {
+	/// Clears the database caches with an index smaller than this
+	database: Option<u32>,
+	/// Clears the service caches with an index smaller than this
+	services: Option<u32>,
+}
+
+/// # `GET /_conduwuit/admin/v1/server`
+///
+/// Returns the version of the server and how many users and rooms it has.
+pub async fn get_server_info_route(_: AdminUser) -> Result<Json<Value>> {
+	let version = match option_env!("CONDUIT_VERSION_EXTRA") {
+		Some(extra) => format!("{} ({})", env!("CARGO_PKG_VERSION"), extra),
+		None => env!("CARGO_PKG_VERSION").to_owned(),
+	};
+
+	Ok(Json(json!({
+		"server_name": services().globals.server_name(),
+		"version": version,
+		"users": services().users.count()?,
+		"rooms": services().rooms.metadata.iter_ids().count(),
+	})))
+}
+
+/// # `GET /_conduwuit/admin/v1/server/memory_usage`
+///
+/// Returns the memory usage reports of `server memory-usage`.
+pub async fn get_memory_usage_route(_: AdminUser) -> Result<Json<Value>> {
+	Ok(Json(json!({
+		"services": services().memory_usage().await,
+		"database": services().globals.db.memory_usage(),
+	})))
+}
+
+/// # `POST /_conduwuit/admin/v1/server/clear_caches`
+///
+/// Clears database and service caches, like `server clear-database-caches`
+/// and `server clear-service-caches`.
+pub async fn clear_caches_route(_: AdminUser, Json(body): Json<ClearCachesRequest>) -> Result<Json<Value>> {
+	if let Some(amount) = body.database {
+		services().globals.db.clear_caches(amount);
+	}
+
+	if let Some(amount) = body.services {
+		services().clear_caches(amount).await;
+	}
+
+	Ok(Json(json!({})))
+}
+
+/// # `POST /_conduwuit/admin/v1/server/backup`
+///
+/// Performs an online backup of the database, like `server backup-database`.
+pub async fn backup_database_route(AdminUser(sender_user): AdminUser) -> Result<Json<Value>> {
+	if !cfg!(feature = "rocksdb") {
+		return Err(Error::BadRequest(
+			ErrorKind::Unknown,
+			"Only RocksDB supports online backups in conduwuit.",
+		));
+	}
+
+	tokio::task::spawn_blocking(move || {
+		services()
+			.globals
+			.db
+			.backup()
+			.map_err(|e| format!("Database backup failed: {e}"))
+	})
+	.await
+	.map_err(|_| Error::BadServerResponse("Database backup task failed."))?
+	.map_err(Error::Error)?;
+
+	info!("{sender_user} backed up the database through the admin API");
+
+	Ok(Json(json!({})))
+}
@@ -0,0 +1,283 @@
+use axum::{extract::Path, Json};
+use ruma::{api::client::error::ErrorKind, OwnedMxcUri, OwnedRoomId, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::{local_user, AdminUser, RoomInfo};
+use crate::{
+	api::client_server::{leave_all_rooms, AUTO_GEN_PASSWORD_LENGTH},
+	service::{
+		admin::user::create_user,
+		login_lockout::{Lockout, Target},
+		server_notices,
+	},
+	services, utils, Error, Result,
+};
+
+#[derive(Deserialize)]
+pub struct CreateUserRequest {
+	username: String,
+	/// Generated if not given
+	password: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateUserResponse {
+	user_id: OwnedUserId,
+	password: String,
+}
+
+#[derive(Serialize)]
+pub struct UserInfo {
+	user_id: OwnedUserId,
+	displayname: Option<String>,
+	avatar_url: Option<OwnedMxcUri>,
+	admin: bool,
+	deactivated: bool,
+	locked: bool,
+	devices: usize,
+	joined_rooms: usize,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+	/// Generated if not given
+	password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeactivateUserRequest {
+	#[serde(default)]
+	leave_rooms: bool,
+}
+
+#[derive(Serialize)]
+pub struct LockoutInfo {
+	target: String,
+	failures: u32,
+	remaining_ms: u128,
+}
+
+impl From<Lockout> for LockoutInfo {
+	fn from(lockout: Lockout) -> Self {
+		Self {
+			target: lockout.target.to_string(),
+			failures: lockout.failures,
+			remaining_ms: lockout.remaining.as_millis(),
+		}
+	}
+}
+
+/// # `GET /_conduwuit/admin/v1/users`
+///
+/// Lists the local users that can log in.
+pub async fn list_users_route(_: AdminUser) -> Result<Json<Value>> {
+	Ok(Json(json!({
+		"users": services().users.list_local_users()?,
+	})))
+}
+
+/// # `POST /_conduwuit/admin/v1/users`
+///
+/// Creates a local user, like `users create`.
+pub async fn create_user_route(
+	AdminUser(sender_user): AdminUser, Json(body): Json<CreateUserRequest>,
+) -> Result<Json<CreateUserResponse>> {
+	let user_id = UserId::parse_with_server_name(body.username.to_lowercase(), services().globals.server_name())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
+
+	if user_id.is_historical() {
+		return Err(Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."));
+	}
+
+	if services().users.exists(&user_id)? {
+		return Err(Error::BadRequest(ErrorKind::UserInUse, "Desired user ID is already taken."));
+	}
+
+	// Only passwords chosen by an admin have to meet the password policy
+	if let Some(password) = &body.password {
+		services().users.check_password_policy(&user_id, password)?;
+	}
+	let password = body
+		.password
+		.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+
+	create_user(&user_id, &password).await?;
+
+	info!("{sender_user} created user {user_id} through the admin API");
+
+	Ok(Json(CreateUserResponse {
+		user_id,
+		password,
+	}))
+}
+
+/// # `GET /_conduwuit/admin/v1/users/{userId}`
+pub async fn get_user_route(_: AdminUser, Path(user_id): Path<String>) -> Result<Json<UserInfo>> {
+	let user_id = local_user(&user_id)?;
+
+	Ok(Json(UserInfo {
+		displayname: services().users.displayname(&user_id)?,
+		avatar_url: services().users.avatar_url(&user_id)?,
+		admin: services().users.is_admin(&user_id)?,
+		deactivated: services().users.is_deactivated(&user_id)?,
+		locked: services().users.is_locked(&user_id)?,
+		devices: services().users.all_device_ids(&user_id).count(),
+		joined_rooms: services().rooms.state_cache.rooms_joined(&user_id).count(),
+		user_id,
+	}))
+}
+
+/// # `POST /_conduwuit/admin/v1/users/{userId}/reset_password`
+///
+/// Sets a new password for a user, like `users reset-password`.
+pub async fn reset_password_route(
+	AdminUser(sender_user): AdminUser, Path(user_id): Path<String>, Json(body): Json<ResetPasswordRequest>,
+) -> Result<Json<Value>> {
+	let user_id = local_user(&user_id)?;
+
+	if server_notices::is_server_account(&user_id) {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"The password of the server user can't be reset.",
+		));
+	}
+
+	if let Some(password) = &body.password {
+		services().users.check_password_policy(&user_id, password)?;
+	}
+	let password = body
+		.password
+		.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+
+	services()
+		.users
+		.set_password(&user_id, Some(password.as_str()))?;
+
+	info!("{sender_user} reset the password of {user_id} through the admin API");
+
+	Ok(Json(json!({
+		"password": password,
+	})))
+}
+
+/// # `POST /_conduwuit/admin/v1/users/{userId}/deactivate`
+///
+/// Deactivates a user, like `users deactivate`.
+pub async fn deactivate_user_route(
+	AdminUser(sender_user): AdminUser, Path(user_id): Path<String>, Json(body): Json<DeactivateUserRequest>,
+) -> Result<Json<Value>> {
+	let user_id = local_user(&user_id)?;
+
+	if server_notices::is_server_account(&user_id) {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"The server user can't be deactivated.",
+		));
+	}
+
+	services().users.deactivate_account(&user_id)?;
+
+	if body.leave_rooms {
+		leave_all_rooms(&user_id).await?;
+	}
+
+	info!("{sender_user} deactivated {user_id} through the admin API");
+
+	Ok(Json(json!({})))
+}
+
+/// # `GET /_conduwuit/admin/v1/users/{userId}/rooms`
+///
+/// Lists the rooms a user is joined to.
+pub async fn get_user_rooms_route(_: AdminUser, Path(user_id): Path<String>) -> Result<Json<Value>> {
+	let user_id = local_user(&user_id)?;
+
+	let rooms = services()
+		.rooms
+		.state_cache
+		.rooms_joined(&user_id)
+		.filter_map(Result::ok)
+		.map(|room_id: OwnedRoomId| RoomInfo::new(&room_id))
+		.collect::<Vec<_>>();
+
+	Ok(Json(json!({
+		"rooms": rooms,
+	})))
+}
+
+/// # `GET /_conduwuit/admin/v1/lockouts`
+///
+/// Lists the users and IP addresses locked out after failed password logins.
+pub async fn list_lockouts_route(_: AdminUser) -> Result<Json<Value>> {
+	let lockouts = services()
+		.login_lockout
+		.lockouts()
+		.into_iter()
+		.map(LockoutInfo::from)
+		.collect::<Vec<_>>();
+
+	Ok(Json(json!({
+		"lockouts": lockouts,
+	})))
+}
+
+/// # `DELETE /_conduwuit/admin/v1/lockouts/{target}`
+///
+/// Lifts the lockout of a user ID or IP address.
+pub async fn clear_lockout_route(_: AdminUser, Path(target): Path<String>) -> Result<Json<Value>> {
+	let target = if let Ok(ip) = target.parse() {
+		Target::Ip(ip)
+	} else {
+		Target::User(
+			UserId::parse(target)
+				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Neither a user ID nor an IP address."))?,
+		)
+	};
+
+	Ok(Json(json!({
+		"cleared": services().login_lockout.clear(&target),
+	})))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use ruma::user_id;
+	use serde_json::json;
+
+	use super::{CreateUserRequest, DeactivateUserRequest, LockoutInfo};
+	use crate::service::login_lockout::{Lockout, Target};
+
+	#[test]
+	fn request_bodies() {
+		let body: CreateUserRequest = serde_json::from_value(json!({ "username": "alice" })).unwrap();
+		assert_eq!(body.username, "alice");
+		assert_eq!(body.password, None);
+
+		let body: DeactivateUserRequest = serde_json::from_value(json!({})).unwrap();
+		assert!(!body.leave_rooms);
+
+		assert!(serde_json::from_value::<CreateUserRequest>(json!({ "password": "hunter2" })).is_err());
+	}
+
+	#[test]
+	fn lockout_info() {
+		let info = LockoutInfo::from(Lockout {
+			target: Target::User(user_id!("@alice:example.com").to_owned()),
+			failures: 5,
+			remaining: Duration::from_secs(2),
+		});
+
+		assert_eq!(
+			serde_json::to_value(info).unwrap(),
+			json!({
+				"target": "@alice:example.com",
+				"failures": 5,
+				"remaining_ms": 2000,
+			})
+		);
+	}
+}
@@ -2,13 +2,12 @@ use register::RegistrationKind;
 use ruma::{
 	api::client::{
 		account::{
-			change_password, deactivate, get_3pids, get_username_availability,
+			change_password, check_registration_token_validity, deactivate, get_username_availability,
 			register::{self, LoginType},
-			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn, whoami,
-			ThirdPartyIdRemovalStatus,
+			whoami, ThirdPartyIdRemovalStatus,
 		},
-		error::ErrorKind,
-		uiaa::{AuthFlow, AuthType, UiaaInfo},
+		error::{ErrorKind, StandardErrorBody},
+		uiaa::{AuthData, AuthFlow, AuthType, EmailIdentity, UiaaInfo},
 	},
 	events::{room::message::RoomMessageEventContent, GlobalAccountDataEventType},
 	push, UserId,
@@ -64,6 +63,25 @@ pub async fn get_register_available_route(
 	})
 }
 
+/// # `GET /_matrix/client/v1/register/m.login.registration_token/validity`
+///
+/// Checks if a registration token can currently be used to register, without
+/// using it.
+pub async fn check_registration_token_validity_route(
+	body: Ruma<check_registration_token_validity::v1::Request>,
+) -> Result<check_registration_token_validity::v1::Response> {
+	if !services().registration_tokens.is_required() {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Registration tokens are not required on this server.",
+		));
+	}
+
+	Ok(check_registration_token_validity::v1::Response {
+		valid: services().registration_tokens.is_valid(&body.token)?,
+	})
+}
+
 /// # `POST /_matrix/client/v3/register`
 ///
 /// Register an account on this homeserver.
@@ -78,6 +96,8 @@ pub async fn get_register_available_route(
 /// - If sender is not appservice: Requires UIAA (but we only use a dummy stage)
 /// - If type is not guest and no username is given: Always fails after UIAA
 ///   check
+/// - Fails with `M_WEAK_PASSWORD` if the password doesn't meet the password
+///   policy
 /// - Creates a new account and populates it with default account data
 /// - If `inhibit_login` is false: Creates a device and returns device id and
 ///   access_token
@@ -96,7 +116,7 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 
 	if is_guest
 		&& (!services().globals.allow_guest_registration()
-			|| (services().globals.allow_registration() && services().globals.config.registration_token.is_some()))
+			|| (services().globals.allow_registration() && services().registration_tokens.is_required()))
 	{
 		info!(
 			"Guest registration disabled / registration enabled with token configured, rejecting guest registration, \
@@ -168,10 +188,16 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 		return Err(Error::BadRequest(ErrorKind::Exclusive, "User ID reserved by appservice."));
 	}
 
+	if !is_guest {
+		if let Some(password) = &body.password {
+			services().users.check_password_policy(&user_id, password)?;
+		}
+	}
+
 	// UIAA
 	let mut uiaainfo;
 	let skip_auth;
-	if services().globals.config.registration_token.is_some() {
+	if services().registration_tokens.is_required() {
 		// Registration token required
 		uiaainfo = UiaaInfo {
 			flows: vec![AuthFlow {
@@ -197,18 +223,33 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 		skip_auth = body.appservice_info.is_some() || is_guest;
 	}
 
+	// Policies have to be accepted in addition to the other stages
+	if services().consent.is_enabled() {
+		for flow in &mut uiaainfo.flows {
+			flow.stages.push(AuthType::Terms);
+		}
+		uiaainfo.params = services().consent.terms_params();
+	}
+
+	let mut uiaa_session = None;
 	if !skip_auth {
 		if let Some(auth) = &body.auth {
-			let (worked, uiaainfo) = services().uiaa.try_auth(
-				&UserId::parse_with_server_name("", services().globals.server_name()).expect("we know this is valid"),
-				"".into(),
-				auth,
-				&uiaainfo,
-			)?;
+			let (worked, uiaainfo) = services()
+				.uiaa
+				.try_auth(
+					&UserId::parse_with_server_name("", services().globals.server_name())
+						.expect("we know this is valid"),
+					"".into(),
+					auth,
+					&uiaainfo,
+					body.client_ip,
+				)
+				.await?;
 			if !worked {
 				return Err(Error::Uiaa(uiaainfo));
 			}
-		// Success!
+			// Success!
+			uiaa_session = uiaainfo.session;
 		} else if let Some(json) = body.json_body {
 			uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 			services().uiaa.create(
@@ -232,13 +273,28 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 	// Create user
 	services().users.create(&user_id, password)?;
 
+	// Only now the registration token is used up
+	if let Some(session) = &uiaa_session {
+		services().registration_tokens.complete(session)?;
+	}
+
+	// Guests don't go through UIAA, so they are sent a link to accept the
+	// policies instead
+	if services().consent.is_enabled() {
+		if is_guest {
+			services().consent.notify(&user_id).await?;
+		} else if !skip_auth {
+			services().consent.accept(&user_id)?;
+		}
+	}
+
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
 
 	// If `new_user_displayname_suffix` is set, registration will push whatever
 	// content is set to the user's display name with a space before it
 	if !services().globals.new_user_displayname_suffix().is_empty() {
-		displayname.push_str(&(" ".to_owned() + services().globals.new_user_displayname_suffix()));
+		displayname.push_str(&(" ".to_owned() + &services().globals.new_user_displayname_suffix()));
 	}
 
 	services()
@@ -286,6 +342,9 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 		.users
 		.create_device(&user_id, &device_id, &token, body.initial_device_display_name.clone())?;
 
+	let (expires_in, refresh_token) =
+		client_server::set_token_lifetime(&user_id, &device_id, body.refresh_token, None)?;
+
 	info!("New user \"{}\" registered on this server.", user_id);
 
 	// log in conduit admin channel if a non-guest user registered
@@ -348,10 +407,10 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 	}
 
 	if body.appservice_info.is_none()
-		&& !services().globals.config.auto_join_rooms.is_empty()
+		&& !services().globals.config.get().auto_join_rooms.is_empty()
 		&& (services().globals.allow_guests_auto_join_rooms() || !is_guest)
 	{
-		for room in &services().globals.config.auto_join_rooms {
+		for room in &services().globals.config.get().auto_join_rooms {
 			if !services()
 				.rooms
 				.state_cache
@@ -384,8 +443,8 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 		access_token: Some(token),
 		user_id,
 		device_id: Some(device_id),
-		refresh_token: None,
-		expires_in: None,
+		refresh_token,
+		expires_in,
 	})
 }
 
@@ -393,11 +452,15 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 ///
 /// Changes the password of this account.
 ///
-/// - Requires UIAA to verify user password
+/// - Requires UIAA to verify user password or a bound email address
 /// - Changes the password of the sender user
+/// - Fails with `M_WEAK_PASSWORD` if the password doesn't meet the password
+///   policy
 /// - The password hash is calculated using argon2 with 32 character salt, the
 ///   plain password is
 /// not saved
+/// - Without an access token, resets the password of the account the email
+///   address validated with `m.login.email.identity` is bound to
 ///
 /// If logout_devices is true it does the following for each device except the
 /// sender device:
@@ -407,13 +470,17 @@ pub async fn register_route(body: Ruma<register::v3::Request>) -> Result<registe
 /// - Forgets to-device events
 /// - Triggers device list updates
 pub async fn change_password_route(body: Ruma<change_password::v3::Request>) -> Result<change_password::v3::Response> {
-	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let Some(sender_user) = body.sender_user.as_ref() else {
+		return reset_password(body).await;
+	};
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
+	services()
+		.users
+		.check_password_policy(sender_user, &body.new_password)?;
+
 	let mut uiaainfo = UiaaInfo {
-		flows: vec![AuthFlow {
-			stages: vec![AuthType::Password],
-		}],
+		flows: services().uiaa.user_flows(sender_user)?,
 		completed: Vec::new(),
 		params: Box::default(),
 		session: None,
@@ -423,7 +490,8 @@ pub async fn change_password_route(body: Ruma<change_password::v3::Request>) ->
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo, body.client_ip)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
@@ -464,6 +532,93 @@ pub async fn change_password_route(body: Ruma<change_password::v3::Request>) ->
 	Ok(change_password::v3::Response {})
 }
 
+/// Resets the password of an account without an access token, after the user
+/// validated an email address bound to it.
+///
+/// - Logs out all devices unless logout_devices is false
+async fn reset_password(body: Ruma<change_password::v3::Request>) -> Result<change_password::v3::Response> {
+	if !services().threepid.email_enabled() {
+		return Err(Error::BadRequest(ErrorKind::MissingToken, "Missing access token."));
+	}
+
+	let mut uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow {
+			stages: vec![AuthType::EmailIdentity],
+		}],
+		completed: Vec::new(),
+		params: Box::default(),
+		session: Some(utils::random_string(SESSION_ID_LENGTH)),
+		auth_error: None,
+	};
+
+	// There is no device to store a UIAA session for, the single stage is
+	// completed in this request
+	let Some(AuthData::EmailIdentity(EmailIdentity {
+		thirdparty_id_creds,
+		..
+	})) = &body.auth
+	else {
+		return Err(Error::Uiaa(uiaainfo));
+	};
+
+	// The session is only consumed once the new password is accepted, so a weak
+	// one doesn't burn the emailed link
+	let Some(user_id) = services().threepid.validated_user(thirdparty_id_creds)? else {
+		uiaainfo.auth_error = Some(StandardErrorBody {
+			kind: ErrorKind::ThreepidAuthFailed,
+			message: "Email address has not been validated.".to_owned(),
+		});
+		return Err(Error::Uiaa(uiaainfo));
+	};
+
+	if services().users.is_deactivated(&user_id)? {
+		return Err(Error::BadRequest(
+			ErrorKind::UserDeactivated,
+			"This account has been deactivated.",
+		));
+	}
+
+	services()
+		.users
+		.check_password_policy(&user_id, &body.new_password)?;
+
+	if services()
+		.threepid
+		.authenticate(thirdparty_id_creds)?
+		.as_deref()
+		!= Some(&*user_id)
+	{
+		uiaainfo.auth_error = Some(StandardErrorBody {
+			kind: ErrorKind::ThreepidAuthFailed,
+			message: "Email address has not been validated.".to_owned(),
+		});
+		return Err(Error::Uiaa(uiaainfo));
+	}
+
+	services()
+		.users
+		.set_password(&user_id, Some(&body.new_password))?;
+
+	if body.logout_devices {
+		for id in services()
+			.users
+			.all_device_ids(&user_id)
+			.filter_map(Result::ok)
+		{
+			services().users.remove_device(&user_id, &id)?;
+		}
+	}
+
+	info!("User {} reset their password.", user_id);
+	services()
+		.admin
+		.send_message(RoomMessageEventContent::notice_plain(format!(
+			"User {user_id} reset their password via email."
+		)));
+
+	Ok(change_password::v3::Response {})
+}
+
 /// # `GET _matrix/client/r0/account/whoami`
 ///
 /// Get `user_id` of the sender user.
@@ -496,9 +651,7 @@ pub async fn deactivate_route(body: Ruma<deactivate::v3::Request>) -> Result<dea
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
 	let mut uiaainfo = UiaaInfo {
-		flows: vec![AuthFlow {
-			stages: vec![AuthType::Password],
-		}],
+		flows: services().uiaa.user_flows(sender_user)?,
 		completed: Vec::new(),
 		params: Box::default(),
 		session: None,
@@ -508,7 +661,8 @@ pub async fn deactivate_route(body: Ruma<deactivate::v3::Request>) -> Result<dea
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo, body.client_ip)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
@@ -540,46 +694,3 @@ pub async fn deactivate_route(body: Ruma<deactivate::v3::Request>) -> Result<dea
 		id_server_unbind_result: ThirdPartyIdRemovalStatus::NoSupport,
 	})
 }
-
-/// # `GET _matrix/client/v3/account/3pid`
-///
-/// Get a list of third party identifiers associated with this account.
-///
-/// - Currently always returns empty list
-pub async fn third_party_route(body: Ruma<get_3pids::v3::Request>) -> Result<get_3pids::v3::Response> {
-	let _sender_user = body.sender_user.as_ref().expect("user is authenticated");
-
-	Ok(get_3pids::v3::Response::new(Vec::new()))
-}
-
-/// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
-///
-/// "This API should be used to request validation tokens when adding an email
-/// address to an account"
-///
-/// - 403 signals that The homeserver does not allow the third party identifier
-///   as a contact option.
-pub async fn request_3pid_management_token_via_email_route(
-	_body: Ruma<request_3pid_management_token_via_email::v3::Request>,
-) -> Result<request_3pid_management_token_via_email::v3::Response> {
-	Err(Error::BadRequest(
-		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
-	))
-}
-
-/// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
-///
-/// "This API should be used to request validation tokens when adding an phone
-/// number to an account"
-///
-/// - 403 signals that The homeserver does not allow the third party identifier
-///   as a contact option.
-pub async fn request_3pid_management_token_via_msisdn_route(
-	_body: Ruma<request_3pid_management_token_via_msisdn::v3::Request>,
-) -> Result<request_3pid_management_token_via_msisdn::v3::Response> {
-	Err(Error::BadRequest(
-		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
-	))
-}
@@ -10,6 +10,7 @@ use ruma::{
 	},
 	OwnedRoomAliasId, OwnedServerName,
 };
+use tracing::warn;
 
 use crate::{services, Error, Result, Ruma};
 
@@ -69,21 +70,21 @@ pub async fn create_alias_route(body: Ruma<create_alias::v3::Request>) -> Result
 ///
 /// Deletes a room alias from this server.
 ///
+/// - Removes the alias from the room's `m.room.canonical_alias` event, sent by
+///   the deleting user if they have the power to, otherwise by the server user
 /// - TODO: additional access control checks
-/// - TODO: Update canonical alias event
 pub async fn delete_alias_route(body: Ruma<delete_alias::v3::Request>) -> Result<delete_alias::v3::Response> {
 	if body.room_alias.server_name() != services().globals.server_name() {
 		return Err(Error::BadRequest(ErrorKind::InvalidParam, "Alias is from another server."));
 	}
 
-	if services()
+	let Some(room_id) = services()
 		.rooms
 		.alias
 		.resolve_local_alias(&body.room_alias)?
-		.is_none()
-	{
+	else {
 		return Err(Error::BadRequest(ErrorKind::NotFound, "Alias does not exist."));
-	}
+	};
 
 	if let Some(ref info) = body.appservice_info {
 		if !info.aliases.is_match(body.room_alias.as_str()) {
@@ -109,7 +110,17 @@ pub async fn delete_alias_route(body: Ruma<delete_alias::v3::Request>) -> Result
 		));
 	};
 
-	// TODO: update alt_aliases?
+	if let Err(e) = services()
+		.rooms
+		.alias
+		.remove_from_canonical_alias(&room_id, &body.room_alias, body.sender_user.as_deref())
+		.await
+	{
+		warn!(
+			"Failed to remove {} from the canonical alias event of {room_id}: {e}",
+			body.room_alias
+		);
+	}
 
 	Ok(delete_alias::v3::Response::new())
 }
@@ -1,9 +1,10 @@
 use std::collections::BTreeMap;
 
 use ruma::api::client::discovery::get_capabilities::{
-	self, Capabilities, ChangePasswordCapability, RoomVersionStability, RoomVersionsCapability, SetAvatarUrlCapability,
-	SetDisplayNameCapability, ThirdPartyIdChangesCapability,
+	self, Capabilities, ChangePasswordCapability, GetLoginTokenCapability, RoomVersionStability,
+	RoomVersionsCapability, SetAvatarUrlCapability, SetDisplayNameCapability, ThirdPartyIdChangesCapability,
 };
+use serde_json::json;
 
 use crate::{services, Result, Ruma};
 
@@ -40,11 +41,30 @@ pub async fn get_capabilities_route(
 		enabled: true,
 	};
 
-	// conduit does not implement 3PID stuff
+	capabilities.get_login_token = GetLoginTokenCapability {
+		enabled: services().globals.config.get().login_via_existing_session,
+	};
+
 	capabilities.thirdparty_id_changes = ThirdPartyIdChangesCapability {
-		enabled: false,
+		enabled: services().threepid.email_enabled(),
 	};
 
+	// Password requirements, using the keys proposed in MSC2000
+	if let Some(policy) = &services().globals.config.get().password_policy {
+		capabilities
+			.set(
+				"org.matrix.msc2000.password_policy",
+				json!({
+					"m.minimum_length": policy.min_length,
+					"m.require_digit": policy.require_digit,
+					"m.require_symbol": policy.require_symbol,
+					"m.require_lowercase": policy.require_lowercase,
+					"m.require_uppercase": policy.require_uppercase,
+				}),
+			)
+			.expect("password policy capability is valid json");
+	}
+
 	Ok(get_capabilities::v3::Response {
 		capabilities,
 	})
@@ -0,0 +1,77 @@
+use std::fmt::Write as _;
+
+use axum::{response::Html, Form};
+use http::Uri;
+use ruma::api::client::error::ErrorKind;
+use serde::Deserialize;
+
+use crate::{services, utils::HtmlEscape, Error, Result};
+
+#[derive(Deserialize)]
+pub struct ConsentParams {
+	token: String,
+}
+
+/// # `GET /_conduwuit/consent`
+///
+/// Shows the current policies of the server to the user a consent link was
+/// sent to, with a button to accept them.
+pub async fn get_consent_route(uri: Uri) -> Result<Html<String>> {
+	let params: ConsentParams = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid consent link."))?;
+
+	let Some(user_id) = services().consent.token_user(&params.token)? else {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Unknown or already used consent link.",
+		));
+	};
+
+	let mut policies = String::new();
+	for policy in services().globals.config.get().policies.values() {
+		let Some(translation) = policy
+			.translations
+			.get("en")
+			.or_else(|| policy.translations.values().next())
+		else {
+			continue;
+		};
+
+		writeln!(
+			policies,
+			"<li><a href=\"{}\" target=\"_blank\">{}</a> (version {})</li>",
+			HtmlEscape(translation.url.as_str()),
+			HtmlEscape(&translation.name),
+			HtmlEscape(&policy.version)
+		)
+		.expect("write to String works");
+	}
+
+	Ok(Html(format!(
+		"<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Policies of \
+		 {server_name}</title></head><body>\n<p>To continue using {server_name} as {user_id}, please review and \
+		 accept the following policies:</p>\n<ul>\n{policies}</ul>\n<form method=\"post\">\n<input type=\"hidden\" \
+		 name=\"token\" value=\"{token}\">\n<button type=\"submit\">I accept</button>\n</form>\n</body></html>\n",
+		server_name = HtmlEscape(services().globals.server_name().as_str()),
+		user_id = HtmlEscape(user_id.as_str()),
+		token = HtmlEscape(&params.token),
+	)))
+}
+
+/// # `POST /_conduwuit/consent`
+///
+/// Accepts the current policies for the user a consent link was sent to.
+pub async fn accept_consent_route(Form(params): Form<ConsentParams>) -> Result<Html<String>> {
+	let Some(user_id) = services().consent.accept_with_token(&params.token)? else {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Unknown or already used consent link.",
+		));
+	};
+
+	Ok(Html(format!(
+		"<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Policies accepted</title></head><body>\n<p>Thank \
+		 you, {}. You can now return to your client.</p>\n</body></html>\n",
+		HtmlEscape(user_id.as_str())
+	)))
+}
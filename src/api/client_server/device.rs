@@ -88,7 +88,8 @@ pub async fn delete_device_route(body: Ruma<delete_device::v3::Request>) -> Resu
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo, body.client_ip)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
@@ -140,7 +141,8 @@ pub async fn delete_devices_route(body: Ruma<delete_devices::v3::Request>) -> Re
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo, body.client_ip)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
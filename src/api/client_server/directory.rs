@@ -1,28 +1,17 @@
 use ruma::{
 	api::{
 		client::{
+			appservice,
 			directory::{get_public_rooms, get_public_rooms_filtered, get_room_visibility, set_room_visibility},
 			error::ErrorKind,
 			room,
 		},
 		federation,
 	},
-	directory::{Filter, PublicRoomJoinRule, PublicRoomsChunk, RoomNetwork},
-	events::{
-		room::{
-			avatar::RoomAvatarEventContent,
-			canonical_alias::RoomCanonicalAliasEventContent,
-			create::RoomCreateEventContent,
-			guest_access::{GuestAccess, RoomGuestAccessEventContent},
-			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
-			join_rules::{JoinRule, RoomJoinRulesEventContent},
-			topic::RoomTopicEventContent,
-		},
-		StateEventType,
-	},
+	directory::{Filter, RoomNetwork},
 	ServerName, UInt,
 };
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 use crate::{services, Error, Result, Ruma};
 
@@ -35,11 +24,7 @@ pub async fn get_public_rooms_filtered_route(
 	body: Ruma<get_public_rooms_filtered::v3::Request>,
 ) -> Result<get_public_rooms_filtered::v3::Response> {
 	if let Some(server) = &body.server {
-		if services()
-			.globals
-			.forbidden_remote_room_directory_server_names()
-			.contains(server)
-		{
+		if !services().server_policy.is_room_directory_allowed(server) {
 			return Err(Error::BadRequest(
 				ErrorKind::forbidden(),
 				"Server is banned on this homeserver.",
@@ -72,11 +57,7 @@ pub async fn get_public_rooms_route(
 	body: Ruma<get_public_rooms::v3::Request>,
 ) -> Result<get_public_rooms::v3::Response> {
 	if let Some(server) = &body.server {
-		if services()
-			.globals
-			.forbidden_remote_room_directory_server_names()
-			.contains(server)
-		{
+		if !services().server_policy.is_room_directory_allowed(server) {
 			return Err(Error::BadRequest(
 				ErrorKind::forbidden(),
 				"Server is banned on this homeserver.",
@@ -122,7 +103,13 @@ pub async fn set_room_visibility_route(
 
 	match &body.visibility {
 		room::Visibility::Public => {
-			if services().globals.config.lockdown_public_room_directory && !services().users.is_admin(sender_user)? {
+			if services()
+				.globals
+				.config
+				.get()
+				.lockdown_public_room_directory
+				&& !services().users.is_admin(sender_user)?
+			{
 				info!(
 					"Non-admin user {sender_user} tried to publish {0} to the room directory while \
 					 \"lockdown_public_room_directory\" is enabled",
@@ -170,8 +157,71 @@ pub async fn get_room_visibility_route(
 	})
 }
 
+/// # `PUT /_matrix/client/v3/directory/list/appservice/{networkId}/{roomId}`
+///
+/// Publishes or removes a room in the room directory of an appservice
+/// network, such as the portal rooms of a bridge.
+///
+/// - Only appservices may use this endpoint
+/// - Rooms listed here are only returned when a client asks for this network or
+///   for all networks
+pub async fn set_room_visibility_for_appservice_network_route(
+	body: Ruma<appservice::set_room_visibility::v3::Request>,
+) -> Result<appservice::set_room_visibility::v3::Response> {
+	let Some(appservice_info) = &body.appservice_info else {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Only appservices can publish rooms to a network room directory.",
+		));
+	};
+
+	if !services().rooms.metadata.exists(&body.room_id)? {
+		// Return 404 if the room doesn't exist
+		return Err(Error::BadRequest(ErrorKind::NotFound, "Room not found"));
+	}
+
+	// Appservices may only change the listings they made themselves
+	if services()
+		.rooms
+		.directory
+		.network_room_appservice(&body.network_id, &body.room_id)?
+		.is_some_and(|appservice_id| appservice_id != appservice_info.registration.id)
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"The room was published to this network by another appservice.",
+		));
+	}
+
+	match &body.visibility {
+		room::Visibility::Public => {
+			services().rooms.directory.set_public_in_network(
+				&body.network_id,
+				&body.room_id,
+				&appservice_info.registration.id,
+			)?;
+			info!(
+				"Appservice {} published {} to network {}",
+				appservice_info.registration.id, body.room_id, body.network_id
+			);
+		},
+		room::Visibility::Private => services()
+			.rooms
+			.directory
+			.set_not_public_in_network(&body.network_id, &body.room_id)?,
+		_ => {
+			return Err(Error::BadRequest(
+				ErrorKind::InvalidParam,
+				"Room visibility type is not supported.",
+			));
+		},
+	}
+
+	Ok(appservice::set_room_visibility::v3::Response {})
+}
+
 pub(crate) async fn get_public_rooms_filtered_helper(
-	server: Option<&ServerName>, limit: Option<UInt>, since: Option<&str>, filter: &Filter, _network: &RoomNetwork,
+	server: Option<&ServerName>, limit: Option<UInt>, since: Option<&str>, filter: &Filter, network: &RoomNetwork,
 ) -> Result<get_public_rooms_filtered::v3::Response> {
 	if let Some(other_server) = server.filter(|server| *server != services().globals.server_name().as_str()) {
 		let response = services()
@@ -185,7 +235,7 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 						generic_search_term: filter.generic_search_term.clone(),
 						room_types: filter.room_types.clone(),
 					},
-					room_network: RoomNetwork::Matrix,
+					room_network: network.clone(),
 				},
 			)
 			.await?;
@@ -219,149 +269,11 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 		}
 	}
 
-	let mut all_rooms: Vec<_> = services()
+	let room_ids = services().rooms.directory.public_rooms_in_network(network);
+	let all_rooms = services()
 		.rooms
 		.directory
-		.public_rooms()
-		.map(|room_id| {
-			let room_id = room_id?;
-
-			let chunk = PublicRoomsChunk {
-				canonical_alias: services()
-					.rooms
-					.state_accessor
-					.room_state_get(&room_id, &StateEventType::RoomCanonicalAlias, "")?
-					.map_or(Ok(None), |s| {
-						serde_json::from_str(s.content.get())
-							.map(|c: RoomCanonicalAliasEventContent| c.alias)
-							.map_err(|_| Error::bad_database("Invalid canonical alias event in database."))
-					})?,
-				name: services().rooms.state_accessor.get_name(&room_id)?,
-				num_joined_members: services()
-					.rooms
-					.state_cache
-					.room_joined_count(&room_id)?
-					.unwrap_or_else(|| {
-						warn!("Room {} has no member count", room_id);
-						0
-					})
-					.try_into()
-					.expect("user count should not be that big"),
-				topic: services()
-					.rooms
-					.state_accessor
-					.room_state_get(&room_id, &StateEventType::RoomTopic, "")?
-					.map_or(Ok(None), |s| {
-						serde_json::from_str(s.content.get())
-							.map(|c: RoomTopicEventContent| Some(c.topic))
-							.map_err(|e| {
-								error!("Invalid room topic event in database for room {room_id}: {e}");
-								Error::bad_database("Invalid room topic event in database.")
-							})
-					})
-					.unwrap_or(None),
-				world_readable: services()
-					.rooms
-					.state_accessor
-					.room_state_get(&room_id, &StateEventType::RoomHistoryVisibility, "")?
-					.map_or(Ok(false), |s| {
-						serde_json::from_str(s.content.get())
-							.map(|c: RoomHistoryVisibilityEventContent| {
-								c.history_visibility == HistoryVisibility::WorldReadable
-							})
-                            .map_err(|e| {
-                                error!(
-                                    "Invalid room history visibility event in database for room {room_id}, assuming is \"shared\": {e}",
-                                );
-                                Error::bad_database("Invalid room history visibility event in database.")
-                            })}).unwrap_or(false),
-				guest_can_join: services()
-					.rooms
-					.state_accessor
-					.room_state_get(&room_id, &StateEventType::RoomGuestAccess, "")?
-					.map_or(Ok(false), |s| {
-						serde_json::from_str(s.content.get())
-							.map(|c: RoomGuestAccessEventContent| c.guest_access == GuestAccess::CanJoin)
-							.map_err(|_| Error::bad_database("Invalid room guest access event in database."))
-					})?,
-				avatar_url: services()
-					.rooms
-					.state_accessor
-					.room_state_get(&room_id, &StateEventType::RoomAvatar, "")?
-					.map(|s| {
-						serde_json::from_str(s.content.get())
-							.map(|c: RoomAvatarEventContent| c.url)
-							.map_err(|_| Error::bad_database("Invalid room avatar event in database."))
-					})
-					.transpose()?
-					// url is now an Option<String> so we must flatten
-					.flatten(),
-				join_rule: services()
-					.rooms
-					.state_accessor
-					.room_state_get(&room_id, &StateEventType::RoomJoinRules, "")?
-					.map(|s| {
-						serde_json::from_str(s.content.get())
-							.map(|c: RoomJoinRulesEventContent| match c.join_rule {
-								JoinRule::Public => Some(PublicRoomJoinRule::Public),
-								JoinRule::Knock => Some(PublicRoomJoinRule::Knock),
-								_ => None,
-							})
-							.map_err(|e| {
-								error!("Invalid room join rule event in database: {}", e);
-								Error::BadDatabase("Invalid room join rule event in database.")
-							})
-					})
-					.transpose()?
-					.flatten()
-					.ok_or_else(|| Error::bad_database("Missing room join rule event for room."))?,
-				room_type: services()
-					.rooms
-					.state_accessor
-					.room_state_get(&room_id, &StateEventType::RoomCreate, "")?
-					.map(|s| {
-						serde_json::from_str::<RoomCreateEventContent>(s.content.get()).map_err(|e| {
-							error!("Invalid room create event in database: {}", e);
-							Error::BadDatabase("Invalid room create event in database.")
-						})
-					})
-					.transpose()?
-					.and_then(|e| e.room_type),
-				room_id,
-			};
-			Ok(chunk)
-		})
-		.filter_map(|r: Result<_>| r.ok()) // Filter out buggy rooms
-		.filter(|chunk| {
-			if let Some(query) = filter.generic_search_term.as_ref().map(|q| q.to_lowercase()) {
-				if let Some(name) = &chunk.name {
-					if name.as_str().to_lowercase().contains(&query) {
-						return true;
-					}
-				}
-
-				if let Some(topic) = &chunk.topic {
-					if topic.to_lowercase().contains(&query) {
-						return true;
-					}
-				}
-
-				if let Some(canonical_alias) = &chunk.canonical_alias {
-					if canonical_alias.as_str().to_lowercase().contains(&query) {
-						return true;
-					}
-				}
-
-				false
-			} else {
-				// No search term
-				true
-			}
-		})
-		// We need to collect all, so we can sort by member count
-		.collect();
-
-	all_rooms.sort_by(|l, r| r.num_joined_members.cmp(&l.num_joined_members));
+		.search_public_rooms(room_ids, filter);
 
 	let total_room_count_estimate = (all_rooms.len() as u32).into();
 
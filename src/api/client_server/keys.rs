@@ -116,7 +116,8 @@ pub async fn upload_signing_keys_route(
 	if let Some(auth) = &body.auth {
 		let (worked, uiaainfo) = services()
 			.uiaa
-			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+			.try_auth(sender_user, sender_device, auth, &uiaainfo, body.client_ip)
+			.await?;
 		if !worked {
 			return Err(Error::Uiaa(uiaainfo));
 		}
@@ -19,7 +19,7 @@ use crate::{
 };
 
 /// generated MXC ID (`media-id`) length
-const MXC_LENGTH: usize = 32;
+pub const MXC_LENGTH: usize = 32;
 
 /// # `GET /_matrix/media/v3/config`
 ///
@@ -219,11 +219,7 @@ pub async fn get_remote_content(
 ) -> Result<get_content::v3::Response, Error> {
 	// we'll lie to the client and say the blocked server's media was not found and
 	// log. the client has no way of telling anyways so this is a security bonus.
-	if services()
-		.globals
-		.prevent_media_downloads_from()
-		.contains(&server_name.to_owned())
-	{
+	if !services().server_policy.is_media_allowed(server_name) {
 		info!(
 			"Received request for remote media `{}` but server is in our media server blocklist. Returning 404.",
 			mxc
@@ -485,11 +481,7 @@ pub async fn get_content_thumbnail_route(
 	} else if &*body.server_name != services().globals.server_name() && body.allow_remote {
 		// we'll lie to the client and say the blocked server's media was not found and
 		// log. the client has no way of telling anyways so this is a security bonus.
-		if services()
-			.globals
-			.prevent_media_downloads_from()
-			.contains(&body.server_name.clone())
-		{
+		if !services().server_policy.is_media_allowed(&body.server_name) {
 			info!(
 				"Received request for remote media `{}` but server is in our media server blocklist. Returning 404.",
 				mxc
@@ -577,11 +569,7 @@ pub async fn get_content_thumbnail_v1_route(
 	} else if &*body.server_name != services().globals.server_name() && body.allow_remote {
 		// we'll lie to the client and say the blocked server's media was not found and
 		// log. the client has no way of telling anyways so this is a security bonus.
-		if services()
-			.globals
-			.prevent_media_downloads_from()
-			.contains(&body.server_name.clone())
-		{
+		if !services().server_policy.is_media_allowed(&body.server_name) {
 			info!(
 				"Received request for remote media `{}` but server is in our media server blocklist. Returning 404.",
 				mxc
@@ -692,7 +680,7 @@ async fn download_html(client: &reqwest::Client, url: &str) -> Result<UrlPreview
 
 async fn request_url_preview(url: &str) -> Result<UrlPreviewData> {
 	if let Ok(ip) = IPAddress::parse(url) {
-		let cidr_ranges_s = services().globals.ip_range_denylist().to_vec();
+		let cidr_ranges_s = services().globals.ip_range_denylist();
 		let mut cidr_ranges: Vec<IPAddress> = Vec::new();
 
 		for cidr in cidr_ranges_s {
@@ -714,7 +702,7 @@ async fn request_url_preview(url: &str) -> Result<UrlPreviewData> {
 
 	if let Some(remote_addr) = response.remote_addr() {
 		if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
-			let cidr_ranges_s = services().globals.ip_range_denylist().to_vec();
+			let cidr_ranges_s = services().globals.ip_range_denylist();
 			let mut cidr_ranges: Vec<IPAddress> = Vec::new();
 
 			for cidr in cidr_ranges_s {
@@ -56,13 +56,7 @@ pub async fn join_room_by_id_route(body: Ruma<join_room_by_id::v3::Request>) ->
 	}
 
 	if let Some(server) = body.room_id.server_name() {
-		if services()
-			.globals
-			.config
-			.forbidden_remote_server_names
-			.contains(&server.to_owned())
-			&& !services().users.is_admin(sender_user)?
-		{
+		if !services().server_policy.is_federation_allowed(server) && !services().users.is_admin(sender_user)? {
 			warn!(
 				"User {sender_user} tried joining room ID {} which has a server name that is globally forbidden. \
 				 Rejecting.",
@@ -121,6 +115,7 @@ pub async fn join_room_by_id_or_alias_route(
 	body: Ruma<join_room_by_id_or_alias::v3::Request>,
 ) -> Result<join_room_by_id_or_alias::v3::Response> {
 	let sender_user = body.sender_user.as_deref().expect("user is authenticated");
+
 	let body = body.body;
 
 	let (servers, room_id) = match OwnedRoomId::try_from(body.room_id_or_alias) {
@@ -133,13 +128,7 @@ pub async fn join_room_by_id_or_alias_route(
 			}
 
 			if let Some(server) = room_id.server_name() {
-				if services()
-					.globals
-					.config
-					.forbidden_remote_server_names
-					.contains(&server.to_owned())
-					&& !services().users.is_admin(sender_user)?
-				{
+				if !services().server_policy.is_federation_allowed(server) && !services().users.is_admin(sender_user)? {
 					warn!(
 						"User {sender_user} tried joining room ID {room_id} which has a server name that is globally \
 						 forbidden. Rejecting.",
@@ -190,11 +179,9 @@ pub async fn join_room_by_id_or_alias_route(
 				));
 			}
 
-			if services()
-				.globals
-				.config
-				.forbidden_remote_server_names
-				.contains(&room_alias.server_name().to_owned())
+			if !services()
+				.server_policy
+				.is_federation_allowed(room_alias.server_name())
 				&& !services().users.is_admin(sender_user)?
 			{
 				warn!(
@@ -209,13 +196,7 @@ pub async fn join_room_by_id_or_alias_route(
 			}
 
 			if let Some(server) = response.room_id.server_name() {
-				if services()
-					.globals
-					.config
-					.forbidden_remote_server_names
-					.contains(&server.to_owned())
-					&& !services().users.is_admin(sender_user)?
-				{
+				if !services().server_policy.is_federation_allowed(server) && !services().users.is_admin(sender_user)? {
 					warn!(
 						"User {sender_user} tried joining room alias {} with room ID {} which has a server name that \
 						 is globally forbidden. Rejecting.",
@@ -288,12 +269,7 @@ pub async fn invite_user_route(body: Ruma<invite_user::v3::Request>) -> Result<i
 	}
 
 	if let Some(server) = body.room_id.server_name() {
-		if services()
-			.globals
-			.config
-			.forbidden_remote_server_names
-			.contains(&server.to_owned())
-		{
+		if !services().server_policy.is_federation_allowed(server) {
 			return Err(Error::BadRequest(
 				ErrorKind::forbidden(),
 				"Server is banned on this homeserver.",
@@ -566,10 +542,15 @@ pub async fn joined_rooms_route(body: Ruma<joined_rooms::v3::Request>) -> Result
 
 /// # `POST /_matrix/client/r0/rooms/{roomId}/members`
 ///
-/// Lists all joined users in a room (TODO: at a specific point in time, with a
-/// specific membership).
+/// Lists all member events in a room, optionally at a specific point in time
+/// and filtered by membership.
 ///
-/// - Only works if the user is currently joined
+/// - Only works if the user can see the current room state
+/// - `at` is a sync token; the member list is taken from the room state
+///   associated with that token, if the history visibility at that point allows
+///   the user to see it
+/// - `membership` only includes members with the given membership
+/// - `not_membership` excludes members with the given membership
 pub async fn get_member_events_route(
 	body: Ruma<get_member_events::v3::Request>,
 ) -> Result<get_member_events::v3::Response> {
@@ -586,19 +567,77 @@ pub async fn get_member_events_route(
 		));
 	}
 
+	let shortstatehash = match body.at.as_deref() {
+		Some(at) => {
+			let token = at
+				.parse()
+				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid at token."))?;
+
+			let shortstatehash = services()
+				.rooms
+				.user
+				.get_token_shortstatehash(&body.room_id, token)?
+				.ok_or(Error::BadRequest(
+					ErrorKind::NotFound,
+					"No room state is known for the given at token.",
+				))?;
+
+			if !services()
+				.rooms
+				.state_accessor
+				.user_can_see_state_at(sender_user, &body.room_id, shortstatehash)?
+			{
+				return Err(Error::BadRequest(
+					ErrorKind::forbidden(),
+					"You don't have permission to view the room state at this point.",
+				));
+			}
+
+			shortstatehash
+		},
+		None => services()
+			.rooms
+			.state
+			.get_room_shortstatehash(&body.room_id)?
+			.ok_or(Error::BadRequest(ErrorKind::NotFound, "Room state not found."))?,
+	};
+
+	let membership = body.membership.as_ref().map(|m| m.as_str());
+	let not_membership = body.not_membership.as_ref().map(|m| m.as_str());
+
 	Ok(get_member_events::v3::Response {
 		chunk: services()
 			.rooms
 			.state_accessor
-			.room_state_full(&body.room_id)
+			.state_full(shortstatehash)
 			.await?
 			.iter()
 			.filter(|(key, _)| key.0 == StateEventType::RoomMember)
-			.map(|(_, pdu)| pdu.to_member_event())
+			.map(|(_, pdu)| pdu)
+			.filter(|pdu| membership_matches(pdu, membership, not_membership))
+			.map(|pdu| pdu.to_member_event())
 			.collect(),
 	})
 }
 
+/// Checks a member event against the `membership` and `not_membership`
+/// filters of `/members`. Events with unparsable content never match a
+/// `membership` filter.
+fn membership_matches(pdu: &PduEvent, membership: Option<&str>, not_membership: Option<&str>) -> bool {
+	if membership.is_none() && not_membership.is_none() {
+		return true;
+	}
+
+	let Ok(content) = serde_json::from_str::<RoomMemberEventContent>(pdu.content.get()) else {
+		return membership.is_none();
+	};
+
+	let current = content.membership.as_str();
+
+	membership.map_or(true, |membership| membership == current)
+		&& not_membership.map_or(true, |not_membership| not_membership != current)
+}
+
 /// # `POST /_matrix/client/r0/rooms/{roomId}/joined_members`
 ///
 /// Lists all members of a room.
@@ -1003,6 +1042,10 @@ pub(crate) async fn join_room_by_id_helper(
 			.rooms
 			.state
 			.set_room_state(room_id, statehash_after_join, &state_lock)?;
+		services()
+			.rooms
+			.state
+			.update_caches_for_state_event(room_id, &parsed_join_pdu.kind)?;
 	} else {
 		info!("We can join locally");
 
@@ -3,6 +3,7 @@ mod alias;
 mod backup;
 mod capabilities;
 mod config;
+mod consent;
 mod context;
 mod device;
 mod directory;
@@ -22,11 +23,13 @@ mod room;
 mod search;
 mod session;
 mod space;
+mod sso;
 mod state;
 mod sync;
 mod tag;
 mod thirdparty;
 mod threads;
+mod threepid;
 mod to_device;
 mod typing;
 mod unstable;
@@ -39,6 +42,7 @@ pub use alias::*;
 pub use backup::*;
 pub use capabilities::*;
 pub use config::*;
+pub use consent::*;
 pub use context::*;
 pub use device::*;
 pub use directory::*;
@@ -58,11 +62,13 @@ pub use room::*;
 pub use search::*;
 pub use session::*;
 pub use space::*;
+pub use sso::*;
 pub use state::*;
 pub use sync::*;
 pub use tag::*;
 pub use thirdparty::*;
 pub use threads::*;
+pub use threepid::*;
 pub use to_device::*;
 pub use typing::*;
 pub use unstable::*;
@@ -1,26 +1,28 @@
-use argon2::{PasswordHash, PasswordVerifier};
+use std::time::Duration;
+
 use ruma::{
 	api::client::{
 		error::ErrorKind,
 		session::{
+			get_login_token,
 			get_login_types::{
 				self,
-				v3::{ApplicationServiceLoginType, PasswordLoginType},
+				v3::{ApplicationServiceLoginType, IdentityProvider, PasswordLoginType, SsoLoginType, TokenLoginType},
 			},
 			login::{
 				self,
 				v3::{DiscoveryInfo, HomeserverInfo},
 			},
-			logout, logout_all,
+			logout, logout_all, refresh_token,
 		},
-		uiaa::UserIdentifier,
+		uiaa::{UiaaInfo, UserIdentifier},
 	},
-	UserId,
+	DeviceId, OwnedDeviceId, UserId,
 };
 use serde::Deserialize;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info, warn};
 
-use super::{DEVICE_ID_LENGTH, TOKEN_LENGTH};
+use super::{DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH};
 use crate::{services, utils, Error, Result, Ruma};
 
 #[derive(Debug, Deserialize)]
@@ -34,10 +36,31 @@ struct Claims {
 /// Get the supported login types of this server. One of these should be used as
 /// the `type` field when logging in.
 pub async fn get_login_types_route(_body: Ruma<get_login_types::v3::Request>) -> Result<get_login_types::v3::Response> {
-	Ok(get_login_types::v3::Response::new(vec![
+	let mut flows = vec![
 		get_login_types::v3::LoginType::Password(PasswordLoginType::default()),
+		get_login_types::v3::LoginType::Token(TokenLoginType {
+			get_login_token: services().globals.config.get().login_via_existing_session,
+		}),
 		get_login_types::v3::LoginType::ApplicationService(ApplicationServiceLoginType::default()),
-	]))
+	];
+
+	let config = services().globals.config.get();
+	let oidc_providers = &config.oidc_providers;
+	if !oidc_providers.is_empty() {
+		flows.push(get_login_types::v3::LoginType::Sso(SsoLoginType {
+			identity_providers: oidc_providers
+				.iter()
+				.map(|provider| IdentityProvider {
+					id: provider.id.clone(),
+					name: provider.name.clone(),
+					icon: provider.icon.clone(),
+					brand: None,
+				})
+				.collect(),
+		}));
+	}
+
+	Ok(get_login_types::v3::Response::new(flows))
 }
 
 /// # `POST /_matrix/client/v3/login`
@@ -45,11 +68,16 @@ pub async fn get_login_types_route(_body: Ruma<get_login_types::v3::Request>) ->
 /// Authenticates the user and returns an access token it can use in subsequent
 /// requests.
 ///
-/// - The user needs to authenticate using their password (or if enabled using a
-///   json web token)
+/// - The user needs to authenticate using their password, a login token
+///   generated from an existing session or by SSO (or if enabled using a json
+///   web token)
 /// - If `device_id` is known: invalidates old access token of that device
 /// - If `device_id` is unknown: creates a new device
 /// - Returns access token that is associated with the user and device
+/// - If the client supports refresh tokens: also returns a refresh token and
+///   the access token expires after the configured lifetime
+/// - Repeatedly failing password logins lock out the user and the client IP
+///   address with `M_LIMIT_EXCEEDED`
 ///
 /// Note: You can use [`GET
 /// /_matrix/client/r0/login`](fn.get_supported_versions_route.html) to see
@@ -83,37 +111,34 @@ pub async fn login_route(body: Ruma<login::v3::Request>) -> Result<login::v3::Re
 				return Err(Error::BadRequest(ErrorKind::Exclusive, "User ID reserved by appservice."));
 			}
 
-			let hash = services()
-				.users
-				.password_hash(&user_id)?
-				.ok_or(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."))?;
-
-			if hash.is_empty() {
+			if services().users.exists(&user_id)? && services().users.is_deactivated(&user_id)? {
 				return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
 			}
 
-			let Ok(parsed_hash) = PasswordHash::new(&hash) else {
-				error!("error while hashing user {}", user_id);
-				return Err(Error::BadServerResponse("could not hash"));
-			};
+			services().login_lockout.check(&user_id, body.client_ip)?;
 
-			let hash_matches = services()
-				.globals
-				.argon
-				.verify_password(password.as_bytes(), &parsed_hash)
-				.is_ok();
-
-			if !hash_matches {
+			if !services().auth.check_password(&user_id, password).await? {
+				services()
+					.login_lockout
+					.record_failure(&user_id, body.client_ip);
 				return Err(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."));
 			}
 
+			services().login_lockout.record_success(&user_id);
+
 			user_id
 		},
 		login::v3::LoginInfo::Token(login::v3::Token {
 			token,
 		}) => {
 			debug!("Got token login type");
-			if let Some(jwt_decoding_key) = services().globals.jwt_decoding_key() {
+			if let Some(user_id) = services().users.find_from_login_token(token)? {
+				if services().users.is_deactivated(&user_id)? {
+					return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+				}
+
+				user_id
+			} else if let Some(jwt_decoding_key) = services().globals.jwt_decoding_key() {
 				let token =
 					jsonwebtoken::decode::<Claims>(token, jwt_decoding_key, &jsonwebtoken::Validation::default())
 						.map_err(|e| {
@@ -135,10 +160,7 @@ pub async fn login_route(body: Ruma<login::v3::Request>) -> Result<login::v3::Re
 
 				user_id
 			} else {
-				return Err(Error::BadRequest(
-					ErrorKind::Unknown,
-					"Token login is not supported (server has no jwt decoding key).",
-				));
+				return Err(Error::BadRequest(ErrorKind::forbidden(), "Invalid or expired login token."));
 			}
 		},
 		#[allow(deprecated)]
@@ -177,6 +199,10 @@ pub async fn login_route(body: Ruma<login::v3::Request>) -> Result<login::v3::Re
 		},
 	};
 
+	if services().users.is_locked(&user_id)? {
+		return Err(Error::user_locked());
+	}
+
 	// Generate new device id if the user didn't specify one
 	let device_id = body
 		.device_id
@@ -202,6 +228,8 @@ pub async fn login_route(body: Ruma<login::v3::Request>) -> Result<login::v3::Re
 			.create_device(&user_id, &device_id, &token, body.initial_device_display_name.clone())?;
 	}
 
+	let (expires_in, refresh_token) = set_token_lifetime(&user_id, &device_id, body.refresh_token, None)?;
+
 	// send client well-known if specified so the client knows to reconfigure itself
 	let client_discovery_info: Option<DiscoveryInfo> = services()
 		.globals
@@ -220,12 +248,140 @@ pub async fn login_route(body: Ruma<login::v3::Request>) -> Result<login::v3::Re
 		access_token: token,
 		device_id,
 		well_known: client_discovery_info,
-		expires_in: None,
+		expires_in,
 		home_server: Some(services().globals.server_name().to_owned()),
-		refresh_token: None,
+		refresh_token,
+	})
+}
+
+/// # `POST /_matrix/client/v1/login/get_token`
+///
+/// Generates a short-lived, single-use login token that can be used with the
+/// `m.login.token` login type to sign in on another device.
+///
+/// - Requires UIAA to verify the user's password, email address or identity
+///   provider account
+pub async fn get_login_token_route(body: Ruma<get_login_token::v1::Request>) -> Result<get_login_token::v1::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+
+	if !services().globals.config.get().login_via_existing_session {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Login via an existing session is not enabled on this server.",
+		));
+	}
+
+	// UIAA
+	let mut uiaainfo = UiaaInfo {
+		flows: services().uiaa.user_flows(sender_user)?,
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	if let Some(auth) = &body.auth {
+		let (worked, uiaainfo) = services()
+			.uiaa
+			.try_auth(sender_user, sender_device, auth, &uiaainfo, body.client_ip)
+			.await?;
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+	// Success!
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services()
+			.uiaa
+			.create(sender_user, sender_device, &uiaainfo, &json)?;
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	}
+
+	let login_token = utils::random_string(TOKEN_LENGTH);
+	let expires_in = Duration::from_secs(services().globals.config.get().login_token_lifetime);
+
+	services()
+		.users
+		.create_login_token(sender_user, &login_token, expires_in)?;
+
+	Ok(get_login_token::v1::Response {
+		expires_in,
+		login_token,
 	})
 }
 
+/// # `POST /_matrix/client/v3/refresh`
+///
+/// Exchanges a refresh token for a new access token and refresh token.
+///
+/// - Invalidates the old access token of the device
+/// - The old refresh token stays valid until the new access token is first used
+/// - The new access token expires after the configured lifetime
+pub async fn refresh_token_route(body: Ruma<refresh_token::v3::Request>) -> Result<refresh_token::v3::Response> {
+	let (user_id, device_id) = services()
+		.users
+		.find_from_refresh_token(&body.refresh_token)?
+		.ok_or(Error::BadRequest(
+			ErrorKind::UnknownToken {
+				soft_logout: false,
+			},
+			"Unknown refresh token.",
+		))?;
+	let device_id = OwnedDeviceId::from(device_id);
+
+	let token = utils::random_string(TOKEN_LENGTH);
+	services().users.set_token(&user_id, &device_id, &token)?;
+
+	let (expires_in_ms, refresh_token) = set_token_lifetime(&user_id, &device_id, true, Some(&body.refresh_token))?;
+
+	debug!("Refreshed access token of device {device_id} of {user_id}");
+
+	Ok(refresh_token::v3::Response {
+		access_token: token,
+		refresh_token,
+		expires_in_ms,
+	})
+}
+
+/// Sets the expiry of a freshly issued access token and, if the client
+/// supports refresh tokens, issues a new refresh token for the device.
+///
+/// `refreshed_with` is the refresh token the client used, which stays valid
+/// until the new access token is first used.
+///
+/// Returns how long the access token is valid for and the refresh token.
+pub(crate) fn set_token_lifetime(
+	user_id: &UserId, device_id: &DeviceId, refreshable: bool, refreshed_with: Option<&str>,
+) -> Result<(Option<Duration>, Option<String>)> {
+	let config = services().globals.config.get();
+
+	let (expires_in, refresh_token) = if refreshable {
+		(
+			Some(Duration::from_secs(config.refreshable_access_token_lifetime)),
+			Some(utils::random_string(TOKEN_LENGTH)),
+		)
+	} else {
+		(
+			config
+				.nonrefreshable_access_token_lifetime
+				.map(Duration::from_secs),
+			None,
+		)
+	};
+
+	services()
+		.users
+		.set_token_expiry(user_id, device_id, expires_in)?;
+	services()
+		.users
+		.set_refresh_token(user_id, device_id, refresh_token.as_deref(), refreshed_with)?;
+
+	Ok((expires_in, refresh_token))
+}
+
 /// # `POST /_matrix/client/v3/logout`
 ///
 /// Log out the current device.
@@ -1,11 +1,16 @@
 use std::str::FromStr;
 
 use ruma::{
-	api::client::{error::ErrorKind, space::get_hierarchy},
-	UInt,
+	api::client::{error::ErrorKind, room::get_summary, space::get_hierarchy},
+	events::{room::encryption::RoomEncryptionEventContent, StateEventType},
+	OwnedRoomId, UInt,
 };
 
-use crate::{service::rooms::spaces::PagnationToken, services, Error, Result, Ruma};
+use super::get_alias_helper;
+use crate::{
+	service::rooms::spaces::{PagnationToken, SummaryAccessibility},
+	services, Error, Result, Ruma, RumaResponse,
+};
 
 /// # `GET /_matrix/client/v1/rooms/{room_id}/hierarchy`
 ///
@@ -52,3 +57,94 @@ pub async fn get_hierarchy_route(body: Ruma<get_hierarchy::v1::Request>) -> Resu
 		)
 		.await
 }
+
+/// # `GET /_matrix/client/unstable/im.nheko.summary/rooms/{roomIdOrAlias}/summary`
+///
+/// Returns a summary of a room so clients can preview it before joining
+/// (MSC3266).
+///
+/// - Rooms this server is not in are requested over federation using
+///   `/hierarchy`
+/// - Without an access token only public or world readable rooms can be
+///   previewed
+pub async fn get_room_summary_route(
+	body: Ruma<get_summary::msc3266::Request>,
+) -> Result<get_summary::msc3266::Response> {
+	let sender_user = body.sender_user.as_deref();
+
+	let (room_id, mut via) = match OwnedRoomId::try_from(body.room_id_or_alias.clone()) {
+		Ok(room_id) => (room_id, body.via.clone()),
+		Err(room_alias) => {
+			let response = get_alias_helper(room_alias).await?;
+			(response.room_id, response.servers)
+		},
+	};
+
+	if services().rooms.metadata.is_banned(&room_id)? {
+		return Err(Error::BadRequest(ErrorKind::NotFound, "The requested room was not found"));
+	}
+
+	if via.is_empty() {
+		if let Some(server_name) = room_id.server_name() {
+			via.push(server_name.to_owned());
+		}
+	}
+
+	let summary = match services()
+		.rooms
+		.spaces
+		.get_room_summary_for_client(&room_id, sender_user, &via)
+		.await?
+	{
+		Some(SummaryAccessibility::Accessible(summary)) => summary,
+		Some(SummaryAccessibility::Inaccessible) => {
+			return Err(Error::BadRequest(ErrorKind::forbidden(), "The requested room is inaccessible"));
+		},
+		None => return Err(Error::BadRequest(ErrorKind::NotFound, "The requested room was not found")),
+	};
+
+	// room version and encryption are only known for rooms we have state for
+	let room_version = services().rooms.state.get_room_version(&room_id).ok();
+
+	let encryption = services()
+		.rooms
+		.state_accessor
+		.room_state_get(&room_id, &StateEventType::RoomEncryption, "")?
+		.and_then(|s| serde_json::from_str::<RoomEncryptionEventContent>(s.content.get()).ok())
+		.map(|c| c.algorithm);
+
+	let membership = match sender_user {
+		Some(sender_user) => services()
+			.rooms
+			.state_accessor
+			.get_member(&room_id, sender_user)?
+			.map(|c| c.membership),
+		None => None,
+	};
+
+	Ok(get_summary::msc3266::Response {
+		room_id: summary.room_id,
+		canonical_alias: summary.canonical_alias,
+		avatar_url: summary.avatar_url,
+		guest_can_join: summary.guest_can_join,
+		name: summary.name,
+		num_joined_members: summary.num_joined_members,
+		topic: summary.topic,
+		world_readable: summary.world_readable,
+		join_rule: summary.join_rule,
+		room_type: summary.room_type,
+		room_version,
+		encryption,
+		membership,
+	})
+}
+
+/// # `GET /_matrix/client/v1/summary/{roomIdOrAlias}`
+///
+/// Same as `get_room_summary_route`, on the path requested by MSC3266 clients
+/// that do not use the unstable prefix.
+pub async fn get_room_summary_v1_route(
+	body: Ruma<get_summary::msc3266::Request>,
+) -> Result<RumaResponse<get_summary::msc3266::Response>> {
+	get_room_summary_route(body).await.map(RumaResponse)
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use http::Uri;
+use ruma::api::client::{
+	error::ErrorKind,
+	session::{sso_login, sso_login_with_provider},
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::TOKEN_LENGTH;
+use crate::{
+	service::sso::{self, SsoCompletion},
+	services,
+	utils::{self, HtmlEscape},
+	Error, Result, Ruma,
+};
+
+#[derive(Deserialize)]
+struct FallbackParams {
+	session: String,
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+	state: String,
+	code: Option<String>,
+	error: Option<String>,
+	error_description: Option<String>,
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect`
+///
+/// Redirects the user to the first configured identity provider to log in
+/// with `m.login.sso`.
+pub async fn sso_login_route(body: Ruma<sso_login::v3::Request>) -> Result<sso_login::v3::Response> {
+	let location = services()
+		.sso
+		.authorization_url(None, &body.redirect_url)
+		.await?;
+
+	Ok(sso_login::v3::Response::new(location.into()))
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect/{idpId}`
+///
+/// Redirects the user to the given identity provider to log in with
+/// `m.login.sso`.
+pub async fn sso_login_with_provider_route(
+	body: Ruma<sso_login_with_provider::v3::Request>,
+) -> Result<sso_login_with_provider::v3::Response> {
+	let location = services()
+		.sso
+		.authorization_url(Some(&body.idp_id), &body.redirect_url)
+		.await?;
+
+	Ok(sso_login_with_provider::v3::Response::new(location.into()))
+}
+
+/// # `GET /_conduwuit/oidc/callback`
+///
+/// Where identity providers send users back to after logging in.
+///
+/// - Completes the OpenID Connect authorization code flow
+/// - Sends the user back to the client with a short-lived login token to log in
+///   with `m.login.token`, asking for confirmation first unless the client is
+///   in `sso_client_allowlist`, or completes the `m.login.sso` stage of a
+///   user-interactive authentication session
+pub async fn sso_callback_route(uri: Uri) -> Result<Response> {
+	let params: CallbackParams = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid SSO callback parameters."))?;
+
+	if let Some(error) = params.error {
+		warn!(
+			"Identity provider returned an error: {error} ({})",
+			params.error_description.unwrap_or_default()
+		);
+		// drop the pending login, it can't be completed anymore
+		services()
+			.sso
+			.pending_auth
+			.lock()
+			.unwrap()
+			.remove(&params.state);
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Login at the identity provider failed.",
+		));
+	}
+
+	let code = params
+		.code
+		.ok_or(Error::BadRequest(ErrorKind::MissingParam, "Missing authorization code."))?;
+
+	let (user_id, mut redirect_url) = match services()
+		.sso
+		.complete_authorization(&params.state, &code)
+		.await?
+	{
+		SsoCompletion::Login {
+			user_id,
+			redirect_url,
+		} => (user_id, redirect_url),
+		SsoCompletion::Uiaa => {
+			// Tell the client the stage is done, see the fallback auth section of the spec
+			return Ok(Html(
+				"<!DOCTYPE html><html><head><title>Authentication complete</title><script>if (window.onAuthDone) { \
+				 window.onAuthDone(); } else if (window.opener && window.opener.postMessage) { \
+				 window.opener.postMessage(\"authDone\", \"*\"); }</script></head><body><p>Thank you. You may now \
+				 close this window and return to the application.</p></body></html>",
+			)
+			.into_response());
+		},
+	};
+
+	let login_token = utils::random_string(TOKEN_LENGTH);
+	services().users.create_login_token(
+		&user_id,
+		&login_token,
+		Duration::from_secs(services().globals.config.get().login_token_lifetime),
+	)?;
+
+	info!("{user_id} authenticated via SSO");
+
+	redirect_url
+		.query_pairs_mut()
+		.append_pair("loginToken", &login_token);
+
+	if sso::is_trusted_client(&redirect_url, &services().globals.config.get().sso_client_allowlist) {
+		return Ok(Redirect::to(redirect_url.as_str()).into_response());
+	}
+
+	// Anyone can start a login with their own redirect URL, so make sure the user
+	// knows where their login token goes
+	let host = redirect_url.host_str().unwrap_or(redirect_url.scheme());
+	Ok(Html(format!(
+		"<!DOCTYPE html><html><head><title>Continue to {host}?</title></head><body><p>You are about to sign in to \
+		 <strong>{host}</strong> as {user_id}.</p><p>Only continue if you started this login and trust this \
+		 application, it gets full access to your account.</p><p><a href=\"{url}\">Continue to \
+		 {host}</a></p></body></html>",
+		host = HtmlEscape(host),
+		user_id = HtmlEscape(user_id.as_str()),
+		url = HtmlEscape(redirect_url.as_str()),
+	))
+	.into_response())
+}
+
+/// # `GET /_matrix/client/v3/auth/m.login.sso/fallback/web`
+///
+/// Fallback page of the `m.login.sso` user-interactive authentication stage.
+///
+/// - Sends the user to the first configured identity provider to confirm their
+///   identity
+pub async fn sso_fallback_route(uri: Uri) -> Result<Redirect> {
+	let params: FallbackParams = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::MissingParam, "Missing UIAA session."))?;
+
+	let location = services()
+		.sso
+		.uiaa_authorization_url(&params.session)
+		.await?;
+
+	Ok(Redirect::to(location.as_str()))
+}
@@ -13,10 +13,11 @@ use ruma::{
 		AnyStateEventContent, StateEventType,
 	},
 	serde::Raw,
-	EventId, RoomId, UserId,
+	EventId, OwnedRoomAliasId, RoomId, UserId,
 };
 use tracing::{error, log::warn};
 
+use super::get_alias_helper;
 use crate::{
 	service::{self, pdu::PduBuilder},
 	services, Error, Result, Ruma, RumaResponse,
@@ -251,9 +252,18 @@ async fn send_state_event_for_key_helper(
 				}
 			}
 		},
-		// TODO: allow alias if it previously existed
+		// aliases already in the current event are allowed even if they were removed
+		// since, so that unrelated aliases can still be changed
 		StateEventType::RoomCanonicalAlias => {
 			if let Ok(canonical_alias) = serde_json::from_str::<RoomCanonicalAliasEventContent>(json.json().get()) {
+				let previous_aliases: Vec<OwnedRoomAliasId> = services()
+					.rooms
+					.state_accessor
+					.room_state_get(room_id, &StateEventType::RoomCanonicalAlias, "")?
+					.and_then(|s| serde_json::from_str::<RoomCanonicalAliasEventContent>(s.content.get()).ok())
+					.map(|c| c.alias.into_iter().chain(c.alt_aliases).collect())
+					.unwrap_or_default();
+
 				let mut aliases = canonical_alias.alt_aliases.clone();
 
 				if let Some(alias) = canonical_alias.alias {
@@ -261,14 +271,21 @@ async fn send_state_event_for_key_helper(
 				}
 
 				for alias in aliases {
-					if alias.server_name() != services().globals.server_name()
-						|| services()
-                        .rooms
-                        .alias
-                        .resolve_local_alias(&alias)?
-                        .filter(|room| room == room_id) // Make sure it's the right room
-                        .is_none()
-					{
+					if previous_aliases.contains(&alias) {
+						continue;
+					}
+
+					let resolved_room_id = if alias.server_name() == services().globals.server_name() {
+						services().rooms.alias.resolve_local_alias(&alias)?
+					} else {
+						get_alias_helper(alias)
+							.await
+							.ok()
+							.map(|response| response.room_id)
+					};
+
+					// Make sure it's the right room
+					if resolved_room_id.as_deref() != Some(room_id) {
 						return Err(Error::BadRequest(
 							ErrorKind::forbidden(),
 							"You are only allowed to send canonical_alias events when its aliases already exist",
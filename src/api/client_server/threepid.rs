@@ -0,0 +1,213 @@
+use axum::{
+	response::{IntoResponse, Redirect, Response},
+	Json,
+};
+use http::Uri;
+use ruma::api::client::{
+	account::{
+		add_3pid, delete_3pid, get_3pids, request_3pid_management_token_via_email,
+		request_3pid_management_token_via_msisdn, request_password_change_token_via_email, ThirdPartyIdRemovalStatus,
+	},
+	error::ErrorKind,
+	uiaa::UiaaInfo,
+};
+use serde::Deserialize;
+
+use super::SESSION_ID_LENGTH;
+use crate::{
+	service::threepid::{self, ValidationPurpose},
+	services, utils, Error, Result, Ruma,
+};
+
+#[derive(Deserialize)]
+pub struct SubmitTokenParams {
+	sid: String,
+	client_secret: String,
+	token: String,
+}
+
+/// # `GET _matrix/client/v3/account/3pid`
+///
+/// Get a list of third party identifiers associated with this account.
+pub async fn third_party_route(body: Ruma<get_3pids::v3::Request>) -> Result<get_3pids::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let threepids = services()
+		.threepid
+		.user_threepids(sender_user)
+		.collect::<Result<_>>()?;
+
+	Ok(get_3pids::v3::Response::new(threepids))
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
+///
+/// "This API should be used to request validation tokens when adding an email
+/// address to an account"
+///
+/// - 403 signals that The homeserver does not allow the third party identifier
+///   as a contact option.
+/// - Sends a link to the email address, which validates the session once opened
+pub async fn request_3pid_management_token_via_email_route(
+	body: Ruma<request_3pid_management_token_via_email::v3::Request>,
+) -> Result<request_3pid_management_token_via_email::v3::Response> {
+	let sid = services()
+		.threepid
+		.request_email_token(
+			&body.email,
+			&body.client_secret,
+			body.send_attempt.into(),
+			body.next_link.clone(),
+			ValidationPurpose::AddThreepid,
+		)
+		.await?;
+
+	Ok(request_3pid_management_token_via_email::v3::Response {
+		sid,
+		submit_url: Some(threepid::submit_url()?.to_string()),
+	})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
+///
+/// "This API should be used to request validation tokens when adding an phone
+/// number to an account"
+///
+/// - 403 signals that The homeserver does not allow the third party identifier
+///   as a contact option.
+pub async fn request_3pid_management_token_via_msisdn_route(
+	_body: Ruma<request_3pid_management_token_via_msisdn::v3::Request>,
+) -> Result<request_3pid_management_token_via_msisdn::v3::Response> {
+	Err(Error::BadRequest(
+		ErrorKind::ThreepidDenied,
+		"Third party identifier is not allowed",
+	))
+}
+
+/// # `POST /_matrix/client/v3/account/password/email/requestToken`
+///
+/// Sends a link to an email address bound to an account, which validates the
+/// session once opened. The session can then be used to reset the password of
+/// the account with `m.login.email.identity`.
+pub async fn request_password_change_token_via_email_route(
+	body: Ruma<request_password_change_token_via_email::v3::Request>,
+) -> Result<request_password_change_token_via_email::v3::Response> {
+	let sid = services()
+		.threepid
+		.request_email_token(
+			&body.email,
+			&body.client_secret,
+			body.send_attempt.into(),
+			body.next_link.clone(),
+			ValidationPurpose::ResetPassword,
+		)
+		.await?;
+
+	Ok(request_password_change_token_via_email::v3::Response {
+		sid,
+		submit_url: Some(threepid::submit_url()?.to_string()),
+	})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/add`
+///
+/// Adds a validated third party identifier to the account.
+///
+/// - Requires UIAA to verify the user's identity
+/// - Fails if the identifier is bound to another account
+pub async fn add_3pid_route(body: Ruma<add_3pid::v3::Request>) -> Result<add_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+
+	let mut uiaainfo = UiaaInfo {
+		flows: services().uiaa.user_flows(sender_user)?,
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	if let Some(auth) = &body.auth {
+		let (worked, uiaainfo) = services()
+			.uiaa
+			.try_auth(sender_user, sender_device, auth, &uiaainfo, body.client_ip)
+			.await?;
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+	// Success!
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services()
+			.uiaa
+			.create(sender_user, sender_device, &uiaainfo, &json)?;
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	}
+
+	services()
+		.threepid
+		.bind(sender_user, body.sid.as_str(), body.client_secret.as_str())?;
+
+	Ok(add_3pid::v3::Response {})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/delete`
+///
+/// Removes a third party identifier from the account.
+///
+/// - We don't bind identifiers on identity servers, so there is nothing to
+///   unbind there
+pub async fn delete_3pid_route(body: Ruma<delete_3pid::v3::Request>) -> Result<delete_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	if !services()
+		.threepid
+		.unbind(sender_user, &body.medium, &body.address)?
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::ThreepidNotFound,
+			"Third party identifier is not bound to this account.",
+		));
+	}
+
+	Ok(delete_3pid::v3::Response {
+		id_server_unbind_result: ThirdPartyIdRemovalStatus::NoSupport,
+	})
+}
+
+/// # `GET /_conduwuit/email/submit_token`
+///
+/// Validates an email address when the user opens the link in the validation
+/// email.
+///
+/// - Redirects to the `next_link` the client asked for, if its host is in
+///   `next_link_domains`
+pub async fn submit_email_token_route(uri: Uri) -> Result<Response> {
+	let params: SubmitTokenParams = serde_html_form::from_str(uri.query().unwrap_or_default())
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid validation link."))?;
+
+	let next_link = services()
+		.threepid
+		.submit_token(&params.sid, &params.client_secret, &params.token)?;
+
+	Ok(match next_link {
+		Some(next_link) => Redirect::to(&next_link).into_response(),
+		None => "Your email address has been validated. You can now return to your client.".into_response(),
+	})
+}
+
+/// # `POST /_conduwuit/email/submit_token`
+///
+/// Validates an email address with a token the user entered in their client.
+/// This is the `submit_url` returned by `requestToken` endpoints.
+pub async fn submit_email_token_json_route(Json(params): Json<SubmitTokenParams>) -> Result<Json<serde_json::Value>> {
+	services()
+		.threepid
+		.submit_token(&params.sid, &params.client_secret, &params.token)?;
+
+	Ok(Json(serde_json::json!({
+		"success": true
+	})))
+}
@@ -50,6 +50,7 @@ pub async fn get_supported_versions_route(
 			("org.matrix.msc2836".to_owned(), true),
 			("org.matrix.msc2946".to_owned(), true),
 			("org.matrix.msc3026.busy_presence".to_owned(), true),
+			("im.nheko.summary".to_owned(), true),
 			("org.matrix.msc3827".to_owned(), true),
 		]),
 	};
@@ -88,15 +89,15 @@ pub async fn well_known_support(_body: Ruma<discover_support::Request>) -> Resul
 		.as_ref()
 		.map(ToString::to_string);
 
-	let role = services().globals.well_known_support_role().clone();
+	let role = services().globals.well_known_support_role();
 
 	// support page or role must be either defined for this to be valid
 	if support_page.is_none() && role.is_none() {
 		return Err(Error::BadRequest(ErrorKind::NotFound, "Not found."));
 	}
 
-	let email_address = services().globals.well_known_support_email().clone();
-	let matrix_id = services().globals.well_known_support_mxid().clone();
+	let email_address = services().globals.well_known_support_email();
+	let matrix_id = services().globals.well_known_support_mxid();
 
 	// if a role is specified, an email address or matrix id is required
 	if role.is_some() && (email_address.is_none() && matrix_id.is_none()) {
@@ -17,7 +17,7 @@ pub async fn turn_server_route(
 ) -> Result<get_turn_server_info::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
-	let turn_secret = services().globals.turn_secret().clone();
+	let turn_secret = services().globals.turn_secret();
 
 	let (username, password) = if !turn_secret.is_empty() {
 		let expiry = SecondsSinceUnixEpoch::from_system_time(
@@ -34,16 +34,13 @@ pub async fn turn_server_route(
 
 		(username, password)
 	} else {
-		(
-			services().globals.turn_username().clone(),
-			services().globals.turn_password().clone(),
-		)
+		(services().globals.turn_username(), services().globals.turn_password())
 	};
 
 	Ok(get_turn_server_info::v3::Response {
 		username,
 		password,
-		uris: services().globals.turn_uris().to_vec(),
+		uris: services().globals.turn_uris(),
 		ttl: Duration::from_secs(services().globals.turn_ttl()),
 	})
 }
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod client_server;
 pub mod ruma_wrapper;
 pub mod server_server;
@@ -1,9 +1,14 @@
-use std::{collections::BTreeMap, str};
+use std::{
+	any::TypeId,
+	collections::BTreeMap,
+	net::{IpAddr, SocketAddr},
+	str,
+};
 
 use axum::{
 	async_trait,
 	body::{Full, HttpBody},
-	extract::{rejection::TypedHeaderRejectionReason, FromRequest, Path, TypedHeader},
+	extract::{rejection::TypedHeaderRejectionReason, ConnectInfo, FromRequest, Path, TypedHeader},
 	headers::{
 		authorization::{Bearer, Credentials},
 		Authorization,
@@ -14,22 +19,119 @@ use axum::{
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use http::{uri::PathAndQuery, Request, StatusCode};
 use ruma::{
-	api::{client::error::ErrorKind, AuthScheme, IncomingRequest, OutgoingResponse},
+	api::{
+		client::{
+			account::{
+				change_password, deactivate, register, request_3pid_management_token_via_email,
+				request_password_change_token_via_email, whoami,
+			},
+			discovery::get_capabilities,
+			error::ErrorKind,
+			filter::{create_filter, get_filter},
+			media::create_content,
+			membership::{invite_user, join_room_by_id, join_room_by_id_or_alias, joined_rooms, leave_room},
+			message::{get_message_events, send_message_event},
+			push::get_pushrules_all,
+			read_marker::set_read_marker,
+			receipt::create_receipt,
+			room::get_room_event,
+			session::{login, logout, logout_all},
+			state::{get_state_events, get_state_events_for_key, send_state_event},
+			sync::sync_events,
+		},
+		AuthScheme, IncomingRequest, OutgoingResponse,
+	},
 	CanonicalJsonValue, OwnedDeviceId, OwnedServerName, OwnedUserId, UserId,
 };
 use serde::Deserialize;
 use tracing::{debug, error, trace, warn};
 
 use super::{Ruma, RumaResponse};
-use crate::{service::appservice::RegistrationInfo, services, Error, Result};
+use crate::{
+	service::{appservice::RegistrationInfo, rate_limit::Bucket},
+	services, Error, Result,
+};
 
 enum Token {
 	Appservice(Box<RegistrationInfo>),
 	User((OwnedUserId, OwnedDeviceId)),
+	Expired,
+	Locked,
 	Invalid,
 	None,
 }
 
+impl Token {
+	/// Finds the appservice or user an access token belongs to
+	async fn from_access_token(token: Option<&str>) -> Result<Self> {
+		let Some(token) = token else {
+			return Ok(Self::None);
+		};
+
+		if let Some(reg_info) = services().appservice.find_from_token(token).await {
+			return Ok(Self::Appservice(Box::new(reg_info)));
+		}
+
+		let Some((user_id, device_id)) = services().users.find_from_token(token)? else {
+			return Ok(Self::Invalid);
+		};
+
+		let device_id = OwnedDeviceId::from(device_id);
+		if services().users.is_token_expired(&user_id, &device_id)? {
+			Ok(Self::Expired)
+		} else if services().users.is_locked(&user_id)? {
+			Ok(Self::Locked)
+		} else {
+			services()
+				.users
+				.remove_previous_refresh_token(&user_id, &device_id)?;
+			Ok(Self::User((user_id, device_id)))
+		}
+	}
+
+	/// The user and device of a user's access token, or why a request that
+	/// has to be sent with one is rejected
+	fn into_user(self) -> Result<(OwnedUserId, OwnedDeviceId)> {
+		match self {
+			Self::User(user) => Ok(user),
+			Self::Appservice(_) => Err(Error::BadRequest(
+				ErrorKind::Unauthorized,
+				"Appservice access tokens can't be used on this endpoint.",
+			)),
+			Self::Expired => Err(Error::BadRequest(
+				ErrorKind::UnknownToken {
+					soft_logout: true,
+				},
+				"Access token has expired.",
+			)),
+			Self::Locked => Err(Error::user_locked()),
+			Self::Invalid => Err(Error::BadRequest(
+				ErrorKind::UnknownToken {
+					soft_logout: false,
+				},
+				"Unknown access token.",
+			)),
+			Self::None => Err(Error::BadRequest(ErrorKind::MissingToken, "Missing access token.")),
+		}
+	}
+}
+
+/// Authenticates the user a request was sent by with their access token, for
+/// endpoints outside of the Matrix APIs that don't use [`Ruma`].
+pub(crate) async fn authenticate_user(parts: &mut http::request::Parts) -> Result<(OwnedUserId, OwnedDeviceId)> {
+	let auth_header: Option<TypedHeader<Authorization<Bearer>>> = parts.extract().await?;
+	let query_params = serde_html_form::from_str::<QueryParams>(parts.uri.query().unwrap_or_default()).ok();
+
+	let token = match &auth_header {
+		Some(TypedHeader(Authorization(bearer))) => Some(bearer.token()),
+		None => query_params
+			.as_ref()
+			.and_then(|params| params.access_token.as_deref()),
+	};
+
+	Token::from_access_token(token).await?.into_user()
+}
+
 #[derive(Deserialize)]
 struct QueryParams {
 	access_token: Option<String>,
@@ -39,7 +141,7 @@ struct QueryParams {
 #[async_trait]
 impl<T, S, B> FromRequest<S, B> for Ruma<T>
 where
-	T: IncomingRequest,
+	T: IncomingRequest + 'static,
 	B: HttpBody + Send + 'static,
 	B::Data: Send,
 	B::Error: Into<BoxError>,
@@ -66,6 +168,7 @@ where
 		};
 
 		let metadata = T::METADATA;
+		let client_ip = client_ip(&parts);
 		let auth_header: Option<TypedHeader<Authorization<Bearer>>> = parts.extract().await?;
 		let path_params: Path<Vec<String>> = parts.extract().await?;
 
@@ -83,17 +186,8 @@ where
 			None => query_params.access_token.as_deref(),
 		};
 
-		let token = if let Some(token) = token {
-			if let Some(reg_info) = services().appservice.find_from_token(token).await {
-				Token::Appservice(Box::new(reg_info))
-			} else if let Some((user_id, device_id)) = services().users.find_from_token(token)? {
-				Token::User((user_id, OwnedDeviceId::from(device_id)))
-			} else {
-				Token::Invalid
-			}
-		} else {
-			Token::None
-		};
+		let token = Token::from_access_token(token).await?;
+		let token = token_for_scheme(metadata.authentication, token);
 
 		if metadata.authentication == AuthScheme::None {
 			match parts.uri.path() {
@@ -102,6 +196,7 @@ where
 					if !services()
 						.globals
 						.config
+						.get()
 						.allow_public_room_directory_without_auth
 					{
 						match token {
@@ -109,7 +204,7 @@ where
 								// we should have validated the token above
 								// already
 							},
-							Token::None | Token::Invalid => {
+							Token::None | Token::Expired | Token::Locked | Token::Invalid => {
 								return Err(Error::BadRequest(
 									ErrorKind::MissingToken,
 									"Missing or invalid access token.",
@@ -125,13 +220,8 @@ where
 		let mut json_body = serde_json::from_slice::<CanonicalJsonValue>(&body).ok();
 
 		let (sender_user, sender_device, sender_servername, appservice_info) = match (metadata.authentication, token) {
-			(_, Token::Invalid) => {
-				return Err(Error::BadRequest(
-					ErrorKind::UnknownToken {
-						soft_logout: false,
-					},
-					"Unknown access token.",
-				))
+			(_, token @ (Token::Invalid | Token::Expired | Token::Locked)) => {
+				return Err(token.into_user().expect_err("token is rejected"));
 			},
 			(AuthScheme::AccessToken | AuthScheme::AccessTokenOptional, Token::Appservice(info)) => {
 				let user_id = query_params
@@ -160,6 +250,7 @@ where
 			(AuthScheme::None | AuthScheme::AppserviceToken, Token::Appservice(info)) => {
 				(None, None, None, Some(*info))
 			},
+			(AuthScheme::AccessToken, Token::None) if allows_email_identity::<T>() => (None, None, None, None),
 			(AuthScheme::AccessToken, Token::None) => {
 				return Err(Error::BadRequest(ErrorKind::MissingToken, "Missing access token."));
 			},
@@ -187,6 +278,17 @@ where
 						Error::BadRequest(ErrorKind::forbidden(), msg)
 					})?;
 
+				if !services()
+					.server_policy
+					.is_federation_allowed(&x_matrix.origin)
+				{
+					debug!("Rejecting federation request from denied server {}", x_matrix.origin);
+					return Err(Error::BadRequest(
+						ErrorKind::forbidden(),
+						"Server is banned on this homeserver.",
+					));
+				}
+
 				let origin_signatures =
 					BTreeMap::from_iter([(x_matrix.key.clone(), CanonicalJsonValue::String(x_matrix.sig))]);
 
@@ -275,6 +377,24 @@ where
 			},
 		};
 
+		if let (Some(user_id), Some(device_id)) = (&sender_user, &sender_device) {
+			services()
+				.users
+				.update_last_seen(user_id, device_id, client_ip)?;
+		}
+
+		if let Some(bucket) = rate_limit_bucket::<T>() {
+			services()
+				.rate_limit
+				.check(bucket, sender_user.as_deref(), client_ip, appservice_info.is_some())?;
+		}
+
+		if let Some(user_id) = &sender_user {
+			if appservice_info.is_none() && !allowed_without_consent::<T>() {
+				services().consent.check(user_id).await?;
+			}
+		}
+
 		let mut http_request = Request::builder().uri(parts.uri).method(parts.method);
 		*http_request.headers_mut().unwrap() = parts.headers;
 
@@ -329,10 +449,95 @@ where
 			sender_servername,
 			json_body,
 			appservice_info,
+			client_ip,
 		})
 	}
 }
 
+/// Endpoints that don't require authentication are served as if no access
+/// token was sent when the sent token has expired or its account is locked.
+fn token_for_scheme(scheme: AuthScheme, token: Token) -> Token {
+	match (scheme, token) {
+		(AuthScheme::None | AuthScheme::AccessTokenOptional, Token::Expired | Token::Locked) => Token::None,
+		(_, token) => token,
+	}
+}
+
+/// Endpoints that require an access token, except when users authenticate
+/// with a validated email address instead, like password resets
+fn allows_email_identity<T: 'static>() -> bool { TypeId::of::<T>() == TypeId::of::<change_password::v3::Request>() }
+
+/// Endpoints users can use before accepting the policies of the server,
+/// enough to read the server notice linking to them and to leave
+fn allowed_without_consent<T: 'static>() -> bool {
+	[
+		TypeId::of::<sync_events::v3::Request>(),
+		TypeId::of::<whoami::v3::Request>(),
+		TypeId::of::<get_capabilities::v3::Request>(),
+		TypeId::of::<create_filter::v3::Request>(),
+		TypeId::of::<get_filter::v3::Request>(),
+		TypeId::of::<get_pushrules_all::v3::Request>(),
+		TypeId::of::<joined_rooms::v3::Request>(),
+		TypeId::of::<get_message_events::v3::Request>(),
+		TypeId::of::<get_room_event::v3::Request>(),
+		TypeId::of::<get_state_events::v3::Request>(),
+		TypeId::of::<get_state_events_for_key::v3::Request>(),
+		TypeId::of::<set_read_marker::v3::Request>(),
+		TypeId::of::<create_receipt::v3::Request>(),
+		TypeId::of::<leave_room::v3::Request>(),
+		TypeId::of::<logout::v3::Request>(),
+		TypeId::of::<logout_all::v3::Request>(),
+		TypeId::of::<deactivate::v3::Request>(),
+	]
+	.contains(&TypeId::of::<T>())
+}
+
+/// The rate limit bucket requests of a type count towards, if any
+fn rate_limit_bucket<T: 'static>() -> Option<Bucket> {
+	[
+		(TypeId::of::<send_message_event::v3::Request>(), Bucket::Message),
+		(TypeId::of::<send_state_event::v3::Request>(), Bucket::Message),
+		(TypeId::of::<join_room_by_id::v3::Request>(), Bucket::Join),
+		(TypeId::of::<join_room_by_id_or_alias::v3::Request>(), Bucket::Join),
+		(TypeId::of::<invite_user::v3::Request>(), Bucket::Invite),
+		(TypeId::of::<register::v3::Request>(), Bucket::Registration),
+		(TypeId::of::<login::v3::Request>(), Bucket::Login),
+		(
+			TypeId::of::<request_3pid_management_token_via_email::v3::Request>(),
+			Bucket::ThreepidRequest,
+		),
+		(
+			TypeId::of::<request_password_change_token_via_email::v3::Request>(),
+			Bucket::ThreepidRequest,
+		),
+		(TypeId::of::<create_content::v3::Request>(), Bucket::MediaUpload),
+	]
+	.into_iter()
+	.find_map(|(type_id, bucket)| (type_id == TypeId::of::<T>()).then_some(bucket))
+}
+
+/// The address of the client that sent a request. Behind a reverse proxy,
+/// this is the last address in the configured `client_ip_header`.
+fn client_ip(parts: &http::request::Parts) -> Option<IpAddr> {
+	if let Some(header) = &services().globals.config.get().client_ip_header {
+		return parts
+			.headers
+			.get(header.as_str())?
+			.to_str()
+			.ok()?
+			.rsplit(',')
+			.next()?
+			.trim()
+			.parse()
+			.ok();
+	}
+
+	parts
+		.extensions
+		.get::<ConnectInfo<SocketAddr>>()
+		.map(|ConnectInfo(addr)| addr.ip())
+}
+
 struct XMatrix {
 	origin: OwnedServerName,
 	destination: Option<String>,
@@ -449,3 +654,51 @@ where
 
 	Ok(vec.into())
 }
+
+#[cfg(test)]
+mod tests {
+	use ruma::api::{
+		client::{membership::join_room_by_id, message::send_message_event, sync::sync_events},
+		AuthScheme,
+	};
+
+	use super::{allowed_without_consent, token_for_scheme, Token};
+
+	#[test]
+	fn expired_token_only_rejected_when_required() {
+		assert!(matches!(token_for_scheme(AuthScheme::None, Token::Expired), Token::None));
+		assert!(matches!(
+			token_for_scheme(AuthScheme::AccessTokenOptional, Token::Expired),
+			Token::None
+		));
+		assert!(matches!(
+			token_for_scheme(AuthScheme::AccessToken, Token::Expired),
+			Token::Expired
+		));
+		assert!(matches!(
+			token_for_scheme(AuthScheme::AppserviceToken, Token::Expired),
+			Token::Expired
+		));
+		assert!(matches!(token_for_scheme(AuthScheme::None, Token::Invalid), Token::Invalid));
+	}
+
+	#[test]
+	fn locked_token_only_rejected_when_required() {
+		assert!(matches!(token_for_scheme(AuthScheme::None, Token::Locked), Token::None));
+		assert!(matches!(
+			token_for_scheme(AuthScheme::AccessTokenOptional, Token::Locked),
+			Token::None
+		));
+		assert!(matches!(
+			token_for_scheme(AuthScheme::AccessToken, Token::Locked),
+			Token::Locked
+		));
+	}
+
+	#[test]
+	fn consent_required_unless_allowlisted() {
+		assert!(allowed_without_consent::<sync_events::v3::Request>());
+		assert!(!allowed_without_consent::<send_message_event::v3::Request>());
+		assert!(!allowed_without_consent::<join_room_by_id::v3::Request>());
+	}
+}
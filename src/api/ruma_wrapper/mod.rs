@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::{net::IpAddr, ops::Deref};
 
 use ruma::{api::client::uiaa::UiaaResponse, CanonicalJsonValue, OwnedDeviceId, OwnedServerName, OwnedUserId};
 
@@ -6,6 +6,8 @@ use crate::{service::appservice::RegistrationInfo, Error};
 
 mod axum;
 
+pub(crate) use self::axum::authenticate_user;
+
 /// Extractor for Ruma request structs
 pub struct Ruma<T> {
 	pub body: T,
@@ -15,6 +17,8 @@ pub struct Ruma<T> {
 	// This is None when body is not a valid string
 	pub json_body: Option<CanonicalJsonValue>,
 	pub appservice_info: Option<RegistrationInfo>,
+	// The address of the client, see `client_ip_header`
+	pub client_ip: Option<IpAddr>,
 }
 
 impl<T> Deref for Ruma<T> {
@@ -396,7 +396,7 @@ pub async fn send_transaction_message_route(
 				}
 			},
 			Edu::Typing(typing) => {
-				if !services().globals.config.allow_incoming_typing {
+				if !services().globals.config.get().allow_incoming_typing {
 					continue;
 				}
 
@@ -407,7 +407,7 @@ pub async fn send_transaction_message_route(
 				{
 					if typing.typing {
 						let timeout = utils::millis_since_unix_epoch()
-							+ services().globals.config.typing_federation_timeout_s * 1000;
+							+ services().globals.config.get().typing_federation_timeout_s * 1000;
 						services()
 							.rooms
 							.typing
@@ -908,11 +908,9 @@ pub async fn create_join_event_template_route(
 		.event_handler
 		.acl_check(sender_servername, &body.room_id)?;
 
-	if services()
-		.globals
-		.config
-		.forbidden_remote_server_names
-		.contains(sender_servername)
+	if !services()
+		.server_policy
+		.is_federation_allowed(sender_servername)
 	{
 		warn!(
 			"Server {sender_servername} for remote user {} tried joining room ID {} which has a server name that is \
@@ -926,12 +924,7 @@ pub async fn create_join_event_template_route(
 	}
 
 	if let Some(server) = body.room_id.server_name() {
-		if services()
-			.globals
-			.config
-			.forbidden_remote_server_names
-			.contains(&server.to_owned())
-		{
+		if !services().server_policy.is_federation_allowed(server) {
 			return Err(Error::BadRequest(
 				ErrorKind::forbidden(),
 				"Server is banned on this homeserver.",
@@ -1232,11 +1225,9 @@ pub async fn create_join_event_v1_route(
 		.as_ref()
 		.expect("server is authenticated");
 
-	if services()
-		.globals
-		.config
-		.forbidden_remote_server_names
-		.contains(sender_servername)
+	if !services()
+		.server_policy
+		.is_federation_allowed(sender_servername)
 	{
 		warn!(
 			"Server {sender_servername} tried joining room ID {} who has a server name that is globally forbidden. \
@@ -1250,12 +1241,7 @@ pub async fn create_join_event_v1_route(
 	}
 
 	if let Some(server) = body.room_id.server_name() {
-		if services()
-			.globals
-			.config
-			.forbidden_remote_server_names
-			.contains(&server.to_owned())
-		{
+		if !services().server_policy.is_federation_allowed(server) {
 			warn!(
 				"Server {sender_servername} tried joining room ID {} which has a server name that is globally \
 				 forbidden. Rejecting.",
@@ -1286,11 +1272,9 @@ pub async fn create_join_event_v2_route(
 		.as_ref()
 		.expect("server is authenticated");
 
-	if services()
-		.globals
-		.config
-		.forbidden_remote_server_names
-		.contains(sender_servername)
+	if !services()
+		.server_policy
+		.is_federation_allowed(sender_servername)
 	{
 		warn!(
 			"Server {sender_servername} tried joining room ID {} who has a server name that is globally forbidden. \
@@ -1304,12 +1288,7 @@ pub async fn create_join_event_v2_route(
 	}
 
 	if let Some(server) = body.room_id.server_name() {
-		if services()
-			.globals
-			.config
-			.forbidden_remote_server_names
-			.contains(&server.to_owned())
-		{
+		if !services().server_policy.is_federation_allowed(server) {
 			return Err(Error::BadRequest(
 				ErrorKind::forbidden(),
 				"Server is banned on this homeserver.",
@@ -1547,12 +1526,7 @@ pub async fn create_invite_route(body: Ruma<create_invite::v2::Request>) -> Resu
 	}
 
 	if let Some(server) = body.room_id.server_name() {
-		if services()
-			.globals
-			.config
-			.forbidden_remote_server_names
-			.contains(&server.to_owned())
-		{
+		if !services().server_policy.is_federation_allowed(server) {
 			warn!(
 				"Received federated/remote invite from banned server {sender_servername} for room ID {}. Rejecting.",
 				body.room_id
@@ -1564,11 +1538,9 @@ pub async fn create_invite_route(body: Ruma<create_invite::v2::Request>) -> Resu
 		}
 	}
 
-	if services()
-		.globals
-		.config
-		.forbidden_remote_server_names
-		.contains(&sender_servername.to_owned())
+	if !services()
+		.server_policy
+		.is_federation_allowed(sender_servername)
 	{
 		warn!(
 			"Received federated/remote invite from banned server {sender_servername} for room ID {}. Rejecting.",
@@ -1891,7 +1863,7 @@ pub async fn claim_keys_route(body: Ruma<claim_keys::v1::Request>) -> Result<cla
 pub async fn well_known_server(_body: Ruma<discover_homeserver::Request>) -> Result<discover_homeserver::Response> {
 	Ok(discover_homeserver::Response {
 		server: match services().globals.well_known_server() {
-			Some(server_name) => server_name.to_owned(),
+			Some(server_name) => server_name,
 			None => return Err(Error::BadRequest(ErrorKind::NotFound, "Not found.")),
 		},
 	})
@@ -2,7 +2,7 @@
 
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Returns the current version of the crate with extra info if supplied
 ///
@@ -26,6 +26,48 @@ pub struct Args {
 	#[arg(short, long)]
 	/// Optional argument to the path of a conduwuit config TOML file
 	pub config: Option<PathBuf>,
+
+	#[arg(long)]
+	/// Read admin commands from stdin while the server is running
+	pub console: bool,
+
+	#[arg(long)]
+	/// Load the config, run all checks on it and exit, failing if it has errors
+	pub check_config: bool,
+
+	#[arg(long)]
+	/// Print every config option with its default value as JSON and exit
+	pub print_default_config: bool,
+
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+/// Commands run against the database instead of starting the server. The
+/// server must not be running at the same time.
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+	/// Create a local user
+	CreateUser {
+		/// Username of the new user
+		username: String,
+		/// Password of the new user, if unspecified one is generated
+		password: Option<String>,
+	},
+
+	/// Set a new password for a local user
+	ResetPassword {
+		/// Username of the user whose password should be reset
+		username: String,
+		/// New password of the user, if unspecified one is generated
+		password: Option<String>,
+	},
+
+	/// Make a local user a server admin by joining them to the admin room
+	MakeAdmin {
+		/// Username of the new admin
+		username: String,
+	},
 }
 
 /// Parse commandline arguments into structured data
@@ -5,7 +5,11 @@ use tracing::{debug, error, info, warn};
 
 use crate::{utils::error::Error, Config};
 
+/// Runs all config checks, logging warnings and failing with every error
+/// found.
 pub fn check(config: &Config) -> Result<(), Error> {
+	let mut errors: Vec<&'static str> = Vec::new();
+
 	config.warn_deprecated();
 	config.warn_unknown_key();
 
@@ -17,10 +21,10 @@ pub fn check(config: &Config) -> Result<(), Error> {
 	}
 
 	if config.unix_socket_path.is_some() && !cfg!(unix) {
-		return Err(Error::bad_config(
+		errors.push(
 			"UNIX socket support is only available on *nix platforms. Please remove \"unix_socket_path\" from your \
 			 config.",
-		));
+		);
 	}
 
 	if config.address.is_loopback() && cfg!(unix) {
@@ -66,16 +70,12 @@ pub fn check(config: &Config) -> Result<(), Error> {
 
 	// rocksdb does not allow max_log_files to be 0
 	if config.rocksdb_max_log_files == 0 && cfg!(feature = "rocksdb") {
-		return Err(Error::bad_config(
-			"When using RocksDB, rocksdb_max_log_files cannot be 0. Please set a value at least 1.",
-		));
+		errors.push("When using RocksDB, rocksdb_max_log_files cannot be 0. Please set a value at least 1.");
 	}
 
 	// yeah, unless the user built a debug build hopefully for local testing only
 	if config.server_name == "your.server.name" && !cfg!(debug_assertions) {
-		return Err(Error::bad_config(
-			"You must specify a valid server name for production usage of conduwuit.",
-		));
+		errors.push("You must specify a valid server name for production usage of conduwuit.");
 	}
 
 	if cfg!(debug_assertions) {
@@ -84,38 +84,46 @@ pub fn check(config: &Config) -> Result<(), Error> {
 
 	// check if the user specified a registration token as `""`
 	if config.registration_token == Some(String::new()) {
-		return Err(Error::bad_config("Registration token was specified but is empty (\"\")"));
+		errors.push("Registration token was specified but is empty (\"\")");
+	}
+
+	if ruma::UserId::parse_with_server_name(config.server_notices_localpart.as_str(), &config.server_name).is_err() {
+		errors.push("\"server_notices_localpart\" is not a valid username.");
 	}
 
 	if config.max_request_size < 16384 {
-		return Err(Error::bad_config("Max request size is less than 16KB. Please increase it."));
+		errors.push("Max request size is less than 16KB. Please increase it.");
 	}
 
 	// check if user specified valid IP CIDR ranges on startup
 	for cidr in &config.ip_range_denylist {
 		if let Err(e) = ipaddress::IPAddress::parse(cidr) {
 			error!("Error parsing specified IP CIDR range from string: {e}");
-			return Err(Error::bad_config("Error parsing specified IP CIDR ranges from strings"));
+			errors.push("Error parsing specified IP CIDR ranges from strings");
+			break;
 		}
 	}
 
 	if config.allow_registration
 		&& !config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
+		&& !config.registration_requires_token
 	{
-		return Err(Error::bad_config(
+		errors.push(
 			"!! You have `allow_registration` enabled without a token configured in your config which means you are \
 			 allowing ANYONE to register on your conduwuit instance without any 2nd-step (e.g. registration token).\n
-If this is not the intended behaviour, please set a registration token with the `registration_token` config option.\n
+If this is not the intended behaviour, please set a registration token with the `registration_token` config option, or \
+			 require database registration tokens with `registration_requires_token`.\n
 For security and safety reasons, conduwuit will shut down. If you are extra sure this is the desired behaviour you \
 			 want, please set the following config option to true:
 `yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse`",
-		));
+		);
 	}
 
 	if config.allow_registration
 		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
+		&& !config.registration_requires_token
 	{
 		warn!(
 			"Open registration is enabled via setting \
@@ -125,10 +133,89 @@ For security and safety reasons, conduwuit will shut down. If you are extra sure
 		);
 	}
 
+	if !config.oidc_providers.is_empty() && config.well_known.client.is_none() {
+		errors.push(
+			"OIDC identity providers are configured but \"well_known.client\" is not set. It is required to build the \
+			 redirect URI identity providers send users back to.",
+		);
+	}
+
+	if config
+		.oidc_providers
+		.iter()
+		.any(|provider| provider.id_token_signing_algs.is_empty())
+	{
+		errors.push("\"id_token_signing_algs\" of OIDC identity providers must not be empty.");
+	}
+
+	for (i, provider) in config.oidc_providers.iter().enumerate() {
+		if config.oidc_providers[..i]
+			.iter()
+			.any(|other| other.id == provider.id)
+		{
+			errors.push("OIDC identity provider IDs must be unique.");
+			break;
+		}
+	}
+
+	if config.ldap.is_some() && !cfg!(feature = "ldap") {
+		errors.push("LDAP authentication is configured but conduwuit was built without the \"ldap\" feature.");
+	}
+
+	if config
+		.ldap
+		.as_ref()
+		.is_some_and(|ldap| !ldap.filter.contains("{username}"))
+	{
+		errors.push("The LDAP filter must contain the {username} placeholder.");
+	}
+
+	if !config.policies.is_empty() && config.well_known.client.is_none() {
+		errors.push(
+			"Policies are configured but \"well_known.client\" is not set. It is required to build the links users \
+			 accept updated policies with.",
+		);
+	}
+
+	if config
+		.policies
+		.values()
+		.any(|policy| policy.translations.is_empty())
+	{
+		errors.push("Every policy needs a name and URL in at least one language.");
+	}
+
+	if config.email.is_some() && config.well_known.client.is_none() {
+		errors.push(
+			"Email is configured but \"well_known.client\" is not set. It is required to build the validation links \
+			 sent by email.",
+		);
+	}
+
+	if let Some(email) = &config.email {
+		if email.from.parse::<lettre::message::Mailbox>().is_err() {
+			errors.push("The \"from\" address of the email config is not a valid mailbox.");
+		}
+	}
+
+	let rate_limits = &config.rate_limits;
+	if [
+		rate_limits.message,
+		rate_limits.join,
+		rate_limits.invite,
+		rate_limits.registration,
+		rate_limits.login,
+		rate_limits.threepid_request,
+		rate_limits.media_upload,
+	]
+	.iter()
+	.any(|limit| !limit.per_second.is_finite() || limit.per_second <= 0.0 || limit.burst_count == 0)
+	{
+		errors.push("Rate limits need a positive \"per_second\" and a \"burst_count\" of at least 1.");
+	}
+
 	if config.allow_outgoing_presence && !config.allow_local_presence {
-		return Err(Error::bad_config(
-			"Outgoing presence requires allowing local presence. Please enable \"allow_local_presence\".",
-		));
+		errors.push("Outgoing presence requires allowing local presence. Please enable \"allow_local_presence\".");
 	}
 
 	if config
@@ -162,5 +249,9 @@ For security and safety reasons, conduwuit will shut down. If you are extra sure
 		);
 	}
 
+	if !errors.is_empty() {
+		return Err(Error::BadConfig(errors.join("\n")));
+	}
+
 	Ok(())
 }
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use super::true_fn;
+
+/// SMTP server used to send validation emails for email 3PIDs, e.g. to add an
+/// email address to an account or to reset a forgotten password.
+///
+/// Requires `well_known.client` to be set, as validation links point to
+/// `<well_known.client>/_conduwuit/email/submit_token`.
+///
+/// ## Example:
+/// ```toml
+/// [global.email]
+/// smtp_host = "smtp.example.com"
+/// smtp_username = "conduwuit"
+/// smtp_password = "change me"
+/// from = "conduwuit <noreply@example.com>"
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EmailConfig {
+	pub smtp_host: String,
+
+	/// Defaults to 465 for `tls`, 587 for `starttls` and 25 for `none`
+	pub smtp_port: Option<u16>,
+
+	/// How to secure the connection to the SMTP server
+	#[serde(default)]
+	pub smtp_tls: SmtpTls,
+
+	pub smtp_username: Option<String>,
+
+	pub smtp_password: Option<String>,
+
+	/// Sender address of validation emails, optionally with a display name
+	pub from: String,
+
+	/// How long in seconds validation tokens sent by email can be used for
+	#[serde(default = "default_validation_token_lifetime")]
+	pub validation_token_lifetime: u64,
+
+	/// Allow users to reset their password with an email address bound to
+	/// their account
+	#[serde(default = "true_fn")]
+	pub allow_password_reset: bool,
+
+	/// Hosts clients may send users to after they validated their email
+	/// address (`next_link`). Links to other hosts are ignored.
+	#[serde(default)]
+	pub next_link_domains: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+	/// Plaintext, only meant for local SMTP servers or testing sinks
+	None,
+
+	/// Upgrade a plaintext connection with `STARTTLS`
+	#[default]
+	StartTls,
+
+	/// Implicit TLS
+	Tls,
+}
+
+fn default_validation_token_lifetime() -> u64 { 60 * 60 }
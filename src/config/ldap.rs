@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::true_fn;
+
+/// LDAP directory used to check the passwords of local users. Requires
+/// building with the `ldap` feature.
+///
+/// Users not found in the directory fall back to their local password. Once
+/// a user logged in with their directory password, they can only log in with
+/// it, even while the directory is unreachable, and can't set a local
+/// password anymore.
+///
+/// ## Example:
+/// ```toml
+/// [global.ldap]
+/// uri = "ldap://localhost:389"
+/// bind_dn = "cn=admin,dc=example,dc=org"
+/// bind_password = "change me"
+/// base_dn = "ou=users,dc=example,dc=org"
+/// filter = "(&(objectClass=inetOrgPerson)(uid={username}))"
+/// admin_filter = "(memberOf=cn=matrix-admins,ou=groups,dc=example,dc=org)"
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LdapConfig {
+	/// `ldap://` or `ldaps://` URI of the directory server
+	pub uri: Url,
+
+	/// DN to bind as when searching for users. Searches anonymously if unset.
+	pub bind_dn: Option<String>,
+
+	pub bind_password: Option<String>,
+
+	/// Base DN to search for users in
+	pub base_dn: String,
+
+	/// Search filter matching a single user, `{username}` is replaced by the
+	/// escaped localpart of the user logging in
+	#[serde(default = "default_filter")]
+	pub filter: String,
+
+	/// Attribute used as the display name of newly provisioned users
+	#[serde(default = "default_displayname_attribute")]
+	pub displayname_attribute: String,
+
+	/// Create accounts for directory users logging in for the first time
+	#[serde(default = "true_fn")]
+	pub auto_provision: bool,
+
+	/// Additional filter directory users have to match to be admins. Users are
+	/// invited to the admin room when they start matching it, and removed
+	/// from it when they stop.
+	pub admin_filter: Option<String>,
+}
+
+fn default_filter() -> String { "(&(objectClass=inetOrgPerson)(uid={username}))".to_owned() }
+
+fn default_displayname_attribute() -> String { "cn".to_owned() }
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use super::true_fn;
+
+/// Protection against brute-forcing passwords. Once a user or a client IP
+/// address failed to log in, or to confirm a sensitive action like a password
+/// change, with a password too often, further attempts are rejected with
+/// `M_LIMIT_EXCEEDED` for a lockout that doubles with every further failure.
+///
+/// Behind a reverse proxy, `client_ip_header` has to be set, otherwise all
+/// clients appear to have the address of the proxy and lock each other out.
+/// Loopback addresses are never locked out.
+///
+/// ## Example:
+/// ```toml
+/// [global.login_lockout]
+/// max_attempts_per_user = 5
+/// initial_lockout = 60
+/// max_lockout = 3600
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LoginLockoutConfig {
+	#[serde(default = "true_fn")]
+	pub enabled: bool,
+
+	/// Failed password logins to an account before it is locked out
+	#[serde(default = "default_max_attempts_per_user")]
+	pub max_attempts_per_user: u32,
+
+	/// Failed password logins from an IP address before it is locked out
+	#[serde(default = "default_max_attempts_per_ip")]
+	pub max_attempts_per_ip: u32,
+
+	/// Duration in seconds of the first lockout
+	#[serde(default = "default_initial_lockout")]
+	pub initial_lockout: u64,
+
+	/// Upper bound in seconds for lockouts. Failures are forgotten once this
+	/// long has passed since the last one.
+	#[serde(default = "default_max_lockout")]
+	pub max_lockout: u64,
+
+	/// Number of consecutive failures of an account or IP address after which
+	/// the admin room is notified. 0 disables notifications.
+	#[serde(default = "default_admin_notify_threshold")]
+	pub admin_notify_threshold: u32,
+}
+
+impl Default for LoginLockoutConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			max_attempts_per_user: default_max_attempts_per_user(),
+			max_attempts_per_ip: default_max_attempts_per_ip(),
+			initial_lockout: default_initial_lockout(),
+			max_lockout: default_max_lockout(),
+			admin_notify_threshold: default_admin_notify_threshold(),
+		}
+	}
+}
+
+fn default_max_attempts_per_user() -> u32 { 5 }
+
+fn default_max_attempts_per_ip() -> u32 { 20 }
+
+fn default_initial_lockout() -> u64 { 60 }
+
+fn default_max_lockout() -> u64 { 60 * 60 }
+
+fn default_admin_notify_threshold() -> u32 { 20 }
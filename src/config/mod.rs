@@ -18,17 +18,35 @@ use regex::RegexSet;
 use ruma::{
 	api::client::discovery::discover_support::ContactRole, OwnedRoomId, OwnedServerName, OwnedUserId, RoomVersionId,
 };
-use serde::{de::IgnoredAny, Deserialize};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
 use tracing::{debug, error, warn};
 use url::Url;
 
 use self::proxy::ProxyConfig;
+pub use self::{
+	email::{EmailConfig, SmtpTls},
+	ldap::LdapConfig,
+	login_lockout::LoginLockoutConfig,
+	oidc::OidcProviderConfig,
+	password_policy::PasswordPolicyConfig,
+	policy::PolicyConfig,
+	rate_limit::{RateLimit, RateLimitConfig},
+	reload::{ConfigReload, LiveConfig},
+};
 use crate::utils::error::Error;
 
 mod check;
+mod email;
+mod ldap;
+mod login_lockout;
+mod oidc;
+mod password_policy;
+mod policy;
 mod proxy;
+mod rate_limit;
+mod reload;
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(transparent)]
 pub struct ListeningPort {
 	#[serde(with = "either::serde_untagged")]
@@ -36,7 +54,7 @@ pub struct ListeningPort {
 }
 
 /// all the config options for conduwuit
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct Config {
 	/// [`IpAddr`] conduwuit will listen on (can be IPv4 or IPv6)
@@ -49,6 +67,8 @@ pub struct Config {
 	pub unix_socket_path: Option<PathBuf>,
 	#[serde(default = "default_unix_socket_perms")]
 	pub unix_socket_perms: u32,
+	pub admin_console_socket_path: Option<PathBuf>,
+	pub client_ip_header: Option<String>,
 	pub server_name: OwnedServerName,
 	#[serde(default = "default_database_backend")]
 	pub database_backend: String,
@@ -85,6 +105,8 @@ pub struct Config {
 	pub stateinfo_cache_capacity: u32,
 	#[serde(default = "default_roomid_spacehierarchy_cache_capacity")]
 	pub roomid_spacehierarchy_cache_capacity: u32,
+	#[serde(default = "default_roomid_spacehierarchy_remote_cache_ttl")]
+	pub roomid_spacehierarchy_remote_cache_ttl: u64,
 
 	#[serde(default = "default_cleanup_second_interval")]
 	pub cleanup_second_interval: u32,
@@ -141,6 +163,15 @@ pub struct Config {
 	#[serde(default)]
 	pub yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse: bool,
 	pub registration_token: Option<String>,
+	#[serde(default)]
+	pub registration_requires_token: bool,
+	#[serde(default)]
+	pub policies: BTreeMap<String, PolicyConfig>,
+	pub password_policy: Option<PasswordPolicyConfig>,
+	#[serde(default)]
+	pub login_lockout: LoginLockoutConfig,
+	#[serde(default)]
+	pub rate_limits: RateLimitConfig,
 	#[serde(default = "true_fn")]
 	pub allow_encryption: bool,
 	#[serde(default = "true_fn")]
@@ -167,9 +198,22 @@ pub struct Config {
 	pub allow_jaeger: bool,
 	#[serde(default)]
 	pub tracing_flame: bool,
-	#[serde(default)]
+	#[serde(default, skip_serializing)]
 	pub proxy: ProxyConfig,
 	pub jwt_secret: Option<String>,
+	#[serde(default = "default_refreshable_access_token_lifetime")]
+	pub refreshable_access_token_lifetime: u64,
+	pub nonrefreshable_access_token_lifetime: Option<u64>,
+	#[serde(default)]
+	pub oidc_providers: Vec<OidcProviderConfig>,
+	#[serde(default)]
+	pub sso_client_allowlist: Vec<Url>,
+	pub ldap: Option<LdapConfig>,
+	pub email: Option<EmailConfig>,
+	#[serde(default = "true_fn")]
+	pub login_via_existing_session: bool,
+	#[serde(default = "default_login_token_lifetime")]
+	pub login_token_lifetime: u64,
 	#[serde(default = "default_trusted_servers")]
 	pub trusted_servers: Vec<OwnedServerName>,
 	#[serde(default = "true_fn")]
@@ -223,6 +267,9 @@ pub struct Config {
 
 	pub emergency_password: Option<String>,
 
+	#[serde(default = "default_server_notices_localpart")]
+	pub server_notices_localpart: String,
+
 	#[serde(default = "default_notification_push_path")]
 	pub notification_push_path: String,
 
@@ -275,6 +322,8 @@ pub struct Config {
 	pub forbidden_remote_server_names: Vec<OwnedServerName>,
 	#[serde(default = "Vec::new")]
 	pub forbidden_remote_room_directory_server_names: Vec<OwnedServerName>,
+	#[serde(default)]
+	pub federation_allowlist_only: bool,
 
 	#[serde(default = "default_ip_range_denylist")]
 	pub ip_range_denylist: Vec<String>,
@@ -315,12 +364,16 @@ pub struct Config {
 	#[serde(default = "default_sentry_traces_sample_rate")]
 	pub sentry_traces_sample_rate: f32,
 
-	#[serde(flatten)]
+	#[serde(flatten, skip_serializing)]
 	#[allow(clippy::zero_sized_map_values)] // this is a catchall, the map shouldn't be zero at runtime
 	pub catchall: BTreeMap<String, IgnoredAny>,
+
+	/// Config file given on the command line, used again when reloading
+	#[serde(skip)]
+	pub config_path: Option<PathBuf>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TlsConfig {
 	pub certs: String,
 	pub key: String,
@@ -331,7 +384,7 @@ pub struct TlsConfig {
 	pub dual_protocol: bool,
 }
 
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct WellKnownConfig {
 	pub client: Option<Url>,
 	pub server: Option<OwnedServerName>,
@@ -358,7 +411,7 @@ impl Config {
 			Figment::new()
 				.merge(Toml::file(config_file_env).nested())
 				.merge(Env::prefixed("CONDUIT_").global())
-		} else if let Some(config_file_arg) = path {
+		} else if let Some(config_file_arg) = path.clone() {
 			Figment::new()
 				.merge(Toml::file(config_file_arg).nested())
 				.merge(Env::prefixed("CONDUIT_").global())
@@ -366,10 +419,11 @@ impl Config {
 			Figment::new().merge(Env::prefixed("CONDUIT_").global())
 		};
 
-		let config = match raw_config.extract::<Config>() {
+		let mut config = match raw_config.extract::<Config>() {
 			Err(e) => return Err(Error::BadConfig(format!("{e}"))),
 			Ok(config) => config,
 		};
+		config.config_path = path;
 
 		check::check(&config)?;
 
@@ -381,6 +435,26 @@ impl Config {
 		Ok(config)
 	}
 
+	/// Config with every option at its default value. Options without a
+	/// default get the placeholder values of the example config.
+	#[must_use]
+	pub fn defaults() -> Self {
+		Figment::new()
+			.merge(Toml::string(
+				"server_name = \"your.server.name\"\ndatabase_path = \"/var/lib/conduwuit\"",
+			))
+			.extract()
+			.expect("placeholder config is valid")
+	}
+
+	/// Every option with its default value as pretty-printed JSON, nested
+	/// under `global` like in the config file.
+	#[must_use]
+	pub fn defaults_json() -> String {
+		let defaults = serde_json::json!({ "global": Self::defaults() });
+		serde_json::to_string_pretty(&defaults).expect("config serializes to JSON")
+	}
+
 	/// Iterates over all the keys in the config file and warns if there is a
 	/// deprecated key specified
 	pub fn warn_deprecated(&self) {
@@ -469,6 +543,19 @@ impl fmt::Display for Config {
 				},
 			),
 			("Database backups to keep", &self.database_backups_to_keep.to_string()),
+			(
+				"Admin console socket path",
+				match &self.admin_console_socket_path {
+					Some(path) => path.to_str().unwrap(),
+					None => "disabled",
+				},
+			),
+			(
+				"Client IP header",
+				self.client_ip_header
+					.as_deref()
+					.unwrap_or("not set (address of the connection)"),
+			),
 			("Database cache capacity (MB)", &self.db_cache_capacity_mb.to_string()),
 			("Cache capacity modifier", &self.conduit_cache_capacity_modifier.to_string()),
 			("PDU cache capacity", &self.pdu_cache_capacity.to_string()),
@@ -490,6 +577,10 @@ impl fmt::Display for Config {
 				"Roomid space hierarchy cache capacity",
 				&self.roomid_spacehierarchy_cache_capacity.to_string(),
 			),
+			(
+				"Roomid space hierarchy remote cache TTL",
+				&self.roomid_spacehierarchy_remote_cache_ttl.to_string(),
+			),
 			("Cleanup interval in seconds", &self.cleanup_second_interval.to_string()),
 			("DNS cache entry limit", &self.dns_cache_entries.to_string()),
 			("DNS minimum ttl", &self.dns_min_ttl.to_string()),
@@ -522,6 +613,40 @@ impl fmt::Display for Config {
 					None => "not set (open registration!)",
 				},
 			),
+			("Registration requires token", &self.registration_requires_token.to_string()),
+			("Policies users have to accept", {
+				let mut lst = vec![];
+				for (name, policy) in &self.policies {
+					lst.push(format!("{name} (version {})", policy.version));
+				}
+				&lst.join(", ")
+			}),
+			(
+				"Password policy",
+				&self.password_policy.as_ref().map_or_else(
+					|| "disabled".to_owned(),
+					|policy| format!("minimum length {}", policy.min_length),
+				),
+			),
+			(
+				"Login lockout",
+				&if self.login_lockout.enabled {
+					format!(
+						"after {} failures per user or {} per IP address",
+						self.login_lockout.max_attempts_per_user, self.login_lockout.max_attempts_per_ip
+					)
+				} else {
+					"disabled".to_owned()
+				},
+			),
+			(
+				"Client API rate limits",
+				if self.rate_limits.enabled {
+					"enabled"
+				} else {
+					"disabled"
+				},
+			),
 			(
 				"Allow guest registration (inherently false if allow registration is false)",
 				&self.allow_guest_registration.to_string(),
@@ -535,6 +660,7 @@ impl fmt::Display for Config {
 				&self.allow_guests_auto_join_rooms.to_string(),
 			),
 			("New user display name suffix", &self.new_user_displayname_suffix),
+			("Server notices user localpart", &self.server_notices_localpart),
 			("Allow encryption", &self.allow_encryption.to_string()),
 			("Allow federation", &self.allow_federation.to_string()),
 			(
@@ -595,6 +721,48 @@ impl fmt::Display for Config {
 					None => "not set",
 				},
 			),
+			(
+				"Refreshable access token lifetime (seconds)",
+				&self.refreshable_access_token_lifetime.to_string(),
+			),
+			(
+				"Non-refreshable access token lifetime (seconds)",
+				&self
+					.nonrefreshable_access_token_lifetime
+					.map_or_else(|| "never expires".to_owned(), |lifetime| lifetime.to_string()),
+			),
+			("OIDC identity providers", {
+				let mut lst = vec![];
+				for provider in &self.oidc_providers {
+					lst.push(format!("{} ({})", provider.id, provider.issuer));
+				}
+				&lst.join(", ")
+			}),
+			(
+				"SSO client allowlist",
+				&self
+					.sso_client_allowlist
+					.iter()
+					.map(Url::as_str)
+					.collect::<Vec<_>>()
+					.join(", "),
+			),
+			(
+				"LDAP authentication",
+				&self
+					.ldap
+					.as_ref()
+					.map_or_else(|| "disabled".to_owned(), |ldap| ldap.uri.to_string()),
+			),
+			(
+				"Email (SMTP server)",
+				&self
+					.email
+					.as_ref()
+					.map_or_else(|| "disabled".to_owned(), |email| email.smtp_host.clone()),
+			),
+			("Login via existing session", &self.login_via_existing_session.to_string()),
+			("Login token lifetime (seconds)", &self.login_token_lifetime.to_string()),
 			("Trusted key servers", {
 				let mut lst = vec![];
 				for server in &self.trusted_servers {
@@ -710,6 +878,7 @@ impl fmt::Display for Config {
 				}
 				&lst.join(", ")
 			}),
+			("Federation allowlist only", &self.federation_allowlist_only.to_string()),
 			("Outbound Request IP Range Denylist", {
 				let mut lst = vec![];
 				for item in self.ip_range_denylist.iter().cloned().enumerate() {
@@ -845,6 +1014,12 @@ fn default_stateinfo_cache_capacity() -> u32 { 100 }
 
 fn default_roomid_spacehierarchy_cache_capacity() -> u32 { 100 }
 
+fn default_roomid_spacehierarchy_remote_cache_ttl() -> u64 { 60 * 60 }
+
+fn default_refreshable_access_token_lifetime() -> u64 { 60 * 5 }
+
+fn default_login_token_lifetime() -> u64 { 60 * 2 }
+
 fn default_cleanup_second_interval() -> u32 {
 	1800 // every 30 minutes
 }
@@ -910,6 +1085,8 @@ fn default_notification_push_path() -> String { "/_matrix/push/v1/notify".to_own
 
 fn default_turn_ttl() -> u64 { 60 * 60 * 24 }
 
+fn default_server_notices_localpart() -> String { "server".to_owned() }
+
 fn default_presence_idle_timeout_s() -> u64 { 5 * 60 }
 
 fn default_presence_offline_timeout_s() -> u64 { 30 * 60 }
@@ -985,3 +1162,34 @@ fn default_new_user_displayname_suffix() -> String { "🏳️‍⚧️".to_owned
 fn default_sentry_traces_sample_rate() -> f32 { 0.15 }
 
 fn default_startup_netburst_keep() -> i64 { 50 }
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeSet;
+
+	use regex::Regex;
+
+	use super::Config;
+
+	#[test]
+	fn example_config_documents_every_option() {
+		let key = Regex::new(r"(?m)^#?\s*(?:\[\[?global\.)?([a-z0-9_]+)(?:\s*=|[.\]])").unwrap();
+		let documented = key
+			.captures_iter(include_str!("../../conduwuit-example.toml"))
+			.map(|captures| captures[1].to_owned())
+			.collect::<BTreeSet<_>>();
+
+		let defaults = serde_json::to_value(Config::defaults()).unwrap();
+		let undocumented = defaults
+			.as_object()
+			.unwrap()
+			.keys()
+			.filter(|option| !documented.contains(*option))
+			.collect::<Vec<_>>();
+
+		assert!(
+			undocumented.is_empty(),
+			"options missing from conduwuit-example.toml: {undocumented:?}"
+		);
+	}
+}
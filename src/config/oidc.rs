@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use jsonwebtoken::Algorithm;
+use ruma::OwnedMxcUri;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::true_fn;
+
+/// An OpenID Connect identity provider users can log in with using
+/// `m.login.sso`.
+///
+/// ## Example:
+/// ```toml
+/// [[global.oidc_providers]]
+/// id = "corp"
+/// name = "Corporate SSO"
+/// issuer = "https://idp.example.com/realms/corp"
+/// client_id = "conduwuit"
+/// client_secret = "change me"
+/// ```
+///
+/// The redirect URI to register with the identity provider is
+/// `<well_known.client>/_conduwuit/oidc/callback`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OidcProviderConfig {
+	/// Identifier of the provider, used by clients to pick a provider. Must be
+	/// unique.
+	pub id: String,
+
+	/// Human readable name of the provider shown by clients
+	pub name: String,
+
+	/// Optional MXC URI of an icon for the provider
+	pub icon: Option<OwnedMxcUri>,
+
+	/// Issuer URL, used to discover the provider's endpoints via
+	/// `/.well-known/openid-configuration`
+	pub issuer: Url,
+
+	pub client_id: String,
+
+	/// Client secret, sent to the token endpoint using `client_secret_post`.
+	/// Public clients relying on PKCE only can leave this unset.
+	pub client_secret: Option<String>,
+
+	#[serde(default = "default_scopes")]
+	pub scopes: Vec<String>,
+
+	/// Algorithms the provider signs ID tokens with. ID tokens signed with any
+	/// other algorithm are rejected. HMAC algorithms (`HS256`, ...) use the
+	/// client secret as key.
+	#[serde(default = "default_id_token_signing_algs")]
+	pub id_token_signing_algs: Vec<Algorithm>,
+
+	/// Claim used to derive the localpart of users logging in for the first
+	/// time
+	#[serde(default = "default_localpart_claim")]
+	pub localpart_claim: String,
+
+	/// Claim used as the display name of newly provisioned users
+	#[serde(default = "default_displayname_claim")]
+	pub displayname_claim: String,
+
+	/// Create accounts for users logging in for the first time
+	#[serde(default = "true_fn")]
+	pub allow_registration: bool,
+
+	/// Link users logging in for the first time to an existing local account
+	/// with the same localpart
+	#[serde(default)]
+	pub allow_existing_users: bool,
+
+	/// Claims that must be present with the given value for a user to be
+	/// allowed to log in, e.g. `{ groups = "matrix-users" }`. Array claims
+	/// match if any of their elements is equal to the value.
+	#[serde(default)]
+	pub required_claims: BTreeMap<String, String>,
+}
+
+fn default_scopes() -> Vec<String> { vec!["openid".to_owned(), "profile".to_owned()] }
+
+fn default_id_token_signing_algs() -> Vec<Algorithm> { vec![Algorithm::RS256] }
+
+fn default_localpart_claim() -> String { "preferred_username".to_owned() }
+
+fn default_displayname_claim() -> String { "name".to_owned() }
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+
+use super::true_fn;
+
+/// Requirements for passwords users choose, enforced when registering,
+/// changing or resetting a password, and for passwords given to the admin
+/// `users create` and `users reset-password` commands.
+///
+/// ## Example:
+/// ```toml
+/// [global.password_policy]
+/// min_length = 12
+/// require_digit = true
+/// require_symbol = true
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PasswordPolicyConfig {
+	/// Minimum number of characters
+	#[serde(default = "default_min_length")]
+	pub min_length: usize,
+
+	#[serde(default)]
+	pub require_digit: bool,
+
+	#[serde(default)]
+	pub require_lowercase: bool,
+
+	#[serde(default)]
+	pub require_uppercase: bool,
+
+	/// Require a character that is neither a letter nor a digit
+	#[serde(default)]
+	pub require_symbol: bool,
+
+	/// Reject a built-in list of commonly used passwords
+	#[serde(default = "true_fn")]
+	pub deny_common_passwords: bool,
+
+	/// Additional passwords to reject, compared case-insensitively
+	#[serde(default)]
+	pub denied_passwords: Vec<String>,
+
+	/// Reject passwords containing the localpart of the user, if it is at
+	/// least 3 characters long
+	#[serde(default = "true_fn")]
+	pub deny_username: bool,
+}
+
+impl PasswordPolicyConfig {
+	/// Checks a password for the user with the given localpart against the
+	/// policy, returning why it was rejected.
+	pub fn check(&self, localpart: &str, password: &str) -> Result<(), &'static str> {
+		if password.chars().count() < self.min_length {
+			return Err("Password is too short.");
+		}
+
+		if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+			return Err("Password must contain a digit.");
+		}
+
+		if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+			return Err("Password must contain a lowercase letter.");
+		}
+
+		if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+			return Err("Password must contain an uppercase letter.");
+		}
+
+		if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+			return Err("Password must contain a symbol.");
+		}
+
+		let lowercase = password.to_lowercase();
+
+		if self.deny_username
+			&& localpart.chars().count() >= MIN_DENIED_USERNAME_LENGTH
+			&& lowercase.contains(&localpart.to_lowercase())
+		{
+			return Err("Password must not contain the username.");
+		}
+
+		if (self.deny_common_passwords && COMMON_PASSWORDS.contains(&lowercase.as_str()))
+			|| self
+				.denied_passwords
+				.iter()
+				.any(|denied| denied.to_lowercase() == lowercase)
+		{
+			return Err("Password is too common.");
+		}
+
+		Ok(())
+	}
+}
+
+impl Default for PasswordPolicyConfig {
+	fn default() -> Self {
+		Self {
+			min_length: default_min_length(),
+			require_digit: false,
+			require_lowercase: false,
+			require_uppercase: false,
+			require_symbol: false,
+			deny_common_passwords: true,
+			denied_passwords: Vec::new(),
+			deny_username: true,
+		}
+	}
+}
+
+fn default_min_length() -> usize { 8 }
+
+/// Shorter localparts are too likely to be part of a password by chance
+const MIN_DENIED_USERNAME_LENGTH: usize = 3;
+
+/// Some of the most commonly used passwords, lowercase
+const COMMON_PASSWORDS: &[&str] = &[
+	"000000",
+	"111111",
+	"11111111",
+	"112233",
+	"121212",
+	"123123",
+	"123321",
+	"1234",
+	"12345",
+	"123456",
+	"1234567",
+	"12345678",
+	"123456789",
+	"1234567890",
+	"123qwe",
+	"1q2w3e",
+	"1q2w3e4r",
+	"1qaz2wsx",
+	"654321",
+	"666666",
+	"696969",
+	"7777777",
+	"87654321",
+	"88888888",
+	"987654321",
+	"aa123456",
+	"abc123",
+	"abcd1234",
+	"access",
+	"admin",
+	"admin123",
+	"administrator",
+	"asdfasdf",
+	"asdfghjkl",
+	"baseball",
+	"batman",
+	"changeme",
+	"charlie",
+	"computer",
+	"dragon",
+	"football",
+	"freedom",
+	"iloveyou",
+	"letmein",
+	"login",
+	"master",
+	"michael",
+	"monkey",
+	"mustang",
+	"passw0rd",
+	"password",
+	"password1",
+	"password123",
+	"princess",
+	"qazwsx",
+	"qwerty",
+	"qwerty123",
+	"qwertyuiop",
+	"shadow",
+	"sunshine",
+	"superman",
+	"trustno1",
+	"welcome",
+	"welcome1",
+	"whatever",
+	"zaq12wsx",
+];
+
+#[cfg(test)]
+mod tests {
+	use super::PasswordPolicyConfig;
+
+	#[test]
+	fn default_policy() {
+		let policy = PasswordPolicyConfig::default();
+
+		assert!(policy.check("alice", "short").is_err());
+		assert!(policy.check("alice", "Password123").is_err());
+		assert!(policy.check("alice", "xALICEx-secret").is_err());
+		assert!(policy
+			.check("alice", "correct horse battery staple")
+			.is_ok());
+	}
+
+	#[test]
+	fn character_classes() {
+		let policy = PasswordPolicyConfig {
+			require_digit: true,
+			require_lowercase: true,
+			require_uppercase: true,
+			require_symbol: true,
+			..Default::default()
+		};
+
+		assert!(policy.check("bob", "nodigitsHere!").is_err());
+		assert!(policy.check("bob", "NOLOWERCASE1!").is_err());
+		assert!(policy.check("bob", "nouppercase1!").is_err());
+		assert!(policy.check("bob", "NoSymbols123").is_err());
+		assert!(policy.check("bob", "Has3verything!").is_ok());
+	}
+
+	#[test]
+	fn denied_passwords() {
+		let policy = PasswordPolicyConfig {
+			denied_passwords: vec!["Conduwuit2024".to_owned()],
+			..Default::default()
+		};
+
+		assert!(policy.check("carol", "conduwuit2024").is_err());
+		assert!(policy.check("carol", "conduwuit2025").is_ok());
+	}
+
+	#[test]
+	fn short_usernames_allowed_in_passwords() {
+		let policy = PasswordPolicyConfig::default();
+
+		assert!(policy.check("al", "always calm at sea").is_ok());
+		assert!(policy.check("eve", "forever and evermore").is_err());
+	}
+}
@@ -0,0 +1,31 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A policy users have to accept to use the server, e.g. terms of service or a
+/// privacy policy. Users accept policies when registering (`m.login.terms`),
+/// and again through a link sent as server notice whenever a version changes.
+///
+/// ## Example:
+/// ```toml
+/// [global.policies.terms_of_service]
+/// version = "1.0"
+/// en = { name = "Terms of Service", url = "https://example.org/terms-1.0-en.html" }
+/// de = { name = "Nutzungsbedingungen", url = "https://example.org/terms-1.0-de.html" }
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PolicyConfig {
+	/// Changing the version makes every user accept the policy again
+	pub version: String,
+
+	/// The policy document in each language, keyed by language code
+	#[serde(flatten)]
+	pub translations: BTreeMap<String, PolicyTranslation>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PolicyTranslation {
+	pub name: String,
+	pub url: Url,
+}
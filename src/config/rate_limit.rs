@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+/// Rate limits for the client-server API. Every kind of request has its own
+/// token bucket per user, or per client IP address for unauthenticated
+/// requests like registration and login. Appservices and admins are exempt.
+/// Disabled by default.
+///
+/// Behind a reverse proxy, `client_ip_header` has to be set for unauthenticated
+/// requests to be limited, as loopback addresses are not limited.
+///
+/// ## Example:
+/// ```toml
+/// [global.rate_limits]
+/// enabled = true
+/// message = { per_second = 0.5, burst_count = 20 }
+/// login = { per_second = 0.1, burst_count = 3 }
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+	#[serde(default)]
+	pub enabled: bool,
+
+	/// Sending message and state events
+	#[serde(default = "default_message")]
+	pub message: RateLimit,
+
+	/// Joining rooms
+	#[serde(default = "default_join")]
+	pub join: RateLimit,
+
+	/// Inviting users to rooms
+	#[serde(default = "default_invite")]
+	pub invite: RateLimit,
+
+	/// Registering accounts, per IP address
+	#[serde(default = "default_registration")]
+	pub registration: RateLimit,
+
+	/// Logging in, per IP address
+	#[serde(default = "default_login")]
+	pub login: RateLimit,
+
+	/// Requesting email validation tokens, per IP address for password resets
+	#[serde(default = "default_threepid_request")]
+	pub threepid_request: RateLimit,
+
+	/// Uploading media
+	#[serde(default = "default_media_upload")]
+	pub media_upload: RateLimit,
+}
+
+/// A token bucket that holds up to `burst_count` requests and refills with
+/// `per_second` requests per second.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct RateLimit {
+	pub per_second: f64,
+	pub burst_count: u32,
+}
+
+impl Default for RateLimitConfig {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			message: default_message(),
+			join: default_join(),
+			invite: default_invite(),
+			registration: default_registration(),
+			login: default_login(),
+			threepid_request: default_threepid_request(),
+			media_upload: default_media_upload(),
+		}
+	}
+}
+
+fn default_message() -> RateLimit {
+	RateLimit {
+		per_second: 0.2,
+		burst_count: 10,
+	}
+}
+
+fn default_join() -> RateLimit {
+	RateLimit {
+		per_second: 0.1,
+		burst_count: 10,
+	}
+}
+
+fn default_invite() -> RateLimit {
+	RateLimit {
+		per_second: 0.3,
+		burst_count: 10,
+	}
+}
+
+fn default_registration() -> RateLimit {
+	RateLimit {
+		per_second: 0.17,
+		burst_count: 5,
+	}
+}
+
+fn default_login() -> RateLimit {
+	RateLimit {
+		per_second: 0.17,
+		burst_count: 3,
+	}
+}
+
+fn default_threepid_request() -> RateLimit {
+	RateLimit {
+		per_second: 0.017,
+		burst_count: 3,
+	}
+}
+
+fn default_media_upload() -> RateLimit {
+	RateLimit {
+		per_second: 1.0,
+		burst_count: 10,
+	}
+}
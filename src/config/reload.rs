@@ -0,0 +1,169 @@
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, RwLock},
+};
+
+use super::Config;
+use crate::utils::error::Error;
+
+/// The running config. Reloading replaces it as a whole, so code reading
+/// several options from one [`LiveConfig::get`] sees them from the same config
+/// file.
+pub struct LiveConfig(RwLock<Arc<Config>>);
+
+impl LiveConfig {
+	pub fn new(config: Config) -> Self { Self(RwLock::new(Arc::new(config))) }
+
+	/// The current config. Hold on to it only for as long as it is needed, so
+	/// a reload takes effect.
+	pub fn get(&self) -> Arc<Config> { Arc::clone(&self.0.read().unwrap()) }
+
+	pub fn replace(&self, config: Config) { *self.0.write().unwrap() = Arc::new(config); }
+}
+
+/// Result of re-reading the config file and environment.
+pub struct ConfigReload {
+	/// The running config with the reloadable options taken from the new one
+	pub config: Config,
+	/// Changed options that were applied
+	pub applied: Vec<String>,
+	/// Changed options that only take effect after a restart
+	pub needs_restart: Vec<String>,
+}
+
+/// Copies the options that can change while running from one config to
+/// another. Only options read where they are used belong here, not ones that
+/// decide what is set up on startup, like `allow_local_presence` starting the
+/// presence timer handler.
+macro_rules! reloadable_options {
+	($($option:ident),* $(,)?) => {
+		fn apply_reloadable(config: &mut Config, new: &Config) {
+			$(config.$option.clone_from(&new.$option);)*
+		}
+	};
+}
+
+reloadable_options!(
+	log,
+	new_user_displayname_suffix,
+	allow_registration,
+	yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse,
+	registration_token,
+	registration_requires_token,
+	password_policy,
+	allow_guest_registration,
+	log_guest_registrations,
+	allow_guests_auto_join_rooms,
+	auto_join_rooms,
+	allow_room_creation,
+	block_non_admin_invites,
+	forbidden_alias_names,
+	forbidden_usernames,
+	url_preview_domain_contains_allowlist,
+	url_preview_domain_explicit_allowlist,
+	url_preview_domain_explicit_denylist,
+	url_preview_url_contains_allowlist,
+	url_preview_max_spider_size,
+	url_preview_check_root_domain,
+	allow_incoming_presence,
+	allow_outgoing_presence,
+	presence_idle_timeout_s,
+	presence_offline_timeout_s,
+	allow_incoming_read_receipts,
+	allow_outgoing_read_receipts,
+	allow_incoming_typing,
+	allow_outgoing_typing,
+	typing_federation_timeout_s,
+	typing_client_timeout_min_s,
+	typing_client_timeout_max_s,
+	well_known,
+	turn_username,
+	turn_password,
+	turn_uris,
+	turn_secret,
+	turn_ttl,
+	server_visibility_cache_capacity,
+	user_visibility_cache_capacity,
+	stateinfo_cache_capacity,
+	roomid_spacehierarchy_cache_capacity,
+);
+
+impl Config {
+	/// Loads the config again from where this one was loaded from and merges
+	/// the options that can change at runtime into this one.
+	pub fn reload(&self) -> Result<ConfigReload, Error> {
+		let new = Self::new(self.config_path.clone())?;
+
+		let mut config = self.clone();
+		apply_reloadable(&mut config, &new);
+
+		Ok(ConfigReload {
+			applied: changed_options(self, &config),
+			needs_restart: changed_options(&config, &new)
+				.into_iter()
+				.filter(|option| option != "catchall")
+				.collect(),
+			config,
+		})
+	}
+}
+
+/// Names the top level options whose values differ, comparing their
+/// pretty-printed debug representations.
+fn changed_options(old: &Config, new: &Config) -> Vec<String> {
+	let old = options_debug(old);
+	let new = options_debug(new);
+
+	new.into_iter()
+		.filter(|(option, value)| old.get(option) != Some(value))
+		.map(|(option, _)| option)
+		.collect()
+}
+
+fn options_debug(config: &Config) -> BTreeMap<String, String> {
+	let mut options = BTreeMap::<String, String>::new();
+	let mut current = None;
+
+	// Fields of the config struct are indented once, anything further indented
+	// belongs to the last field
+	for line in format!("{config:#?}").lines() {
+		let Some(field) = line.strip_prefix("    ") else {
+			continue;
+		};
+
+		if !field.starts_with(' ') {
+			if let Some((name, _)) = field.split_once(':') {
+				current = Some(name.to_owned());
+			}
+		}
+
+		if let Some(name) = &current {
+			let value = options.entry(name.clone()).or_default();
+			value.push_str(field);
+			value.push('\n');
+		}
+	}
+
+	options
+}
+
+#[cfg(test)]
+mod tests {
+	use super::options_debug;
+	use crate::Config;
+
+	#[test]
+	fn debug_options_are_split_by_field() {
+		let config: Config = figment::Figment::new()
+			.merge(figment::providers::Toml::string(
+				"server_name = \"example.com\"\ndatabase_path = \"/tmp\"\n[well_known]\nclient = \"https://example.com\"\n",
+			))
+			.extract()
+			.unwrap();
+
+		let options = options_debug(&config);
+		assert!(options["server_name"].contains("example.com"));
+		assert!(options["well_known"].contains("https://example.com"));
+		assert!(!options["allow_registration"].contains("example.com"));
+	}
+}
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use ruma::{OwnedUserId, UserId};
+
+use crate::{database::KeyValueDatabase, service, utils, Error, Result};
+
+impl service::consent::Data for KeyValueDatabase {
+	fn accepted_versions(&self, user_id: &UserId) -> Result<BTreeMap<String, String>> {
+		self.userid_acceptedpolicies
+			.get(user_id.as_bytes())?
+			.map_or_else(
+				|| Ok(BTreeMap::new()),
+				|bytes| {
+					serde_json::from_slice(&bytes)
+						.map_err(|_| Error::bad_database("Invalid policy versions in userid_acceptedpolicies."))
+				},
+			)
+	}
+
+	fn set_accepted_versions(&self, user_id: &UserId, versions: &BTreeMap<String, String>) -> Result<()> {
+		self.userid_acceptedpolicies.insert(
+			user_id.as_bytes(),
+			&serde_json::to_vec(versions).expect("BTreeMap::to_vec always works"),
+		)
+	}
+
+	fn notified_versions(&self, user_id: &UserId) -> Result<BTreeMap<String, String>> {
+		self.userid_notifiedpolicies
+			.get(user_id.as_bytes())?
+			.map_or_else(
+				|| Ok(BTreeMap::new()),
+				|bytes| {
+					serde_json::from_slice(&bytes)
+						.map_err(|_| Error::bad_database("Invalid policy versions in userid_notifiedpolicies."))
+				},
+			)
+	}
+
+	fn set_notified_versions(&self, user_id: &UserId, versions: &BTreeMap<String, String>) -> Result<()> {
+		self.userid_notifiedpolicies.insert(
+			user_id.as_bytes(),
+			&serde_json::to_vec(versions).expect("BTreeMap::to_vec always works"),
+		)
+	}
+
+	fn consent_token_user(&self, token: &str) -> Result<Option<OwnedUserId>> {
+		self.consenttoken_userid
+			.get(token.as_bytes())?
+			.map(|bytes| utils::user_id_from_bytes(&bytes))
+			.transpose()
+	}
+
+	fn set_consent_token(&self, token: &str, user_id: &UserId) -> Result<()> {
+		self.consenttoken_userid
+			.insert(token.as_bytes(), user_id.as_bytes())
+	}
+
+	fn remove_consent_token(&self, token: &str) -> Result<()> { self.consenttoken_userid.remove(token.as_bytes()) }
+}
@@ -1,14 +1,20 @@
 mod account_data;
 //mod admin;
 mod appservice;
+mod consent;
 mod globals;
 mod key_backups;
 mod media;
 //mod pdu;
 mod presence;
 mod pusher;
+mod registration_tokens;
 mod rooms;
 mod sending;
+mod server_notices;
+mod server_policy;
+mod sso;
+mod threepid;
 mod transaction_ids;
 mod uiaa;
 mod users;
@@ -0,0 +1,39 @@
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, registration_tokens::RegistrationTokenInfo},
+	utils, Error, Result,
+};
+
+impl service::registration_tokens::Data for KeyValueDatabase {
+	fn get_registration_token(&self, token: &str) -> Result<Option<RegistrationTokenInfo>> {
+		self.registrationtoken_info
+			.get(token.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid registration token info in db."))
+			})
+			.transpose()
+	}
+
+	fn set_registration_token(&self, token: &str, info: &RegistrationTokenInfo) -> Result<()> {
+		self.registrationtoken_info.insert(
+			token.as_bytes(),
+			&serde_json::to_vec(info).expect("RegistrationTokenInfo::to_vec always works"),
+		)
+	}
+
+	fn remove_registration_token(&self, token: &str) -> Result<()> {
+		self.registrationtoken_info.remove(token.as_bytes())
+	}
+
+	fn registration_tokens<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(String, RegistrationTokenInfo)>> + 'a> {
+		Box::new(self.registrationtoken_info.iter().map(|(token, bytes)| {
+			let token = utils::string_from_bytes(&token)
+				.map_err(|_| Error::bad_database("Registration token in db is invalid unicode."))?;
+			let info = serde_json::from_slice(&bytes)
+				.map_err(|_| Error::bad_database("Invalid registration token info in db."))?;
+
+			Ok((token, info))
+		}))
+	}
+}
@@ -20,4 +20,67 @@ impl service::rooms::directory::Data for KeyValueDatabase {
 			.map_err(|_| Error::bad_database("Room ID in publicroomids is invalid."))
 		}))
 	}
+
+	fn set_public_in_network(&self, network_id: &str, room_id: &RoomId, appservice_id: &str) -> Result<()> {
+		let mut key = network_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(room_id.as_bytes());
+
+		self.networkroomid_appserviceid
+			.insert(&key, appservice_id.as_bytes())
+	}
+
+	fn set_not_public_in_network(&self, network_id: &str, room_id: &RoomId) -> Result<()> {
+		let mut key = network_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(room_id.as_bytes());
+
+		self.networkroomid_appserviceid.remove(&key)
+	}
+
+	fn network_room_appservice(&self, network_id: &str, room_id: &RoomId) -> Result<Option<String>> {
+		let mut key = network_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(room_id.as_bytes());
+
+		self.networkroomid_appserviceid
+			.get(&key)?
+			.map(|bytes| {
+				utils::string_from_bytes(&bytes)
+					.map_err(|_| Error::bad_database("Appservice ID in networkroomid_appserviceid is invalid unicode."))
+			})
+			.transpose()
+	}
+
+	fn network_public_rooms<'a>(&'a self, network_id: &str) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a> {
+		let mut prefix = network_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		Box::new(
+			self.networkroomid_appserviceid
+				.scan_prefix(prefix)
+				.map(|(key, _)| room_id_from_networkroomid(&key)),
+		)
+	}
+
+	fn all_network_public_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a> {
+		Box::new(
+			self.networkroomid_appserviceid
+				.iter()
+				.map(|(key, _)| room_id_from_networkroomid(&key)),
+		)
+	}
+}
+
+fn room_id_from_networkroomid(key: &[u8]) -> Result<OwnedRoomId> {
+	let room_id = key
+		.splitn(2, |&b| b == 0xFF)
+		.nth(1)
+		.ok_or_else(|| Error::bad_database("Invalid key in networkroomid_appserviceid."))?;
+
+	RoomId::parse(
+		utils::string_from_bytes(room_id)
+			.map_err(|_| Error::bad_database("Room ID in networkroomid_appserviceid is invalid unicode."))?,
+	)
+	.map_err(|_| Error::bad_database("Room ID in networkroomid_appserviceid is invalid."))
 }
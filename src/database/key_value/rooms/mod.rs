@@ -8,6 +8,7 @@ mod pdu_metadata;
 mod read_receipt;
 mod search;
 mod short;
+mod spaces;
 mod state;
 mod state_accessor;
 mod state_cache;
@@ -0,0 +1,30 @@
+use ruma::RoomId;
+
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, rooms::spaces::CachedSpaceHierarchySummary},
+	Error, Result,
+};
+
+impl service::rooms::spaces::Data for KeyValueDatabase {
+	fn get_cached_summary(&self, room_id: &RoomId) -> Result<Option<CachedSpaceHierarchySummary>> {
+		self.roomid_spacehierarchy
+			.get(room_id.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid space hierarchy summary in roomid_spacehierarchy."))
+			})
+			.transpose()
+	}
+
+	fn cache_summary(&self, room_id: &RoomId, summary: &CachedSpaceHierarchySummary) -> Result<()> {
+		self.roomid_spacehierarchy.insert(
+			room_id.as_bytes(),
+			&serde_json::to_vec(summary).expect("CachedSpaceHierarchySummary can be serialized"),
+		)
+	}
+
+	fn remove_cached_summary(&self, room_id: &RoomId) -> Result<()> {
+		self.roomid_spacehierarchy.remove(room_id.as_bytes())
+	}
+}
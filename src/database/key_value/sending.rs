@@ -78,6 +78,16 @@ impl service::sending::Data for KeyValueDatabase {
 		Ok(keys)
 	}
 
+	fn queued_requests_all<'a>(
+		&'a self,
+	) -> Box<dyn Iterator<Item = Result<(Vec<u8>, OutgoingKind, SendingEventType)>> + 'a> {
+		Box::new(
+			self.servernameevent_data
+				.iter()
+				.map(|(key, v)| parse_servercurrentevent(&key, v).map(|(k, e)| (key, k, e))),
+		)
+	}
+
 	fn queued_requests<'a>(
 		&'a self, outgoing_kind: &OutgoingKind,
 	) -> Box<dyn Iterator<Item = Result<(SendingEventType, Vec<u8>)>> + 'a> {
@@ -0,0 +1,23 @@
+use ruma::{OwnedRoomId, RoomId, UserId};
+
+use crate::{database::KeyValueDatabase, service, utils, Error, Result};
+
+impl service::server_notices::Data for KeyValueDatabase {
+	fn notice_room(&self, user_id: &UserId) -> Result<Option<OwnedRoomId>> {
+		self.userid_noticeroomid
+			.get(user_id.as_bytes())?
+			.map(|bytes| {
+				RoomId::parse(
+					utils::string_from_bytes(&bytes)
+						.map_err(|_| Error::bad_database("Room ID in userid_noticeroomid is invalid unicode."))?,
+				)
+				.map_err(|_| Error::bad_database("Room ID in userid_noticeroomid is invalid."))
+			})
+			.transpose()
+	}
+
+	fn set_notice_room(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
+		self.userid_noticeroomid
+			.insert(user_id.as_bytes(), room_id.as_bytes())
+	}
+}
@@ -0,0 +1,43 @@
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, server_policy::PolicyList},
+	utils, Error, Result,
+};
+
+impl service::server_policy::Data for KeyValueDatabase {
+	fn add_server_policy(&self, list: PolicyList, pattern: &str) -> Result<()> {
+		self.serverpolicy_entries
+			.insert(&policy_key(list, pattern), &[])
+	}
+
+	fn remove_server_policy(&self, list: PolicyList, pattern: &str) -> Result<()> {
+		self.serverpolicy_entries.remove(&policy_key(list, pattern))
+	}
+
+	fn server_policies<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(PolicyList, String)>> + 'a> {
+		Box::new(self.serverpolicy_entries.iter().map(|(key, _)| {
+			let mut parts = key.splitn(2, |&b| b == 0xFF);
+			let list = match parts.next().expect("splitn always returns one element") {
+				b"deny" => PolicyList::Deny,
+				b"allow" => PolicyList::Allow,
+				b"media" => PolicyList::Media,
+				_ => return Err(Error::bad_database("Invalid policy list in serverpolicy_entries.")),
+			};
+			let pattern = utils::string_from_bytes(
+				parts
+					.next()
+					.ok_or_else(|| Error::bad_database("Invalid key in serverpolicy_entries."))?,
+			)
+			.map_err(|_| Error::bad_database("Server name pattern in serverpolicy_entries is invalid unicode."))?;
+
+			Ok((list, pattern))
+		}))
+	}
+}
+
+fn policy_key(list: PolicyList, pattern: &str) -> Vec<u8> {
+	let mut key = list.as_str().as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(pattern.as_bytes());
+	key
+}
@@ -0,0 +1,144 @@
+use ruma::{
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	ClientSecret, MilliSecondsSinceUnixEpoch, OwnedSessionId, OwnedUserId, UInt, UserId,
+};
+
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, threepid::ValidationSession},
+	utils, Error, Result,
+};
+
+impl service::threepid::Data for KeyValueDatabase {
+	fn threepid_user(&self, medium: &Medium, address: &str) -> Result<Option<OwnedUserId>> {
+		self.threepid_userid
+			.get(&threepid_key(medium, address))?
+			.map(|bytes| utils::user_id_from_bytes(&bytes))
+			.transpose()
+	}
+
+	fn add_threepid(&self, user_id: &UserId, threepid: &ThirdPartyIdentifier) -> Result<()> {
+		let threepid_key = threepid_key(&threepid.medium, &threepid.address);
+
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid_key);
+
+		let mut value = u64::from(threepid.validated_at.get())
+			.to_be_bytes()
+			.to_vec();
+		value.extend_from_slice(&u64::from(threepid.added_at.get()).to_be_bytes());
+
+		self.userthreepid_timestamps.insert(&key, &value)?;
+		self.threepid_userid
+			.insert(&threepid_key, user_id.as_bytes())
+	}
+
+	fn remove_threepid(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<()> {
+		let threepid_key = threepid_key(medium, address);
+
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid_key);
+
+		self.userthreepid_timestamps.remove(&key)?;
+		self.threepid_userid.remove(&threepid_key)
+	}
+
+	fn user_threepids<'a>(&'a self, user_id: &UserId) -> Box<dyn Iterator<Item = Result<ThirdPartyIdentifier>> + 'a> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		Box::new(
+			self.userthreepid_timestamps
+				.scan_prefix(prefix.clone())
+				.map(move |(key, value)| {
+					let mut parts = key[prefix.len()..].splitn(2, |&b| b == 0xFF);
+					let medium = utils::string_from_bytes(parts.next().expect("splitn always returns one element"))
+						.map_err(|_| Error::bad_database("Medium in userthreepid_timestamps is invalid unicode."))?;
+					let address = utils::string_from_bytes(
+						parts
+							.next()
+							.ok_or_else(|| Error::bad_database("Invalid key in userthreepid_timestamps."))?,
+					)
+					.map_err(|_| Error::bad_database("Address in userthreepid_timestamps is invalid unicode."))?;
+
+					if value.len() != 16 {
+						return Err(Error::bad_database("Invalid timestamps in userthreepid_timestamps."));
+					}
+					let timestamp = |bytes: &[u8]| {
+						utils::u64_from_bytes(bytes)
+							.ok()
+							.and_then(UInt::new)
+							.map(MilliSecondsSinceUnixEpoch)
+							.ok_or_else(|| Error::bad_database("Invalid timestamp in userthreepid_timestamps."))
+					};
+
+					Ok(ThirdPartyIdentifier {
+						medium: medium.as_str().into(),
+						address,
+						validated_at: timestamp(&value[..8])?,
+						added_at: timestamp(&value[8..])?,
+					})
+				}),
+		)
+	}
+
+	fn validation_session(&self, sid: &str) -> Result<Option<ValidationSession>> {
+		self.sid_validationsession
+			.get(sid.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid validation session in db."))
+			})
+			.transpose()
+	}
+
+	fn validation_session_id(
+		&self, client_secret: &ClientSecret, medium: &Medium, address: &str,
+	) -> Result<Option<OwnedSessionId>> {
+		let mut key = client_secret.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid_key(medium, address));
+
+		self.clientsecretthreepid_sid
+			.get(&key)?
+			.map(|bytes| {
+				utils::string_from_bytes(&bytes)
+					.ok()
+					.and_then(|sid| OwnedSessionId::try_from(sid).ok())
+					.ok_or_else(|| Error::bad_database("Invalid session ID in clientsecretthreepid_sid."))
+			})
+			.transpose()
+	}
+
+	fn set_validation_session(&self, sid: &str, session: &ValidationSession) -> Result<()> {
+		let mut key = session.client_secret.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid_key(&session.medium, &session.address));
+
+		self.clientsecretthreepid_sid.insert(&key, sid.as_bytes())?;
+		self.sid_validationsession.insert(
+			sid.as_bytes(),
+			&serde_json::to_vec(session).expect("ValidationSession::to_vec always works"),
+		)
+	}
+
+	fn remove_validation_session(&self, sid: &str) -> Result<()> {
+		if let Some(session) = self.validation_session(sid)? {
+			let mut key = session.client_secret.as_bytes().to_vec();
+			key.push(0xFF);
+			key.extend_from_slice(&threepid_key(&session.medium, &session.address));
+
+			self.clientsecretthreepid_sid.remove(&key)?;
+		}
+
+		self.sid_validationsession.remove(sid.as_bytes())
+	}
+}
+
+fn threepid_key(medium: &Medium, address: &str) -> Vec<u8> {
+	let mut key = medium.as_str().as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(address.as_bytes());
+	key
+}
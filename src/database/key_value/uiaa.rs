@@ -1,6 +1,6 @@
 use ruma::{
 	api::client::{error::ErrorKind, uiaa::UiaaInfo},
-	CanonicalJsonValue, DeviceId, UserId,
+	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId,
 };
 
 use crate::{database::KeyValueDatabase, service, Error, Result};
@@ -28,6 +28,15 @@ impl service::uiaa::Data for KeyValueDatabase {
 			.map(ToOwned::to_owned)
 	}
 
+	fn find_uiaa_session(&self, session: &str) -> Option<(OwnedUserId, OwnedDeviceId)> {
+		self.userdevicesessionid_uiaarequest
+			.read()
+			.unwrap()
+			.keys()
+			.find(|(_, _, s)| s == session)
+			.map(|(user_id, device_id, _)| (user_id.clone(), device_id.clone()))
+	}
+
 	fn update_uiaa_session(
 		&self, user_id: &UserId, device_id: &DeviceId, session: &str, uiaainfo: Option<&UiaaInfo>,
 	) -> Result<()> {
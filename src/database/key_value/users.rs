@@ -29,6 +29,36 @@ impl service::users::Data for KeyValueDatabase {
 			.is_empty())
 	}
 
+	fn locked_at(&self, user_id: &UserId) -> Result<Option<u64>> {
+		self.userid_lockedat
+			.get(user_id.as_bytes())?
+			.map(|bytes| utils::u64_from_bytes(&bytes).map_err(|_| Error::bad_database("Invalid locked_at in db.")))
+			.transpose()
+	}
+
+	fn set_locked_at(&self, user_id: &UserId, locked_at: Option<u64>) -> Result<()> {
+		match locked_at {
+			Some(locked_at) => self
+				.userid_lockedat
+				.insert(user_id.as_bytes(), &locked_at.to_be_bytes()),
+			None => self.userid_lockedat.remove(user_id.as_bytes()),
+		}
+	}
+
+	fn auth_provider(&self, user_id: &UserId) -> Result<Option<String>> {
+		self.userid_authprovider
+			.get(user_id.as_bytes())?
+			.map(|bytes| {
+				utils::string_from_bytes(&bytes).map_err(|_| Error::bad_database("Invalid auth provider in db."))
+			})
+			.transpose()
+	}
+
+	fn set_auth_provider(&self, user_id: &UserId, provider: &str) -> Result<()> {
+		self.userid_authprovider
+			.insert(user_id.as_bytes(), provider.as_bytes())
+	}
+
 	/// Returns the number of users registered on this server.
 	fn count(&self) -> Result<usize> { Ok(self.userid_password.iter().count()) }
 
@@ -211,7 +241,8 @@ impl service::users::Data for KeyValueDatabase {
 			&serde_json::to_vec(&Device {
 				device_id: device_id.into(),
 				display_name: initial_device_display_name,
-				last_seen_ip: None, // TODO
+				// Set by the first request made with the device
+				last_seen_ip: None,
 				last_seen_ts: Some(MilliSecondsSinceUnixEpoch::now()),
 			})
 			.expect("Device::to_string never fails."),
@@ -233,6 +264,14 @@ impl service::users::Data for KeyValueDatabase {
 			self.userdeviceid_token.remove(&userdeviceid)?;
 			self.token_userdeviceid.remove(&old_token)?;
 		}
+		self.userdeviceid_tokenexpiresat.remove(&userdeviceid)?;
+
+		for tree in [&self.userdeviceid_refreshtoken, &self.userdeviceid_previousrefreshtoken] {
+			if let Some(old_refresh_token) = tree.get(&userdeviceid)? {
+				tree.remove(&userdeviceid)?;
+				self.refreshtoken_userdeviceid.remove(&old_refresh_token)?;
+			}
+		}
 
 		// Remove todevice events
 		let mut prefix = userdeviceid.clone();
@@ -305,6 +344,180 @@ impl service::users::Data for KeyValueDatabase {
 		Ok(())
 	}
 
+	/// Returns when the access token of one device expires, in milliseconds
+	/// since the unix epoch.
+	fn token_expires_at(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Option<u64>> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		self.userdeviceid_tokenexpiresat
+			.get(&userdeviceid)?
+			.map(|bytes| {
+				utils::u64_from_bytes(&bytes)
+					.map_err(|_| Error::bad_database("Token expiry in userdeviceid_tokenexpiresat is invalid."))
+			})
+			.transpose()
+	}
+
+	/// Sets or removes the expiry of the access token of one device.
+	fn set_token_expires_at(&self, user_id: &UserId, device_id: &DeviceId, expires_at: Option<u64>) -> Result<()> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		if let Some(expires_at) = expires_at {
+			self.userdeviceid_tokenexpiresat
+				.insert(&userdeviceid, &expires_at.to_be_bytes())?;
+		} else {
+			self.userdeviceid_tokenexpiresat.remove(&userdeviceid)?;
+		}
+
+		Ok(())
+	}
+
+	/// Find out which user and device a refresh token belongs to.
+	fn find_from_refresh_token(&self, refresh_token: &str) -> Result<Option<(OwnedUserId, String)>> {
+		self.refreshtoken_userdeviceid
+			.get(refresh_token.as_bytes())?
+			.map_or(Ok(None), |bytes| {
+				let mut parts = bytes.split(|&b| b == 0xFF);
+				let user_bytes = parts
+					.next()
+					.ok_or_else(|| Error::bad_database("User ID in refreshtoken_userdeviceid is invalid."))?;
+				let device_bytes = parts
+					.next()
+					.ok_or_else(|| Error::bad_database("Device ID in refreshtoken_userdeviceid is invalid."))?;
+
+				Ok(Some((
+					UserId::parse(utils::string_from_bytes(user_bytes).map_err(|_| {
+						Error::bad_database("User ID in refreshtoken_userdeviceid is invalid unicode.")
+					})?)
+					.map_err(|_| Error::bad_database("User ID in refreshtoken_userdeviceid is invalid."))?,
+					utils::string_from_bytes(device_bytes)
+						.map_err(|_| Error::bad_database("Device ID in refreshtoken_userdeviceid is invalid."))?,
+				)))
+			})
+	}
+
+	/// Replaces or removes the refresh token of one device. `previous` stays
+	/// valid until the device uses its new access token.
+	fn set_refresh_token(
+		&self, user_id: &UserId, device_id: &DeviceId, refresh_token: Option<&str>, previous: Option<&str>,
+	) -> Result<()> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		// Remove old refresh tokens
+		for tree in [&self.userdeviceid_refreshtoken, &self.userdeviceid_previousrefreshtoken] {
+			if let Some(old_refresh_token) = tree.get(&userdeviceid)? {
+				if previous.map(str::as_bytes) != Some(&*old_refresh_token) {
+					self.refreshtoken_userdeviceid.remove(&old_refresh_token)?;
+				}
+				tree.remove(&userdeviceid)?;
+			}
+		}
+
+		if let Some(previous) = previous {
+			self.userdeviceid_previousrefreshtoken
+				.insert(&userdeviceid, previous.as_bytes())?;
+		}
+
+		if let Some(refresh_token) = refresh_token {
+			self.userdeviceid_refreshtoken
+				.insert(&userdeviceid, refresh_token.as_bytes())?;
+			self.refreshtoken_userdeviceid
+				.insert(refresh_token.as_bytes(), &userdeviceid)?;
+		}
+
+		Ok(())
+	}
+
+	/// Invalidates the refresh token a device refreshed its access token with.
+	fn remove_previous_refresh_token(&self, user_id: &UserId, device_id: &DeviceId) -> Result<()> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		if let Some(previous) = self.userdeviceid_previousrefreshtoken.get(&userdeviceid)? {
+			self.refreshtoken_userdeviceid.remove(&previous)?;
+			self.userdeviceid_previousrefreshtoken
+				.remove(&userdeviceid)?;
+		}
+
+		Ok(())
+	}
+
+	/// Stores a single-use login token for a user, removing expired ones.
+	fn create_login_token(&self, user_id: &UserId, token: &str, expires_at: u64) -> Result<()> {
+		let mut value = expires_at.to_be_bytes().to_vec();
+		value.extend_from_slice(user_id.as_bytes());
+
+		let _lock = self.logintoken_mutex.lock().unwrap();
+
+		// Tokens that were never used would stay around forever otherwise. They
+		// are ordered by expiry, so only the expired ones are read.
+		let now = utils::millis_since_unix_epoch();
+		let expired: Vec<_> = self
+			.expiresatlogintoken
+			.iter()
+			.map(|(key, _)| key)
+			.take_while(|key| {
+				key.get(..size_of::<u64>())
+					.and_then(|bytes| utils::u64_from_bytes(bytes).ok())
+					.map_or(true, |old_expires_at| old_expires_at <= now)
+			})
+			.collect();
+
+		for key in expired {
+			if let Some(old_token) = key.get(size_of::<u64>()..) {
+				self.logintoken_expiresatuserid.remove(old_token)?;
+			}
+			self.expiresatlogintoken.remove(&key)?;
+		}
+
+		let mut key = expires_at.to_be_bytes().to_vec();
+		key.extend_from_slice(token.as_bytes());
+		self.expiresatlogintoken.insert(&key, &[])?;
+
+		self.logintoken_expiresatuserid
+			.insert(token.as_bytes(), &value)?;
+
+		Ok(())
+	}
+
+	/// Removes a login token and returns the user it belongs to and when it
+	/// expires, in milliseconds since the unix epoch.
+	fn take_login_token(&self, token: &str) -> Result<Option<(OwnedUserId, u64)>> {
+		// Only one login may consume a token
+		let value = {
+			let _lock = self.logintoken_mutex.lock().unwrap();
+
+			let Some(value) = self.logintoken_expiresatuserid.get(token.as_bytes())? else {
+				return Ok(None);
+			};
+			self.logintoken_expiresatuserid.remove(token.as_bytes())?;
+
+			let mut key = value.get(..size_of::<u64>()).unwrap_or_default().to_vec();
+			key.extend_from_slice(token.as_bytes());
+			self.expiresatlogintoken.remove(&key)?;
+
+			value
+		};
+
+		let (expires_at_bytes, user_bytes) = value.split_at(size_of::<u64>().min(value.len()));
+		let expires_at = utils::u64_from_bytes(expires_at_bytes)
+			.map_err(|_| Error::bad_database("Expiry in logintoken_expiresatuserid is invalid."))?;
+		let user_id = UserId::parse(
+			utils::string_from_bytes(user_bytes)
+				.map_err(|_| Error::bad_database("User ID in logintoken_expiresatuserid is invalid unicode."))?,
+		)
+		.map_err(|_| Error::bad_database("User ID in logintoken_expiresatuserid is invalid."))?;
+
+		Ok(Some((user_id, expires_at)))
+	}
+
 	fn add_one_time_key(
 		&self, user_id: &UserId, device_id: &DeviceId, one_time_key_key: &DeviceKeyId,
 		one_time_key_value: &Raw<OneTimeKey>,
@@ -803,6 +1016,18 @@ impl service::users::Data for KeyValueDatabase {
 		Ok(())
 	}
 
+	fn update_device_last_seen(&self, user_id: &UserId, device_id: &DeviceId, device: &Device) -> Result<()> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		// Other users aren't told about this, so the device list version stays
+		self.userdeviceid_metadata.insert(
+			&userdeviceid,
+			&serde_json::to_vec(device).expect("Device::to_string always works"),
+		)
+	}
+
 	/// Get device metadata.
 	fn get_device_metadata(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Option<Device>> {
 		let mut userdeviceid = user_id.as_bytes().to_vec();
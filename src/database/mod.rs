@@ -55,6 +55,8 @@ pub struct KeyValueDatabase {
 
 	//pub users: users::Users,
 	pub(super) userid_password: Arc<dyn KvTree>,
+	pub(super) userid_lockedat: Arc<dyn KvTree>, // LockedAt = u64 milliseconds since epoch
+	pub(super) userid_authprovider: Arc<dyn KvTree>,
 	pub(super) userid_displayname: Arc<dyn KvTree>,
 	pub(super) userid_avatarurl: Arc<dyn KvTree>,
 	pub(super) userid_blurhash: Arc<dyn KvTree>,
@@ -62,6 +64,22 @@ pub struct KeyValueDatabase {
 	pub(super) userdeviceid_metadata: Arc<dyn KvTree>, // This is also used to check if a device exists
 	pub(super) userid_devicelistversion: Arc<dyn KvTree>, // DevicelistVersion = u64
 	pub(super) token_userdeviceid: Arc<dyn KvTree>,
+	pub(super) userdeviceid_tokenexpiresat: Arc<dyn KvTree>, // TokenExpiresAt = u64 (ms since unix epoch)
+	pub(super) userdeviceid_refreshtoken: Arc<dyn KvTree>,
+	pub(super) userdeviceid_previousrefreshtoken: Arc<dyn KvTree>,
+	pub(super) refreshtoken_userdeviceid: Arc<dyn KvTree>,
+	pub(super) logintoken_expiresatuserid: Arc<dyn KvTree>, // ExpiresAtUserId = u64 + UserId
+	pub(super) expiresatlogintoken: Arc<dyn KvTree>,        // ExpiresAtLoginToken = u64 + LoginToken
+	pub(super) idpsubject_userid: Arc<dyn KvTree>,          // IdpSubject = IdpId + Subject
+	pub(super) registrationtoken_info: Arc<dyn KvTree>,     // Info = JSON RegistrationTokenInfo
+	pub(super) threepid_userid: Arc<dyn KvTree>,            // Threepid = Medium + Address
+	pub(super) userthreepid_timestamps: Arc<dyn KvTree>,    // Timestamps = u64 validated_at + u64 added_at
+	pub(super) sid_validationsession: Arc<dyn KvTree>,      // ValidationSession = JSON
+	pub(super) clientsecretthreepid_sid: Arc<dyn KvTree>,
+	pub(super) userid_acceptedpolicies: Arc<dyn KvTree>, // AcceptedPolicies = JSON map of policy name to version
+	pub(super) userid_notifiedpolicies: Arc<dyn KvTree>,
+	pub(super) consenttoken_userid: Arc<dyn KvTree>,
+	pub(super) userid_noticeroomid: Arc<dyn KvTree>,
 
 	pub(super) onetimekeyid_onetimekeys: Arc<dyn KvTree>, // OneTimeKeyId = UserId + DeviceKeyId
 	pub(super) userid_lastonetimekeyupdate: Arc<dyn KvTree>, // LastOneTimeKeyUpdate = Count
@@ -93,6 +111,11 @@ pub struct KeyValueDatabase {
 	pub(super) alias_roomid: Arc<dyn KvTree>,
 	pub(super) aliasid_alias: Arc<dyn KvTree>, // AliasId = RoomId + Count
 	pub(super) publicroomids: Arc<dyn KvTree>,
+	pub(super) networkroomid_appserviceid: Arc<dyn KvTree>, // NetworkRoomId = NetworkId + RoomId
+
+	/// RoomId -> CachedSpaceHierarchySummary, invalidated on relevant state
+	/// changes.
+	pub(super) roomid_spacehierarchy: Arc<dyn KvTree>,
 
 	pub(super) threadid_userids: Arc<dyn KvTree>, // ThreadId = RoomId + Count
 
@@ -176,6 +199,7 @@ pub struct KeyValueDatabase {
 	                                                  * PduId / Id (for edus), Data = EDU content */
 	pub(super) servercurrentevent_data: Arc<dyn KvTree>, /* ServerCurrentEvents = (+ / $)ServerName / UserId + PduId
 	                                                      * / Id (for edus), Data = EDU content */
+	pub(super) serverpolicy_entries: Arc<dyn KvTree>, // Entry = PolicyList + ServerNamePattern
 
 	//pub appservice: appservice::Appservice,
 	pub(super) id_appserviceregistrations: Arc<dyn KvTree>,
@@ -187,6 +211,7 @@ pub struct KeyValueDatabase {
 	pub(super) our_real_users_cache: RwLock<HashMap<OwnedRoomId, Arc<HashSet<OwnedUserId>>>>,
 	pub(super) appservice_in_room_cache: RwLock<HashMap<OwnedRoomId, HashMap<String, bool>>>,
 	pub(super) lasttimelinecount_cache: Mutex<HashMap<OwnedRoomId, PduCount>>,
+	pub(super) logintoken_mutex: Mutex<()>,
 }
 
 #[derive(Deserialize)]
@@ -249,6 +274,8 @@ impl KeyValueDatabase {
 		let db_raw = Box::new(Self {
 			db: builder.clone(),
 			userid_password: builder.open_tree("userid_password")?,
+			userid_lockedat: builder.open_tree("userid_lockedat")?,
+			userid_authprovider: builder.open_tree("userid_authprovider")?,
 			userid_displayname: builder.open_tree("userid_displayname")?,
 			userid_avatarurl: builder.open_tree("userid_avatarurl")?,
 			userid_blurhash: builder.open_tree("userid_blurhash")?,
@@ -256,6 +283,22 @@ impl KeyValueDatabase {
 			userdeviceid_metadata: builder.open_tree("userdeviceid_metadata")?,
 			userid_devicelistversion: builder.open_tree("userid_devicelistversion")?,
 			token_userdeviceid: builder.open_tree("token_userdeviceid")?,
+			userdeviceid_tokenexpiresat: builder.open_tree("userdeviceid_tokenexpiresat")?,
+			userdeviceid_refreshtoken: builder.open_tree("userdeviceid_refreshtoken")?,
+			userdeviceid_previousrefreshtoken: builder.open_tree("userdeviceid_previousrefreshtoken")?,
+			refreshtoken_userdeviceid: builder.open_tree("refreshtoken_userdeviceid")?,
+			logintoken_expiresatuserid: builder.open_tree("logintoken_expiresatuserid")?,
+			expiresatlogintoken: builder.open_tree("expiresatlogintoken")?,
+			idpsubject_userid: builder.open_tree("idpsubject_userid")?,
+			registrationtoken_info: builder.open_tree("registrationtoken_info")?,
+			threepid_userid: builder.open_tree("threepid_userid")?,
+			userthreepid_timestamps: builder.open_tree("userthreepid_timestamps")?,
+			sid_validationsession: builder.open_tree("sid_validationsession")?,
+			clientsecretthreepid_sid: builder.open_tree("clientsecretthreepid_sid")?,
+			userid_acceptedpolicies: builder.open_tree("userid_acceptedpolicies")?,
+			userid_notifiedpolicies: builder.open_tree("userid_notifiedpolicies")?,
+			consenttoken_userid: builder.open_tree("consenttoken_userid")?,
+			userid_noticeroomid: builder.open_tree("userid_noticeroomid")?,
 			onetimekeyid_onetimekeys: builder.open_tree("onetimekeyid_onetimekeys")?,
 			userid_lastonetimekeyupdate: builder.open_tree("userid_lastonetimekeyupdate")?,
 			keychangeid_userid: builder.open_tree("keychangeid_userid")?,
@@ -280,6 +323,9 @@ impl KeyValueDatabase {
 			alias_roomid: builder.open_tree("alias_roomid")?,
 			aliasid_alias: builder.open_tree("aliasid_alias")?,
 			publicroomids: builder.open_tree("publicroomids")?,
+			networkroomid_appserviceid: builder.open_tree("networkroomid_appserviceid")?,
+
+			roomid_spacehierarchy: builder.open_tree("roomid_spacehierarchy")?,
 
 			threadid_userids: builder.open_tree("threadid_userids")?,
 
@@ -339,6 +385,7 @@ impl KeyValueDatabase {
 			servername_educount: builder.open_tree("servername_educount")?,
 			servernameevent_data: builder.open_tree("servernameevent_data")?,
 			servercurrentevent_data: builder.open_tree("servercurrentevent_data")?,
+			serverpolicy_entries: builder.open_tree("serverpolicy_entries")?,
 			id_appserviceregistrations: builder.open_tree("id_appserviceregistrations")?,
 			senderkey_pusher: builder.open_tree("senderkey_pusher")?,
 			global: builder.open_tree("global")?,
@@ -352,6 +399,7 @@ impl KeyValueDatabase {
 			our_real_users_cache: RwLock::new(HashMap::new()),
 			appservice_in_room_cache: RwLock::new(HashMap::new()),
 			lasttimelinecount_cache: Mutex::new(HashMap::new()),
+			logintoken_mutex: Mutex::new(()),
 		});
 
 		let db = Box::leak(db_raw);
@@ -386,6 +434,10 @@ impl KeyValueDatabase {
 			},
 		};
 
+		if let Err(e) = services().server_notices.create_notices_user().await {
+			error!("Could not create the server notices user: {e}");
+		}
+
 		services().sending.start_handler();
 
 		if config.allow_local_presence {
@@ -491,7 +543,7 @@ impl KeyValueDatabase {
 
 	#[tracing::instrument]
 	async fn start_cleanup_task() {
-		let timer_interval = Duration::from_secs(u64::from(services().globals.config.cleanup_second_interval));
+		let timer_interval = Duration::from_secs(u64::from(services().globals.config.get().cleanup_second_interval));
 
 		tokio::spawn(async move {
 			let mut i = interval(timer_interval);
@@ -532,7 +584,7 @@ impl KeyValueDatabase {
 	}
 
 	fn perform_cleanup() {
-		if !services().globals.config.rocksdb_periodic_cleanup {
+		if !services().globals.config.get().rocksdb_periodic_cleanup {
 			return;
 		}
 
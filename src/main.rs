@@ -57,6 +57,8 @@ static GLOBAL: hardened_malloc_rs::HardenedMalloc = hardened_malloc_rs::Hardened
 struct Server {
 	config: Config,
 
+	args: clap::Args,
+
 	runtime: tokio::runtime::Runtime,
 
 	tracing_reload_handle: reload::Handle<EnvFilter, Registry>,
@@ -67,6 +69,16 @@ struct Server {
 
 fn main() -> Result<(), Error> {
 	let args = clap::parse();
+
+	if args.print_default_config {
+		println!("{}", Config::defaults_json());
+		return Ok(());
+	}
+
+	if args.check_config {
+		check_config(&args);
+	}
+
 	let conduwuit: Server = init(args)?;
 
 	conduwuit
@@ -80,6 +92,13 @@ async fn async_main(server: &Server) -> Result<(), Error> {
 		return Err(Error::Error(format!("{error}")));
 	}
 
+	if let Some(command) = server.args.command.clone() {
+		let output = services().admin.run_offline_command(command).await?;
+		println!("{output}");
+
+		return Ok(());
+	}
+
 	if let Err(error) = run(server).await {
 		error!("Critical error running server: {error}");
 		return Err(Error::Error(format!("{error}")));
@@ -94,6 +113,18 @@ async fn async_main(server: &Server) -> Result<(), Error> {
 }
 
 async fn run(server: &Server) -> io::Result<()> {
+	if server.args.console {
+		services().admin.start_console();
+	}
+
+	#[cfg(unix)]
+	if let Some(path) = &server.config.admin_console_socket_path {
+		services().admin.start_console_socket(path).await?;
+	}
+
+	#[cfg(unix)]
+	tokio::spawn(reload_on_sighup());
+
 	let app = build(server).await?;
 	let (tx, rx) = oneshot::channel::<()>();
 	let handle = ServerHandle::new();
@@ -111,7 +142,12 @@ async fn run(server: &Server) -> io::Result<()> {
 
 	let mut join_set = JoinSet::new();
 	for addr in &addrs {
-		join_set.spawn(bind(*addr).handle(handle.clone()).serve(app.clone()));
+		join_set.spawn(
+			bind(*addr).handle(handle.clone()).serve(
+				app.clone()
+					.into_make_service_with_connect_info::<SocketAddr>(),
+			),
+		);
 	}
 
 	#[allow(clippy::let_underscore_untyped)] // error[E0658]: attributes on expressions are experimental
@@ -124,9 +160,7 @@ async fn run(server: &Server) -> io::Result<()> {
 	Ok(())
 }
 
-async fn run_tls_server(
-	server: &Server, app: axum::routing::IntoMakeService<Router>, handle: ServerHandle, addrs: Vec<SocketAddr>,
-) -> io::Result<()> {
+async fn run_tls_server(server: &Server, app: Router, handle: ServerHandle, addrs: Vec<SocketAddr>) -> io::Result<()> {
 	let tls = server.config.tls.as_ref().unwrap();
 
 	debug!(
@@ -154,7 +188,10 @@ async fn run_tls_server(
 				axum_server_dual_protocol::bind_dual_protocol(*addr, conf.clone())
 					.set_upgrade(false)
 					.handle(handle.clone())
-					.serve(app.clone()),
+					.serve(
+						app.clone()
+							.into_make_service_with_connect_info::<SocketAddr>(),
+					),
 			);
 		}
 	} else {
@@ -162,7 +199,10 @@ async fn run_tls_server(
 			join_set.spawn(
 				bind_rustls(*addr, conf.clone())
 					.handle(handle.clone())
-					.serve(app.clone()),
+					.serve(
+						app.clone()
+							.into_make_service_with_connect_info::<SocketAddr>(),
+					),
 			);
 		}
 	}
@@ -186,9 +226,7 @@ async fn run_tls_server(
 }
 
 #[cfg(unix)]
-async fn run_unix_socket_server(
-	server: &Server, app: axum::routing::IntoMakeService<Router>, rx: oneshot::Receiver<()>,
-) -> io::Result<()> {
+async fn run_unix_socket_server(server: &Server, app: Router, rx: oneshot::Receiver<()>) -> io::Result<()> {
 	let path = server.config.unix_socket_path.as_ref().unwrap();
 
 	if path.exists() {
@@ -214,7 +252,7 @@ async fn run_unix_socket_server(
 	#[cfg(feature = "systemd")]
 	let _ = sd_notify::notify(true, &[sd_notify::NotifyState::Ready]);
 	info!("Listening at {:?}", path);
-	let server = hyper::Server::builder(socket).serve(app);
+	let server = hyper::Server::builder(socket).serve(app.into_make_service());
 	let graceful = server.with_graceful_shutdown(async {
 		rx.await.ok();
 	});
@@ -268,6 +306,31 @@ async fn shutdown(handle: ServerHandle, tx: Sender<()>) -> Result<()> {
 	Ok(())
 }
 
+/// Reloads the config whenever we receive SIGHUP.
+#[cfg(unix)]
+async fn reload_on_sighup() {
+	let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+		Ok(hangup) => hangup,
+		Err(e) => {
+			error!("Failed to install SIGHUP handler, config reloading on SIGHUP is unavailable: {e}");
+			return;
+		},
+	};
+
+	while hangup.recv().await.is_some() {
+		info!("Received SIGHUP, reloading config...");
+		match services().reload_config() {
+			Ok(msg) => {
+				info!("{msg}");
+				services()
+					.admin
+					.send_message(ruma::events::room::message::RoomMessageEventContent::text_plain(msg));
+			},
+			Err(e) => error!("Failed to reload the config, keeping the running one: {e}"),
+		}
+	}
+}
+
 async fn stop(_server: &Server) -> io::Result<()> {
 	info!("Shutdown complete.");
 
@@ -281,7 +344,7 @@ async fn start(server: &Server) -> Result<(), Error> {
 	Ok(())
 }
 
-async fn build(server: &Server) -> io::Result<axum::routing::IntoMakeService<Router>> {
+async fn build(server: &Server) -> io::Result<Router> {
 	let base_middlewares = ServiceBuilder::new();
 	#[cfg(feature = "sentry_telemetry")]
 	let base_middlewares = base_middlewares.layer(sentry_tower::NewSentryLayer::<http::Request<_>>::new_from_top());
@@ -310,12 +373,11 @@ async fn build(server: &Server) -> io::Result<axum::routing::IntoMakeService<Rou
 	{
 		Ok(routes::routes(&server.config)
 			.layer(compression_layer(server))
-			.layer(middlewares)
-			.into_make_service())
+			.layer(middlewares))
 	}
 	#[cfg(not(any(feature = "zstd_compression", feature = "gzip_compression", feature = "brotli_compression")))]
 	{
-		Ok(routes::routes().layer(middlewares).into_make_service())
+		Ok(routes::routes().layer(middlewares))
 	}
 }
 
@@ -424,9 +486,29 @@ fn tracing_span<T>(request: &http::Request<T>) -> tracing::Span {
 	tracing::info_span!("handle", %path)
 }
 
+/// Loads the config with warnings logged to stderr and exits, with a failure
+/// exit code if the config has errors.
+fn check_config(args: &clap::Args) -> ! {
+	tracing_subscriber::fmt()
+		.with_writer(io::stderr)
+		.with_env_filter(EnvFilter::new("warn"))
+		.init();
+
+	match Config::new(args.config.clone()) {
+		Ok(_) => {
+			println!("Config is valid.");
+			std::process::exit(0);
+		},
+		Err(e) => {
+			eprintln!("{e}");
+			std::process::exit(1);
+		},
+	}
+}
+
 /// Non-async initializations
 fn init(args: clap::Args) -> Result<Server, Error> {
-	let config = Config::new(args.config)?;
+	let config = Config::new(args.config.clone())?;
 
 	#[cfg(feature = "sentry_telemetry")]
 	let sentry_guard = if config.sentry {
@@ -468,6 +550,8 @@ fn init(args: clap::Args) -> Result<Server, Error> {
 	Ok(Server {
 		config,
 
+		args,
+
 		runtime: tokio::runtime::Builder::new_multi_thread()
 			.enable_io()
 			.enable_time()
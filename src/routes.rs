@@ -3,11 +3,11 @@ use std::future::Future;
 use axum::{
 	extract::FromRequestParts,
 	response::IntoResponse,
-	routing::{any, get, on, post, MethodFilter},
+	routing::{any, delete, get, on, post, put, MethodFilter},
 	Router,
 };
 use conduit::{
-	api::{client_server, server_server},
+	api::{admin, client_server, server_server},
 	Config, Error, Result, Ruma, RumaResponse,
 };
 use http::{Method, Uri};
@@ -19,8 +19,22 @@ pub fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::get_supported_versions_route)
 		.ruma_route(client_server::get_register_available_route)
 		.ruma_route(client_server::register_route)
+		.ruma_route(client_server::check_registration_token_validity_route)
 		.ruma_route(client_server::get_login_types_route)
 		.ruma_route(client_server::login_route)
+		.ruma_route(client_server::refresh_token_route)
+		.ruma_route(client_server::get_login_token_route)
+		.ruma_route(client_server::sso_login_route)
+		.ruma_route(client_server::sso_login_with_provider_route)
+		.route("/_conduwuit/oidc/callback", get(client_server::sso_callback_route))
+		.route(
+			"/_matrix/client/v3/auth/m.login.sso/fallback/web",
+			get(client_server::sso_fallback_route),
+		)
+		.route(
+			"/_matrix/client/r0/auth/m.login.sso/fallback/web",
+			get(client_server::sso_fallback_route),
+		)
 		.ruma_route(client_server::whoami_route)
 		.ruma_route(client_server::logout_route)
 		.ruma_route(client_server::logout_all_route)
@@ -29,6 +43,17 @@ pub fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::third_party_route)
 		.ruma_route(client_server::request_3pid_management_token_via_email_route)
 		.ruma_route(client_server::request_3pid_management_token_via_msisdn_route)
+		.ruma_route(client_server::request_password_change_token_via_email_route)
+		.ruma_route(client_server::add_3pid_route)
+		.ruma_route(client_server::delete_3pid_route)
+		.route(
+			"/_conduwuit/consent",
+			get(client_server::get_consent_route).post(client_server::accept_consent_route),
+		)
+		.route(
+			"/_conduwuit/email/submit_token",
+			get(client_server::submit_email_token_route).post(client_server::submit_email_token_json_route),
+		)
 		.ruma_route(client_server::get_capabilities_route)
 		.ruma_route(client_server::get_pushrules_all_route)
 		.ruma_route(client_server::set_pushrule_route)
@@ -93,6 +118,7 @@ pub fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::get_room_visibility_route)
 		.ruma_route(client_server::get_public_rooms_route)
 		.ruma_route(client_server::get_public_rooms_filtered_route)
+		.ruma_route(client_server::set_room_visibility_for_appservice_network_route)
 		.ruma_route(client_server::search_users_route)
 		.ruma_route(client_server::get_member_events_route)
 		.ruma_route(client_server::get_protocols_route)
@@ -181,12 +207,53 @@ pub fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::get_relating_events_with_rel_type_route)
 		.ruma_route(client_server::get_relating_events_route)
 		.ruma_route(client_server::get_hierarchy_route)
+		.ruma_route(client_server::get_room_summary_route)
+		.route(
+			"/_matrix/client/v1/summary/:room_id_or_alias",
+			get(client_server::get_room_summary_v1_route),
+		)
         .ruma_route(client_server::get_mutual_rooms_route)
         .ruma_route(client_server::well_known_support)
         .ruma_route(client_server::well_known_client)
         .route("/_conduwuit/server_version", get(client_server::conduwuit_server_version))
 		.route("/_matrix/client/r0/rooms/:room_id/initialSync", get(initial_sync))
 		.route("/_matrix/client/v3/rooms/:room_id/initialSync", get(initial_sync))
+		.route("/_conduwuit/admin/v1/command", post(admin::run_command_route))
+		.route(
+			"/_conduwuit/admin/v1/users",
+			get(admin::list_users_route).post(admin::create_user_route),
+		)
+		.route("/_conduwuit/admin/v1/users/:user_id", get(admin::get_user_route))
+		.route(
+			"/_conduwuit/admin/v1/users/:user_id/reset_password",
+			post(admin::reset_password_route),
+		)
+		.route(
+			"/_conduwuit/admin/v1/users/:user_id/deactivate",
+			post(admin::deactivate_user_route),
+		)
+		.route("/_conduwuit/admin/v1/users/:user_id/rooms", get(admin::get_user_rooms_route))
+		.route("/_conduwuit/admin/v1/lockouts", get(admin::list_lockouts_route))
+		.route("/_conduwuit/admin/v1/lockouts/:target", delete(admin::clear_lockout_route))
+		.route("/_conduwuit/admin/v1/rooms", get(admin::list_rooms_route))
+		.route("/_conduwuit/admin/v1/rooms/:room_id", get(admin::get_room_route))
+		.route(
+			"/_conduwuit/admin/v1/media/delete_past_remote",
+			post(admin::delete_past_remote_media_route),
+		)
+		.route(
+			"/_conduwuit/admin/v1/media/:server_name/:media_id",
+			delete(admin::delete_media_route),
+		)
+		.route("/_conduwuit/admin/v1/federation/incoming", get(admin::incoming_federation_route))
+		.route(
+			"/_conduwuit/admin/v1/federation/rooms/:room_id",
+			put(admin::set_room_federation_route),
+		)
+		.route("/_conduwuit/admin/v1/server", get(admin::get_server_info_route))
+		.route("/_conduwuit/admin/v1/server/memory_usage", get(admin::get_memory_usage_route))
+		.route("/_conduwuit/admin/v1/server/clear_caches", post(admin::clear_caches_route))
+		.route("/_conduwuit/admin/v1/server/backup", post(admin::backup_database_route))
 		.route("/client/server.json", get(client_server::syncv3_client_server_json))
 		.route("/", get(it_works))
 		.fallback(not_found);
@@ -0,0 +1,264 @@
+//! Local admin console, running admin room commands read from stdin or a
+//! control UNIX socket, and the offline commands of the command line.
+
+#[cfg(unix)]
+use std::{
+	fs::{DirBuilder, Permissions},
+	os::unix::fs::{DirBuilderExt as _, PermissionsExt as _},
+	path::Path,
+};
+use std::{io, sync::Arc};
+
+use ruma::{OwnedUserId, UserId};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt as _, AsyncWrite, AsyncWriteExt as _, BufReader, Lines};
+use tracing::{error, info, warn};
+
+use super::{user::create_user, Service};
+use crate::{
+	api::client_server::AUTO_GEN_PASSWORD_LENGTH, clap::Command, service::server_notices, services, utils, Error,
+	Result,
+};
+
+const CONSOLE_HELP: &str = "conduwuit admin console. Type admin room commands without the `@conduit:server.name:` \
+                            prefix, e.g. `users list`, or `help` for all commands.\nEnd a command with ``` to give it \
+                            a body, which ends with a line of ```.\n";
+
+impl Service {
+	/// Runs admin commands read from stdin until it is closed.
+	pub fn start_console(self: &Arc<Self>) {
+		let self2 = Arc::clone(self);
+		tokio::spawn(async move {
+			if let Err(e) = self2
+				.serve_console(BufReader::new(tokio::io::stdin()), tokio::io::stdout())
+				.await
+			{
+				error!("Admin console on stdin failed: {e}");
+			}
+		});
+	}
+
+	/// Listens on a UNIX socket only the user running conduwuit can connect to
+	/// and runs the admin commands read from each connection.
+	#[cfg(unix)]
+	pub async fn start_console_socket(self: &Arc<Self>, path: &Path) -> io::Result<()> {
+		if path.exists() {
+			warn!(
+				"Admin console socket path {:#?} already exists (unclean shutdown?), attempting to remove it.",
+				path.display()
+			);
+			tokio::fs::remove_file(path).await?;
+		}
+
+		// Bind in a directory only we can access and move the socket into place
+		// once its permissions are restricted, so nobody can connect before
+		let private_dir =
+			path.with_file_name(format!(".{}.tmp", path.file_name().unwrap_or_default().to_string_lossy()));
+		if private_dir.exists() {
+			tokio::fs::remove_dir_all(&private_dir).await?;
+		}
+		DirBuilder::new().mode(0o700).create(&private_dir)?;
+
+		let private_path = private_dir.join("admin.sock");
+		let listener = tokio::net::UnixListener::bind(&private_path)?;
+		tokio::fs::set_permissions(&private_path, Permissions::from_mode(0o600)).await?;
+		tokio::fs::rename(&private_path, path).await?;
+		tokio::fs::remove_dir(&private_dir).await?;
+		info!("Admin console listening at {:?}", path);
+
+		let self2 = Arc::clone(self);
+		tokio::spawn(async move {
+			loop {
+				let stream = match listener.accept().await {
+					Ok((stream, _)) => stream,
+					Err(e) => {
+						error!("Failed to accept admin console connection: {e}");
+						continue;
+					},
+				};
+
+				let self3 = Arc::clone(&self2);
+				tokio::spawn(async move {
+					let (reader, writer) = stream.into_split();
+					if let Err(e) = self3.serve_console(BufReader::new(reader), writer).await {
+						warn!("Admin console connection failed: {e}");
+					}
+				});
+			}
+		});
+
+		Ok(())
+	}
+
+	async fn serve_console<R, W>(&self, reader: R, mut writer: W) -> io::Result<()>
+	where
+		R: AsyncBufRead + Unpin,
+		W: AsyncWrite + Unpin,
+	{
+		writer.write_all(CONSOLE_HELP.as_bytes()).await?;
+		writer.write_all(b"> ").await?;
+		writer.flush().await?;
+
+		let mut lines = reader.lines();
+		while let Some(command) = read_command(&mut lines).await? {
+			if command.is_empty() {
+				writer.write_all(b"> ").await?;
+				writer.flush().await?;
+				continue;
+			}
+
+			info!("Running admin command from the admin console: {command}");
+			let output = self.run_command(&command).await;
+
+			writer.write_all(output.body().as_bytes()).await?;
+			writer.write_all(b"\n> ").await?;
+			writer.flush().await?;
+		}
+
+		Ok(())
+	}
+
+	/// Runs a command given on the command line instead of starting the
+	/// server, returning its output.
+	pub async fn run_offline_command(&self, command: Command) -> Result<String> {
+		match command {
+			Command::CreateUser {
+				username,
+				password,
+			} => {
+				let user_id = parse_local_user(&username)?;
+				if user_id.is_historical() {
+					return Err(Error::Error(format!("User ID {user_id} is not allowed due to historical")));
+				}
+				if services().users.exists(&user_id)? {
+					return Err(Error::Error(format!("User {user_id} already exists")));
+				}
+
+				if let Some(password) = &password {
+					services().users.check_password_policy(&user_id, password)?;
+				}
+				let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+
+				create_user(&user_id, &password).await?;
+
+				Ok(format!("Created user {user_id} with password: {password}"))
+			},
+			Command::ResetPassword {
+				username,
+				password,
+			} => {
+				let user_id = parse_local_user(&username)?;
+				if !services().users.exists(&user_id)? || server_notices::is_server_account(&user_id) {
+					return Err(Error::Error(format!("User {user_id} does not exist")));
+				}
+
+				if let Some(password) = &password {
+					services().users.check_password_policy(&user_id, password)?;
+				}
+				let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+
+				services()
+					.users
+					.set_password(&user_id, Some(password.as_str()))?;
+
+				Ok(format!("Password of {user_id} reset to: {password}"))
+			},
+			Command::MakeAdmin {
+				username,
+			} => {
+				let user_id = parse_local_user(&username)?;
+				if !services().users.exists(&user_id)? || services().users.is_deactivated(&user_id)? {
+					return Err(Error::Error(format!("User {user_id} does not exist")));
+				}
+				if services().users.is_admin(&user_id)? {
+					return Err(Error::Error(format!("User {user_id} is already an admin")));
+				}
+
+				let displayname = services()
+					.users
+					.displayname(&user_id)?
+					.unwrap_or_else(|| user_id.localpart().to_owned());
+
+				self.make_user_admin(&user_id, displayname).await?;
+
+				Ok(format!("{user_id} is now an admin"))
+			},
+		}
+	}
+}
+
+/// Reads the next command, with its body if the command line ends with a code
+/// block, like in the admin room. Blank lines are read as empty commands.
+async fn read_command<R: AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> io::Result<Option<String>> {
+	let Some(line) = lines.next_line().await? else {
+		return Ok(None);
+	};
+
+	let mut command = line.trim().to_owned();
+	if let Some(command_line) = command.strip_suffix("```") {
+		command = format!("{}\n```", command_line.trim_end());
+		while let Some(body_line) = lines.next_line().await? {
+			command.push('\n');
+			command.push_str(&body_line);
+			if body_line.trim() == "```" {
+				break;
+			}
+		}
+	}
+
+	Ok(Some(command))
+}
+
+/// Accepts a localpart or a full user ID of this server.
+fn parse_local_user(username: &str) -> Result<OwnedUserId> {
+	let user_id = UserId::parse_with_server_name(username.to_lowercase(), services().globals.server_name())
+		.map_err(|e| Error::Error(format!("The supplied username is not a valid username: {e}")))?;
+
+	if user_id.server_name() != services().globals.server_name() {
+		return Err(Error::Error(format!("User {user_id} does not belong to this server")));
+	}
+
+	Ok(user_id)
+}
+
+#[cfg(test)]
+mod tests {
+	use tokio::io::AsyncBufReadExt as _;
+
+	use super::read_command;
+
+	#[tokio::test]
+	async fn commands() {
+		let mut lines = b"  users list  \n\nrooms list 2\n".as_slice().lines();
+
+		assert_eq!(read_command(&mut lines).await.unwrap().as_deref(), Some("users list"));
+		assert_eq!(read_command(&mut lines).await.unwrap().as_deref(), Some(""));
+		assert_eq!(read_command(&mut lines).await.unwrap().as_deref(), Some("rooms list 2"));
+		assert_eq!(read_command(&mut lines).await.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn code_blocks() {
+		let mut lines = b"users deactivate-all ```\n@alice:example.com\n@bob:example.com\n```\nusers list\n"
+			.as_slice()
+			.lines();
+
+		assert_eq!(
+			read_command(&mut lines).await.unwrap().as_deref(),
+			Some("users deactivate-all\n```\n@alice:example.com\n@bob:example.com\n```")
+		);
+		assert_eq!(read_command(&mut lines).await.unwrap().as_deref(), Some("users list"));
+	}
+
+	#[tokio::test]
+	async fn unterminated_code_block() {
+		let mut lines = b"users deactivate-all```\n@alice:example.com"
+			.as_slice()
+			.lines();
+
+		assert_eq!(
+			read_command(&mut lines).await.unwrap().as_deref(),
+			Some("users deactivate-all\n```\n@alice:example.com")
+		);
+		assert_eq!(read_command(&mut lines).await.unwrap(), None);
+	}
+}
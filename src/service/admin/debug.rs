@@ -185,7 +185,7 @@ pub(crate) async fn process(command: DebugCommand, body: Vec<&str>) -> Result<Ro
 			event_id,
 			server,
 		} => {
-			if !services().globals.config.allow_federation {
+			if !services().globals.config.get().allow_federation {
 				return Ok(RoomMessageEventContent::text_plain(
 					"Federation is disabled on this homeserver.",
 				));
@@ -372,7 +372,7 @@ pub(crate) async fn process(command: DebugCommand, body: Vec<&str>) -> Result<Ro
 			reset,
 		} => {
 			if reset {
-				let old_filter_layer = match EnvFilter::try_new(&services().globals.config.log) {
+				let old_filter_layer = match EnvFilter::try_new(&services().globals.config.get().log) {
 					Ok(s) => s,
 					Err(e) => {
 						return Ok(RoomMessageEventContent::text_plain(format!(
@@ -389,7 +389,7 @@ pub(crate) async fn process(command: DebugCommand, body: Vec<&str>) -> Result<Ro
 					Ok(()) => {
 						return Ok(RoomMessageEventContent::text_plain(format!(
 							"Successfully changed log level back to config value {}",
-							services().globals.config.log
+							services().globals.config.get().log
 						)));
 					},
 					Err(e) => {
@@ -1,10 +1,15 @@
-use std::{collections::BTreeMap, fmt::Write as _};
+use std::{collections::BTreeMap, fmt::Write as _, time::Duration};
 
 use clap::Subcommand;
 use ruma::{events::room::message::RoomMessageEventContent, RoomId, ServerName};
 use tokio::sync::RwLock;
 
-use crate::{services, utils::HtmlEscape, Result};
+use crate::{
+	service::{sending::TransactionStatus, server_policy::PolicyList},
+	services,
+	utils::HtmlEscape,
+	Result,
+};
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Subcommand)]
@@ -46,6 +51,54 @@ pub(crate) enum FederationCommand {
 	FetchSupportWellKnown {
 		server_name: Box<ServerName>,
 	},
+
+	/// - List the servers we are sending to, with their queued events, failures
+	///   and last successful transaction
+	Destinations,
+
+	/// - Reset the backoff of a failing server and send its queued events now
+	Retry {
+		server_name: Box<ServerName>,
+	},
+
+	/// - Drop all events queued for a server
+	///
+	/// Use this for servers that are gone for good; their events are never
+	/// sent.
+	PurgeQueue {
+		server_name: Box<ServerName>,
+	},
+
+	/// - List the rooms we share with a server
+	RoomsWith {
+		server_name: Box<ServerName>,
+	},
+
+	/// - List the server policy lists
+	Policy,
+
+	/// - Add a server name pattern to a server policy list
+	///
+	/// Patterns are server names where `*` matches any number of characters
+	/// and `?` a single one, e.g. `*.example.com`. Like room server ACLs they
+	/// match server names without their port. Servers on the deny list
+	/// can't federate with us, we don't download media from servers on the
+	/// media list, and if `federation_allowlist_only` is enabled we only
+	/// federate with servers on the allow list and the `trusted_servers`.
+	/// Changes apply immediately, events queued for newly denied servers are
+	/// dropped.
+	PolicyAdd {
+		#[arg(value_enum)]
+		list: PolicyList,
+		pattern: String,
+	},
+
+	/// - Remove a server name pattern from a server policy list
+	PolicyRemove {
+		#[arg(value_enum)]
+		list: PolicyList,
+		pattern: String,
+	},
 }
 
 pub(crate) async fn process(command: FederationCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -168,5 +221,134 @@ pub(crate) async fn process(command: FederationCommand, body: Vec<&str>) -> Resu
 				),
 			))
 		},
+		FederationCommand::Destinations => {
+			let destinations = services().sending.destinations()?;
+			let mut msg = format!("{} destinations:\n", destinations.len());
+
+			for destination in destinations {
+				let status = match destination.status {
+					None => "idle".to_owned(),
+					Some(TransactionStatus::Running) => "sending".to_owned(),
+					Some(TransactionStatus::Retrying(failures)) => format!("retrying after {failures} failures"),
+					Some(TransactionStatus::Failed(failures, time)) => {
+						format!("{failures} failures, last {} ago", format_duration(time.elapsed()))
+					},
+				};
+				let last_success = destination
+					.last_success
+					.map_or_else(|| "never".to_owned(), |time| format!("{} ago", format_duration(time.elapsed())));
+
+				writeln!(
+					msg,
+					"{}: {} queued, {} in transaction, {status}, last success {last_success}",
+					destination.server, destination.queued, destination.active
+				)
+				.expect("write to String works");
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+		FederationCommand::Retry {
+			server_name,
+		} => {
+			if services().sending.retry_destination(&server_name)? {
+				Ok(RoomMessageEventContent::text_plain(format!(
+					"Reset the backoff of {server_name}, retrying now."
+				)))
+			} else {
+				Ok(RoomMessageEventContent::text_plain(format!(
+					"{server_name} was not backing off, flushed its queue."
+				)))
+			}
+		},
+		FederationCommand::PurgeQueue {
+			server_name,
+		} => {
+			let count = services().sending.purge_destination(&server_name)?;
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"Dropped {count} events queued for {server_name}."
+			)))
+		},
+		FederationCommand::RoomsWith {
+			server_name,
+		} => {
+			let rooms = services()
+				.rooms
+				.state_cache
+				.server_rooms(&server_name)
+				.filter_map(Result::ok)
+				.collect::<Vec<_>>();
+
+			let mut msg = format!("{} rooms shared with {server_name}:\n", rooms.len());
+			for room_id in rooms {
+				let name = services()
+					.rooms
+					.state_accessor
+					.get_name(&room_id)?
+					.unwrap_or_else(|| room_id.to_string());
+				writeln!(msg, "{room_id}\tName: {name}").expect("write to String works");
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+		FederationCommand::Policy => {
+			let mut msg = String::new();
+			if services().globals.config.get().federation_allowlist_only {
+				msg.push_str("Only federating with servers on the allow list.\n");
+			}
+
+			for list in [PolicyList::Deny, PolicyList::Allow, PolicyList::Media] {
+				let patterns = services().server_policy.list(list);
+				writeln!(msg, "{} list ({} patterns):", list.as_str(), patterns.len()).expect("write to String works");
+				for pattern in patterns {
+					writeln!(msg, "- {pattern}").expect("write to String works");
+				}
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+		FederationCommand::PolicyAdd {
+			list,
+			pattern,
+		} => {
+			if services().server_policy.add(list, &pattern)? {
+				Ok(RoomMessageEventContent::text_plain(format!(
+					"Added {pattern} to the {} list.",
+					list.as_str()
+				)))
+			} else {
+				Ok(RoomMessageEventContent::text_plain(format!(
+					"The {} list already contains {pattern}.",
+					list.as_str()
+				)))
+			}
+		},
+		FederationCommand::PolicyRemove {
+			list,
+			pattern,
+		} => {
+			if services().server_policy.remove(list, &pattern)? {
+				Ok(RoomMessageEventContent::text_plain(format!(
+					"Removed {pattern} from the {} list.",
+					list.as_str()
+				)))
+			} else {
+				Ok(RoomMessageEventContent::text_plain(format!(
+					"The {} list does not contain {pattern}.",
+					list.as_str()
+				)))
+			}
+		},
+	}
+}
+
+fn format_duration(duration: Duration) -> String {
+	let secs = duration.as_secs();
+	if secs < 60 {
+		format!("{secs}s")
+	} else if secs < 60 * 60 {
+		format!("{}m{}s", secs / 60, secs % 60)
+	} else {
+		format!("{}h{}m", secs / 3600, secs % 3600 / 60)
 	}
 }
@@ -0,0 +1,261 @@
+use std::{
+	fmt::Write as _,
+	sync::{atomic::Ordering, Arc},
+	time::{Duration, Instant},
+};
+
+use clap::Subcommand;
+use ruma::{
+	events::{
+		relation::Replacement,
+		room::message::{FileMessageEventContent, MessageType, Relation, RoomMessageEventContent},
+	},
+	EventId, RoomId,
+};
+use tokio::task::{AbortHandle, JoinError, JoinHandle};
+use tracing::error;
+
+use super::Service;
+use crate::{api::client_server::MXC_LENGTH, service::server_notices, services, utils, Result};
+
+/// How often the reply to a running command is updated with its progress
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Output longer than this is attached as a file instead of sent as message
+const MAX_MESSAGE_LENGTH: usize = 32_000;
+
+tokio::task_local! {
+	/// ID of the job the current task runs
+	static JOB_ID: u64;
+}
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Subcommand)]
+pub(crate) enum JobCommand {
+	/// - List the admin commands that are still running
+	List,
+
+	/// - Cancel a running admin command
+	Cancel {
+		/// ID of the job, as shown by `jobs list`
+		job_id: u64,
+	},
+}
+
+/// An admin command running in the background
+pub(crate) struct Job {
+	command: String,
+	started: Instant,
+	progress: Option<String>,
+	abort: AbortHandle,
+}
+
+pub(crate) struct SpawnedJob {
+	id: u64,
+	handle: JoinHandle<RoomMessageEventContent>,
+}
+
+pub(crate) async fn process(command: JobCommand, _body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	match command {
+		JobCommand::List => {
+			let jobs = services().admin.jobs.lock().unwrap();
+
+			let mut msg = format!("{} running job(s):\n", jobs.len());
+			for (id, job) in jobs.iter() {
+				write!(
+					msg,
+					"\n#{id} {} (running for {}s)",
+					job.command,
+					job.started.elapsed().as_secs()
+				)
+				.expect("should be able to write to string buffer");
+				if let Some(progress) = &job.progress {
+					write!(msg, ": {progress}").expect("should be able to write to string buffer");
+				}
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+		JobCommand::Cancel {
+			job_id,
+		} => {
+			if JOB_ID.try_with(|&id| id == job_id).unwrap_or(false) {
+				return Ok(RoomMessageEventContent::text_plain("A job can't cancel itself."));
+			}
+
+			match services().admin.jobs.lock().unwrap().get(&job_id) {
+				Some(job) => {
+					job.abort.abort();
+					Ok(RoomMessageEventContent::text_plain(format!("Cancelling job #{job_id}.")))
+				},
+				None => Ok(RoomMessageEventContent::text_plain(format!(
+					"There is no running job #{job_id}."
+				))),
+			}
+		},
+	}
+}
+
+/// Reports the progress of the admin command running in the current task,
+/// shown by `jobs list` and in the reply to long-running commands.
+pub(crate) fn set_progress(progress: impl Into<String>) {
+	_ = JOB_ID.try_with(|id| {
+		if let Some(job) = services().admin.jobs.lock().unwrap().get_mut(id) {
+			job.progress = Some(progress.into());
+		}
+	});
+}
+
+impl Service {
+	/// Starts processing an admin command in the background.
+	pub(super) fn spawn_job(&self, room_message: String) -> SpawnedJob {
+		let id = self.next_job_id.fetch_add(1, Ordering::Relaxed);
+		let command = command_line(&room_message);
+
+		// Holding the lock while spawning makes sure the job is registered before
+		// it can report progress
+		let mut jobs = self.jobs.lock().unwrap();
+		let handle =
+			tokio::spawn(JOB_ID.scope(id, async move { services().admin.process_admin_message(room_message).await }));
+
+		jobs.insert(
+			id,
+			Job {
+				command,
+				started: Instant::now(),
+				progress: None,
+				abort: handle.abort_handle(),
+			},
+		);
+
+		SpawnedJob {
+			id,
+			handle,
+		}
+	}
+
+	/// Waits for a job to finish and returns its output.
+	pub(super) async fn finish_job(&self, job: SpawnedJob) -> RoomMessageEventContent {
+		let output = job.handle.await;
+		self.jobs.lock().unwrap().remove(&job.id);
+
+		job_output(job.id, output)
+	}
+
+	/// Replies to the admin command with the output of its job. Commands that
+	/// take a while get a reply that is edited with their progress.
+	pub(super) async fn reply_to_job(&self, mut job: SpawnedJob, conduit_room: &RoomId, reply_id: Arc<EventId>) {
+		let mut status_event: Option<Arc<EventId>> = None;
+		let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+		interval.tick().await;
+
+		let output = loop {
+			tokio::select! {
+				output = &mut job.handle => {
+					self.jobs.lock().unwrap().remove(&job.id);
+					break job_output(job.id, output);
+				},
+				_ = interval.tick() => {
+					let progress = self
+						.jobs
+						.lock()
+						.unwrap()
+						.get(&job.id)
+						.and_then(|job| job.progress.clone());
+
+					let status = RoomMessageEventContent::text_plain(match progress {
+						Some(progress) => format!("Job #{} is still running: {progress}", job.id),
+						None => format!("Job #{} is still running, use `jobs cancel {}` to cancel it.", job.id, job.id),
+					});
+
+					match &status_event {
+						Some(event_id) => {
+							_ = self
+								.send_to_admin_room(conduit_room, replacement(status, event_id), None)
+								.await;
+						},
+						None => {
+							status_event = self
+								.send_to_admin_room(conduit_room, status, Some(Arc::clone(&reply_id)))
+								.await
+								.ok();
+						},
+					}
+				},
+			}
+		};
+
+		let output = attach_long_output(output).await;
+
+		_ = match status_event {
+			Some(event_id) => {
+				self.send_to_admin_room(conduit_room, replacement(output, &event_id), None)
+					.await
+			},
+			None => {
+				self.send_to_admin_room(conduit_room, output, Some(reply_id))
+					.await
+			},
+		};
+	}
+}
+
+fn job_output(id: u64, output: Result<RoomMessageEventContent, JoinError>) -> RoomMessageEventContent {
+	match output {
+		Ok(output) => output,
+		Err(e) if e.is_cancelled() => RoomMessageEventContent::text_plain(format!("Job #{id} was cancelled.")),
+		Err(e) => RoomMessageEventContent::text_plain(format!("Job #{id} failed: {e}")),
+	}
+}
+
+/// The first line of an admin room message without the server user prefix
+fn command_line(room_message: &str) -> String {
+	let command_line = room_message.lines().next().unwrap_or_default();
+
+	command_line
+		.split_once(": ")
+		.map_or(command_line, |(_, command)| command)
+		.to_owned()
+}
+
+/// Edits an earlier message of the server user to the given content
+fn replacement(content: RoomMessageEventContent, event_id: &EventId) -> RoomMessageEventContent {
+	let mut edit = RoomMessageEventContent::text_plain(format!("* {}", content.body()));
+	edit.relates_to = Some(Relation::Replacement(Replacement::new(event_id.to_owned(), content.into())));
+
+	edit
+}
+
+/// Uploads output too long for a message as a text file and returns a message
+/// with the file instead.
+async fn attach_long_output(content: RoomMessageEventContent) -> RoomMessageEventContent {
+	if content.body().len() <= MAX_MESSAGE_LENGTH {
+		return content;
+	}
+
+	let mxc = format!(
+		"mxc://{}/{}",
+		services().globals.server_name(),
+		utils::random_string(MXC_LENGTH)
+	);
+
+	if let Err(e) = services()
+		.media
+		.create(
+			Some(server_notices::server_user()),
+			mxc.clone(),
+			Some("inline; filename=output.txt"),
+			Some("text/plain"),
+			content.body().as_bytes(),
+		)
+		.await
+	{
+		error!("Failed to upload long admin command output: {e}");
+		return content;
+	}
+
+	RoomMessageEventContent::new(MessageType::File(FileMessageEventContent::plain(
+		"output.txt".to_owned(),
+		mxc.into(),
+	)))
+}
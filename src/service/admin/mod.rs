@@ -1,4 +1,7 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+	collections::BTreeMap,
+	sync::{atomic::AtomicU64, Arc, Mutex as StdMutex},
+};
 
 use clap::Parser;
 use regex::Regex;
@@ -18,7 +21,7 @@ use ruma::{
 			power_levels::RoomPowerLevelsEventContent,
 			topic::RoomTopicEventContent,
 		},
-		TimelineEventType,
+		StateEventType, TimelineEventType,
 	},
 	EventId, MxcUri, OwnedRoomAliasId, OwnedRoomId, RoomAliasId, RoomId, RoomVersionId, ServerName, UserId,
 };
@@ -26,19 +29,27 @@ use serde_json::value::to_raw_value;
 use tokio::sync::Mutex;
 use tracing::{error, warn};
 
+use self::jobs::Job;
 use super::pdu::PduBuilder;
 use crate::{
-	service::admin::{
-		appservice::AppserviceCommand, debug::DebugCommand, federation::FederationCommand, media::MediaCommand,
-		room::RoomCommand, server::ServerCommand, user::UserCommand,
+	service::{
+		admin::{
+			appservice::AppserviceCommand, debug::DebugCommand, federation::FederationCommand, jobs::JobCommand,
+			media::MediaCommand, registration_token::RegistrationTokenCommand, room::RoomCommand,
+			server::ServerCommand, user::UserCommand,
+		},
+		server_notices,
 	},
 	services, Error, Result,
 };
 
 pub(crate) mod appservice;
+pub(crate) mod console;
 pub(crate) mod debug;
 pub(crate) mod federation;
+pub(crate) mod jobs;
 pub(crate) mod media;
+pub(crate) mod registration_token;
 pub(crate) mod room;
 pub(crate) mod room_alias;
 pub(crate) mod room_directory;
@@ -46,7 +57,7 @@ pub(crate) mod room_moderation;
 pub(crate) mod server;
 pub(crate) mod user;
 
-const PAGE_SIZE: usize = 100;
+pub(crate) const PAGE_SIZE: usize = 100;
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Parser)]
@@ -76,6 +87,14 @@ enum AdminCommand {
 	/// - Commands for managing media
 	Media(MediaCommand),
 
+	#[command(subcommand)]
+	/// - Commands for managing registration tokens
+	RegistrationTokens(RegistrationTokenCommand),
+
+	#[command(subcommand)]
+	/// - Commands for managing running admin commands
+	Jobs(JobCommand),
+
 	#[command(subcommand)]
 	// TODO: should i split out debug commands to a separate thing? the
 	// debug commands seem like they could fit in the other categories fine
@@ -93,6 +112,8 @@ pub enum AdminRoomEvent {
 pub struct Service {
 	pub sender: loole::Sender<AdminRoomEvent>,
 	receiver: Mutex<loole::Receiver<AdminRoomEvent>>,
+	jobs: StdMutex<BTreeMap<u64, Job>>,
+	next_job_id: AtomicU64,
 }
 
 impl Service {
@@ -101,6 +122,8 @@ impl Service {
 		Arc::new(Self {
 			sender,
 			receiver: Mutex::new(receiver),
+			jobs: StdMutex::new(BTreeMap::new()),
+			next_job_id: AtomicU64::new(1),
 		})
 	}
 
@@ -116,79 +139,28 @@ impl Service {
 
 	async fn handler(&self) -> Result<()> {
 		let receiver = self.receiver.lock().await;
-		// TODO: Use futures when we have long admin commands
-		//let mut futures = FuturesUnordered::new();
-
-		let conduit_user = UserId::parse(format!("@conduit:{}", services().globals.server_name()))
-			.expect("@conduit:server_name is valid");
 
 		if let Ok(Some(conduit_room)) = Self::get_admin_room() {
 			loop {
-				tokio::select! {
-						event = receiver.recv_async() => {
-							match event {
-								Ok(event) => {
-									let (mut message_content, reply) = match event {
-										AdminRoomEvent::SendMessage(content) => (content, None),
-										AdminRoomEvent::ProcessMessage(room_message, reply_id) => {
-											(self.process_admin_message(room_message).await, Some(reply_id))
-										}
-									};
-
-									let mutex_state = Arc::clone(
-										services().globals
-											.roomid_mutex_state
-											.write()
-											.await
-											.entry(conduit_room.clone())
-											.or_default(),
-									);
-
-									let state_lock = mutex_state.lock().await;
-
-									if let Some(reply) = reply {
-										message_content.relates_to = Some(Reply { in_reply_to: InReplyTo { event_id: reply.into() } });
-									}
-
-									if let Err(e) = services().rooms.timeline.build_and_append_pdu(
-										PduBuilder {
-										  event_type: TimelineEventType::RoomMessage,
-										  content: to_raw_value(&message_content)
-											  .expect("event is valid, we just created it"),
-										  unsigned: None,
-										  state_key: None,
-										  redacts: None,
-										},
-										&conduit_user,
-										&conduit_room,
-										&state_lock)
-									  .await {
-										error!("Failed to build and append admin room response PDU: \"{e}\"");
-
-										let error_room_message = RoomMessageEventContent::text_plain(format!("Failed to build and append admin room PDU: \"{e}\"\n\nThe original admin command may have finished successfully, but we could not return the output."));
-
-										services().rooms.timeline.build_and_append_pdu(
-											PduBuilder {
-											  event_type: TimelineEventType::RoomMessage,
-											  content: to_raw_value(&error_room_message)
-												  .expect("event is valid, we just created it"),
-											  unsigned: None,
-											  state_key: None,
-											  redacts: None,
-											},
-											&conduit_user,
-											&conduit_room,
-											&state_lock)
-										  .await?;
-									}
-									drop(state_lock);
-							}
-							Err(e) => {
-								 // generally shouldn't happen
-								 error!("Failed to receive admin room event from channel: {e}");
-							}
-						}
-					}
+				match receiver.recv_async().await {
+					Ok(AdminRoomEvent::SendMessage(content)) => {
+						_ = self.send_to_admin_room(&conduit_room, content, None).await;
+					},
+					Ok(AdminRoomEvent::ProcessMessage(room_message, reply_id)) => {
+						// Commands run as jobs, so slow ones don't hold up the others
+						let job = self.spawn_job(room_message);
+						let conduit_room = conduit_room.clone();
+						tokio::spawn(async move {
+							services()
+								.admin
+								.reply_to_job(job, &conduit_room, reply_id)
+								.await;
+						});
+					},
+					Err(e) => {
+						// generally shouldn't happen
+						error!("Failed to receive admin room event from channel: {e}");
+					},
 				}
 			}
 		}
@@ -196,6 +168,81 @@ impl Service {
 		Ok(())
 	}
 
+	/// Sends a message as the server user to the admin room, optionally as a
+	/// reply. If that fails, tries to tell the admins about it.
+	async fn send_to_admin_room(
+		&self, conduit_room: &RoomId, mut message_content: RoomMessageEventContent, reply: Option<Arc<EventId>>,
+	) -> Result<Arc<EventId>> {
+		let conduit_user = server_notices::server_user();
+
+		let mutex_state = Arc::clone(
+			services()
+				.globals
+				.roomid_mutex_state
+				.write()
+				.await
+				.entry(conduit_room.to_owned())
+				.or_default(),
+		);
+		let state_lock = mutex_state.lock().await;
+
+		if let Some(reply) = reply {
+			message_content.relates_to = Some(Reply {
+				in_reply_to: InReplyTo {
+					event_id: reply.into(),
+				},
+			});
+		}
+
+		let result = services()
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					event_type: TimelineEventType::RoomMessage,
+					content: to_raw_value(&message_content).expect("event is valid, we just created it"),
+					unsigned: None,
+					state_key: None,
+					redacts: None,
+				},
+				&conduit_user,
+				conduit_room,
+				&state_lock,
+			)
+			.await;
+
+		if let Err(e) = &result {
+			error!("Failed to build and append admin room response PDU: \"{e}\"");
+
+			let error_room_message = RoomMessageEventContent::text_plain(format!(
+				"Failed to build and append admin room PDU: \"{e}\"\n\nThe original admin command may have finished \
+				 successfully, but we could not return the output."
+			));
+
+			if let Err(e) = services()
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder {
+						event_type: TimelineEventType::RoomMessage,
+						content: to_raw_value(&error_room_message).expect("event is valid, we just created it"),
+						unsigned: None,
+						state_key: None,
+						redacts: None,
+					},
+					&conduit_user,
+					conduit_room,
+					&state_lock,
+				)
+				.await
+			{
+				error!("Failed to tell the admin room about it: \"{e}\"");
+			}
+		}
+
+		result
+	}
+
 	pub fn process_message(&self, room_message: String, event_id: Arc<EventId>) {
 		self.sender
 			.send(AdminRoomEvent::ProcessMessage(room_message, event_id))
@@ -208,6 +255,15 @@ impl Service {
 			.unwrap();
 	}
 
+	/// Runs an admin command outside of the admin room, e.g. from the admin
+	/// API, and returns its output. Lines after the first one are the command
+	/// body.
+	pub async fn run_command(&self, command: &str) -> RoomMessageEventContent {
+		let job = self.spawn_job(format!("@conduit:{}: {command}", services().globals.server_name()));
+
+		self.finish_job(job).await
+	}
+
 	// Parse and process a message from the admin room
 	async fn process_admin_message(&self, room_message: String) -> RoomMessageEventContent {
 		let mut lines = room_message.lines().filter(|l| !l.trim().is_empty());
@@ -262,11 +318,13 @@ impl Service {
 		let reply_message_content = match command {
 			AdminCommand::Appservices(command) => appservice::process(command, body).await?,
 			AdminCommand::Media(command) => media::process(command, body).await?,
+			AdminCommand::RegistrationTokens(command) => registration_token::process(command, body).await?,
 			AdminCommand::Users(command) => user::process(command, body).await?,
 			AdminCommand::Rooms(command) => room::process(command, body).await?,
 			AdminCommand::Federation(command) => federation::process(command, body).await?,
 			AdminCommand::Server(command) => server::process(command, body).await?,
 			AdminCommand::Debug(command) => debug::process(command, body).await?,
+			AdminCommand::Jobs(command) => jobs::process(command, body).await?,
 		};
 
 		Ok(reply_message_content)
@@ -610,6 +668,77 @@ impl Service {
 			.resolve_local_alias(&admin_room_alias)
 	}
 
+	/// Kicks the user from the conduit admin room and removes their power
+	/// level, revoking their admin privileges.
+	pub(crate) async fn revoke_user_admin(&self, user_id: &UserId) -> Result<()> {
+		let Some(room_id) = Self::get_admin_room()? else {
+			return Ok(());
+		};
+
+		let mutex_state = Arc::clone(
+			services()
+				.globals
+				.roomid_mutex_state
+				.write()
+				.await
+				.entry(room_id.clone())
+				.or_default(),
+		);
+		let state_lock = mutex_state.lock().await;
+
+		let conduit_user = server_notices::server_user();
+
+		// Kicking needs a higher power level than the user has
+		if let Some(event) =
+			services()
+				.rooms
+				.state_accessor
+				.room_state_get(&room_id, &StateEventType::RoomPowerLevels, "")?
+		{
+			let mut power_levels: RoomPowerLevelsEventContent = serde_json::from_str(event.content.get())
+				.map_err(|_| Error::bad_database("Invalid power levels event in database."))?;
+
+			if power_levels.users.remove(user_id).is_some() {
+				services()
+					.rooms
+					.timeline
+					.build_and_append_pdu(
+						PduBuilder {
+							event_type: TimelineEventType::RoomPowerLevels,
+							content: to_raw_value(&power_levels).expect("event is valid, we just created it"),
+							unsigned: None,
+							state_key: Some(String::new()),
+							redacts: None,
+						},
+						&conduit_user,
+						&room_id,
+						&state_lock,
+					)
+					.await?;
+			}
+		}
+
+		services()
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					event_type: TimelineEventType::RoomMember,
+					content: to_raw_value(&RoomMemberEventContent::new(MembershipState::Leave))
+						.expect("event is valid, we just created it"),
+					unsigned: None,
+					state_key: Some(user_id.to_string()),
+					redacts: None,
+				},
+				&conduit_user,
+				&room_id,
+				&state_lock,
+			)
+			.await?;
+
+		Ok(())
+	}
+
 	/// Invite the user to the conduit admin room.
 	///
 	/// In conduit, this is equivalent to granting admin privileges.
@@ -741,7 +870,7 @@ fn escape_html(s: &str) -> String {
 		.replace('>', "&gt;")
 }
 
-fn get_room_info(id: &OwnedRoomId) -> (OwnedRoomId, u64, String) {
+pub(crate) fn get_room_info(id: &OwnedRoomId) -> (OwnedRoomId, u64, String) {
 	(
 		id.clone(),
 		services()
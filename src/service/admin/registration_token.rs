@@ -0,0 +1,148 @@
+use std::fmt::Write as _;
+
+use chrono::{DateTime, Utc};
+use clap::Subcommand;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{
+	api::client_server::TOKEN_LENGTH, service::registration_tokens::RegistrationTokenInfo, services, utils, Result,
+};
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Subcommand)]
+pub(crate) enum RegistrationTokenCommand {
+	/// - Create a registration token new users can register with
+	Create {
+		/// The token, a random one is generated if not set
+		#[arg(long)]
+		token: Option<String>,
+
+		/// How often the token can be used, unlimited if not set
+		#[arg(long)]
+		uses_allowed: Option<u64>,
+
+		/// How long the token is valid for, e.g. "7d", never expires if not set
+		#[arg(long)]
+		expires_in: Option<String>,
+	},
+
+	/// - List all registration tokens and their usage
+	List,
+
+	/// - Show the usage and expiry of a registration token
+	Info {
+		token: String,
+	},
+
+	/// - Revoke a registration token so it can no longer be used
+	Revoke {
+		token: String,
+	},
+}
+
+pub(crate) async fn process(command: RegistrationTokenCommand, _body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	match command {
+		RegistrationTokenCommand::Create {
+			token,
+			uses_allowed,
+			expires_in,
+		} => {
+			let token = token.unwrap_or_else(|| utils::random_string(TOKEN_LENGTH));
+			if token.is_empty() || token.len() > 64 || !token.chars().all(is_token_char) {
+				return Ok(RoomMessageEventContent::text_plain(
+					"Registration tokens must be 1 to 64 characters of A-Z, a-z, 0-9, \".\", \"_\", \"~\" and \"-\".",
+				));
+			}
+
+			if services().registration_tokens.get(&token)?.is_some() {
+				return Ok(RoomMessageEventContent::text_plain("This registration token already exists."));
+			}
+
+			let expiry_time = match expires_in.as_deref().map(cyborgtime::parse_duration) {
+				Some(Ok(duration)) => Some(
+					utils::millis_since_unix_epoch()
+						.saturating_add(duration.as_millis().try_into().unwrap_or(u64::MAX)),
+				),
+				Some(Err(e)) => {
+					return Ok(RoomMessageEventContent::text_plain(format!(
+						"Failed to parse expiry duration: {e}"
+					)))
+				},
+				None => None,
+			};
+
+			services()
+				.registration_tokens
+				.create(&token, uses_allowed, expiry_time)?;
+
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"Created registration token {token}"
+			)))
+		},
+		RegistrationTokenCommand::List => {
+			let mut output = String::from("Registration tokens:\n");
+			let mut count = 0_usize;
+			for result in services().registration_tokens.all() {
+				let (token, info) = result?;
+				writeln!(output, "- {token}: {}", describe(&info)).expect("write to String works");
+				count = count.saturating_add(1);
+			}
+
+			if count == 0 {
+				return Ok(RoomMessageEventContent::text_plain("There are no registration tokens."));
+			}
+
+			Ok(RoomMessageEventContent::text_plain(output))
+		},
+		RegistrationTokenCommand::Info {
+			token,
+		} => match services().registration_tokens.get(&token)? {
+			Some(info) => Ok(RoomMessageEventContent::text_plain(format!("{token}: {}", describe(&info)))),
+			None => Ok(RoomMessageEventContent::text_plain("Registration token does not exist.")),
+		},
+		RegistrationTokenCommand::Revoke {
+			token,
+		} => {
+			if services().registration_tokens.get(&token)?.is_none() {
+				return Ok(RoomMessageEventContent::text_plain("Registration token does not exist."));
+			}
+
+			services().registration_tokens.revoke(&token)?;
+
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"Revoked registration token {token}"
+			)))
+		},
+	}
+}
+
+/// Characters allowed in registration tokens by the spec
+fn is_token_char(c: char) -> bool { c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '~' | '-') }
+
+fn describe(info: &RegistrationTokenInfo) -> String {
+	let uses = match info.uses_allowed {
+		Some(uses_allowed) => format!(
+			"used {}/{uses_allowed} times ({} remaining)",
+			info.completed,
+			info.uses_remaining().unwrap_or_default()
+		),
+		None => format!("used {} times (unlimited)", info.completed),
+	};
+
+	let expiry = match info
+		.expiry_time
+		.and_then(|expiry_time| DateTime::<Utc>::from_timestamp_millis(expiry_time.try_into().ok()?))
+	{
+		Some(expiry_time) if info.is_expired() => format!("expired at {}", expiry_time.to_rfc3339()),
+		Some(expiry_time) => format!("expires at {}", expiry_time.to_rfc3339()),
+		None => "never expires".to_owned(),
+	};
+
+	let state = if info.is_valid() {
+		"valid"
+	} else {
+		"invalid"
+	};
+
+	format!("{state}, {uses}, {expiry}")
+}
@@ -1,14 +1,32 @@
-use std::fmt::Write as _;
+use std::{fmt::Write as _, sync::Arc};
 
 use clap::Subcommand;
-use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId};
+use ruma::{
+	events::{
+		room::{
+			member::{MembershipState, RoomMemberEventContent},
+			message::RoomMessageEventContent,
+			power_levels::RoomPowerLevelsEventContent,
+		},
+		StateEventType, TimelineEventType,
+	},
+	OwnedRoomId, RoomId, RoomOrAliasId, UserId,
+};
+use serde_json::value::to_raw_value;
+use tracing::{info, warn};
 
 use crate::{
-	service::admin::{
-		escape_html, get_room_info, room_alias, room_alias::RoomAliasCommand, room_directory,
-		room_directory::RoomDirectoryCommand, room_moderation, room_moderation::RoomModerationCommand, PAGE_SIZE,
+	api::client_server::{get_alias_helper, leave_room},
+	service::{
+		admin::{
+			escape_html, get_room_info, room_alias, room_alias::RoomAliasCommand, room_directory,
+			room_directory::RoomDirectoryCommand, room_moderation, room_moderation::RoomModerationCommand, Service,
+			PAGE_SIZE,
+		},
+		pdu::PduBuilder,
+		server_notices,
 	},
-	services, Result,
+	services, Error, Result,
 };
 
 #[cfg_attr(test, derive(Debug))]
@@ -30,6 +48,55 @@ pub(crate) enum RoomCommand {
 	#[command(subcommand)]
 	/// - Manage the room directory
 	Directory(RoomDirectoryCommand),
+
+	/// - Show the version, creator, members, state and forward extremities of a
+	///   room
+	Info {
+		/// Room ID or alias
+		room: Box<RoomOrAliasId>,
+	},
+
+	/// - List the joined members of a room
+	Members {
+		/// Room ID or alias
+		room: Box<RoomOrAliasId>,
+
+		#[arg(short, long)]
+		/// Only list users of this server
+		local: bool,
+	},
+
+	/// - Kick a user from a room
+	///
+	/// The kick is sent by the server user, which has to be in the room with
+	/// enough power to kick the user.
+	ForceKick {
+		/// Room ID or alias
+		room: Box<RoomOrAliasId>,
+
+		user_id: Box<UserId>,
+
+		/// Reason shown to the user
+		reason: Vec<String>,
+	},
+
+	/// - Shut down a room
+	///
+	/// Bans the room, makes all local users leave it, and joins them to a new
+	/// room with a notice instead. Pending invites of local users are
+	/// rejected. Local aliases of the room are moved to the new room and it is
+	/// removed from the room directory.
+	Shutdown {
+		/// Room ID or alias
+		room: Box<RoomOrAliasId>,
+
+		#[arg(long, default_value = "Content Violation Notification")]
+		/// Name of the new room
+		room_name: String,
+
+		/// Notice posted in the new room
+		message: Vec<String>,
+	},
 }
 
 pub(crate) async fn process(command: RoomCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -40,6 +107,296 @@ pub(crate) async fn process(command: RoomCommand, body: Vec<&str>) -> Result<Roo
 
 		RoomCommand::Moderation(command) => room_moderation::process(command, body).await,
 
+		RoomCommand::Info {
+			room,
+		} => {
+			let room_id = resolve_room(&room).await?;
+			if !services().rooms.metadata.exists(&room_id)? {
+				return Ok(RoomMessageEventContent::text_plain("This server doesn't know the room."));
+			}
+
+			let creator = services()
+				.rooms
+				.state_accessor
+				.room_state_get(&room_id, &StateEventType::RoomCreate, "")?
+				.map_or_else(|| "unknown".to_owned(), |create| create.sender.to_string());
+			let local_members = services()
+				.rooms
+				.state_cache
+				.room_members(&room_id)
+				.filter_map(Result::ok)
+				.filter(|user_id| user_id.server_name() == services().globals.server_name())
+				.count();
+			let forward_extremities = services().rooms.state.get_forward_extremities(&room_id)?;
+			let servers = services()
+				.rooms
+				.state_cache
+				.room_servers(&room_id)
+				.filter_map(Result::ok)
+				.collect::<Vec<_>>();
+
+			let mut msg = format!("{room_id}\n");
+			writeln!(msg, "Name: {}", get_room_info(&room_id).2).expect("write to String works");
+			writeln!(msg, "Version: {}", services().rooms.state.get_room_version(&room_id)?)
+				.expect("write to String works");
+			writeln!(msg, "Creator: {creator}").expect("write to String works");
+			writeln!(
+				msg,
+				"Members: {} joined ({local_members} local), {} invited",
+				services()
+					.rooms
+					.state_cache
+					.room_joined_count(&room_id)?
+					.unwrap_or(0),
+				services()
+					.rooms
+					.state_cache
+					.room_invited_count(&room_id)?
+					.unwrap_or(0)
+			)
+			.expect("write to String works");
+			writeln!(
+				msg,
+				"State events: {}",
+				services()
+					.rooms
+					.state_accessor
+					.room_state_full(&room_id)
+					.await?
+					.len()
+			)
+			.expect("write to String works");
+			writeln!(
+				msg,
+				"Banned: {}, federation disabled: {}, published: {}",
+				services().rooms.metadata.is_banned(&room_id)?,
+				services().rooms.metadata.is_disabled(&room_id)?,
+				services().rooms.directory.is_public_room(&room_id)?
+			)
+			.expect("write to String works");
+			writeln!(msg, "\nForward extremities ({}):", forward_extremities.len()).expect("write to String works");
+			for event_id in &forward_extremities {
+				writeln!(msg, "{event_id}").expect("write to String works");
+			}
+			writeln!(msg, "\nParticipating servers ({}):", servers.len()).expect("write to String works");
+			for server in &servers {
+				writeln!(msg, "{server}").expect("write to String works");
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+
+		RoomCommand::Members {
+			room,
+			local,
+		} => {
+			let room_id = resolve_room(&room).await?;
+
+			let members = services()
+				.rooms
+				.state_cache
+				.room_members(&room_id)
+				.filter_map(Result::ok)
+				.filter(|user_id| !local || user_id.server_name() == services().globals.server_name())
+				.collect::<Vec<_>>();
+
+			if members.is_empty() {
+				return Ok(RoomMessageEventContent::text_plain("The room has no joined members."));
+			}
+
+			let mut msg = format!("{} joined member(s) of {room_id}:\n", members.len());
+			for user_id in &members {
+				writeln!(msg, "{user_id}").expect("write to String works");
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+
+		RoomCommand::ForceKick {
+			room,
+			user_id,
+			reason,
+		} => {
+			let room_id = resolve_room(&room).await?;
+
+			if !services().rooms.state_cache.is_joined(&user_id, &room_id)?
+				&& !services()
+					.rooms
+					.state_cache
+					.is_invited(&user_id, &room_id)?
+			{
+				return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is not in {room_id}.")));
+			}
+
+			let server_user = server_notices::server_user();
+			if !server_user_can_kick(&room_id, &user_id)? {
+				return Ok(RoomMessageEventContent::text_plain(format!(
+					"The server user {server_user} is not in {room_id} or doesn't have enough power to kick {user_id}."
+				)));
+			}
+
+			let mutex_state = Arc::clone(
+				services()
+					.globals
+					.roomid_mutex_state
+					.write()
+					.await
+					.entry(room_id.clone())
+					.or_default(),
+			);
+			let state_lock = mutex_state.lock().await;
+
+			services()
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder {
+						event_type: TimelineEventType::RoomMember,
+						content: to_raw_value(&RoomMemberEventContent {
+							reason: (!reason.is_empty()).then(|| reason.join(" ")),
+							..RoomMemberEventContent::new(MembershipState::Leave)
+						})
+						.expect("event is valid, we just created it"),
+						unsigned: None,
+						state_key: Some(user_id.to_string()),
+						redacts: None,
+					},
+					&server_user,
+					&room_id,
+					&state_lock,
+				)
+				.await?;
+
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"{server_user} kicked {user_id} from {room_id}."
+			)))
+		},
+
+		RoomCommand::Shutdown {
+			room,
+			room_name,
+			message,
+		} => {
+			let room_id = resolve_room(&room).await?;
+
+			if Service::get_admin_room()?.is_some_and(|admin_room_id| admin_room_id == room_id) {
+				return Ok(RoomMessageEventContent::text_plain("Not allowed to shut down the admin room."));
+			}
+
+			// The server accounts can't be moved, @conduit being the one creating the
+			// new room
+			let local_users = services()
+				.rooms
+				.state_cache
+				.room_members(&room_id)
+				.filter_map(Result::ok)
+				.filter(|user_id| {
+					user_id.server_name() == services().globals.server_name()
+						&& !server_notices::is_server_account(user_id)
+				})
+				.collect::<Vec<_>>();
+
+			// Created first, so nothing is changed if it fails
+			let new_room_id = services()
+				.server_notices
+				.create_server_room(&server_notices::server_user(), &room_name, &local_users)
+				.await?;
+
+			services().rooms.metadata.ban_room(&room_id, true)?;
+			services().rooms.directory.set_not_public(&room_id)?;
+
+			let mut evicted = 0_usize;
+			for user_id in &local_users {
+				match leave_room(user_id, &room_id, None).await {
+					Ok(()) => evicted += 1,
+					Err(e) => warn!("Failed to make {user_id} leave {room_id} while shutting it down: {e}"),
+				}
+			}
+
+			// Invited users are only prevented from joining, not moved
+			let invited_users = services()
+				.rooms
+				.state_cache
+				.room_members_invited(&room_id)
+				.filter_map(Result::ok)
+				.filter(|user_id| {
+					user_id.server_name() == services().globals.server_name()
+						&& !server_notices::is_server_account(user_id)
+				})
+				.collect::<Vec<_>>();
+
+			let mut revoked_invites = 0_usize;
+			for user_id in invited_users {
+				match leave_room(&user_id, &room_id, None).await {
+					Ok(()) => revoked_invites += 1,
+					Err(e) => {
+						warn!("Failed to revoke the invite of {user_id} to {room_id} while shutting it down: {e}")
+					},
+				}
+			}
+
+			let aliases = services()
+				.rooms
+				.alias
+				.local_aliases_for_room(&room_id)
+				.filter_map(Result::ok)
+				.collect::<Vec<_>>();
+			for alias in &aliases {
+				services().rooms.alias.remove_alias(alias)?;
+				services().rooms.alias.set_alias(alias, &new_room_id)?;
+			}
+
+			let message = if message.is_empty() {
+				format!(
+					"{room_id}{} has been shut down by the admins of this server. You have been moved to this room \
+					 instead.",
+					aliases
+						.first()
+						.map(|alias| format!(" ({alias})"))
+						.unwrap_or_default()
+				)
+			} else {
+				message.join(" ")
+			};
+
+			let mutex_state = Arc::clone(
+				services()
+					.globals
+					.roomid_mutex_state
+					.write()
+					.await
+					.entry(new_room_id.clone())
+					.or_default(),
+			);
+			let state_lock = mutex_state.lock().await;
+
+			services()
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder {
+						event_type: TimelineEventType::RoomMessage,
+						content: to_raw_value(&RoomMessageEventContent::text_plain(message))
+							.expect("event is valid, we just created it"),
+						unsigned: None,
+						state_key: None,
+						redacts: None,
+					},
+					&server_notices::server_user(),
+					&new_room_id,
+					&state_lock,
+				)
+				.await?;
+
+			info!("Shut down {room_id}, evicted {evicted} local user(s) into {new_room_id}");
+
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"Shut down {room_id}: banned it, evicted {evicted} local user(s) into {new_room_id}, revoked \
+				 {revoked_invites} invite(s) and moved {} alias(es) to it.",
+				aliases.len()
+			)))
+		},
+
 		RoomCommand::List {
 			page,
 		} => {
@@ -94,3 +451,52 @@ pub(crate) async fn process(command: RoomCommand, body: Vec<&str>) -> Result<Roo
 		},
 	}
 }
+
+/// Resolves a room alias to a room ID, asking other servers for remote aliases
+pub(crate) async fn resolve_room(room: &RoomOrAliasId) -> Result<OwnedRoomId> {
+	match <&RoomId>::try_from(room) {
+		Ok(room_id) => Ok(room_id.to_owned()),
+		Err(room_alias) => {
+			if let Some(room_id) = services().rooms.alias.resolve_local_alias(room_alias)? {
+				return Ok(room_id);
+			}
+
+			Ok(get_alias_helper(room_alias.to_owned()).await?.room_id)
+		},
+	}
+}
+
+/// Whether the server user is in the room and has enough power to kick the
+/// given user.
+fn server_user_can_kick(room_id: &RoomId, user_id: &UserId) -> Result<bool> {
+	let server_user = server_notices::server_user();
+	if !services()
+		.rooms
+		.state_cache
+		.is_joined(&server_user, room_id)?
+	{
+		return Ok(false);
+	}
+
+	let power_levels: RoomPowerLevelsEventContent = services()
+		.rooms
+		.state_accessor
+		.room_state_get(room_id, &StateEventType::RoomPowerLevels, "")?
+		.map(|event| {
+			serde_json::from_str(event.content.get())
+				.map_err(|_| Error::bad_database("Invalid power levels event in database."))
+		})
+		.transpose()?
+		.unwrap_or_default();
+
+	let power_level = |user_id: &UserId| {
+		power_levels
+			.users
+			.get(user_id)
+			.copied()
+			.unwrap_or(power_levels.users_default)
+	};
+	let level = power_level(&server_user);
+
+	Ok(level >= power_levels.kick && level > power_level(user_id))
+}
@@ -21,7 +21,8 @@ pub(crate) enum RoomAliasCommand {
 		room_alias_localpart: String,
 	},
 
-	/// - Remove an alias
+	/// - Remove an alias, also removing it from the room's canonical alias
+	///   event
 	Remove {
 		/// The alias localpart to remove (`alias`, not `#alias:servername.tld`)
 		room_alias_localpart: String,
@@ -85,7 +86,17 @@ pub(crate) async fn process(command: RoomAliasCommand, _body: Vec<&str>) -> Resu
 					..
 				} => match services().rooms.alias.resolve_local_alias(&room_alias) {
 					Ok(Some(id)) => match services().rooms.alias.remove_alias(&room_alias) {
-						Ok(()) => Ok(RoomMessageEventContent::text_plain(format!("Removed alias from {}", id))),
+						Ok(()) => match services()
+							.rooms
+							.alias
+							.remove_from_canonical_alias(&id, &room_alias, None)
+							.await
+						{
+							Ok(()) => Ok(RoomMessageEventContent::text_plain(format!("Removed alias from {}", id))),
+							Err(err) => Ok(RoomMessageEventContent::text_plain(format!(
+								"Removed alias from {id}, but failed to update its canonical alias event: {err}"
+							))),
+						},
 						Err(err) => Ok(RoomMessageEventContent::text_plain(format!("Failed to remove alias: {}", err))),
 					},
 					Ok(None) => Ok(RoomMessageEventContent::text_plain("Alias isn't in use.")),
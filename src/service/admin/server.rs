@@ -9,6 +9,12 @@ pub(crate) enum ServerCommand {
 	/// - Show configuration values
 	ShowConfig,
 
+	/// - Re-read the config file and environment and apply the options that can
+	///   change while running, like sending SIGHUP does
+	///
+	/// Changed options that still need a restart are listed.
+	ReloadConfig,
+
 	/// - Print database memory usage statistics
 	MemoryUsage,
 
@@ -39,7 +45,16 @@ pub(crate) async fn process(command: ServerCommand, _body: Vec<&str>) -> Result<
 	match command {
 		ServerCommand::ShowConfig => {
 			// Construct and send the response
-			Ok(RoomMessageEventContent::text_plain(format!("{}", services().globals.config)))
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"{}",
+				services().globals.config.get()
+			)))
+		},
+		ServerCommand::ReloadConfig => match services().reload_config() {
+			Ok(msg) => Ok(RoomMessageEventContent::text_plain(msg)),
+			Err(e) => Ok(RoomMessageEventContent::text_plain(format!(
+				"Failed to reload the config, keeping the running one: {e}"
+			))),
 		},
 		ServerCommand::MemoryUsage => {
 			let response1 = services().memory_usage().await;
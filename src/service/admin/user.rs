@@ -1,13 +1,24 @@
-use std::{fmt::Write as _, sync::Arc};
+use std::{fmt::Write as _, net::IpAddr, sync::Arc};
 
+use chrono::{DateTime, Utc};
 use clap::Subcommand;
 use itertools::Itertools;
-use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, UserId};
+use ruma::{
+	api::client::device::Device, events::room::message::RoomMessageEventContent, OwnedDeviceId, OwnedMxcUri,
+	OwnedRoomId, RoomId, UserId,
+};
 use tracing::{error, info, warn};
 
 use crate::{
-	api::client_server::{join_room_by_id_helper, leave_all_rooms, AUTO_GEN_PASSWORD_LENGTH},
-	service::admin::{escape_html, get_room_info},
+	api::client_server::{
+		join_room_by_id_helper, leave_all_rooms, leave_room, update_avatar_url, update_displayname,
+		AUTO_GEN_PASSWORD_LENGTH,
+	},
+	service::{
+		admin::{escape_html, get_room_info, jobs},
+		login_lockout::Target,
+		server_notices,
+	},
 	services, utils, Result,
 };
 
@@ -26,6 +37,8 @@ pub(crate) enum UserCommand {
 	ResetPassword {
 		/// Username of the user for whom the password should be reset
 		username: String,
+		/// New password of the user, if unspecified one is generated
+		password: Option<String>,
 	},
 
 	/// - Deactivate a user
@@ -67,6 +80,108 @@ pub(crate) enum UserCommand {
 	ListJoinedRooms {
 		user_id: Box<UserId>,
 	},
+
+	/// - List users and IP addresses locked out after failed password logins
+	ListLockouts,
+
+	/// - Lift the lockout of a user or IP address and forget their failed
+	///   password logins
+	ClearLockout {
+		/// Full user ID or IP address
+		target: String,
+	},
+
+	/// - Set the displayname of a local user, or remove it if none is given
+	SetDisplayname {
+		user_id: Box<UserId>,
+		displayname: Vec<String>,
+	},
+
+	/// - Set the avatar of a local user, or remove it if none is given
+	SetAvatar {
+		user_id: Box<UserId>,
+		/// MXC URI of the new avatar
+		avatar_url: Option<String>,
+	},
+
+	/// - List the devices of a local user and when they were last seen
+	ListDevices {
+		user_id: Box<UserId>,
+	},
+
+	/// - Delete a device of a local user, logging out its session
+	DeleteDevice {
+		user_id: Box<UserId>,
+		device_id: String,
+	},
+
+	/// - Log out all sessions of a local user by deleting all their devices
+	LogoutAll {
+		user_id: Box<UserId>,
+	},
+
+	/// - Make a local user join a room
+	///
+	/// The join rules of the room still apply, so invite-only rooms need an
+	/// invite first.
+	ForceJoinRoom {
+		user_id: Box<UserId>,
+		room_id: Box<RoomId>,
+	},
+
+	/// - Make a local user leave a room
+	ForceLeaveRoom {
+		user_id: Box<UserId>,
+		room_id: Box<RoomId>,
+	},
+
+	/// - Grant a local user admin privileges by joining them to the admin room
+	MakeAdmin {
+		user_id: Box<UserId>,
+	},
+
+	/// - Revoke the admin privileges of a local user by removing them from the
+	///   admin room
+	RevokeAdmin {
+		user_id: Box<UserId>,
+	},
+
+	/// - Lock an account, so the user can't log in or use their sessions until
+	///   it is unlocked
+	Lock {
+		user_id: Box<UserId>,
+	},
+
+	/// - Unlock a locked account
+	Unlock {
+		user_id: Box<UserId>,
+	},
+
+	/// - Show everything about a local user, including their sessions and the
+	///   IP addresses they were last seen from
+	Whois {
+		user_id: Box<UserId>,
+	},
+
+	/// - Send a server notice to a local user
+	///
+	/// The notice is sent to the user's server notices room, which is created
+	/// if needed. A multi-line notice can be given in a code block below the
+	/// command instead.
+	SendNotice {
+		user_id: Box<UserId>,
+		/// Text of the notice
+		message: Vec<String>,
+	},
+
+	/// - Send a server notice to all local users, e.g. to announce maintenance
+	///
+	/// A multi-line notice can be given in a code block below the command
+	/// instead.
+	SendNoticeAll {
+		/// Text of the notice
+		message: Vec<String>,
+	},
 }
 
 pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -83,7 +198,6 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 			username,
 			password,
 		} => {
-			let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
 			// Validate user id
 			let user_id = match UserId::parse_with_server_name(
 				username.as_str().to_lowercase(),
@@ -104,70 +218,18 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 			if services().users.exists(&user_id)? {
 				return Ok(RoomMessageEventContent::text_plain(format!("Userid {user_id} already exists")));
 			}
-			// Create user
-			services().users.create(&user_id, Some(password.as_str()))?;
-
-			// Default to pretty displayname
-			let mut displayname = user_id.localpart().to_owned();
-
-			// If `new_user_displayname_suffix` is set, registration will push whatever
-			// content is set to the user's display name with a space before it
-			if !services().globals.new_user_displayname_suffix().is_empty() {
-				displayname.push_str(&(" ".to_owned() + services().globals.new_user_displayname_suffix()));
-			}
-
-			services()
-				.users
-				.set_displayname(&user_id, Some(displayname))
-				.await?;
-
-			// Initial account data
-			services().account_data.update(
-				None,
-				&user_id,
-				ruma::events::GlobalAccountDataEventType::PushRules
-					.to_string()
-					.into(),
-				&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
-					content: ruma::events::push_rules::PushRulesEventContent {
-						global: ruma::push::Ruleset::server_default(&user_id),
-					},
-				})
-				.expect("to json value always works"),
-			)?;
-
-			if !services().globals.config.auto_join_rooms.is_empty() {
-				for room in &services().globals.config.auto_join_rooms {
-					if !services()
-						.rooms
-						.state_cache
-						.server_in_room(services().globals.server_name(), room)?
-					{
-						warn!("Skipping room {room} to automatically join as we have never joined before.");
-						continue;
-					}
 
-					if let Some(room_id_server_name) = room.server_name() {
-						match join_room_by_id_helper(
-							Some(&user_id),
-							room,
-							Some("Automatically joining this room upon registration".to_owned()),
-							&[room_id_server_name.to_owned(), services().globals.server_name().to_owned()],
-							None,
-						)
-						.await
-						{
-							Ok(_) => {
-								info!("Automatically joined room {room} for user {user_id}");
-							},
-							Err(e) => {
-								// don't return this error so we don't fail registrations
-								error!("Failed to automatically join room {room} for user {user_id}: {e}");
-							},
-						};
-					}
+			// Only passwords chosen by an admin have to meet the password policy
+			if let Some(password) = &password {
+				if let Err(e) = services().users.check_password_policy(&user_id, password) {
+					return Ok(RoomMessageEventContent::text_plain(format!(
+						"The supplied password is too weak: {e}"
+					)));
 				}
 			}
+			let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+
+			create_user(&user_id, &password).await?;
 
 			// we dont add a device since we're not the user, just the creator
 
@@ -219,6 +281,7 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 		},
 		UserCommand::ResetPassword {
 			username,
+			password,
 		} => {
 			let user_id = match UserId::parse_with_server_name(
 				username.as_str().to_lowercase(),
@@ -248,7 +311,14 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 				return Ok(RoomMessageEventContent::text_plain("The specified user does not exist!"));
 			}
 
-			let new_password = utils::random_string(AUTO_GEN_PASSWORD_LENGTH);
+			if let Some(password) = &password {
+				if let Err(e) = services().users.check_password_policy(&user_id, password) {
+					return Ok(RoomMessageEventContent::text_plain(format!(
+						"The supplied password is too weak: {e}"
+					)));
+				}
+			}
+			let new_password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
 
 			match services()
 				.users
@@ -321,10 +391,16 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 					if services().users.deactivate_account(user_id).is_ok() {
 						deactivation_count += 1;
 					}
+					jobs::set_progress(format!("deactivated {deactivation_count} of {} accounts", user_ids.len()));
 				}
 
 				if leave_rooms {
-					for &user_id in &user_ids {
+					for (i, &user_id) in user_ids.iter().enumerate() {
+						jobs::set_progress(format!(
+							"deactivated {deactivation_count} accounts, leaving rooms for {} of {}",
+							i + 1,
+							user_ids.len()
+						));
 						_ = leave_all_rooms(user_id).await;
 					}
 				}
@@ -402,5 +478,474 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 			);
 			Ok(RoomMessageEventContent::text_html(output_plain, output_html))
 		},
+		UserCommand::ListLockouts => {
+			let lockouts = services().login_lockout.lockouts();
+			if lockouts.is_empty() {
+				return Ok(RoomMessageEventContent::text_plain("Nobody is locked out."));
+			}
+
+			let mut msg = format!("{} lockout(s):\n", lockouts.len());
+			for lockout in lockouts {
+				writeln!(
+					msg,
+					"{}: {} failed logins, locked out for {}s",
+					lockout.target,
+					lockout.failures,
+					lockout.remaining.as_secs()
+				)
+				.expect("write to String works");
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+		UserCommand::ClearLockout {
+			target,
+		} => {
+			let target = if let Ok(ip) = target.parse::<IpAddr>() {
+				Target::Ip(ip)
+			} else if let Ok(user_id) = UserId::parse(&target) {
+				Target::User(user_id)
+			} else {
+				return Ok(RoomMessageEventContent::text_plain(format!(
+					"{target} is neither a user ID nor an IP address."
+				)));
+			};
+
+			if services().login_lockout.clear(&target) {
+				Ok(RoomMessageEventContent::text_plain(format!("Cleared the lockout of {target}.")))
+			} else {
+				Ok(RoomMessageEventContent::text_plain(format!("{target} has no failed logins.")))
+			}
+		},
+		UserCommand::SetDisplayname {
+			user_id,
+			displayname,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			let displayname = (!displayname.is_empty()).then(|| displayname.join(" "));
+			update_displayname(&user_id, displayname.clone()).await?;
+
+			Ok(RoomMessageEventContent::text_plain(match displayname {
+				Some(displayname) => format!("Set the displayname of {user_id} to {displayname}."),
+				None => format!("Removed the displayname of {user_id}."),
+			}))
+		},
+		UserCommand::SetAvatar {
+			user_id,
+			avatar_url,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			let avatar_url = avatar_url.map(OwnedMxcUri::from);
+			if avatar_url.as_ref().is_some_and(|mxc| !mxc.is_valid()) {
+				return Ok(RoomMessageEventContent::text_plain("The avatar has to be an MXC URI."));
+			}
+
+			update_avatar_url(&user_id, avatar_url.clone(), None).await?;
+
+			Ok(RoomMessageEventContent::text_plain(match avatar_url {
+				Some(avatar_url) => format!("Set the avatar of {user_id} to {avatar_url}."),
+				None => format!("Removed the avatar of {user_id}."),
+			}))
+		},
+		UserCommand::ListDevices {
+			user_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			let devices = services()
+				.users
+				.all_devices_metadata(&user_id)
+				.filter_map(Result::ok)
+				.collect::<Vec<_>>();
+			if devices.is_empty() {
+				return Ok(RoomMessageEventContent::text_plain(format!("{user_id} has no devices.")));
+			}
+
+			let mut msg = format!("{} device(s) of {user_id}:\n", devices.len());
+			for device in &devices {
+				writeln!(msg, "{}", device_summary(device)).expect("write to String works");
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+		UserCommand::DeleteDevice {
+			user_id,
+			device_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			let device_id = OwnedDeviceId::from(device_id);
+			if services()
+				.users
+				.get_device_metadata(&user_id, &device_id)?
+				.is_none()
+			{
+				return Ok(RoomMessageEventContent::text_plain(format!(
+					"{user_id} has no device {device_id}."
+				)));
+			}
+
+			services().users.remove_device(&user_id, &device_id)?;
+
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"Deleted device {device_id} of {user_id}."
+			)))
+		},
+		UserCommand::LogoutAll {
+			user_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			let mut count = 0;
+			for device_id in services()
+				.users
+				.all_device_ids(&user_id)
+				.collect::<Vec<_>>()
+			{
+				services().users.remove_device(&user_id, &device_id?)?;
+				count += 1;
+			}
+
+			Ok(RoomMessageEventContent::text_plain(format!(
+				"Logged out {count} session(s) of {user_id}."
+			)))
+		},
+		UserCommand::ForceJoinRoom {
+			user_id,
+			room_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			let mut servers = vec![services().globals.server_name().to_owned()];
+			if let Some(server_name) = room_id.server_name() {
+				servers.push(server_name.to_owned());
+			}
+
+			join_room_by_id_helper(Some(&user_id), &room_id, None, &servers, None).await?;
+
+			Ok(RoomMessageEventContent::text_plain(format!("{user_id} joined {room_id}.")))
+		},
+		UserCommand::ForceLeaveRoom {
+			user_id,
+			room_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			if !services().rooms.state_cache.is_joined(&user_id, &room_id)?
+				&& !services()
+					.rooms
+					.state_cache
+					.is_invited(&user_id, &room_id)?
+			{
+				return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is not in {room_id}.")));
+			}
+
+			leave_room(&user_id, &room_id, None).await?;
+
+			Ok(RoomMessageEventContent::text_plain(format!("{user_id} left {room_id}.")))
+		},
+		UserCommand::MakeAdmin {
+			user_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			if services().users.is_admin(&user_id)? {
+				return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is already an admin.")));
+			}
+
+			let displayname = services()
+				.users
+				.displayname(&user_id)?
+				.unwrap_or_else(|| user_id.localpart().to_owned());
+			services()
+				.admin
+				.make_user_admin(&user_id, displayname)
+				.await?;
+
+			Ok(RoomMessageEventContent::text_plain(format!("{user_id} is now an admin.")))
+		},
+		UserCommand::RevokeAdmin {
+			user_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			if !services().users.is_admin(&user_id)? {
+				return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is not an admin.")));
+			}
+
+			services().admin.revoke_user_admin(&user_id).await?;
+
+			Ok(RoomMessageEventContent::text_plain(format!("{user_id} is no longer an admin.")))
+		},
+		UserCommand::Lock {
+			user_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			if services().users.is_admin(&user_id)? {
+				return Ok(RoomMessageEventContent::text_plain(
+					"Admins can't be locked, revoke their admin privileges first.",
+				));
+			}
+
+			services().users.set_locked(&user_id, true)?;
+
+			Ok(RoomMessageEventContent::text_plain(format!("Locked {user_id}.")))
+		},
+		UserCommand::Unlock {
+			user_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			services().users.set_locked(&user_id, false)?;
+
+			Ok(RoomMessageEventContent::text_plain(format!("Unlocked {user_id}.")))
+		},
+		UserCommand::Whois {
+			user_id,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+
+			let mut msg = format!("{user_id}\n");
+			writeln!(
+				msg,
+				"Displayname: {}",
+				services()
+					.users
+					.displayname(&user_id)?
+					.unwrap_or_else(|| "none".to_owned())
+			)
+			.expect("write to String works");
+			writeln!(
+				msg,
+				"Avatar: {}",
+				services()
+					.users
+					.avatar_url(&user_id)?
+					.map_or_else(|| "none".to_owned(), |avatar_url| avatar_url.to_string())
+			)
+			.expect("write to String works");
+			writeln!(msg, "Admin: {}", services().users.is_admin(&user_id)?).expect("write to String works");
+			writeln!(msg, "Deactivated: {}", services().users.is_deactivated(&user_id)?)
+				.expect("write to String works");
+			writeln!(
+				msg,
+				"Locked: {}",
+				services()
+					.users
+					.locked_at(&user_id)?
+					.map_or_else(|| "no".to_owned(), |locked_at| format!("since {}", format_millis(locked_at)))
+			)
+			.expect("write to String works");
+			writeln!(
+				msg,
+				"Joined rooms: {}",
+				services().rooms.state_cache.rooms_joined(&user_id).count()
+			)
+			.expect("write to String works");
+
+			let devices = services()
+				.users
+				.all_devices_metadata(&user_id)
+				.filter_map(Result::ok)
+				.collect::<Vec<_>>();
+			writeln!(msg, "\nSessions ({}):", devices.len()).expect("write to String works");
+			for device in &devices {
+				writeln!(msg, "{}", device_summary(device)).expect("write to String works");
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+		UserCommand::SendNotice {
+			user_id,
+			message,
+		} => {
+			if let Some(error) = check_local_user(&user_id)? {
+				return Ok(error);
+			}
+			if services().users.is_deactivated(&user_id)? {
+				return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is deactivated.")));
+			}
+
+			let Some(notice) = notice_text(&message, &body) else {
+				return Ok(RoomMessageEventContent::text_plain(
+					"Expected a notice after the command or in a code block below it.",
+				));
+			};
+
+			services()
+				.server_notices
+				.send_notice(&user_id, server_notices::message_notice(notice))
+				.await?;
+
+			Ok(RoomMessageEventContent::text_plain(format!("Sent the notice to {user_id}.")))
+		},
+		UserCommand::SendNoticeAll {
+			message,
+		} => {
+			let Some(notice) = notice_text(&message, &body) else {
+				return Ok(RoomMessageEventContent::text_plain(
+					"Expected a notice after the command or in a code block below it.",
+				));
+			};
+
+			let (sent, failed) = services()
+				.server_notices
+				.send_notice_to_all(server_notices::message_notice(notice))
+				.await?;
+
+			let mut msg = format!("Sent the notice to {sent} user(s).");
+			if !failed.is_empty() {
+				writeln!(msg, "\nFailed to send it to {} user(s):", failed.len()).expect("write to String works");
+				for (user_id, e) in failed {
+					writeln!(msg, "{user_id}: {e}").expect("write to String works");
+				}
+			}
+
+			Ok(RoomMessageEventContent::text_plain(msg))
+		},
+	}
+}
+
+/// Takes the text of a notice from the command arguments, or from the code
+/// block below the command if there are none.
+fn notice_text(message: &[String], body: &[&str]) -> Option<String> {
+	if !message.is_empty() {
+		return Some(message.join(" "));
+	}
+
+	if body.len() > 2 && body[0].trim().starts_with("```") && body.last().unwrap().trim() == "```" {
+		return Some(body[1..body.len() - 1].join("\n"));
+	}
+
+	None
+}
+
+/// Creates a local user with a display name, the default push rules and the
+/// configured `auto_join_rooms`, like registration does.
+pub(crate) async fn create_user(user_id: &UserId, password: &str) -> Result<()> {
+	// Create user
+	services().users.create(user_id, Some(password))?;
+
+	// Default to pretty displayname
+	let mut displayname = user_id.localpart().to_owned();
+
+	// If `new_user_displayname_suffix` is set, registration will push whatever
+	// content is set to the user's display name with a space before it
+	if !services().globals.new_user_displayname_suffix().is_empty() {
+		displayname.push_str(&(" ".to_owned() + &services().globals.new_user_displayname_suffix()));
+	}
+
+	services()
+		.users
+		.set_displayname(user_id, Some(displayname))
+		.await?;
+
+	// Initial account data
+	services().account_data.update(
+		None,
+		user_id,
+		ruma::events::GlobalAccountDataEventType::PushRules
+			.to_string()
+			.into(),
+		&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
+			content: ruma::events::push_rules::PushRulesEventContent {
+				global: ruma::push::Ruleset::server_default(user_id),
+			},
+		})
+		.expect("to json value always works"),
+	)?;
+
+	if !services().globals.config.get().auto_join_rooms.is_empty() {
+		for room in &services().globals.config.get().auto_join_rooms {
+			if !services()
+				.rooms
+				.state_cache
+				.server_in_room(services().globals.server_name(), room)?
+			{
+				warn!("Skipping room {room} to automatically join as we have never joined before.");
+				continue;
+			}
+
+			if let Some(room_id_server_name) = room.server_name() {
+				match join_room_by_id_helper(
+					Some(user_id),
+					room,
+					Some("Automatically joining this room upon registration".to_owned()),
+					&[room_id_server_name.to_owned(), services().globals.server_name().to_owned()],
+					None,
+				)
+				.await
+				{
+					Ok(_) => {
+						info!("Automatically joined room {room} for user {user_id}");
+					},
+					Err(e) => {
+						// don't return this error so we don't fail registrations
+						error!("Failed to automatically join room {room} for user {user_id}: {e}");
+					},
+				};
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Returns the message to reply with if the user is not an existing local user
+fn check_local_user(user_id: &UserId) -> Result<Option<RoomMessageEventContent>> {
+	if user_id.server_name() != services().globals.server_name() {
+		return Ok(Some(RoomMessageEventContent::text_plain("User does not belong to our server.")));
+	}
+
+	if !services().users.exists(user_id)? || server_notices::is_server_account(user_id) {
+		return Ok(Some(RoomMessageEventContent::text_plain("User does not exist on this server.")));
 	}
+
+	Ok(None)
+}
+
+fn device_summary(device: &Device) -> String {
+	format!(
+		"{}\tName: {}\tLast seen: {} from {}",
+		device.device_id,
+		device.display_name.as_deref().unwrap_or("none"),
+		device
+			.last_seen_ts
+			.map_or_else(|| "never".to_owned(), |ts| format_millis(ts.get().into())),
+		device.last_seen_ip.as_deref().unwrap_or("unknown"),
+	)
+}
+
+fn format_millis(millis: u64) -> String {
+	i64::try_from(millis)
+		.ok()
+		.and_then(DateTime::<Utc>::from_timestamp_millis)
+		.map_or_else(|| millis.to_string(), |time| time.to_string())
 }
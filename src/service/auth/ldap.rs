@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use ldap3::{ldap_escape, LdapConnAsync, Scope, SearchEntry};
+use ruma::UserId;
+use tracing::{debug, warn};
+
+use super::{AuthProvider, AuthenticatedUser, PasswordCheck};
+use crate::{config::LdapConfig, Result};
+
+/// Checks passwords by binding to an LDAP directory as the user.
+pub(super) struct Ldap {
+	config: LdapConfig,
+}
+
+impl Ldap {
+	pub(super) fn new(config: LdapConfig) -> Self {
+		Self {
+			config,
+		}
+	}
+
+	async fn connect(&self) -> Result<ldap3::Ldap> {
+		let (conn, ldap) = LdapConnAsync::from_url(&self.config.uri).await?;
+		ldap3::drive!(conn);
+
+		Ok(ldap)
+	}
+
+	/// Binds as the configured service account, or stays anonymous if none is
+	/// configured.
+	async fn bind_service(&self, ldap: &mut ldap3::Ldap) -> Result<()> {
+		if let Some(bind_dn) = &self.config.bind_dn {
+			ldap.simple_bind(bind_dn, self.config.bind_password.as_deref().unwrap_or_default())
+				.await?
+				.success()?;
+		}
+
+		Ok(())
+	}
+
+	async fn search(&self, ldap: &mut ldap3::Ldap, filter: &str) -> Result<Vec<SearchEntry>> {
+		let (entries, _) = ldap
+			.search(
+				&self.config.base_dn,
+				Scope::Subtree,
+				filter,
+				vec![self.config.displayname_attribute.as_str()],
+			)
+			.await?
+			.success()?;
+
+		Ok(entries.into_iter().map(SearchEntry::construct).collect())
+	}
+}
+
+#[async_trait]
+impl AuthProvider for Ldap {
+	fn name(&self) -> &'static str { "LDAP" }
+
+	async fn check_password(&self, user_id: &UserId, password: &str) -> Result<PasswordCheck> {
+		let mut ldap = self.connect().await?;
+		self.bind_service(&mut ldap).await?;
+
+		let filter = self
+			.config
+			.filter
+			.replace("{username}", &ldap_escape(user_id.localpart()));
+		let entries = self.search(&mut ldap, &filter).await?;
+
+		let entry = match entries.as_slice() {
+			[] => return Ok(PasswordCheck::Unknown),
+			[entry] => entry,
+			_ => {
+				warn!(
+					"LDAP filter matched {} entries for {user_id}, refusing to log in",
+					entries.len()
+				);
+				return Ok(PasswordCheck::Denied);
+			},
+		};
+
+		// An empty password would make this an unauthenticated bind, which always
+		// succeeds
+		if password.is_empty() || ldap.simple_bind(&entry.dn, password).await?.rc != 0 {
+			return Ok(PasswordCheck::Denied);
+		}
+
+		let admin = if let Some(admin_filter) = &self.config.admin_filter {
+			self.bind_service(&mut ldap).await?;
+			let admin_filter = format!("(&{filter}{admin_filter})");
+			Some(!self.search(&mut ldap, &admin_filter).await?.is_empty())
+		} else {
+			None
+		};
+
+		if let Err(e) = ldap.unbind().await {
+			debug!("Failed to unbind from LDAP server: {e}");
+		}
+
+		Ok(PasswordCheck::Accepted(AuthenticatedUser {
+			provision: self.config.auto_provision,
+			displayname: entry
+				.attrs
+				.get(&self.config.displayname_attribute)
+				.and_then(|values| values.first())
+				.cloned(),
+			admin,
+		}))
+	}
+}
@@ -0,0 +1,46 @@
+use argon2::{PasswordHash, PasswordVerifier};
+use async_trait::async_trait;
+use ruma::UserId;
+use tracing::error;
+
+use super::{AuthProvider, AuthenticatedUser, PasswordCheck};
+use crate::{services, Error, Result};
+
+/// Name of the provider checking the local password hashes
+pub(super) const NAME: &str = "local password";
+
+/// Checks passwords against the argon2 hashes in the database.
+pub(super) struct Local;
+
+#[async_trait]
+impl AuthProvider for Local {
+	fn name(&self) -> &'static str { NAME }
+
+	async fn check_password(&self, user_id: &UserId, password: &str) -> Result<PasswordCheck> {
+		let Some(hash) = services().users.password_hash(user_id)? else {
+			return Ok(PasswordCheck::Unknown);
+		};
+
+		// deactivated
+		if hash.is_empty() {
+			return Ok(PasswordCheck::Denied);
+		}
+
+		let Ok(parsed_hash) = PasswordHash::new(&hash) else {
+			error!("error while hashing user {}", user_id);
+			return Err(Error::BadServerResponse("could not hash"));
+		};
+
+		let hash_matches = services()
+			.globals
+			.argon
+			.verify_password(password.as_bytes(), &parsed_hash)
+			.is_ok();
+
+		Ok(if hash_matches {
+			PasswordCheck::Accepted(AuthenticatedUser::default())
+		} else {
+			PasswordCheck::Denied
+		})
+	}
+}
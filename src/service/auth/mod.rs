@@ -0,0 +1,280 @@
+#[cfg(feature = "ldap")]
+mod ldap;
+mod local;
+
+use async_trait::async_trait;
+use ruma::{events::room::message::RoomMessageEventContent, UserId};
+use tracing::{debug, info, warn};
+
+use crate::{api::client_server::leave_room, service, services, Config, Result};
+
+/// A source of truth for user passwords.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+	/// Name of the provider, used in logs
+	fn name(&self) -> &'static str;
+
+	/// Checks the password of a local user.
+	async fn check_password(&self, user_id: &UserId, password: &str) -> Result<PasswordCheck>;
+}
+
+pub enum PasswordCheck {
+	/// The provider does not know the user, the next provider is asked
+	Unknown,
+
+	/// The provider knows the user but the password is wrong
+	Denied,
+
+	/// The password is correct
+	Accepted(AuthenticatedUser),
+}
+
+/// What a provider knows about a user whose password it accepted.
+#[derive(Default)]
+pub struct AuthenticatedUser {
+	/// Create the account if it does not exist yet
+	pub provision: bool,
+
+	/// Display name for newly provisioned accounts
+	pub displayname: Option<String>,
+
+	/// Whether the user should be in the admin room, if the provider manages
+	/// that
+	pub admin: Option<bool>,
+}
+
+pub struct Service {
+	pub providers: Vec<Box<dyn AuthProvider>>,
+}
+
+impl Service {
+	pub fn build(config: &Config) -> Self {
+		let mut providers: Vec<Box<dyn AuthProvider>> = Vec::new();
+
+		#[cfg(feature = "ldap")]
+		if let Some(ldap) = &config.ldap {
+			providers.push(Box::new(ldap::Ldap::new(ldap.clone())));
+		}
+		#[cfg(not(feature = "ldap"))]
+		let _ = config;
+
+		providers.push(Box::new(local::Local));
+
+		Self {
+			providers,
+		}
+	}
+
+	/// Checks the password of a local user against the configured providers,
+	/// in order, until one of them knows the user. Providers that fail are
+	/// skipped, so users can still log in with their local password when the
+	/// LDAP server is down.
+	///
+	/// Accounts a provider other than the local one accepted before are only
+	/// checked by that provider, so directory users can't log in with a local
+	/// password when it is down or doesn't know them anymore.
+	///
+	/// - Provisions the account if the accepting provider allows it
+	/// - Grants or revokes admin privileges if the accepting provider manages
+	///   them
+	pub async fn check_password(&self, user_id: &UserId, password: &str) -> Result<bool> {
+		if user_id.server_name() != services().globals.server_name() {
+			return Ok(false);
+		}
+
+		if services().users.exists(user_id)? && services().users.is_deactivated(user_id)? {
+			return Ok(false);
+		}
+
+		let owner = services().users.auth_provider(user_id)?;
+		match check_providers(&self.providers, owner.as_deref(), user_id, password).await {
+			Some((provider, PasswordCheck::Accepted(user))) => self.sync_user(user_id, user, provider).await,
+			Some((provider, _)) => {
+				debug!("{provider} rejected the password of {user_id}");
+				Ok(false)
+			},
+			None => Ok(false),
+		}
+	}
+
+	/// Creates the account of a user a provider accepted and applies what the
+	/// provider knows about them. Returns false if the account does not exist
+	/// and the provider does not allow creating it.
+	async fn sync_user(&self, user_id: &UserId, user: AuthenticatedUser, provider: &str) -> Result<bool> {
+		if !services().users.exists(user_id)? {
+			if !user.provision {
+				debug!("{provider} accepted {user_id}, but the account does not exist and provisioning is disabled");
+				return Ok(false);
+			}
+
+			services()
+				.users
+				.provision(user_id, user.displayname.clone())
+				.await?;
+
+			info!("New user \"{user_id}\" registered on this server via {provider}.");
+			services()
+				.admin
+				.send_message(RoomMessageEventContent::notice_plain(format!(
+					"New user \"{user_id}\" registered on this server via {provider}."
+				)));
+		}
+
+		if provider != local::NAME && services().users.auth_provider(user_id)?.is_none() {
+			services().users.set_auth_provider(user_id, provider)?;
+			info!("The password of {user_id} is managed by {provider} from now on");
+		}
+
+		match user.admin {
+			Some(true) if !services().users.is_admin(user_id)? => {
+				let displayname = services()
+					.users
+					.displayname(user_id)?
+					.unwrap_or_else(|| user_id.localpart().to_owned());
+
+				services()
+					.admin
+					.make_user_admin(user_id, displayname)
+					.await?;
+
+				warn!("Granting {user_id} admin privileges as mapped by {provider}");
+			},
+			Some(false) if services().users.is_admin(user_id)? => {
+				if let Some(admin_room) = service::admin::Service::get_admin_room()? {
+					leave_room(user_id, &admin_room, Some(format!("Admin privileges revoked by {provider}"))).await?;
+
+					warn!("Revoking admin privileges of {user_id} as mapped by {provider}");
+				}
+			},
+			_ => {},
+		}
+
+		Ok(true)
+	}
+}
+
+/// Asks the providers in order until one of them knows the user. Providers
+/// that fail, e.g. because their server is unreachable, are skipped. If a
+/// provider owns the account, only it is asked.
+async fn check_providers(
+	providers: &[Box<dyn AuthProvider>], owner: Option<&str>, user_id: &UserId, password: &str,
+) -> Option<(&'static str, PasswordCheck)> {
+	for provider in providers
+		.iter()
+		.filter(|provider| owner.map_or(true, |owner| provider.name() == owner))
+	{
+		match provider.check_password(user_id, password).await {
+			Ok(PasswordCheck::Unknown) => {},
+			Ok(check) => return Some((provider.name(), check)),
+			Err(e) => warn!("Failed to check the password of {user_id} with {}: {e}", provider.name()),
+		}
+	}
+
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use async_trait::async_trait;
+	use ruma::{user_id, UserId};
+
+	use super::{check_providers, AuthProvider, AuthenticatedUser, PasswordCheck};
+	use crate::{Error, Result};
+
+	enum Mock {
+		Down,
+		Unknown,
+		Password(&'static str),
+	}
+
+	#[async_trait]
+	impl AuthProvider for Mock {
+		fn name(&self) -> &'static str {
+			match self {
+				Self::Down => "down",
+				Self::Unknown => "unknown",
+				Self::Password(_) => "password",
+			}
+		}
+
+		async fn check_password(&self, _user_id: &UserId, password: &str) -> Result<PasswordCheck> {
+			match self {
+				Self::Down => Err(Error::BadServerResponse("Connection refused.")),
+				Self::Unknown => Ok(PasswordCheck::Unknown),
+				Self::Password(expected) if password == *expected => {
+					Ok(PasswordCheck::Accepted(AuthenticatedUser::default()))
+				},
+				Self::Password(_) => Ok(PasswordCheck::Denied),
+			}
+		}
+	}
+
+	async fn check(providers: Vec<Box<dyn AuthProvider>>, password: &str) -> Option<(&'static str, bool)> {
+		check_owned(providers, None, password).await
+	}
+
+	async fn check_owned(
+		providers: Vec<Box<dyn AuthProvider>>, owner: Option<&str>, password: &str,
+	) -> Option<(&'static str, bool)> {
+		check_providers(&providers, owner, user_id!("@alice:example.com"), password)
+			.await
+			.map(|(name, check)| (name, matches!(check, PasswordCheck::Accepted(_))))
+	}
+
+	#[tokio::test]
+	async fn first_provider_knowing_the_user_decides() {
+		assert_eq!(
+			check(vec![Box::new(Mock::Unknown), Box::new(Mock::Password("hunter2"))], "hunter2").await,
+			Some(("password", true))
+		);
+		assert_eq!(
+			check(
+				vec![Box::new(Mock::Password("other")), Box::new(Mock::Password("hunter2"))],
+				"hunter2"
+			)
+			.await,
+			Some(("password", false))
+		);
+		assert_eq!(check(vec![Box::new(Mock::Unknown)], "hunter2").await, None);
+	}
+
+	#[tokio::test]
+	async fn unreachable_provider_falls_through() {
+		assert_eq!(
+			check(vec![Box::new(Mock::Down), Box::new(Mock::Password("hunter2"))], "hunter2").await,
+			Some(("password", true))
+		);
+		assert_eq!(check(vec![Box::new(Mock::Down)], "hunter2").await, None);
+	}
+
+	#[tokio::test]
+	async fn owned_accounts_only_checked_by_owner() {
+		assert_eq!(
+			check_owned(
+				vec![Box::new(Mock::Down), Box::new(Mock::Password("hunter2"))],
+				Some("down"),
+				"hunter2"
+			)
+			.await,
+			None
+		);
+		assert_eq!(
+			check_owned(
+				vec![Box::new(Mock::Unknown), Box::new(Mock::Password("hunter2"))],
+				Some("unknown"),
+				"hunter2"
+			)
+			.await,
+			None
+		);
+		assert_eq!(
+			check_owned(
+				vec![Box::new(Mock::Unknown), Box::new(Mock::Password("hunter2"))],
+				Some("password"),
+				"hunter2"
+			)
+			.await,
+			Some(("password", true))
+		);
+	}
+}
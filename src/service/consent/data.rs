@@ -0,0 +1,24 @@
+use std::collections::BTreeMap;
+
+use ruma::{OwnedUserId, UserId};
+
+use crate::Result;
+
+pub trait Data: Send + Sync {
+	/// Returns the policy versions a user accepted, keyed by policy name.
+	fn accepted_versions(&self, user_id: &UserId) -> Result<BTreeMap<String, String>>;
+
+	fn set_accepted_versions(&self, user_id: &UserId, versions: &BTreeMap<String, String>) -> Result<()>;
+
+	/// Returns the policy versions a user was last sent a consent notice for.
+	fn notified_versions(&self, user_id: &UserId) -> Result<BTreeMap<String, String>>;
+
+	fn set_notified_versions(&self, user_id: &UserId, versions: &BTreeMap<String, String>) -> Result<()>;
+
+	/// Returns the user a consent link token was created for.
+	fn consent_token_user(&self, token: &str) -> Result<Option<OwnedUserId>>;
+
+	fn set_consent_token(&self, token: &str, user_id: &UserId) -> Result<()>;
+
+	fn remove_consent_token(&self, token: &str) -> Result<()>;
+}
@@ -0,0 +1,143 @@
+mod data;
+
+use std::collections::BTreeMap;
+
+pub use data::Data;
+use ruma::{api::client::error::ErrorKind, OwnedUserId, UserId};
+use serde_json::{json, value::to_raw_value};
+use tracing::info;
+use url::Url;
+
+use crate::{api::client_server::TOKEN_LENGTH, service::server_notices, services, utils, Error, Result};
+
+/// Path of the page users accept updated policies on
+pub const CONSENT_PATH: &str = "/_conduwuit/consent";
+
+pub struct Service {
+	pub db: &'static dyn Data,
+}
+
+impl Service {
+	/// Whether users have to accept policies to use the server.
+	pub fn is_enabled(&self) -> bool { !services().globals.config.get().policies.is_empty() }
+
+	/// The parameters of the `m.login.terms` UIAA stage, listing the current
+	/// policies.
+	pub fn terms_params(&self) -> Box<serde_json::value::RawValue> {
+		to_raw_value(&json!({
+			"m.login.terms": {
+				"policies": services().globals.config.get().policies,
+			}
+		}))
+		.expect("policies serialize to json")
+	}
+
+	/// Records that a user accepted the current version of every policy.
+	pub fn accept(&self, user_id: &UserId) -> Result<()> {
+		self.db
+			.set_accepted_versions(user_id, &current_versions())?;
+
+		info!("{user_id} accepted the policies of this server");
+
+		Ok(())
+	}
+
+	pub fn has_accepted(&self, user_id: &UserId) -> Result<bool> {
+		let accepted = self.db.accepted_versions(user_id)?;
+
+		Ok(services()
+			.globals
+			.config
+			.get()
+			.policies
+			.iter()
+			.all(|(name, policy)| accepted.get(name) == Some(&policy.version)))
+	}
+
+	/// Fails if a user hasn't accepted the current policies yet, sending them a
+	/// link to accept them with [`Service::notify`].
+	///
+	/// Appservice users and the server accounts are exempt.
+	pub async fn check(&self, user_id: &UserId) -> Result<()> {
+		if !self.is_enabled()
+			|| server_notices::is_server_account(user_id)
+			|| self.has_accepted(user_id)?
+			|| services().appservice.is_exclusive_user_id(user_id).await
+		{
+			return Ok(());
+		}
+
+		self.notify(user_id).await?;
+
+		Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"You have to accept the updated policies of this server to continue, see your server notices.",
+		))
+	}
+
+	/// Sends a user a server notice with a link to accept the current policies,
+	/// unless they were already sent one for these policy versions.
+	pub async fn notify(&self, user_id: &UserId) -> Result<()> {
+		let versions = current_versions();
+		if self.db.notified_versions(user_id)? == versions {
+			return Ok(());
+		}
+
+		let token = utils::random_string(TOKEN_LENGTH);
+		self.db.set_consent_token(&token, user_id)?;
+
+		let mut url = consent_url()?;
+		url.query_pairs_mut().append_pair("token", &token);
+
+		services()
+			.server_notices
+			.send_notice(
+				user_id,
+				server_notices::message_notice(format!(
+					"The policies of {} have changed. To continue using this server, please review and accept them \
+					 here: {url}",
+					services().globals.server_name()
+				)),
+			)
+			.await?;
+
+		self.db.set_notified_versions(user_id, &versions)
+	}
+
+	/// Returns the user a link to the consent page was created for.
+	pub fn token_user(&self, token: &str) -> Result<Option<OwnedUserId>> { self.db.consent_token_user(token) }
+
+	/// Accepts the current policies for the user a link to the consent page was
+	/// created for. Consumes the token.
+	pub fn accept_with_token(&self, token: &str) -> Result<Option<OwnedUserId>> {
+		let Some(user_id) = self.db.consent_token_user(token)? else {
+			return Ok(None);
+		};
+
+		self.accept(&user_id)?;
+		self.db.remove_consent_token(token)?;
+
+		Ok(Some(user_id))
+	}
+}
+
+fn current_versions() -> BTreeMap<String, String> {
+	services()
+		.globals
+		.config
+		.get()
+		.policies
+		.iter()
+		.map(|(name, policy)| (name.clone(), policy.version.clone()))
+		.collect()
+}
+
+fn consent_url() -> Result<Url> {
+	services()
+		.globals
+		.well_known_client()
+		.as_ref()
+		.ok_or_else(|| Error::bad_config("\"well_known.client\" must be set to require accepting policies."))?
+		.join(CONSENT_PATH)
+		.map_err(|_| Error::bad_config("\"well_known.client\" is not a valid base URL."))
+}
@@ -29,7 +29,7 @@ use tracing::{error, info};
 use tracing_subscriber::{EnvFilter, Registry};
 use url::Url;
 
-use crate::{services, Config, Result};
+use crate::{config::LiveConfig, services, Config, Result};
 
 pub mod client;
 mod data;
@@ -45,7 +45,10 @@ pub struct Service<'a> {
 	pub db: &'static dyn Data,
 
 	pub tracing_reload_handle: tracing_subscriber::reload::Handle<EnvFilter, Registry>,
-	pub config: Config,
+	pub config: LiveConfig,
+	/// Kept apart from the config, which is swapped out on reload, as it can't
+	/// change at runtime anyway
+	server_name: OwnedServerName,
 	keypair: Arc<ruma::signatures::Ed25519KeyPair>,
 	jwt_decoding_key: Option<jsonwebtoken::DecodingKey>,
 	pub resolver: Arc<resolver::Resolver>,
@@ -138,10 +141,19 @@ impl Service<'_> {
 			argon2::Params::new(19456, 2, 1, None).expect("valid parameters"),
 		);
 
-		let mut s = Self {
+		let mut running_config = config.clone();
+		if !stable_room_versions.contains(&config.default_room_version)
+			&& !(config.allow_unstable_room_versions && unstable_room_versions.contains(&config.default_room_version))
+		{
+			error!(config=?config.default_room_version, fallback=?crate::config::default_default_room_version(), "Room version in config isn't supported, falling back to default version");
+			running_config.default_room_version = crate::config::default_default_room_version();
+		};
+
+		let s = Self {
 			tracing_reload_handle,
 			db,
-			config: config.clone(),
+			config: LiveConfig::new(running_config),
+			server_name: config.server_name.clone(),
 			keypair: Arc::new(keypair),
 			resolver: resolver.clone(),
 			client: client::Client::new(config, &resolver),
@@ -165,14 +177,6 @@ impl Service<'_> {
 
 		fs::create_dir_all(s.get_media_folder())?;
 
-		if !s
-			.supported_room_versions()
-			.contains(&s.config.default_room_version)
-		{
-			error!(config=?s.config.default_room_version, fallback=?crate::config::default_default_room_version(), "Room version in config isn't supported, falling back to default version");
-			s.config.default_room_version = crate::config::default_default_room_version();
-		};
-
 		Ok(s)
 	}
 
@@ -199,147 +203,167 @@ impl Service<'_> {
 
 	pub fn flush(&self) -> Result<()> { self.db.flush() }
 
-	pub fn server_name(&self) -> &ServerName { self.config.server_name.as_ref() }
+	pub fn server_name(&self) -> &ServerName { &self.server_name }
 
-	pub fn max_request_size(&self) -> u32 { self.config.max_request_size }
+	pub fn max_request_size(&self) -> u32 { self.config.get().max_request_size }
 
-	pub fn max_fetch_prev_events(&self) -> u16 { self.config.max_fetch_prev_events }
+	pub fn max_fetch_prev_events(&self) -> u16 { self.config.get().max_fetch_prev_events }
 
-	pub fn allow_registration(&self) -> bool { self.config.allow_registration }
+	pub fn allow_registration(&self) -> bool { self.config.get().allow_registration }
 
-	pub fn allow_guest_registration(&self) -> bool { self.config.allow_guest_registration }
+	pub fn allow_guest_registration(&self) -> bool { self.config.get().allow_guest_registration }
 
-	pub fn allow_guests_auto_join_rooms(&self) -> bool { self.config.allow_guests_auto_join_rooms }
+	pub fn allow_guests_auto_join_rooms(&self) -> bool { self.config.get().allow_guests_auto_join_rooms }
 
-	pub fn log_guest_registrations(&self) -> bool { self.config.log_guest_registrations }
+	pub fn log_guest_registrations(&self) -> bool { self.config.get().log_guest_registrations }
 
-	pub fn allow_encryption(&self) -> bool { self.config.allow_encryption }
+	pub fn allow_encryption(&self) -> bool { self.config.get().allow_encryption }
 
-	pub fn allow_federation(&self) -> bool { self.config.allow_federation }
+	pub fn allow_federation(&self) -> bool { self.config.get().allow_federation }
 
 	pub fn allow_public_room_directory_over_federation(&self) -> bool {
-		self.config.allow_public_room_directory_over_federation
+		self.config
+			.get()
+			.allow_public_room_directory_over_federation
 	}
 
 	pub fn allow_public_room_directory_without_auth(&self) -> bool {
-		self.config.allow_public_room_directory_without_auth
+		self.config.get().allow_public_room_directory_without_auth
 	}
 
-	pub fn allow_device_name_federation(&self) -> bool { self.config.allow_device_name_federation }
+	pub fn allow_device_name_federation(&self) -> bool { self.config.get().allow_device_name_federation }
 
-	pub fn allow_room_creation(&self) -> bool { self.config.allow_room_creation }
+	pub fn allow_room_creation(&self) -> bool { self.config.get().allow_room_creation }
 
-	pub fn allow_unstable_room_versions(&self) -> bool { self.config.allow_unstable_room_versions }
+	pub fn allow_unstable_room_versions(&self) -> bool { self.config.get().allow_unstable_room_versions }
 
-	pub fn default_room_version(&self) -> RoomVersionId { self.config.default_room_version.clone() }
+	pub fn default_room_version(&self) -> RoomVersionId { self.config.get().default_room_version.clone() }
 
-	pub fn new_user_displayname_suffix(&self) -> &String { &self.config.new_user_displayname_suffix }
+	pub fn new_user_displayname_suffix(&self) -> String { self.config.get().new_user_displayname_suffix.clone() }
 
-	pub fn allow_check_for_updates(&self) -> bool { self.config.allow_check_for_updates }
+	pub fn allow_check_for_updates(&self) -> bool { self.config.get().allow_check_for_updates }
 
-	pub fn trusted_servers(&self) -> &[OwnedServerName] { &self.config.trusted_servers }
+	pub fn trusted_servers(&self) -> Vec<OwnedServerName> { self.config.get().trusted_servers.clone() }
 
-	pub fn query_trusted_key_servers_first(&self) -> bool { self.config.query_trusted_key_servers_first }
+	pub fn query_trusted_key_servers_first(&self) -> bool { self.config.get().query_trusted_key_servers_first }
 
 	pub fn dns_resolver(&self) -> &TokioAsyncResolver { &self.resolver.resolver }
 
-	pub fn query_all_nameservers(&self) -> bool { self.config.query_all_nameservers }
+	pub fn query_all_nameservers(&self) -> bool { self.config.get().query_all_nameservers }
 
 	pub fn actual_destinations(&self) -> &Arc<RwLock<resolver::WellKnownMap>> { &self.resolver.destinations }
 
 	pub fn jwt_decoding_key(&self) -> Option<&jsonwebtoken::DecodingKey> { self.jwt_decoding_key.as_ref() }
 
-	pub fn turn_password(&self) -> &String { &self.config.turn_password }
+	pub fn turn_password(&self) -> String { self.config.get().turn_password.clone() }
 
-	pub fn turn_ttl(&self) -> u64 { self.config.turn_ttl }
+	pub fn turn_ttl(&self) -> u64 { self.config.get().turn_ttl }
 
-	pub fn turn_uris(&self) -> &[String] { &self.config.turn_uris }
+	pub fn turn_uris(&self) -> Vec<String> { self.config.get().turn_uris.clone() }
 
-	pub fn turn_username(&self) -> &String { &self.config.turn_username }
+	pub fn turn_username(&self) -> String { self.config.get().turn_username.clone() }
 
-	pub fn turn_secret(&self) -> &String { &self.config.turn_secret }
+	pub fn turn_secret(&self) -> String { self.config.get().turn_secret.clone() }
 
-	pub fn auto_join_rooms(&self) -> &[OwnedRoomId] { &self.config.auto_join_rooms }
+	pub fn auto_join_rooms(&self) -> Vec<OwnedRoomId> { self.config.get().auto_join_rooms.clone() }
 
 	pub fn allow_profile_lookup_federation_requests(&self) -> bool {
-		self.config.allow_profile_lookup_federation_requests
+		self.config.get().allow_profile_lookup_federation_requests
 	}
 
-	pub fn notification_push_path(&self) -> &String { &self.config.notification_push_path }
+	pub fn notification_push_path(&self) -> String { self.config.get().notification_push_path.clone() }
 
-	pub fn emergency_password(&self) -> &Option<String> { &self.config.emergency_password }
+	pub fn emergency_password(&self) -> Option<String> { self.config.get().emergency_password.clone() }
 
-	pub fn url_preview_domain_contains_allowlist(&self) -> &Vec<String> {
-		&self.config.url_preview_domain_contains_allowlist
+	pub fn url_preview_domain_contains_allowlist(&self) -> Vec<String> {
+		self.config
+			.get()
+			.url_preview_domain_contains_allowlist
+			.clone()
 	}
 
-	pub fn url_preview_domain_explicit_allowlist(&self) -> &Vec<String> {
-		&self.config.url_preview_domain_explicit_allowlist
+	pub fn url_preview_domain_explicit_allowlist(&self) -> Vec<String> {
+		self.config
+			.get()
+			.url_preview_domain_explicit_allowlist
+			.clone()
 	}
 
-	pub fn url_preview_domain_explicit_denylist(&self) -> &Vec<String> {
-		&self.config.url_preview_domain_explicit_denylist
+	pub fn url_preview_domain_explicit_denylist(&self) -> Vec<String> {
+		self.config
+			.get()
+			.url_preview_domain_explicit_denylist
+			.clone()
 	}
 
-	pub fn url_preview_url_contains_allowlist(&self) -> &Vec<String> { &self.config.url_preview_url_contains_allowlist }
+	pub fn url_preview_url_contains_allowlist(&self) -> Vec<String> {
+		self.config.get().url_preview_url_contains_allowlist.clone()
+	}
 
-	pub fn url_preview_max_spider_size(&self) -> usize { self.config.url_preview_max_spider_size }
+	pub fn url_preview_max_spider_size(&self) -> usize { self.config.get().url_preview_max_spider_size }
 
-	pub fn url_preview_check_root_domain(&self) -> bool { self.config.url_preview_check_root_domain }
+	pub fn url_preview_check_root_domain(&self) -> bool { self.config.get().url_preview_check_root_domain }
 
-	pub fn forbidden_alias_names(&self) -> &RegexSet { &self.config.forbidden_alias_names }
+	pub fn forbidden_alias_names(&self) -> RegexSet { self.config.get().forbidden_alias_names.clone() }
 
-	pub fn forbidden_usernames(&self) -> &RegexSet { &self.config.forbidden_usernames }
+	pub fn forbidden_usernames(&self) -> RegexSet { self.config.get().forbidden_usernames.clone() }
 
-	pub fn allow_local_presence(&self) -> bool { self.config.allow_local_presence }
+	pub fn allow_local_presence(&self) -> bool { self.config.get().allow_local_presence }
 
-	pub fn allow_incoming_presence(&self) -> bool { self.config.allow_incoming_presence }
+	pub fn allow_incoming_presence(&self) -> bool { self.config.get().allow_incoming_presence }
 
-	pub fn allow_outgoing_presence(&self) -> bool { self.config.allow_outgoing_presence }
+	pub fn allow_outgoing_presence(&self) -> bool { self.config.get().allow_outgoing_presence }
 
-	pub fn presence_idle_timeout_s(&self) -> u64 { self.config.presence_idle_timeout_s }
+	pub fn presence_idle_timeout_s(&self) -> u64 { self.config.get().presence_idle_timeout_s }
 
-	pub fn presence_offline_timeout_s(&self) -> u64 { self.config.presence_offline_timeout_s }
+	pub fn presence_offline_timeout_s(&self) -> u64 { self.config.get().presence_offline_timeout_s }
 
-	pub fn allow_incoming_read_receipts(&self) -> bool { self.config.allow_incoming_read_receipts }
+	pub fn allow_incoming_read_receipts(&self) -> bool { self.config.get().allow_incoming_read_receipts }
 
-	pub fn allow_outgoing_read_receipts(&self) -> bool { self.config.allow_outgoing_read_receipts }
+	pub fn allow_outgoing_read_receipts(&self) -> bool { self.config.get().allow_outgoing_read_receipts }
 
-	pub fn rocksdb_log_level(&self) -> &String { &self.config.rocksdb_log_level }
+	pub fn rocksdb_log_level(&self) -> String { self.config.get().rocksdb_log_level.clone() }
 
-	pub fn rocksdb_max_log_file_size(&self) -> usize { self.config.rocksdb_max_log_file_size }
+	pub fn rocksdb_max_log_file_size(&self) -> usize { self.config.get().rocksdb_max_log_file_size }
 
-	pub fn rocksdb_log_time_to_roll(&self) -> usize { self.config.rocksdb_log_time_to_roll }
+	pub fn rocksdb_log_time_to_roll(&self) -> usize { self.config.get().rocksdb_log_time_to_roll }
 
-	pub fn rocksdb_optimize_for_spinning_disks(&self) -> bool { self.config.rocksdb_optimize_for_spinning_disks }
+	pub fn rocksdb_optimize_for_spinning_disks(&self) -> bool { self.config.get().rocksdb_optimize_for_spinning_disks }
 
-	pub fn rocksdb_parallelism_threads(&self) -> usize { self.config.rocksdb_parallelism_threads }
+	pub fn rocksdb_parallelism_threads(&self) -> usize { self.config.get().rocksdb_parallelism_threads }
 
-	pub fn rocksdb_compression_algo(&self) -> &String { &self.config.rocksdb_compression_algo }
+	pub fn rocksdb_compression_algo(&self) -> String { self.config.get().rocksdb_compression_algo.clone() }
 
-	pub fn rocksdb_compression_level(&self) -> i32 { self.config.rocksdb_compression_level }
+	pub fn rocksdb_compression_level(&self) -> i32 { self.config.get().rocksdb_compression_level }
 
-	pub fn rocksdb_bottommost_compression_level(&self) -> i32 { self.config.rocksdb_bottommost_compression_level }
+	pub fn rocksdb_bottommost_compression_level(&self) -> i32 { self.config.get().rocksdb_bottommost_compression_level }
 
-	pub fn prevent_media_downloads_from(&self) -> &[OwnedServerName] { &self.config.prevent_media_downloads_from }
+	pub fn prevent_media_downloads_from(&self) -> Vec<OwnedServerName> {
+		self.config.get().prevent_media_downloads_from.clone()
+	}
 
-	pub fn forbidden_remote_server_names(&self) -> &[OwnedServerName] { &self.config.forbidden_remote_server_names }
+	pub fn forbidden_remote_server_names(&self) -> Vec<OwnedServerName> {
+		self.config.get().forbidden_remote_server_names.clone()
+	}
 
-	pub fn forbidden_remote_room_directory_server_names(&self) -> &[OwnedServerName] {
-		&self.config.forbidden_remote_room_directory_server_names
+	pub fn forbidden_remote_room_directory_server_names(&self) -> Vec<OwnedServerName> {
+		self.config
+			.get()
+			.forbidden_remote_room_directory_server_names
+			.clone()
 	}
 
-	pub fn ip_range_denylist(&self) -> &[String] { &self.config.ip_range_denylist }
+	pub fn ip_range_denylist(&self) -> Vec<String> { self.config.get().ip_range_denylist.clone() }
 
-	pub fn well_known_support_page(&self) -> &Option<Url> { &self.config.well_known.support_page }
+	pub fn well_known_support_page(&self) -> Option<Url> { self.config.get().well_known.support_page.clone() }
 
-	pub fn well_known_support_role(&self) -> &Option<ContactRole> { &self.config.well_known.support_role }
+	pub fn well_known_support_role(&self) -> Option<ContactRole> { self.config.get().well_known.support_role.clone() }
 
-	pub fn well_known_support_email(&self) -> &Option<String> { &self.config.well_known.support_email }
+	pub fn well_known_support_email(&self) -> Option<String> { self.config.get().well_known.support_email.clone() }
 
-	pub fn well_known_support_mxid(&self) -> &Option<OwnedUserId> { &self.config.well_known.support_mxid }
+	pub fn well_known_support_mxid(&self) -> Option<OwnedUserId> { self.config.get().well_known.support_mxid.clone() }
 
-	pub fn block_non_admin_invites(&self) -> bool { self.config.block_non_admin_invites }
+	pub fn block_non_admin_invites(&self) -> bool { self.config.get().block_non_admin_invites }
 
 	pub fn supported_room_versions(&self) -> Vec<RoomVersionId> {
 		let mut room_versions: Vec<RoomVersionId> = vec![];
@@ -387,7 +411,7 @@ impl Service<'_> {
 
 	pub fn get_media_folder(&self) -> PathBuf {
 		let mut r = PathBuf::new();
-		r.push(self.config.database_path.clone());
+		r.push(self.config.get().database_path.clone());
 		r.push("media");
 		r
 	}
@@ -398,7 +422,7 @@ impl Service<'_> {
 	#[cfg(feature = "sha256_media")]
 	pub fn get_media_file_new(&self, key: &[u8]) -> PathBuf {
 		let mut r = PathBuf::new();
-		r.push(self.config.database_path.clone());
+		r.push(self.config.get().database_path.clone());
 		r.push("media");
 		// Using the hash of the base64 key as the filename
 		// This is to prevent the total length of the path from exceeding the maximum
@@ -412,17 +436,17 @@ impl Service<'_> {
 	/// key as the filename.
 	pub fn get_media_file(&self, key: &[u8]) -> PathBuf {
 		let mut r = PathBuf::new();
-		r.push(self.config.database_path.clone());
+		r.push(self.config.get().database_path.clone());
 		r.push("media");
 		r.push(general_purpose::URL_SAFE_NO_PAD.encode(key));
 		r
 	}
 
-	pub fn well_known_client(&self) -> &Option<Url> { &self.config.well_known.client }
+	pub fn well_known_client(&self) -> Option<Url> { self.config.get().well_known.client.clone() }
 
-	pub fn well_known_server(&self) -> &Option<OwnedServerName> { &self.config.well_known.server }
+	pub fn well_known_server(&self) -> Option<OwnedServerName> { self.config.get().well_known.server.clone() }
 
-	pub fn unix_socket_path(&self) -> &Option<PathBuf> { &self.config.unix_socket_path }
+	pub fn unix_socket_path(&self) -> Option<PathBuf> { self.config.get().unix_socket_path.clone() }
 
 	pub fn shutdown(&self) {
 		self.shutdown.store(true, atomic::Ordering::Relaxed);
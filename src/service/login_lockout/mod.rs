@@ -0,0 +1,220 @@
+use std::{
+	collections::HashMap,
+	fmt,
+	net::IpAddr,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	events::room::message::RoomMessageEventContent,
+	OwnedUserId, UserId,
+};
+use tracing::warn;
+
+use crate::{services, Error, Result};
+
+/// What failed password logins are counted for
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Target {
+	User(OwnedUserId),
+	Ip(IpAddr),
+}
+
+impl fmt::Display for Target {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::User(user_id) => write!(f, "{user_id}"),
+			Self::Ip(ip) => write!(f, "{ip}"),
+		}
+	}
+}
+
+struct Failures {
+	/// Consecutive failures
+	count: u32,
+	last: Instant,
+	locked_until: Option<Instant>,
+}
+
+/// A user or IP address that is currently locked out
+pub struct Lockout {
+	pub target: Target,
+	pub failures: u32,
+	pub remaining: Duration,
+}
+
+pub struct Service {
+	failures: Mutex<HashMap<Target, Failures>>,
+}
+
+impl Service {
+	pub fn build() -> Self {
+		Self {
+			failures: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Fails with `M_LIMIT_EXCEEDED` while the user or the IP address a login
+	/// comes from is locked out.
+	pub fn check(&self, user_id: &UserId, ip: Option<IpAddr>) -> Result<()> {
+		if !services().globals.config.get().login_lockout.enabled {
+			return Ok(());
+		}
+
+		let now = Instant::now();
+		let failures = self.failures.lock().unwrap();
+		let remaining = targets(user_id, ip)
+			.filter_map(|target| {
+				failures
+					.get(&target)?
+					.locked_until?
+					.checked_duration_since(now)
+			})
+			.max();
+
+		if let Some(remaining) = remaining {
+			return Err(Error::BadRequest(
+				ErrorKind::LimitExceeded {
+					retry_after: Some(RetryAfter::Delay(remaining)),
+				},
+				"Too many failed login attempts, try again later.",
+			));
+		}
+
+		Ok(())
+	}
+
+	/// Counts a failed password login for the user and the IP address it came
+	/// from, locking them out once they failed too often.
+	///
+	/// - Every failure past the limit doubles the lockout, up to `max_lockout`
+	/// - Notifies the admin room when `admin_notify_threshold` consecutive
+	///   failures are reached
+	pub fn record_failure(&self, user_id: &UserId, ip: Option<IpAddr>) {
+		let config = services().globals.config.get();
+		let config = &config.login_lockout;
+		if !config.enabled {
+			return;
+		}
+
+		let now = Instant::now();
+		let max_lockout = Duration::from_secs(config.max_lockout);
+		let mut notify = Vec::new();
+
+		{
+			let mut failures = self.failures.lock().unwrap();
+
+			// Forget failures nobody is locked out for anymore
+			failures.retain(|_, failures| {
+				failures.locked_until.is_some_and(|until| until > now)
+					|| now.duration_since(failures.last) <= max_lockout
+			});
+
+			for target in targets(user_id, ip) {
+				let max_attempts = match target {
+					Target::User(_) => config.max_attempts_per_user,
+					Target::Ip(_) => config.max_attempts_per_ip,
+				};
+
+				let entry = failures.entry(target.clone()).or_insert(Failures {
+					count: 0,
+					last: now,
+					locked_until: None,
+				});
+				entry.count = entry.count.saturating_add(1);
+				entry.last = now;
+
+				if entry.count >= max_attempts {
+					let lockout = lockout_duration(config.initial_lockout, entry.count - max_attempts).min(max_lockout);
+					entry.locked_until = Some(now + lockout);
+					warn!(
+						"Locking out {target} for {}s after {} failed login attempts",
+						lockout.as_secs(),
+						entry.count
+					);
+				}
+
+				if config.admin_notify_threshold != 0 && entry.count == config.admin_notify_threshold {
+					notify.push((target, entry.count));
+				}
+			}
+		}
+
+		for (target, count) in notify {
+			services()
+				.admin
+				.send_message(RoomMessageEventContent::notice_plain(format!(
+					"Possible brute-force attack: {count} consecutive failed password logins for {target}. Lockouts \
+					 can be listed with `users list-lockouts`."
+				)));
+		}
+	}
+
+	/// Forgets the failed logins of a user after they logged in successfully.
+	pub fn record_success(&self, user_id: &UserId) {
+		self.failures
+			.lock()
+			.unwrap()
+			.remove(&Target::User(user_id.to_owned()));
+	}
+
+	/// Returns the users and IP addresses that are currently locked out.
+	pub fn lockouts(&self) -> Vec<Lockout> {
+		let now = Instant::now();
+		let mut lockouts = self
+			.failures
+			.lock()
+			.unwrap()
+			.iter()
+			.filter_map(|(target, failures)| {
+				Some(Lockout {
+					target: target.clone(),
+					failures: failures.count,
+					remaining: failures.locked_until?.checked_duration_since(now)?,
+				})
+			})
+			.collect::<Vec<_>>();
+		lockouts.sort_unstable_by(|a, b| a.target.cmp(&b.target));
+
+		lockouts
+	}
+
+	/// Lifts the lockout of a user or IP address and forgets their failed
+	/// logins. Returns whether there were any.
+	pub fn clear(&self, target: &Target) -> bool { self.failures.lock().unwrap().remove(target).is_some() }
+}
+
+/// The user, and the IP address unless it is a loopback address, which is
+/// likely a reverse proxy
+fn targets(user_id: &UserId, ip: Option<IpAddr>) -> impl Iterator<Item = Target> {
+	[
+		Some(Target::User(user_id.to_owned())),
+		ip.filter(|ip| !ip.is_loopback()).map(Target::Ip),
+	]
+	.into_iter()
+	.flatten()
+}
+
+/// The initial lockout, doubled for every failure past the limit
+fn lockout_duration(initial_lockout: u64, failures_past_limit: u32) -> Duration {
+	let factor = 1_u64.checked_shl(failures_past_limit).unwrap_or(u64::MAX);
+
+	Duration::from_secs(initial_lockout.saturating_mul(factor))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::lockout_duration;
+
+	#[test]
+	fn lockout_doubles() {
+		assert_eq!(lockout_duration(60, 0), Duration::from_secs(60));
+		assert_eq!(lockout_duration(60, 1), Duration::from_secs(120));
+		assert_eq!(lockout_duration(60, 3), Duration::from_secs(480));
+		assert_eq!(lockout_duration(60, 100), Duration::from_secs(u64::MAX));
+	}
+}
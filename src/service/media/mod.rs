@@ -12,7 +12,7 @@ use tokio::{
 };
 use tracing::{debug, error};
 
-use crate::{services, utils, Error, Result};
+use crate::{service::admin, services, utils, Error, Result};
 
 #[derive(Debug)]
 pub struct FileMeta {
@@ -269,11 +269,13 @@ impl Service {
 			debug!("Deleting media now in the past \"{:?}\".", user_duration);
 
 			let mut deletion_count = 0;
+			let total = remote_mxcs.len();
 
 			for mxc in remote_mxcs {
 				debug!("Deleting MXC {mxc} from database and filesystem");
 				self.delete(mxc).await?;
 				deletion_count += 1;
+				admin::jobs::set_progress(format!("deleted {deletion_count} of {total} remote media files"));
 			}
 
 			Ok(deletion_count)
@@ -1,31 +1,46 @@
 use std::{
 	collections::{BTreeMap, HashMap},
+	fmt::Write as _,
 	sync::{Arc, Mutex as StdMutex},
 };
 
 use lru_cache::LruCache;
 use tokio::sync::{broadcast, Mutex, RwLock};
+use tracing_subscriber::EnvFilter;
 
-use crate::{Config, Result};
+use crate::{Config, Error, Result};
 
 pub(crate) mod account_data;
 pub(crate) mod admin;
 pub(crate) mod appservice;
+pub(crate) mod auth;
+pub(crate) mod consent;
 pub(crate) mod globals;
 pub(crate) mod key_backups;
+pub(crate) mod login_lockout;
 pub(crate) mod media;
 pub(crate) mod pdu;
 pub(crate) mod presence;
 pub(crate) mod pusher;
+pub(crate) mod rate_limit;
+pub(crate) mod registration_tokens;
 pub(crate) mod rooms;
 pub(crate) mod sending;
+pub(crate) mod server_notices;
+pub(crate) mod server_policy;
+pub(crate) mod sso;
+pub(crate) mod threepid;
 pub(crate) mod transaction_ids;
 pub(crate) mod uiaa;
 pub(crate) mod users;
 
 pub struct Services<'a> {
 	pub appservice: appservice::Service,
+	pub auth: auth::Service,
+	pub consent: consent::Service,
 	pub pusher: pusher::Service,
+	pub rate_limit: rate_limit::Service,
+	pub registration_tokens: registration_tokens::Service,
 	pub rooms: rooms::Service,
 	pub transaction_ids: transaction_ids::Service,
 	pub uiaa: uiaa::Service,
@@ -35,14 +50,20 @@ pub struct Services<'a> {
 	pub admin: Arc<admin::Service>,
 	pub globals: globals::Service<'a>,
 	pub key_backups: key_backups::Service,
+	pub login_lockout: login_lockout::Service,
 	pub media: media::Service,
 	pub sending: Arc<sending::Service>,
+	pub server_policy: server_policy::Service,
+	pub server_notices: server_notices::Service,
+	pub sso: sso::Service,
+	pub threepid: threepid::Service,
 }
 
 impl Services<'_> {
 	pub fn build<
 		D: appservice::Data
 			+ pusher::Data
+			+ registration_tokens::Data
 			+ rooms::Data
 			+ transaction_ids::Data
 			+ uiaa::Data
@@ -53,6 +74,11 @@ impl Services<'_> {
 			+ key_backups::Data
 			+ media::Data
 			+ sending::Data
+			+ server_policy::Data
+			+ server_notices::Data
+			+ consent::Data
+			+ sso::Data
+			+ threepid::Data
 			+ 'static,
 	>(
 		db: &'static D, config: &Config,
@@ -63,9 +89,18 @@ impl Services<'_> {
 	) -> Result<Self> {
 		Ok(Self {
 			appservice: appservice::Service::build(db)?,
+			auth: auth::Service::build(config),
+			consent: consent::Service {
+				db,
+			},
 			pusher: pusher::Service {
 				db,
 			},
+			rate_limit: rate_limit::Service::build(),
+			registration_tokens: registration_tokens::Service {
+				db,
+				pending: StdMutex::new(registration_tokens::PendingUses::default()),
+			},
 			rooms: rooms::Service {
 				alias: rooms::alias::Service {
 					db,
@@ -75,6 +110,7 @@ impl Services<'_> {
 				},
 				directory: rooms::directory::Service {
 					db,
+					public_room_index: StdMutex::new(rooms::directory::PublicRoomIndex::default()),
 				},
 				event_handler: rooms::event_handler::Service,
 				lazy_loading: rooms::lazy_loading::Service {
@@ -135,7 +171,8 @@ impl Services<'_> {
 					typing_update_sender: broadcast::channel(100).0,
 				},
 				spaces: rooms::spaces::Service {
-					roomid_spacehierarchy_cache: Mutex::new(LruCache::new(
+					db,
+					roomid_spacehierarchy_cache: StdMutex::new(LruCache::new(
 						(f64::from(config.roomid_spacehierarchy_cache_capacity)
 							* config.conduit_cache_capacity_modifier) as usize,
 					)),
@@ -162,17 +199,28 @@ impl Services<'_> {
 			key_backups: key_backups::Service {
 				db,
 			},
+			login_lockout: login_lockout::Service::build(),
 			media: media::Service {
 				db,
 				url_preview_mutex: RwLock::new(HashMap::new()),
 			},
 			sending: sending::Service::build(db, config),
+			server_policy: server_policy::Service::build(db)?,
+			server_notices: server_notices::Service {
+				db,
+			},
+			sso: sso::Service {
+				db,
+				pending_auth: StdMutex::new(HashMap::new()),
+				provider_metadata: StdMutex::new(HashMap::new()),
+			},
+			threepid: threepid::Service::build(db, config)?,
 
 			globals: globals::Service::load(db, config, tracing_reload_handle)?,
 		})
 	}
 
-	async fn memory_usage(&self) -> String {
+	pub(crate) async fn memory_usage(&self) -> String {
 		let lazy_load_waiting = self.rooms.lazy_loading.lazy_load_waiting.lock().await.len();
 		let server_visibility_cache = self
 			.rooms
@@ -207,7 +255,7 @@ impl Services<'_> {
 			.spaces
 			.roomid_spacehierarchy_cache
 			.lock()
-			.await
+			.unwrap()
 			.len();
 		let resolver_overrides_cache = self.globals.resolver.overrides.read().unwrap().len();
 		let resolver_destinations_cache = self.globals.resolver.destinations.read().await.len();
@@ -234,7 +282,7 @@ bad_signature_ratelimiter: {bad_signature_ratelimiter}
 		)
 	}
 
-	async fn clear_caches(&self, amount: u32) {
+	pub(crate) async fn clear_caches(&self, amount: u32) {
 		if amount > 0 {
 			self.rooms
 				.lazy_loading
@@ -280,7 +328,7 @@ bad_signature_ratelimiter: {bad_signature_ratelimiter}
 				.spaces
 				.roomid_spacehierarchy_cache
 				.lock()
-				.await
+				.unwrap()
 				.clear();
 		}
 		if amount > 6 {
@@ -303,4 +351,64 @@ bad_signature_ratelimiter: {bad_signature_ratelimiter}
 			self.globals.bad_signature_ratelimiter.write().await.clear();
 		}
 	}
+
+	/// Re-reads the config file and environment and applies the options that
+	/// can change at runtime. Returns a summary of the changed options.
+	pub fn reload_config(&self) -> Result<String> {
+		let reload = self.globals.config.get().reload()?;
+		let config = &reload.config;
+
+		if reload.applied.iter().any(|option| option == "log") {
+			let filter = EnvFilter::try_new(&config.log)
+				.map_err(|e| Error::BadConfig(format!("Invalid log level filter in config: {e}")))?;
+			self.globals
+				.tracing_reload_handle
+				.modify(|current| *current = filter)
+				.map_err(|e| Error::Error(format!("Failed to reload the log level: {e}")))?;
+		}
+
+		let capacity = |capacity: u32| (f64::from(capacity) * config.conduit_cache_capacity_modifier) as usize;
+		self.rooms
+			.state_accessor
+			.server_visibility_cache
+			.lock()
+			.unwrap()
+			.set_capacity(capacity(config.server_visibility_cache_capacity));
+		self.rooms
+			.state_accessor
+			.user_visibility_cache
+			.lock()
+			.unwrap()
+			.set_capacity(capacity(config.user_visibility_cache_capacity));
+		self.rooms
+			.state_compressor
+			.stateinfo_cache
+			.lock()
+			.unwrap()
+			.set_capacity(capacity(config.stateinfo_cache_capacity));
+		self.rooms
+			.spaces
+			.roomid_spacehierarchy_cache
+			.lock()
+			.unwrap()
+			.set_capacity(capacity(config.roomid_spacehierarchy_cache_capacity));
+
+		let mut msg = if reload.applied.is_empty() {
+			"Reloaded the config, no options changed.".to_owned()
+		} else {
+			format!("Reloaded the config, applied: {}.", reload.applied.join(", "))
+		};
+		if !reload.needs_restart.is_empty() {
+			write!(
+				msg,
+				" Changed options that need a restart to take effect: {}.",
+				reload.needs_restart.join(", ")
+			)
+			.expect("write to String works");
+		}
+
+		self.globals.config.replace(reload.config);
+
+		Ok(msg)
+	}
 }
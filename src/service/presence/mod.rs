@@ -154,8 +154,8 @@ impl Service {
 
 		if self.timeout_remote_users || user_id.server_name() == services().globals.server_name() {
 			let timeout = match presence_state {
-				PresenceState::Online => services().globals.config.presence_idle_timeout_s,
-				_ => services().globals.config.presence_offline_timeout_s,
+				PresenceState::Online => services().globals.config.get().presence_idle_timeout_s,
+				_ => services().globals.config.get().presence_offline_timeout_s,
 			};
 
 			self.timer_sender
@@ -212,8 +212,8 @@ async fn presence_timer(user_id: OwnedUserId, timeout: Duration) -> OwnedUserId
 }
 
 fn process_presence_timer(user_id: &OwnedUserId) -> Result<()> {
-	let idle_timeout = services().globals.config.presence_idle_timeout_s * 1_000;
-	let offline_timeout = services().globals.config.presence_offline_timeout_s * 1_000;
+	let idle_timeout = services().globals.config.get().presence_idle_timeout_s * 1_000;
+	let offline_timeout = services().globals.config.get().presence_offline_timeout_s * 1_000;
 
 	let mut presence_state = PresenceState::Offline;
 	let mut last_active_ago = None;
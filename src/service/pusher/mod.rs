@@ -48,7 +48,7 @@ impl Service {
 	where
 		T: OutgoingRequest + Debug,
 	{
-		let destination = destination.replace(services().globals.notification_push_path(), "");
+		let destination = destination.replace(&services().globals.notification_push_path(), "");
 
 		let http_request = request
 			.try_into_http_request::<BytesMut>(&destination, SendAccessToken::IfRequired(""), &[MatrixVersion::V1_0])
@@ -68,7 +68,7 @@ impl Service {
 		if let Some(url_host) = url.host_str() {
 			debug!("Checking request URL for IP");
 			if let Ok(ip) = IPAddress::parse(url_host) {
-				let cidr_ranges_s = services().globals.ip_range_denylist().to_vec();
+				let cidr_ranges_s = services().globals.ip_range_denylist();
 				let mut cidr_ranges: Vec<IPAddress> = Vec::new();
 
 				for cidr in cidr_ranges_s {
@@ -97,7 +97,7 @@ impl Service {
 				debug!("Checking response destination's IP");
 				if let Some(remote_addr) = response.remote_addr() {
 					if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
-						let cidr_ranges_s = services().globals.ip_range_denylist().to_vec();
+						let cidr_ranges_s = services().globals.ip_range_denylist();
 						let mut cidr_ranges: Vec<IPAddress> = Vec::new();
 
 						for cidr in cidr_ranges_s {
@@ -0,0 +1,174 @@
+use std::{
+	net::IpAddr,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use lru_cache::LruCache;
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	OwnedUserId, UserId,
+};
+
+use crate::{config::RateLimit, services, Error, Result};
+
+/// Number of tracked buckets above which the least recently used ones are
+/// forgotten
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// The kinds of requests that are limited separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bucket {
+	Message,
+	Join,
+	Invite,
+	Registration,
+	Login,
+	ThreepidRequest,
+	MediaUpload,
+}
+
+impl Bucket {
+	fn limit(self) -> RateLimit {
+		let config = services().globals.config.get();
+		let config = &config.rate_limits;
+		match self {
+			Self::Message => config.message,
+			Self::Join => config.join,
+			Self::Invite => config.invite,
+			Self::Registration => config.registration,
+			Self::Login => config.login,
+			Self::ThreepidRequest => config.threepid_request,
+			Self::MediaUpload => config.media_upload,
+		}
+	}
+}
+
+/// Who a bucket belongs to
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Client {
+	User(OwnedUserId),
+	Ip(IpAddr),
+}
+
+struct TokenBucket {
+	tokens: f64,
+	last_refill: Instant,
+}
+
+impl TokenBucket {
+	fn new(limit: RateLimit, now: Instant) -> Self {
+		Self {
+			tokens: f64::from(limit.burst_count),
+			last_refill: now,
+		}
+	}
+
+	/// Takes a token out of the bucket, or returns how long it takes until the
+	/// next one is available.
+	fn take(&mut self, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+		let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+		self.tokens = elapsed
+			.mul_add(limit.per_second, self.tokens)
+			.min(f64::from(limit.burst_count));
+		self.last_refill = now;
+
+		if self.tokens < 1.0 {
+			return Err(Duration::from_secs_f64((1.0 - self.tokens) / limit.per_second));
+		}
+
+		self.tokens -= 1.0;
+
+		Ok(())
+	}
+}
+
+pub struct Service {
+	buckets: Mutex<LruCache<(Bucket, Client), TokenBucket>>,
+}
+
+impl Service {
+	pub fn build() -> Self {
+		Self {
+			buckets: Mutex::new(LruCache::new(MAX_TRACKED_BUCKETS)),
+		}
+	}
+
+	/// Fails with `M_LIMIT_EXCEEDED` if the user, or the IP address of an
+	/// unauthenticated request, sent too many requests of a kind.
+	///
+	/// - Appservices and admins are exempt
+	/// - Unauthenticated requests from loopback addresses are not limited
+	pub fn check(
+		&self, bucket: Bucket, sender_user: Option<&UserId>, client_ip: Option<IpAddr>, is_appservice: bool,
+	) -> Result<()> {
+		if !services().globals.config.get().rate_limits.enabled || is_appservice {
+			return Ok(());
+		}
+
+		let client = match (sender_user, client_ip) {
+			(Some(user_id), _) => {
+				if services().users.is_admin(user_id)? {
+					return Ok(());
+				}
+				Client::User(user_id.to_owned())
+			},
+			// Loopback addresses are likely a reverse proxy all clients share
+			(None, Some(ip)) if !ip.is_loopback() => Client::Ip(ip),
+			(None, _) => return Ok(()),
+		};
+
+		let limit = bucket.limit();
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock().unwrap();
+
+		let key = (bucket, client);
+		if !buckets.contains_key(&key) {
+			buckets.insert(key.clone(), TokenBucket::new(limit, now));
+		}
+
+		buckets
+			.get_mut(&key)
+			.expect("bucket was just inserted")
+			.take(limit, now)
+			.map_err(|retry_after| {
+				Error::BadRequest(
+					ErrorKind::LimitExceeded {
+						retry_after: Some(RetryAfter::Delay(retry_after)),
+					},
+					"Too many requests, try again later.",
+				)
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::TokenBucket;
+	use crate::config::RateLimit;
+
+	#[test]
+	fn token_bucket() {
+		let limit = RateLimit {
+			per_second: 0.5,
+			burst_count: 2,
+		};
+		let start = Instant::now();
+		let mut bucket = TokenBucket::new(limit, start);
+
+		assert!(bucket.take(limit, start).is_ok());
+		assert!(bucket.take(limit, start).is_ok());
+		assert_eq!(bucket.take(limit, start), Err(Duration::from_secs(2)));
+
+		let later = start + Duration::from_secs(2);
+		assert!(bucket.take(limit, later).is_ok());
+		assert!(bucket.take(limit, later).is_err());
+
+		let much_later = later + Duration::from_secs(60);
+		assert!(bucket.take(limit, much_later).is_ok());
+		assert!(bucket.take(limit, much_later).is_ok());
+		assert!(bucket.take(limit, much_later).is_err());
+	}
+}
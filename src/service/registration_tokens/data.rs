@@ -0,0 +1,16 @@
+use super::RegistrationTokenInfo;
+use crate::Result;
+
+pub trait Data: Send + Sync {
+	/// Returns the usage information of a registration token.
+	fn get_registration_token(&self, token: &str) -> Result<Option<RegistrationTokenInfo>>;
+
+	/// Creates or updates a registration token.
+	fn set_registration_token(&self, token: &str, info: &RegistrationTokenInfo) -> Result<()>;
+
+	/// Deletes a registration token.
+	fn remove_registration_token(&self, token: &str) -> Result<()>;
+
+	/// Returns an iterator over all registration tokens stored in the database.
+	fn registration_tokens<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(String, RegistrationTokenInfo)>> + 'a>;
+}
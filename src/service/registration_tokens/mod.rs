@@ -0,0 +1,254 @@
+mod data;
+
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+pub use data::Data;
+use serde::{Deserialize, Serialize};
+
+use crate::{services, utils, Result};
+
+/// How long a registration may take after completing its registration token
+/// stage before the use of the token expires
+const PENDING_USE_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+pub struct Service {
+	pub db: &'static dyn Data,
+
+	/// Tokens used by registrations that have not completed yet. Also
+	/// serializes token uses so a token can't be used more often than allowed.
+	pub pending: Mutex<PendingUses>,
+}
+
+/// Registration tokens used in the UIAA stage of registrations that have not
+/// created their account yet, keyed by UIAA session.
+#[derive(Default)]
+pub struct PendingUses(HashMap<String, (String, Instant)>);
+
+impl PendingUses {
+	/// How many unfinished registrations use a token
+	fn count(&self, token: &str, now: Instant) -> u64 {
+		self.0
+			.values()
+			.filter(|(pending, expires_at)| pending == token && *expires_at > now)
+			.count() as u64
+	}
+
+	fn insert(&mut self, session: &str, token: &str, now: Instant) {
+		self.0.retain(|_, (_, expires_at)| *expires_at > now);
+		self.0
+			.insert(session.to_owned(), (token.to_owned(), now + PENDING_USE_LIFETIME));
+	}
+
+	fn remove(&mut self, session: &str) -> Option<String> { self.0.remove(session).map(|(token, _)| token) }
+}
+
+/// Usage limits and statistics of a registration token (MSC3231).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RegistrationTokenInfo {
+	/// How often the token can be used, unlimited if None
+	pub uses_allowed: Option<u64>,
+
+	/// How often the token has been used to complete a registration
+	pub completed: u64,
+
+	/// Timestamp in milliseconds after which the token is no longer valid
+	pub expiry_time: Option<u64>,
+}
+
+impl RegistrationTokenInfo {
+	/// How often the token can still be used, unlimited if None
+	pub fn uses_remaining(&self) -> Option<u64> {
+		self.uses_allowed
+			.map(|uses_allowed| uses_allowed.saturating_sub(self.completed))
+	}
+
+	pub fn is_expired(&self) -> bool {
+		self.expiry_time
+			.is_some_and(|expiry_time| expiry_time <= utils::millis_since_unix_epoch())
+	}
+
+	pub fn is_valid(&self) -> bool { self.can_be_used(0) }
+
+	/// Whether the token can be used for another registration while `pending`
+	/// registrations using it have not completed yet
+	pub fn can_be_used(&self, pending: u64) -> bool {
+		!self.is_expired()
+			&& self
+				.uses_remaining()
+				.map_or(true, |uses_remaining| uses_remaining > pending)
+	}
+}
+
+impl Service {
+	/// Whether new users have to provide a registration token.
+	pub fn is_required(&self) -> bool {
+		services().globals.config.get().registration_token.is_some()
+			|| services().globals.config.get().registration_requires_token
+	}
+
+	/// Checks whether a token can currently be used to register, without
+	/// using it.
+	pub fn is_valid(&self, token: &str) -> Result<bool> {
+		if self.is_static(token) {
+			return Ok(true);
+		}
+
+		let pending = self.pending.lock().unwrap();
+
+		Ok(self
+			.db
+			.get_registration_token(token)?
+			.is_some_and(|info| info.can_be_used(pending.count(token, Instant::now()))))
+	}
+
+	/// Uses a token to complete the registration token stage of the
+	/// registration with the given UIAA session. Returns false if the token is
+	/// invalid, expired or used up.
+	///
+	/// The use only counts once the registration creates the account, see
+	/// [`Self::complete`].
+	pub fn use_token(&self, token: &str, session: &str) -> Result<bool> {
+		if self.is_static(token) {
+			return Ok(true);
+		}
+
+		let mut pending = self.pending.lock().unwrap();
+
+		// Retrying the stage must not use up the token
+		pending.remove(session);
+
+		let now = Instant::now();
+		if !self
+			.db
+			.get_registration_token(token)?
+			.is_some_and(|info| info.can_be_used(pending.count(token, now)))
+		{
+			return Ok(false);
+		}
+
+		pending.insert(session, token, now);
+
+		Ok(true)
+	}
+
+	/// Counts the use of the registration token of a UIAA session now that the
+	/// registration created the account.
+	pub fn complete(&self, session: &str) -> Result<()> {
+		let mut pending = self.pending.lock().unwrap();
+
+		let Some(token) = pending.remove(session) else {
+			return Ok(());
+		};
+
+		// The token may have been revoked in the meantime
+		if let Some(mut info) = self.db.get_registration_token(&token)? {
+			info.completed = info.completed.saturating_add(1);
+			self.db.set_registration_token(&token, &info)?;
+		}
+
+		Ok(())
+	}
+
+	/// Creates a new registration token, replacing an existing one with the
+	/// same value.
+	pub fn create(&self, token: &str, uses_allowed: Option<u64>, expiry_time: Option<u64>) -> Result<()> {
+		self.db.set_registration_token(
+			token,
+			&RegistrationTokenInfo {
+				uses_allowed,
+				completed: 0,
+				expiry_time,
+			},
+		)
+	}
+
+	pub fn get(&self, token: &str) -> Result<Option<RegistrationTokenInfo>> { self.db.get_registration_token(token) }
+
+	pub fn revoke(&self, token: &str) -> Result<()> { self.db.remove_registration_token(token) }
+
+	pub fn all(&self) -> impl Iterator<Item = Result<(String, RegistrationTokenInfo)>> + '_ {
+		self.db.registration_tokens()
+	}
+
+	fn is_static(&self, token: &str) -> bool {
+		services()
+			.globals
+			.config
+			.get()
+			.registration_token
+			.as_deref()
+			== Some(token)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Instant;
+
+	use super::{PendingUses, RegistrationTokenInfo, PENDING_USE_LIFETIME};
+
+	#[test]
+	fn uses_remaining() {
+		let mut info = RegistrationTokenInfo {
+			uses_allowed: Some(1),
+			..Default::default()
+		};
+		assert_eq!(info.uses_remaining(), Some(1));
+		assert!(info.is_valid());
+
+		info.completed = 1;
+		assert_eq!(info.uses_remaining(), Some(0));
+		assert!(!info.is_valid());
+
+		info.uses_allowed = None;
+		assert_eq!(info.uses_remaining(), None);
+		assert!(info.is_valid());
+	}
+
+	#[test]
+	fn expiry() {
+		let info = RegistrationTokenInfo {
+			expiry_time: Some(1),
+			..Default::default()
+		};
+		assert!(info.is_expired());
+		assert!(!info.is_valid());
+	}
+
+	#[test]
+	fn pending_uses_count_against_the_limit() {
+		let info = RegistrationTokenInfo {
+			uses_allowed: Some(2),
+			..Default::default()
+		};
+		let now = Instant::now();
+		let mut pending = PendingUses::default();
+
+		pending.insert("session1", "token", now);
+		assert!(info.can_be_used(pending.count("token", now)));
+
+		pending.insert("session2", "token", now);
+		assert!(!info.can_be_used(pending.count("token", now)));
+		assert_eq!(pending.count("other", now), 0);
+
+		// Retrying the stage in the same session does not count twice
+		pending.insert("session2", "token", now);
+		assert_eq!(pending.count("token", now), 2);
+
+		assert_eq!(pending.remove("session1").as_deref(), Some("token"));
+		assert!(info.can_be_used(pending.count("token", now)));
+	}
+
+	#[test]
+	fn pending_uses_expire() {
+		let now = Instant::now();
+		let mut pending = PendingUses::default();
+
+		pending.insert("session", "token", now);
+		assert_eq!(pending.count("token", now + PENDING_USE_LIFETIME), 0);
+	}
+}
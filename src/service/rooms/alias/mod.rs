@@ -1,9 +1,18 @@
 mod data;
 
+use std::sync::Arc;
+
 pub use data::Data;
-use ruma::{OwnedRoomAliasId, OwnedRoomId, RoomAliasId, RoomId};
+use ruma::{
+	events::{room::canonical_alias::RoomCanonicalAliasEventContent, StateEventType, TimelineEventType},
+	OwnedRoomAliasId, OwnedRoomId, RoomAliasId, RoomId, UserId,
+};
+use serde_json::value::to_raw_value;
 
-use crate::Result;
+use crate::{
+	service::{pdu::PduBuilder, server_notices},
+	services, Error, Result,
+};
 
 pub struct Service {
 	pub db: &'static dyn Data,
@@ -32,4 +41,112 @@ impl Service {
 	pub fn all_local_aliases<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(OwnedRoomId, String)>> + 'a> {
 		self.db.all_local_aliases()
 	}
+
+	/// Removes an alias from the `alias` and `alt_aliases` of the room's
+	/// `m.room.canonical_alias` event, if it is listed there.
+	///
+	/// The new event is sent by `sender` if their power level allows it,
+	/// otherwise by the server user.
+	#[tracing::instrument(skip(self))]
+	pub async fn remove_from_canonical_alias(
+		&self, room_id: &RoomId, alias: &RoomAliasId, sender: Option<&UserId>,
+	) -> Result<()> {
+		let mutex_state = Arc::clone(
+			services()
+				.globals
+				.roomid_mutex_state
+				.write()
+				.await
+				.entry(room_id.to_owned())
+				.or_default(),
+		);
+		let state_lock = mutex_state.lock().await;
+
+		// Read the event only after locking, so no concurrent change gets lost
+		let Some(content) = services()
+			.rooms
+			.state_accessor
+			.room_state_get(room_id, &StateEventType::RoomCanonicalAlias, "")?
+			.map(|s| {
+				serde_json::from_str::<RoomCanonicalAliasEventContent>(s.content.get())
+					.map_err(|_| Error::bad_database("Invalid canonical alias event in database."))
+			})
+			.transpose()?
+			.and_then(|content| without_alias(content, alias))
+		else {
+			return Ok(());
+		};
+
+		let server_user = server_notices::server_user();
+		let sender = sender
+			.filter(|sender| {
+				services()
+					.rooms
+					.state_accessor
+					.user_can_send_state(room_id, sender, StateEventType::RoomCanonicalAlias)
+					.unwrap_or(false)
+			})
+			.unwrap_or(&*server_user);
+
+		services()
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					event_type: TimelineEventType::RoomCanonicalAlias,
+					content: to_raw_value(&content).expect("event is valid, we just created it"),
+					unsigned: None,
+					state_key: Some(String::new()),
+					redacts: None,
+				},
+				sender,
+				room_id,
+				&state_lock,
+			)
+			.await?;
+
+		Ok(())
+	}
+}
+
+/// Removes an alias from the content of a canonical alias event, returning
+/// `None` if it isn't listed there.
+fn without_alias(
+	mut content: RoomCanonicalAliasEventContent, alias: &RoomAliasId,
+) -> Option<RoomCanonicalAliasEventContent> {
+	let alt_aliases_count = content.alt_aliases.len();
+	content
+		.alt_aliases
+		.retain(|alt_alias| alt_alias.as_str() != alias.as_str());
+
+	if content.alias.as_deref() == Some(alias) {
+		content.alias = None;
+	} else if content.alt_aliases.len() == alt_aliases_count {
+		return None;
+	}
+
+	Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::{events::room::canonical_alias::RoomCanonicalAliasEventContent, owned_room_alias_id, room_alias_id};
+
+	use super::without_alias;
+
+	#[test]
+	fn removes_alias_and_alt_aliases() {
+		let mut content = RoomCanonicalAliasEventContent::new();
+		content.alias = Some(owned_room_alias_id!("#main:example.org"));
+		content.alt_aliases = vec![
+			owned_room_alias_id!("#main:example.org"),
+			owned_room_alias_id!("#other:example.org"),
+		];
+
+		let content = without_alias(content, room_alias_id!("#main:example.org")).unwrap();
+		assert_eq!(content.alias, None);
+		assert_eq!(content.alt_aliases, vec![owned_room_alias_id!("#other:example.org")]);
+
+		assert!(without_alias(content, room_alias_id!("#unlisted:example.org")).is_none());
+	}
 }
@@ -14,4 +14,20 @@ pub trait Data: Send + Sync {
 
 	/// Returns the unsorted public room directory
 	fn public_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a>;
+
+	/// Adds the room to the room directory of an appservice network
+	fn set_public_in_network(&self, network_id: &str, room_id: &RoomId, appservice_id: &str) -> Result<()>;
+
+	/// Removes the room from the room directory of an appservice network
+	fn set_not_public_in_network(&self, network_id: &str, room_id: &RoomId) -> Result<()>;
+
+	/// Returns the ID of the appservice that published a room to a network
+	fn network_room_appservice(&self, network_id: &str, room_id: &RoomId) -> Result<Option<String>>;
+
+	/// Returns the unsorted room directory of an appservice network
+	fn network_public_rooms<'a>(&'a self, network_id: &str) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a>;
+
+	/// Returns the unsorted room directories of all appservice networks, a
+	/// room may be returned more than once
+	fn all_network_public_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a>;
 }
@@ -1,12 +1,83 @@
 mod data;
 
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	sync::Mutex,
+};
+
 pub use data::Data;
-use ruma::{OwnedRoomId, RoomId};
+use ruma::{
+	directory::{Filter, PublicRoomJoinRule, PublicRoomsChunk, RoomNetwork, RoomTypeFilter},
+	events::{
+		room::{
+			avatar::RoomAvatarEventContent,
+			canonical_alias::RoomCanonicalAliasEventContent,
+			create::RoomCreateEventContent,
+			guest_access::{GuestAccess, RoomGuestAccessEventContent},
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			topic::RoomTopicEventContent,
+		},
+		StateEventType, TimelineEventType,
+	},
+	OwnedRoomId, RoomId, UInt,
+};
+use tracing::{error, warn};
 
-use crate::Result;
+use crate::{services, Error, Result};
 
 pub struct Service {
 	pub db: &'static dyn Data,
+	pub public_room_index: Mutex<PublicRoomIndex>,
+}
+
+/// Directory entries of public rooms, indexed by the words of their name,
+/// alias and topic. Entries are kept until the room state changes or the room
+/// is unpublished, the joined member count is always looked up live.
+#[derive(Default)]
+pub struct PublicRoomIndex {
+	chunks: HashMap<OwnedRoomId, PublicRoomsChunk>,
+	/// Every suffix of every lowercase word, so that prefix lookups also find
+	/// matches inside words
+	words: BTreeMap<String, HashSet<OwnedRoomId>>,
+}
+
+impl PublicRoomIndex {
+	fn insert(&mut self, chunk: PublicRoomsChunk) {
+		self.remove(&chunk.room_id);
+
+		for word in index_words(&chunk) {
+			self.words
+				.entry(word)
+				.or_default()
+				.insert(chunk.room_id.clone());
+		}
+		self.chunks.insert(chunk.room_id.clone(), chunk);
+	}
+
+	fn remove(&mut self, room_id: &RoomId) {
+		let Some(chunk) = self.chunks.remove(room_id) else {
+			return;
+		};
+
+		for word in index_words(&chunk) {
+			if let Some(room_ids) = self.words.get_mut(&word) {
+				room_ids.remove(room_id);
+				if room_ids.is_empty() {
+					self.words.remove(&word);
+				}
+			}
+		}
+	}
+
+	/// Rooms with a word containing the given lowercase word
+	fn matching(&self, word: &str) -> HashSet<OwnedRoomId> {
+		self.words
+			.range(word.to_owned()..)
+			.take_while(|(indexed, _)| indexed.starts_with(word))
+			.flat_map(|(_, room_ids)| room_ids.iter().cloned())
+			.collect()
+	}
 }
 
 impl Service {
@@ -14,11 +85,423 @@ impl Service {
 	pub fn set_public(&self, room_id: &RoomId) -> Result<()> { self.db.set_public(room_id) }
 
 	#[tracing::instrument(skip(self))]
-	pub fn set_not_public(&self, room_id: &RoomId) -> Result<()> { self.db.set_not_public(room_id) }
+	pub fn set_not_public(&self, room_id: &RoomId) -> Result<()> {
+		self.public_room_index.lock().unwrap().remove(room_id);
+
+		self.db.set_not_public(room_id)
+	}
 
 	#[tracing::instrument(skip(self))]
 	pub fn is_public_room(&self, room_id: &RoomId) -> Result<bool> { self.db.is_public_room(room_id) }
 
 	#[tracing::instrument(skip(self))]
 	pub fn public_rooms(&self) -> impl Iterator<Item = Result<OwnedRoomId>> + '_ { self.db.public_rooms() }
+
+	#[tracing::instrument(skip(self))]
+	pub fn set_public_in_network(&self, network_id: &str, room_id: &RoomId, appservice_id: &str) -> Result<()> {
+		self.db
+			.set_public_in_network(network_id, room_id, appservice_id)
+	}
+
+	#[tracing::instrument(skip(self))]
+	pub fn set_not_public_in_network(&self, network_id: &str, room_id: &RoomId) -> Result<()> {
+		self.db.set_not_public_in_network(network_id, room_id)
+	}
+
+	/// Returns the ID of the appservice that published a room to a network
+	#[tracing::instrument(skip(self))]
+	pub fn network_room_appservice(&self, network_id: &str, room_id: &RoomId) -> Result<Option<String>> {
+		self.db.network_room_appservice(network_id, room_id)
+	}
+
+	#[tracing::instrument(skip(self))]
+	pub fn network_public_rooms<'a>(&'a self, network_id: &str) -> impl Iterator<Item = Result<OwnedRoomId>> + 'a {
+		self.db.network_public_rooms(network_id)
+	}
+
+	#[tracing::instrument(skip(self))]
+	pub fn all_network_public_rooms(&self) -> impl Iterator<Item = Result<OwnedRoomId>> + '_ {
+		self.db.all_network_public_rooms()
+	}
+
+	/// Returns the public rooms listed in the given network, unsorted and
+	/// without duplicates
+	pub fn public_rooms_in_network(&self, network: &RoomNetwork) -> Vec<OwnedRoomId> {
+		let mut room_ids: Vec<OwnedRoomId> = match network {
+			RoomNetwork::Matrix => self.public_rooms().filter_map(Result::ok).collect(),
+			RoomNetwork::ThirdParty(network_id) => self
+				.network_public_rooms(network_id)
+				.filter_map(Result::ok)
+				.collect(),
+			_ => self
+				.public_rooms()
+				.chain(self.all_network_public_rooms())
+				.filter_map(Result::ok)
+				.collect(),
+		};
+
+		room_ids.sort_unstable();
+		room_ids.dedup();
+		room_ids
+	}
+
+	/// Searches the given public rooms, ranking them by how well they match
+	/// the filter and then by joined member count. Rooms that fail to load are
+	/// skipped.
+	pub fn search_public_rooms(&self, room_ids: Vec<OwnedRoomId>, filter: &Filter) -> Vec<PublicRoomsChunk> {
+		let search_term = filter
+			.generic_search_term
+			.as_deref()
+			.map(str::trim)
+			.filter(|term| !term.is_empty())
+			.map(str::to_lowercase);
+
+		let missing: Vec<_> = {
+			let index = self.public_room_index.lock().unwrap();
+			room_ids
+				.iter()
+				.filter(|room_id| !index.chunks.contains_key(*room_id))
+				.collect()
+		};
+
+		let loaded: Vec<_> = missing
+			.into_iter()
+			.filter_map(|room_id| {
+				Self::load_public_room_chunk(room_id)
+					.map_err(|e| warn!("Failed to load directory entry of {room_id}: {e}"))
+					.ok()
+			})
+			.collect();
+
+		let mut index = self.public_room_index.lock().unwrap();
+		for chunk in loaded {
+			index.insert(chunk);
+		}
+
+		// Only rooms containing the first word of the search term can match it
+		let candidates = match search_term.as_deref().and_then(|term| words(term).next()) {
+			Some(word) => {
+				let matching = index.matching(word);
+				room_ids
+					.into_iter()
+					.filter(|room_id| matching.contains(room_id))
+					.collect()
+			},
+			None => room_ids,
+		};
+
+		let mut ranked: Vec<_> = candidates
+			.iter()
+			.filter_map(|room_id| index.chunks.get(room_id))
+			.filter(|chunk| {
+				filter.room_types.is_empty()
+					|| filter
+						.room_types
+						.contains(&RoomTypeFilter::from(chunk.room_type.clone()))
+			})
+			.filter_map(|chunk| match &search_term {
+				Some(term) => search_rank(chunk, term).map(|rank| (rank, chunk.clone())),
+				None => Some((0, chunk.clone())),
+			})
+			.collect();
+		drop(index);
+
+		for (_, chunk) in &mut ranked {
+			chunk.num_joined_members = joined_member_count(&chunk.room_id);
+		}
+
+		ranked.sort_by(|(l_rank, l), (r_rank, r)| {
+			r_rank
+				.cmp(l_rank)
+				.then_with(|| r.num_joined_members.cmp(&l.num_joined_members))
+		});
+
+		ranked.into_iter().map(|(_, chunk)| chunk).collect()
+	}
+
+	/// Removes the cached directory entry of a room if a state event of the
+	/// given type is shown in it
+	pub fn update_cache_for_state_event(&self, room_id: &RoomId, kind: &TimelineEventType) {
+		if is_directory_state_event(kind) {
+			self.public_room_index.lock().unwrap().remove(room_id);
+		}
+	}
+
+	fn load_public_room_chunk(room_id: &RoomId) -> Result<PublicRoomsChunk> {
+		Ok(PublicRoomsChunk {
+			canonical_alias: services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, &StateEventType::RoomCanonicalAlias, "")?
+				.map_or(Ok(None), |s| {
+					serde_json::from_str(s.content.get())
+						.map(|c: RoomCanonicalAliasEventContent| c.alias)
+						.map_err(|_| Error::bad_database("Invalid canonical alias event in database."))
+				})?,
+			name: services().rooms.state_accessor.get_name(room_id)?,
+			// Filled in when the entry is returned
+			num_joined_members: UInt::default(),
+			topic: services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, &StateEventType::RoomTopic, "")?
+				.map_or(Ok(None), |s| {
+					serde_json::from_str(s.content.get())
+						.map(|c: RoomTopicEventContent| Some(c.topic))
+						.map_err(|e| {
+							error!("Invalid room topic event in database for room {room_id}: {e}");
+							Error::bad_database("Invalid room topic event in database.")
+						})
+				})
+				.unwrap_or(None),
+			world_readable: services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, &StateEventType::RoomHistoryVisibility, "")?
+				.map_or(Ok(false), |s| {
+					serde_json::from_str(s.content.get())
+						.map(|c: RoomHistoryVisibilityEventContent| {
+							c.history_visibility == HistoryVisibility::WorldReadable
+						})
+						.map_err(|e| {
+							error!(
+								"Invalid room history visibility event in database for room {room_id}, assuming is \
+								 \"shared\": {e}",
+							);
+							Error::bad_database("Invalid room history visibility event in database.")
+						})
+				})
+				.unwrap_or(false),
+			guest_can_join: services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, &StateEventType::RoomGuestAccess, "")?
+				.map_or(Ok(false), |s| {
+					serde_json::from_str(s.content.get())
+						.map(|c: RoomGuestAccessEventContent| c.guest_access == GuestAccess::CanJoin)
+						.map_err(|_| Error::bad_database("Invalid room guest access event in database."))
+				})?,
+			avatar_url: services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, &StateEventType::RoomAvatar, "")?
+				.map(|s| {
+					serde_json::from_str(s.content.get())
+						.map(|c: RoomAvatarEventContent| c.url)
+						.map_err(|_| Error::bad_database("Invalid room avatar event in database."))
+				})
+				.transpose()?
+				// url is now an Option<String> so we must flatten
+				.flatten(),
+			join_rule: services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, &StateEventType::RoomJoinRules, "")?
+				.map(|s| {
+					serde_json::from_str(s.content.get())
+						.map(|c: RoomJoinRulesEventContent| match c.join_rule {
+							JoinRule::Public => Some(PublicRoomJoinRule::Public),
+							JoinRule::Knock => Some(PublicRoomJoinRule::Knock),
+							_ => None,
+						})
+						.map_err(|e| {
+							error!("Invalid room join rule event in database: {}", e);
+							Error::BadDatabase("Invalid room join rule event in database.")
+						})
+				})
+				.transpose()?
+				.flatten()
+				.ok_or_else(|| Error::bad_database("Missing room join rule event for room."))?,
+			room_type: services()
+				.rooms
+				.state_accessor
+				.room_state_get(room_id, &StateEventType::RoomCreate, "")?
+				.map(|s| {
+					serde_json::from_str::<RoomCreateEventContent>(s.content.get()).map_err(|e| {
+						error!("Invalid room create event in database: {}", e);
+						Error::BadDatabase("Invalid room create event in database.")
+					})
+				})
+				.transpose()?
+				.and_then(|e| e.room_type),
+			room_id: room_id.to_owned(),
+		})
+	}
+}
+
+/// Whether state events of this type are shown in directory entries
+pub(crate) fn is_directory_state_event(kind: &TimelineEventType) -> bool {
+	matches!(
+		kind,
+		TimelineEventType::RoomCanonicalAlias
+			| TimelineEventType::RoomName
+			| TimelineEventType::RoomTopic
+			| TimelineEventType::RoomHistoryVisibility
+			| TimelineEventType::RoomGuestAccess
+			| TimelineEventType::RoomAvatar
+			| TimelineEventType::RoomJoinRules
+			| TimelineEventType::RoomCreate
+	)
+}
+
+fn joined_member_count(room_id: &RoomId) -> UInt {
+	services()
+		.rooms
+		.state_cache
+		.room_joined_count(room_id)
+		.ok()
+		.flatten()
+		.unwrap_or_else(|| {
+			warn!("Room {} has no member count", room_id);
+			0
+		})
+		.try_into()
+		.expect("user count should not be that big")
+}
+
+/// Splits lowercase text into the words the index and ranking work on
+fn words(text: &str) -> impl Iterator<Item = &str> {
+	text.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+}
+
+/// Every suffix of every word of a directory entry, lowercased
+fn index_words(chunk: &PublicRoomsChunk) -> HashSet<String> {
+	let texts = [
+		chunk.name.as_deref(),
+		chunk.canonical_alias.as_ref().map(|alias| alias.as_str()),
+		chunk.topic.as_deref(),
+	];
+
+	texts
+		.into_iter()
+		.flatten()
+		.map(str::to_lowercase)
+		.flat_map(|text| {
+			words(&text)
+				.flat_map(|word| word.char_indices().map(move |(i, _)| word[i..].to_owned()))
+				.collect::<Vec<_>>()
+		})
+		.collect()
+}
+
+/// Ranks how well a room matches a lowercase directory search term, higher is
+/// better. Exact and prefix matches on the name or alias, either the full one
+/// or its localpart, rank above matches inside them, which rank above matches
+/// in the topic.
+fn search_rank(chunk: &PublicRoomsChunk, term: &str) -> Option<u8> {
+	let name = chunk.name.as_deref().map(str::to_lowercase);
+	let alias = chunk
+		.canonical_alias
+		.as_ref()
+		.map(|alias| alias.as_str().to_lowercase());
+	let alias_localpart = chunk
+		.canonical_alias
+		.as_ref()
+		.map(|alias| alias.alias().to_lowercase());
+	let topic = chunk.topic.as_deref().map(str::to_lowercase);
+
+	let names = || {
+		name.iter()
+			.chain(alias.iter())
+			.chain(alias_localpart.iter())
+	};
+
+	if names().any(|name| name == term) {
+		Some(5)
+	} else if names().any(|name| name.starts_with(term)) {
+		Some(4)
+	} else if names().any(|name| words(name).any(|word| word.starts_with(term))) {
+		Some(3)
+	} else if names().any(|name| name.contains(term)) {
+		Some(2)
+	} else if topic.is_some_and(|topic| topic.contains(term)) {
+		Some(1)
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::{owned_room_id, UInt};
+
+	use super::*;
+
+	fn chunk(name: &str, alias: Option<&str>, topic: &str) -> PublicRoomsChunk {
+		PublicRoomsChunk {
+			canonical_alias: alias.map(|alias| alias.try_into().unwrap()),
+			name: Some(name.to_owned()),
+			num_joined_members: UInt::from(1_u32),
+			room_id: owned_room_id!("!room:example.org"),
+			topic: Some(topic.to_owned()),
+			world_readable: false,
+			guest_can_join: false,
+			avatar_url: None,
+			join_rule: PublicRoomJoinRule::Public,
+			room_type: None,
+		}
+	}
+
+	#[test]
+	fn search_rank_order() {
+		let exact = chunk("Rust", None, "");
+		let prefix = chunk("Rustaceans", None, "");
+		let word = chunk("Learning rust together", None, "");
+		let inner = chunk("Trustworthy", None, "");
+		let topic = chunk("Programming", None, "we talk about rust here");
+
+		assert_eq!(search_rank(&exact, "rust"), Some(5));
+		assert_eq!(search_rank(&prefix, "rust"), Some(4));
+		assert_eq!(search_rank(&word, "rust"), Some(3));
+		assert_eq!(search_rank(&inner, "rust"), Some(2));
+		assert_eq!(search_rank(&topic, "rust"), Some(1));
+		assert_eq!(search_rank(&topic, "python"), None);
+	}
+
+	#[test]
+	fn search_rank_alias() {
+		let room = chunk("Some room", Some("#rust:example.org"), "");
+
+		assert_eq!(search_rank(&room, "rust"), Some(5));
+		assert_eq!(search_rank(&room, "ru"), Some(4));
+		assert_eq!(search_rank(&room, "#rust:example.org"), Some(5));
+		assert_eq!(search_rank(&room, "#rust:exa"), Some(4));
+		assert_eq!(search_rank(&room, "example.org"), Some(2));
+	}
+
+	#[test]
+	fn index_lookup_full_alias() {
+		let mut index = PublicRoomIndex::default();
+		let room = chunk("Some room", Some("#rust:example.org"), "");
+		index.insert(room.clone());
+
+		// Searches look up the first word of the term
+		let term = "#rust:example.org";
+		let first_word = words(term).next().unwrap();
+		assert!(index.matching(first_word).contains(&room.room_id));
+		assert!(index.matching("example").contains(&room.room_id));
+		assert_eq!(search_rank(&room, term), Some(5));
+	}
+
+	#[test]
+	fn index_lookup() {
+		let mut index = PublicRoomIndex::default();
+		let mut rust = chunk("Trustworthy Rustaceans", Some("#rust:example.org"), "systems programming");
+		rust.room_id = owned_room_id!("!rust:example.org");
+		let mut python = chunk("Python", None, "snakes");
+		python.room_id = owned_room_id!("!python:example.org");
+		index.insert(rust.clone());
+		index.insert(python);
+
+		assert_eq!(index.matching("rust").len(), 1);
+		assert_eq!(index.matching("worth").len(), 1);
+		assert_eq!(index.matching("prog").len(), 1);
+		assert!(index.matching("java").is_empty());
+
+		index.remove(&rust.room_id);
+		assert!(index.matching("rust").is_empty());
+		assert_eq!(index.matching("python").len(), 1);
+		assert!(!index.words.keys().any(|word| word.contains("rust")));
+	}
 }
@@ -199,7 +199,7 @@ impl super::Service {
 		&self, mut servers: BTreeMap<OwnedServerName, BTreeMap<OwnedServerSigningKeyId, QueryCriteria>>,
 		pub_key_map: &RwLock<BTreeMap<String, BTreeMap<String, Base64>>>,
 	) -> Result<()> {
-		for server in services().globals.trusted_servers() {
+		for server in &services().globals.trusted_servers() {
 			debug!("Asking batch signing keys from trusted server {}", server);
 			match services()
 				.sending
@@ -447,7 +447,7 @@ impl super::Service {
 				 keys"
 			);
 
-			for server in services().globals.trusted_servers() {
+			for server in &services().globals.trusted_servers() {
 				debug!("Asking notary server {server} for {origin}'s signing key");
 				if let Some(server_keys) = services()
 					.sending
@@ -554,7 +554,7 @@ impl super::Service {
 				}
 			}
 
-			for server in services().globals.trusted_servers() {
+			for server in &services().globals.trusted_servers() {
 				debug!("Asking notary server {server} for {origin}'s signing key");
 				if let Some(server_keys) = services()
 					.sending
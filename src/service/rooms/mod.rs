@@ -30,6 +30,7 @@ pub trait Data:
 	+ read_receipt::Data
 	+ search::Data
 	+ short::Data
+	+ spaces::Data
 	+ state::Data
 	+ state_accessor::Data
 	+ state_cache::Data
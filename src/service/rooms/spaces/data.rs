@@ -0,0 +1,15 @@
+use ruma::RoomId;
+
+use super::CachedSpaceHierarchySummary;
+use crate::Result;
+
+pub trait Data: Send + Sync {
+	/// Returns the persisted hierarchy summary of a room, if any.
+	fn get_cached_summary(&self, room_id: &RoomId) -> Result<Option<CachedSpaceHierarchySummary>>;
+
+	/// Persists the hierarchy summary of a room, replacing any previous one.
+	fn cache_summary(&self, room_id: &RoomId, summary: &CachedSpaceHierarchySummary) -> Result<()>;
+
+	/// Removes the persisted hierarchy summary of a room.
+	fn remove_cached_summary(&self, room_id: &RoomId) -> Result<()>;
+}
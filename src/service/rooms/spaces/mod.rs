@@ -1,16 +1,17 @@
+mod data;
+
 use std::{
 	fmt::{Display, Formatter},
 	str::FromStr,
+	sync::Mutex,
 };
 
+pub use data::Data;
 use lru_cache::LruCache;
 use ruma::{
 	api::{
 		client::{self, error::ErrorKind, space::SpaceHierarchyRoomsChunk},
-		federation::{
-			self,
-			space::{SpaceHierarchyChildSummary, SpaceHierarchyParentSummary},
-		},
+		federation::{self, space::SpaceHierarchyParentSummary},
 	},
 	events::{
 		room::{
@@ -23,19 +24,30 @@ use ruma::{
 			topic::RoomTopicEventContent,
 		},
 		space::child::{HierarchySpaceChildEvent, SpaceChildEventContent},
-		StateEventType,
+		StateEventType, TimelineEventType,
 	},
 	serde::Raw,
 	space::SpaceRoomJoinRule,
 	OwnedRoomId, OwnedServerName, RoomId, ServerName, UInt, UserId,
 };
-use tokio::sync::Mutex;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, warn};
 
-use crate::{services, Error, Result};
+use super::directory::is_directory_state_event;
+use crate::{services, utils, Error, Result};
 
+#[derive(Clone, Deserialize, Serialize)]
 pub struct CachedSpaceHierarchySummary {
 	summary: SpaceHierarchyParentSummary,
+	/// Milliseconds since the unix epoch after which the summary has to be
+	/// fetched again, only set for rooms this server does not receive state
+	/// changes for
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	expires_at: Option<u64>,
+}
+
+impl CachedSpaceHierarchySummary {
+	fn is_expired(&self, now: u64) -> bool { self.expires_at.is_some_and(|expires_at| expires_at <= now) }
 }
 
 pub enum SummaryAccessibility {
@@ -295,6 +307,7 @@ pub enum Identifier<'a> {
 }
 
 pub struct Service {
+	pub db: &'static dyn Data,
 	pub roomid_spacehierarchy_cache: Mutex<LruCache<OwnedRoomId, Option<CachedSpaceHierarchySummary>>>,
 }
 
@@ -380,13 +393,7 @@ impl Service {
 	async fn get_summary_and_children_local(
 		&self, current_room: &OwnedRoomId, identifier: Identifier<'_>,
 	) -> Result<Option<SummaryAccessibility>> {
-		if let Some(cached) = self
-			.roomid_spacehierarchy_cache
-			.lock()
-			.await
-			.get_mut(&current_room.to_owned())
-			.as_ref()
-		{
+		if let Some(cached) = self.get_cached_summary(current_room)? {
 			return Ok(if let Some(cached) = cached {
 				if is_accessable_child(
 					current_room,
@@ -394,7 +401,7 @@ impl Service {
 					&identifier,
 					&cached.summary.allowed_room_ids,
 				)? {
-					Some(SummaryAccessibility::Accessible(Box::new(cached.summary.clone())))
+					Some(SummaryAccessibility::Accessible(Box::new(cached.summary)))
 				} else {
 					Some(SummaryAccessibility::Inaccessible)
 				}
@@ -407,12 +414,7 @@ impl Service {
 			if let Some(children_pdus) = get_stripped_space_child_events(current_room).await? {
 				let summary = Self::get_room_summary(current_room, children_pdus, &identifier);
 				if let Ok(summary) = summary {
-					self.roomid_spacehierarchy_cache.lock().await.insert(
-						current_room.clone(),
-						Some(CachedSpaceHierarchySummary {
-							summary: summary.clone(),
-						}),
-					);
+					self.cache_summary(current_room, Some(summary.clone()), false)?;
 
 					Some(SummaryAccessibility::Accessible(Box::new(summary)))
 				} else {
@@ -425,7 +427,8 @@ impl Service {
 	}
 
 	async fn get_summary_and_children_federation(
-		&self, current_room: &OwnedRoomId, suggested_only: bool, user_id: &UserId, via: &Vec<OwnedServerName>,
+		&self, current_room: &OwnedRoomId, suggested_only: bool, identifier: &Identifier<'_>,
+		via: &Vec<OwnedServerName>,
 	) -> Result<Option<SummaryAccessibility>> {
 		for server in via {
 			debug!("Asking {server} for /hierarchy");
@@ -441,73 +444,113 @@ impl Service {
 				.await
 			{
 				debug!("Got response from {server} for /hierarchy\n{response:?}");
-				let summary = response.room.clone();
-
-				self.roomid_spacehierarchy_cache.lock().await.insert(
-					current_room.clone(),
-					Some(CachedSpaceHierarchySummary {
-						summary: summary.clone(),
-					}),
-				);
-
-				for child in response.children {
-					let mut guard = self.roomid_spacehierarchy_cache.lock().await;
-					if !guard.contains_key(current_room) {
-						guard.insert(
-							current_room.clone(),
-							Some(CachedSpaceHierarchySummary {
-								summary: {
-									let SpaceHierarchyChildSummary {
-										canonical_alias,
-										name,
-										num_joined_members,
-										room_id,
-										topic,
-										world_readable,
-										guest_can_join,
-										avatar_url,
-										join_rule,
-										room_type,
-										allowed_room_ids,
-									} = child;
-
-									SpaceHierarchyParentSummary {
-										canonical_alias,
-										name,
-										num_joined_members,
-										room_id: room_id.clone(),
-										topic,
-										world_readable,
-										guest_can_join,
-										avatar_url,
-										join_rule,
-										room_type,
-										children_state: get_stripped_space_child_events(&room_id).await?.unwrap(),
-										allowed_room_ids,
-									}
-								},
-							}),
-						);
-					}
-				}
-				if is_accessable_child(
-					current_room,
-					&response.room.join_rule,
-					&Identifier::UserId(user_id),
-					&response.room.allowed_room_ids,
-				)? {
-					return Ok(Some(SummaryAccessibility::Accessible(Box::new(summary.clone()))));
+				let summary = response.room;
+
+				self.cache_summary(current_room, Some(summary.clone()), true)?;
+
+				if is_accessable_child(current_room, &summary.join_rule, identifier, &summary.allowed_room_ids)? {
+					return Ok(Some(SummaryAccessibility::Accessible(Box::new(summary))));
 				}
 
 				return Ok(Some(SummaryAccessibility::Inaccessible));
 			}
 
+			self.cache_summary(current_room, None, true)?;
+		}
+		Ok(None)
+	}
+
+	/// Gets the cached summary of a room, first from memory and then from the
+	/// database. `Some(None)` means the room is known to be unavailable.
+	fn get_cached_summary(&self, room_id: &RoomId) -> Result<Option<Option<CachedSpaceHierarchySummary>>> {
+		let now = utils::millis_since_unix_epoch();
+
+		if let Some(cached) = self
+			.roomid_spacehierarchy_cache
+			.lock()
+			.unwrap()
+			.get_mut(room_id)
+		{
+			if !cached.as_ref().is_some_and(|cached| cached.is_expired(now)) {
+				return Ok(Some(cached.clone()));
+			}
+		}
+
+		let Some(cached) = self.db.get_cached_summary(room_id)? else {
+			return Ok(None);
+		};
+
+		if cached.is_expired(now) {
+			self.remove_cached_summary(room_id)?;
+			return Ok(None);
+		}
+
+		self.roomid_spacehierarchy_cache
+			.lock()
+			.unwrap()
+			.insert(room_id.to_owned(), Some(cached.clone()));
+
+		Ok(Some(Some(cached)))
+	}
+
+	/// Caches the summary of a room. Summaries of remote rooms expire after
+	/// `roomid_spacehierarchy_remote_cache_ttl` as we don't receive their state
+	/// changes, and rooms that could not be found are only remembered in
+	/// memory.
+	fn cache_summary(
+		&self, room_id: &RoomId, summary: Option<SpaceHierarchyParentSummary>, remote: bool,
+	) -> Result<()> {
+		let Some(summary) = summary else {
 			self.roomid_spacehierarchy_cache
 				.lock()
-				.await
-				.insert(current_room.clone(), None);
+				.unwrap()
+				.insert(room_id.to_owned(), None);
+			return Ok(());
+		};
+
+		let cached = CachedSpaceHierarchySummary {
+			summary,
+			expires_at: remote.then(|| {
+				utils::millis_since_unix_epoch().saturating_add(
+					services()
+						.globals
+						.config
+						.get()
+						.roomid_spacehierarchy_remote_cache_ttl
+						* 1000,
+				)
+			}),
+		};
+
+		self.db.cache_summary(room_id, &cached)?;
+		self.roomid_spacehierarchy_cache
+			.lock()
+			.unwrap()
+			.insert(room_id.to_owned(), Some(cached));
+
+		Ok(())
+	}
+
+	fn remove_cached_summary(&self, room_id: &RoomId) -> Result<()> {
+		self.roomid_spacehierarchy_cache
+			.lock()
+			.unwrap()
+			.remove(room_id);
+
+		self.db.remove_cached_summary(room_id)
+	}
+
+	/// Invalidates the cached summary of a room when its state changes. The
+	/// summary holds the directory fields, the children and the joined member
+	/// count.
+	pub fn update_cache_for_state_event(&self, room_id: &RoomId, kind: &TimelineEventType) -> Result<()> {
+		if is_directory_state_event(kind)
+			|| matches!(kind, TimelineEventType::SpaceChild | TimelineEventType::RoomMember)
+		{
+			self.remove_cached_summary(room_id)?;
 		}
-		Ok(None)
+
+		Ok(())
 	}
 
 	async fn get_summary_and_children_client(
@@ -519,11 +562,40 @@ impl Service {
 		{
 			Ok(Some(response))
 		} else {
-			self.get_summary_and_children_federation(current_room, suggested_only, user_id, via)
+			self.get_summary_and_children_federation(current_room, suggested_only, &Identifier::UserId(user_id), via)
 				.await
 		}
 	}
 
+	/// Gets the summary of a single room for the room summary API (MSC3266)
+	///
+	/// Rooms this server is not in are requested over federation from the
+	/// `via` servers. Without a user only public or world readable rooms are
+	/// considered accessible.
+	pub async fn get_room_summary_for_client(
+		&self, room_id: &RoomId, sender_user: Option<&UserId>, via: &Vec<OwnedServerName>,
+	) -> Result<Option<SummaryAccessibility>> {
+		let current_room = room_id.to_owned();
+
+		let Some(user_id) = sender_user else {
+			let summary = match self
+				.get_summary_and_children_local(&current_room, Identifier::None)
+				.await?
+			{
+				Some(summary) => Some(summary),
+				None => {
+					self.get_summary_and_children_federation(&current_room, false, &Identifier::None, via)
+						.await?
+				},
+			};
+
+			return Ok(summary.map(unauthenticated_accessibility));
+		};
+
+		self.get_summary_and_children_client(&current_room, false, user_id, via)
+			.await
+	}
+
 	fn get_room_summary(
 		current_room: &OwnedRoomId, children_state: Vec<Raw<HierarchySpaceChildEvent>>, identifier: &Identifier<'_>,
 	) -> Result<SpaceHierarchyParentSummary, Error> {
@@ -544,14 +616,7 @@ impl Service {
 			.transpose()?
 			.unwrap_or(JoinRule::Invite);
 
-		let allowed_room_ids = allowed_room_ids(join_rule.clone());
-
-		if !is_accessable_child(current_room, &join_rule.clone().into(), identifier, &allowed_room_ids)? {
-			debug!("User is not allowed to see room {room_id}");
-			// This error will be caught later
-			return Err(Error::BadRequest(ErrorKind::forbidden(), "User is not allowed to see the room"));
-		}
-
+		let allowed_room_ids = summary_access(&StoredRooms, current_room, join_rule.clone(), identifier)?;
 		let join_rule = join_rule.into();
 
 		Ok(SpaceHierarchyParentSummary {
@@ -685,7 +750,7 @@ impl Service {
 						skip.map(|skip| {
 							PagnationToken {
 								skip,
-								limit: UInt::new(max_depth as u64)
+								limit: UInt::new(limit as u64)
 									.expect("When sent in request it must have been valid UInt"),
 								max_depth: UInt::new(max_depth as u64)
 									.expect("When sent in request it must have been valid UInt"),
@@ -746,16 +811,86 @@ async fn get_stripped_space_child_events(
 	}
 }
 
+/// Restricts a summary to public or world readable rooms for requests
+/// without a user.
+fn unauthenticated_accessibility(summary: SummaryAccessibility) -> SummaryAccessibility {
+	match summary {
+		SummaryAccessibility::Accessible(summary)
+			if summary.join_rule == SpaceRoomJoinRule::Public || summary.world_readable =>
+		{
+			SummaryAccessibility::Accessible(summary)
+		},
+		_ => SummaryAccessibility::Inaccessible,
+	}
+}
+
+/// Whether anyone may see a room with this join rule, without being joined
+/// or invited. Restricted rooms depend on the allowed rooms instead.
+fn is_open_join_rule(join_rule: &SpaceRoomJoinRule) -> bool {
+	matches!(
+		join_rule,
+		SpaceRoomJoinRule::Public | SpaceRoomJoinRule::Knock | SpaceRoomJoinRule::KnockRestricted
+	)
+}
+
+/// Room state the accessibility of a room depends on
+trait RoomAccess {
+	/// Whether the server ACL of the room allows the server to participate
+	fn acl_allows(&self, server_name: &ServerName, room_id: &RoomId) -> bool;
+
+	/// Whether the user is joined to or invited to the room
+	fn is_joined_or_invited(&self, user_id: &UserId, room_id: &RoomId) -> Result<bool>;
+
+	/// The join rule of the room and the rooms allowing to join it
+	fn join_rule(&self, room_id: &RoomId) -> Result<(SpaceRoomJoinRule, Vec<OwnedRoomId>)>;
+}
+
+/// Room state stored in the database
+struct StoredRooms;
+
+impl RoomAccess for StoredRooms {
+	fn acl_allows(&self, server_name: &ServerName, room_id: &RoomId) -> bool {
+		services()
+			.rooms
+			.event_handler
+			.acl_check(server_name, room_id)
+			.is_ok()
+	}
+
+	fn is_joined_or_invited(&self, user_id: &UserId, room_id: &RoomId) -> Result<bool> {
+		Ok(services().rooms.state_cache.is_joined(user_id, room_id)?
+			|| services().rooms.state_cache.is_invited(user_id, room_id)?)
+	}
+
+	fn join_rule(&self, room_id: &RoomId) -> Result<(SpaceRoomJoinRule, Vec<OwnedRoomId>)> { get_join_rule(room_id) }
+}
+
+/// Checks if the summary of a room may be shown with the given identifier,
+/// returning the rooms whose members may join it
+fn summary_access(
+	rooms: &impl RoomAccess, current_room: &OwnedRoomId, join_rule: JoinRule, identifier: &Identifier<'_>,
+) -> Result<Vec<OwnedRoomId>> {
+	let allowed_room_ids = allowed_room_ids(join_rule.clone());
+
+	if !is_accessable_child_recurse(rooms, current_room, &join_rule.into(), identifier, &allowed_room_ids, 0)? {
+		debug!("User is not allowed to see room {current_room}");
+		// This error will be caught later
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "User is not allowed to see the room"));
+	}
+
+	Ok(allowed_room_ids)
+}
+
 /// With the given identifier, checks if a room is accessable
 fn is_accessable_child(
 	current_room: &OwnedRoomId, join_rule: &SpaceRoomJoinRule, identifier: &Identifier<'_>,
 	allowed_room_ids: &Vec<OwnedRoomId>,
 ) -> Result<bool, Error> {
-	is_accessable_child_recurse(current_room, join_rule, identifier, allowed_room_ids, 0)
+	is_accessable_child_recurse(&StoredRooms, current_room, join_rule, identifier, allowed_room_ids, 0)
 }
 
 fn is_accessable_child_recurse(
-	current_room: &OwnedRoomId, join_rule: &SpaceRoomJoinRule, identifier: &Identifier<'_>,
+	rooms: &impl RoomAccess, current_room: &OwnedRoomId, join_rule: &SpaceRoomJoinRule, identifier: &Identifier<'_>,
 	allowed_room_ids: &Vec<OwnedRoomId>, recurse_num: usize,
 ) -> Result<bool, Error> {
 	// Set limit at 10, as we cannot keep going up parents forever
@@ -763,28 +898,13 @@ fn is_accessable_child_recurse(
 	if recurse_num < 10 {
 		match identifier {
 			Identifier::ServerName(server_name) => {
-				let room_id: &RoomId = current_room;
-
 				// Checks if ACLs allow for the server to participate
-				if services()
-					.rooms
-					.event_handler
-					.acl_check(server_name, room_id)
-					.is_err()
-				{
+				if !rooms.acl_allows(server_name, current_room) {
 					return Ok(false);
 				}
 			},
 			Identifier::UserId(user_id) => {
-				if services()
-					.rooms
-					.state_cache
-					.is_joined(user_id, current_room)?
-					|| services()
-						.rooms
-						.state_cache
-						.is_invited(user_id, current_room)?
-				{
+				if rooms.is_joined_or_invited(user_id, current_room)? {
 					return Ok(true);
 				}
 			},
@@ -793,8 +913,9 @@ fn is_accessable_child_recurse(
 		Ok(match join_rule {
 			SpaceRoomJoinRule::Restricted => {
 				for room in allowed_room_ids {
-					if let Ok((join_rule, allowed_room_ids)) = get_join_rule(room) {
+					if let Ok((join_rule, allowed_room_ids)) = rooms.join_rule(room) {
 						if let Ok(true) = is_accessable_child_recurse(
+							rooms,
 							room,
 							&join_rule,
 							identifier,
@@ -807,9 +928,8 @@ fn is_accessable_child_recurse(
 				}
 				false
 			},
-			SpaceRoomJoinRule::Public | SpaceRoomJoinRule::Knock | SpaceRoomJoinRule::KnockRestricted => true,
-			// Custom join rules, Invite, or Private
-			_ => false,
+			// Custom join rules, Invite, or Private are not open
+			join_rule => is_open_join_rule(join_rule),
 		})
 	} else {
 		// If you need to go up 10 parents, we just assume it is inaccessable
@@ -939,9 +1059,11 @@ fn get_parent_children_via(
 
 #[cfg(test)]
 mod tests {
+	use std::collections::{HashMap, HashSet};
+
 	use ruma::{
 		api::federation::space::SpaceHierarchyParentSummaryInit, events::room::join_rules::Restricted, owned_room_id,
-		owned_server_name,
+		owned_server_name, owned_user_id, OwnedUserId,
 	};
 
 	use super::*;
@@ -1193,6 +1315,38 @@ mod tests {
 		assert_eq!(allowed_room_ids(invite_join_rule), empty_vec);
 	}
 
+	#[test]
+	fn cached_summary_expiry() {
+		let summary: SpaceHierarchyParentSummary = SpaceHierarchyParentSummaryInit {
+			num_joined_members: UInt::from(1_u32),
+			room_id: owned_room_id!("!root:example.org"),
+			world_readable: true,
+			guest_can_join: true,
+			join_rule: SpaceRoomJoinRule::Public,
+			children_state: vec![],
+			allowed_room_ids: vec![],
+		}
+		.into();
+
+		let local = CachedSpaceHierarchySummary {
+			summary: summary.clone(),
+			expires_at: None,
+		};
+		let remote = CachedSpaceHierarchySummary {
+			summary,
+			expires_at: Some(1000),
+		};
+
+		assert!(!local.is_expired(u64::MAX), "local summaries never expire");
+		assert!(!remote.is_expired(999), "remote summary expired early");
+		assert!(remote.is_expired(1000), "remote summary did not expire");
+
+		let remote: CachedSpaceHierarchySummary =
+			serde_json::from_slice(&serde_json::to_vec(&remote).unwrap()).unwrap();
+		assert_eq!(remote.expires_at, Some(1000));
+		assert_eq!(remote.summary.room_id, owned_room_id!("!root:example.org"));
+	}
+
 	#[test]
 	fn invalid_pagnation_tokens() {
 		fn token_is_err(token: &str) {
@@ -1299,4 +1453,109 @@ mod tests {
 		first(&mut arena, &owned_room_id!("!subspace2:example.org"));
 		assert!(arena.first_untraversed().is_none());
 	}
+
+	fn summary(join_rule: SpaceRoomJoinRule, world_readable: bool) -> SummaryAccessibility {
+		SummaryAccessibility::Accessible(Box::new(
+			SpaceHierarchyParentSummaryInit {
+				num_joined_members: UInt::from(1_u32),
+				room_id: owned_room_id!("!room:example.org"),
+				world_readable,
+				guest_can_join: false,
+				join_rule,
+				children_state: vec![],
+				allowed_room_ids: vec![],
+			}
+			.into(),
+		))
+	}
+
+	fn is_accessible(summary: &SummaryAccessibility) -> bool { matches!(summary, SummaryAccessibility::Accessible(_)) }
+
+	#[test]
+	fn unauthenticated_summary_visibility() {
+		assert!(is_accessible(&unauthenticated_accessibility(summary(
+			SpaceRoomJoinRule::Public,
+			false
+		))));
+		assert!(is_accessible(&unauthenticated_accessibility(summary(
+			SpaceRoomJoinRule::Invite,
+			true
+		))));
+		assert!(!is_accessible(&unauthenticated_accessibility(summary(
+			SpaceRoomJoinRule::Invite,
+			false
+		))));
+		assert!(!is_accessible(&unauthenticated_accessibility(summary(
+			SpaceRoomJoinRule::Knock,
+			false
+		))));
+		assert!(!is_accessible(&unauthenticated_accessibility(
+			SummaryAccessibility::Inaccessible
+		)));
+	}
+
+	/// Rooms with fixed join rules and memberships
+	#[derive(Default)]
+	struct TestRooms {
+		join_rules: HashMap<OwnedRoomId, JoinRule>,
+		memberships: HashSet<(OwnedUserId, OwnedRoomId)>,
+	}
+
+	impl RoomAccess for TestRooms {
+		fn acl_allows(&self, _server_name: &ServerName, _room_id: &RoomId) -> bool { true }
+
+		fn is_joined_or_invited(&self, user_id: &UserId, room_id: &RoomId) -> Result<bool> {
+			Ok(self
+				.memberships
+				.contains(&(user_id.to_owned(), room_id.to_owned())))
+		}
+
+		fn join_rule(&self, room_id: &RoomId) -> Result<(SpaceRoomJoinRule, Vec<OwnedRoomId>)> {
+			let join_rule = self
+				.join_rules
+				.get(room_id)
+				.cloned()
+				.unwrap_or(JoinRule::Invite);
+			Ok((join_rule.clone().into(), allowed_room_ids(join_rule)))
+		}
+	}
+
+	#[test]
+	fn non_joined_summary_visibility() {
+		let user_id = owned_user_id!("@alice:example.org");
+		let room_id = owned_room_id!("!room:example.org");
+		let allowed = owned_room_id!("!allowed:example.org");
+		let restricted = || JoinRule::Restricted(Restricted::new(vec![AllowRule::room_membership(allowed.clone())]));
+
+		let mut rooms = TestRooms::default();
+		let can_see = |rooms: &TestRooms, join_rule: JoinRule| {
+			summary_access(rooms, &room_id, join_rule, &Identifier::UserId(&user_id)).is_ok()
+		};
+
+		assert!(can_see(&rooms, JoinRule::Public));
+		assert!(can_see(&rooms, JoinRule::Knock));
+		assert!(!can_see(&rooms, JoinRule::Invite));
+		assert!(!can_see(&rooms, JoinRule::Private));
+		assert!(!can_see(&rooms, restricted()));
+		assert_eq!(
+			summary_access(&rooms, &room_id, JoinRule::Invite, &Identifier::UserId(&user_id))
+				.unwrap_err()
+				.to_string(),
+			Error::BadRequest(ErrorKind::forbidden(), "User is not allowed to see the room").to_string()
+		);
+
+		// Members of an allowed room may see restricted rooms
+		rooms.memberships.insert((user_id.clone(), allowed.clone()));
+		assert!(can_see(&rooms, restricted()));
+		assert_eq!(
+			summary_access(&rooms, &room_id, restricted(), &Identifier::UserId(&user_id)).unwrap(),
+			vec![allowed.clone()]
+		);
+		assert!(!can_see(&rooms, JoinRule::Invite));
+
+		// Members and invitees may always see the room
+		rooms.memberships.insert((user_id.clone(), room_id.clone()));
+		assert!(can_see(&rooms, JoinRule::Invite));
+		assert!(can_see(&rooms, JoinRule::Private));
+	}
 }
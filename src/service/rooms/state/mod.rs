@@ -35,6 +35,8 @@ impl Service {
 		_statediffremoved: Arc<HashSet<CompressedStateEvent>>,
 		state_lock: &MutexGuard<'_, ()>, // Take mutex guard to make sure users get the room state mutex
 	) -> Result<()> {
+		let mut changed_kinds = HashSet::new();
+
 		for event_id in statediffnew.iter().filter_map(|new| {
 			services()
 				.rooms
@@ -54,40 +56,30 @@ impl Service {
 				Err(_) => continue,
 			};
 
-			match pdu.kind {
-				TimelineEventType::RoomMember => {
-					let Ok(membership_event) = serde_json::from_str::<RoomMemberEventContent>(pdu.content.get()) else {
-						continue;
-					};
-
-					let Some(state_key) = pdu.state_key else {
-						continue;
-					};
-
-					let Ok(user_id) = UserId::parse(state_key) else {
-						continue;
-					};
-
-					services().rooms.state_cache.update_membership(
-						room_id,
-						&user_id,
-						membership_event,
-						&pdu.sender,
-						None,
-						None,
-						false,
-					)?;
-				},
-				TimelineEventType::SpaceChild => {
-					services()
-						.rooms
-						.spaces
-						.roomid_spacehierarchy_cache
-						.lock()
-						.await
-						.remove(&pdu.room_id);
-				},
-				_ => continue,
+			changed_kinds.insert(pdu.kind.clone());
+
+			if pdu.kind == TimelineEventType::RoomMember {
+				let Ok(membership_event) = serde_json::from_str::<RoomMemberEventContent>(pdu.content.get()) else {
+					continue;
+				};
+
+				let Some(state_key) = pdu.state_key else {
+					continue;
+				};
+
+				let Ok(user_id) = UserId::parse(state_key) else {
+					continue;
+				};
+
+				services().rooms.state_cache.update_membership(
+					room_id,
+					&user_id,
+					membership_event,
+					&pdu.sender,
+					None,
+					None,
+					false,
+				)?;
 			}
 		}
 
@@ -96,6 +88,26 @@ impl Service {
 		self.db
 			.set_room_state(room_id, shortstatehash, state_lock)?;
 
+		for kind in &changed_kinds {
+			self.update_caches_for_state_event(room_id, kind)?;
+		}
+
+		Ok(())
+	}
+
+	/// Invalidates the caches derived from the room state after a state event
+	/// of the given type became part of the current state. Call this only after
+	/// the room state was set, or the old state could be cached again.
+	pub fn update_caches_for_state_event(&self, room_id: &RoomId, kind: &TimelineEventType) -> Result<()> {
+		services()
+			.rooms
+			.spaces
+			.update_cache_for_state_event(room_id, kind)?;
+		services()
+			.rooms
+			.directory
+			.update_cache_for_state_event(room_id, kind);
+
 		Ok(())
 	}
 
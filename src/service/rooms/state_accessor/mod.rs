@@ -13,6 +13,7 @@ use ruma::{
 			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
 			member::{MembershipState, RoomMemberEventContent},
 			name::RoomNameEventContent,
+			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
 		},
 		StateEventType,
 	},
@@ -156,6 +157,12 @@ impl Service {
 			return Ok(true);
 		};
 
+		self.user_can_see_state_at(user_id, room_id, shortstatehash)
+	}
+
+	/// Whether a user is allowed to see events at a room state, based on the
+	/// room's history_visibility in that state.
+	pub fn user_can_see_state_at(&self, user_id: &UserId, room_id: &RoomId, shortstatehash: u64) -> Result<bool> {
 		if let Some(visibility) = self
 			.user_visibility_cache
 			.lock()
@@ -290,6 +297,27 @@ impl Service {
 			})
 	}
 
+	/// Whether a joined user has the power level to send a state event of the
+	/// given type. Rooms without power levels are treated as allowing nobody.
+	pub fn user_can_send_state(&self, room_id: &RoomId, user_id: &UserId, event_type: StateEventType) -> Result<bool> {
+		if !services().rooms.state_cache.is_joined(user_id, room_id)? {
+			return Ok(false);
+		}
+
+		let Some(power_levels) = self
+			.room_state_get(room_id, &StateEventType::RoomPowerLevels, "")?
+			.map(|s| {
+				serde_json::from_str::<RoomPowerLevelsEventContent>(s.content.get())
+					.map_err(|_| Error::bad_database("Invalid power levels event in database."))
+			})
+			.transpose()?
+		else {
+			return Ok(false);
+		};
+
+		Ok(RoomPowerLevels::from(power_levels).user_can_send_state(user_id, event_type))
+	}
+
 	pub async fn user_can_invite(
 		&self, room_id: &RoomId, sender: &UserId, target_user: &UserId, state_lock: &MutexGuard<'_, ()>,
 	) -> Result<bool> {
@@ -449,17 +449,6 @@ impl Service {
 					},
 				};
 			},
-			TimelineEventType::SpaceChild => {
-				if let Some(_state_key) = &pdu.state_key {
-					services()
-						.rooms
-						.spaces
-						.roomid_spacehierarchy_cache
-						.lock()
-						.await
-						.remove(&pdu.room_id);
-				}
-			},
 			TimelineEventType::RoomMember => {
 				if let Some(state_key) = &pdu.state_key {
 					// if the state_key fails
@@ -922,6 +911,13 @@ impl Service {
 			.state
 			.set_room_state(room_id, statehashid, state_lock)?;
 
+		if pdu.state_key.is_some() {
+			services()
+				.rooms
+				.state
+				.update_caches_for_state_event(room_id, &pdu.kind)?;
+		}
+
 		let mut servers: HashSet<OwnedServerName> = services()
 			.rooms
 			.state_cache
@@ -157,7 +157,7 @@ impl Service {
 			user_id.server_name() == services().globals.server_name(),
 			"tried to broadcast typing status of remote user",
 		);
-		if !services().globals.config.allow_outgoing_typing {
+		if !services().globals.config.get().allow_outgoing_typing {
 			return Ok(());
 		}
 
@@ -13,6 +13,7 @@ pub trait Data: Send + Sync {
 	fn delete_all_active_requests_for(&self, outgoing_kind: &OutgoingKind) -> Result<()>;
 	fn delete_all_requests_for(&self, outgoing_kind: &OutgoingKind) -> Result<()>;
 	fn queue_requests(&self, requests: &[(&OutgoingKind, SendingEventType)]) -> Result<Vec<Vec<u8>>>;
+	fn queued_requests_all(&self) -> OutgoingSendingIter<'_>;
 	fn queued_requests<'a>(
 		&'a self, outgoing_kind: &OutgoingKind,
 	) -> Box<dyn Iterator<Item = Result<(SendingEventType, Vec<u8>)>> + 'a>;
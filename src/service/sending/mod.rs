@@ -2,7 +2,7 @@ use std::{
 	cmp,
 	collections::{BTreeMap, HashMap, HashSet},
 	fmt::Debug,
-	sync::Arc,
+	sync::{Arc, Mutex as StdMutex},
 	time::{Duration, Instant},
 };
 
@@ -26,7 +26,7 @@ use ruma::{
 	push, uint, MilliSecondsSinceUnixEpoch, OwnedServerName, OwnedUserId, RoomId, ServerName, UInt, UserId,
 };
 use tokio::sync::{mpsc, Mutex, Semaphore};
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 use crate::{service::presence::Presence, services, utils::calculate_hash, Config, Error, PduEvent, Result};
 
@@ -47,6 +47,12 @@ pub struct Service {
 	startup_netburst: bool,
 	startup_netburst_keep: i64,
 	timeout: u64,
+
+	/// Status of the destinations with a transaction running or backing off,
+	/// shared between the sender and the admin commands.
+	transaction_status: StdMutex<HashMap<OutgoingKind, TransactionStatus>>,
+	/// When the last transaction to each destination went through.
+	last_success: StdMutex<HashMap<OutgoingKind, Instant>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -64,12 +70,25 @@ pub enum SendingEventType {
 	Flush,        // none
 }
 
-enum TransactionStatus {
+#[derive(Clone, Copy, Debug)]
+pub enum TransactionStatus {
 	Running,
 	Failed(u32, Instant), // number of times failed, time of last failure
 	Retrying(u32),        // number of times failed
 }
 
+/// What the sender knows about a federation destination.
+#[derive(Debug)]
+pub struct DestinationInfo {
+	pub server: OwnedServerName,
+	/// Events queued behind the running or failed transaction
+	pub queued: usize,
+	/// Events in the running or failed transaction
+	pub active: usize,
+	pub status: Option<TransactionStatus>,
+	pub last_success: Option<Instant>,
+}
+
 impl Service {
 	pub fn build(db: &'static dyn Data, config: &Config) -> Arc<Self> {
 		let (sender, receiver) = mpsc::unbounded_channel();
@@ -81,6 +100,8 @@ impl Service {
 			startup_netburst: config.startup_netburst,
 			startup_netburst_keep: config.startup_netburst_keep,
 			timeout: config.sender_timeout,
+			transaction_status: StdMutex::new(HashMap::new()),
+			last_success: StdMutex::new(HashMap::new()),
 		})
 	}
 
@@ -126,6 +147,7 @@ impl Service {
 	pub fn send_pdu_servers<I: Iterator<Item = OwnedServerName>>(&self, servers: I, pdu_id: &[u8]) -> Result<()> {
 		let requests = servers
 			.into_iter()
+			.filter(|server| services().server_policy.is_federation_allowed(server))
 			.map(|server| (OutgoingKind::Normal(server), SendingEventType::Pdu(pdu_id.to_owned())))
 			.collect::<Vec<_>>();
 		let _cork = services().globals.db.cork()?;
@@ -146,6 +168,10 @@ impl Service {
 
 	#[tracing::instrument(skip(self, server, serialized))]
 	pub fn send_edu_server(&self, server: &ServerName, serialized: Vec<u8>) -> Result<()> {
+		if !services().server_policy.is_federation_allowed(server) {
+			return Ok(());
+		}
+
 		let outgoing_kind = OutgoingKind::Normal(server.to_owned());
 		let event = SendingEventType::Edu(serialized);
 		let _cork = services().globals.db.cork()?;
@@ -173,6 +199,7 @@ impl Service {
 	pub fn send_edu_servers<I: Iterator<Item = OwnedServerName>>(&self, servers: I, serialized: Vec<u8>) -> Result<()> {
 		let requests = servers
 			.into_iter()
+			.filter(|server| services().server_policy.is_federation_allowed(server))
 			.map(|server| (OutgoingKind::Normal(server), SendingEventType::Edu(serialized.clone())))
 			.collect::<Vec<_>>();
 		let _cork = services().globals.db.cork()?;
@@ -205,7 +232,10 @@ impl Service {
 
 	#[tracing::instrument(skip(self, servers))]
 	pub fn flush_servers<I: Iterator<Item = OwnedServerName>>(&self, servers: I) -> Result<()> {
-		let requests = servers.into_iter().map(OutgoingKind::Normal);
+		let requests = servers
+			.into_iter()
+			.filter(|server| services().server_policy.is_federation_allowed(server))
+			.map(OutgoingKind::Normal);
 
 		for outgoing_kind in requests {
 			self.sender
@@ -226,6 +256,110 @@ impl Service {
 		Ok(())
 	}
 
+	/// Lists the federation destinations with queued events or a known
+	/// transaction status, sorted by server name.
+	pub fn destinations(&self) -> Result<Vec<DestinationInfo>> {
+		fn entry<'a>(
+			destinations: &'a mut BTreeMap<OwnedServerName, DestinationInfo>, server: &ServerName,
+		) -> &'a mut DestinationInfo {
+			destinations
+				.entry(server.to_owned())
+				.or_insert_with(|| DestinationInfo {
+					server: server.to_owned(),
+					queued: 0,
+					active: 0,
+					status: None,
+					last_success: None,
+				})
+		}
+
+		let mut destinations = BTreeMap::<OwnedServerName, DestinationInfo>::new();
+		for (_, outgoing_kind, _) in self.db.queued_requests_all().filter_map(Result::ok) {
+			if let OutgoingKind::Normal(server) = outgoing_kind {
+				entry(&mut destinations, &server).queued += 1;
+			}
+		}
+
+		for (_, outgoing_kind, _) in self.db.active_requests().filter_map(Result::ok) {
+			if let OutgoingKind::Normal(server) = outgoing_kind {
+				entry(&mut destinations, &server).active += 1;
+			}
+		}
+
+		for (outgoing_kind, status) in self.transaction_status.lock().unwrap().iter() {
+			if let OutgoingKind::Normal(server) = outgoing_kind {
+				entry(&mut destinations, server).status = Some(*status);
+			}
+		}
+
+		for (outgoing_kind, time) in self.last_success.lock().unwrap().iter() {
+			if let OutgoingKind::Normal(server) = outgoing_kind {
+				entry(&mut destinations, server).last_success = Some(*time);
+			}
+		}
+
+		Ok(destinations.into_values().collect())
+	}
+
+	/// Resets the backoff of a failed destination and sends its queued events
+	/// right away. Returns false if the destination was not backing off.
+	pub fn retry_destination(&self, server: &ServerName) -> Result<bool> {
+		let outgoing_kind = OutgoingKind::Normal(server.to_owned());
+		let backing_off = match self
+			.transaction_status
+			.lock()
+			.unwrap()
+			.get_mut(&outgoing_kind)
+		{
+			Some(status @ TransactionStatus::Failed(..)) => {
+				// No failures means no backoff; the next attempt resends the failed
+				// transaction
+				*status = TransactionStatus::Failed(0, Instant::now());
+				true
+			},
+			_ => false,
+		};
+
+		self.flush_servers(std::iter::once(server.to_owned()))?;
+
+		Ok(backing_off)
+	}
+
+	/// Drops the events queued for a destination, including those of a failed
+	/// transaction. Returns how many were dropped.
+	pub fn purge_destination(&self, server: &ServerName) -> Result<usize> {
+		let outgoing_kind = OutgoingKind::Normal(server.to_owned());
+		let count =
+			self.db.queued_requests(&outgoing_kind).count() + self.db.active_requests_for(&outgoing_kind).count();
+
+		self.db.delete_all_requests_for(&outgoing_kind)?;
+
+		// A running transaction still has to finish, a failed one is gone
+		let mut transaction_status = self.transaction_status.lock().unwrap();
+		if matches!(transaction_status.get(&outgoing_kind), Some(TransactionStatus::Failed(..))) {
+			transaction_status.remove(&outgoing_kind);
+		}
+
+		Ok(count)
+	}
+
+	/// Drops the queued events of every destination we don't federate with
+	/// anymore, e.g. after it was added to the deny list. Returns how many
+	/// were dropped.
+	pub fn purge_denied_destinations(&self) -> Result<usize> {
+		let mut count = 0;
+		for destination in self.destinations()? {
+			if !services()
+				.server_policy
+				.is_federation_allowed(&destination.server)
+			{
+				count += self.purge_destination(&destination.server)?;
+			}
+		}
+
+		Ok(count)
+	}
+
 	#[tracing::instrument(skip(self, request), name = "request")]
 	pub async fn send_federation_request<T>(&self, dest: &ServerName, request: T) -> Result<T::IncomingResponse>
 	where
@@ -277,7 +411,12 @@ impl Service {
 		let mut receiver = self.receiver.lock().await;
 
 		let mut futures = FuturesUnordered::new();
-		let mut current_transaction_status = HashMap::<OutgoingKind, TransactionStatus>::new();
+
+		// Denied servers might have changed since the last start
+		let purged = self.purge_denied_destinations()?;
+		if purged > 0 {
+			info!("Dropped {purged} queued event(s) for servers we don't federate with");
+		}
 
 		// Retry requests we could not finish yet
 		if self.startup_netburst {
@@ -299,7 +438,10 @@ impl Service {
 			}
 
 			for (outgoing_kind, events) in initial_transactions {
-				current_transaction_status.insert(outgoing_kind.clone(), TransactionStatus::Running);
+				self.transaction_status
+					.lock()
+					.unwrap()
+					.insert(outgoing_kind.clone(), TransactionStatus::Running);
 				futures.push(handle_events(outgoing_kind.clone(), events));
 			}
 		}
@@ -309,6 +451,7 @@ impl Service {
 				Some(response) = futures.next() => {
 					match response {
 						Ok(outgoing_kind) => {
+							self.last_success.lock().unwrap().insert(outgoing_kind.clone(), Instant::now());
 							let _cork = services().globals.db.cork();
 							self.db.delete_all_active_requests_for(&outgoing_kind)?;
 
@@ -327,11 +470,11 @@ impl Service {
 									new_events.into_iter().map(|(event, _)| event).collect(),
 								));
 							} else {
-								current_transaction_status.remove(&outgoing_kind);
+								self.transaction_status.lock().unwrap().remove(&outgoing_kind);
 							}
 						}
 						Err((outgoing_kind, _)) => {
-							current_transaction_status.entry(outgoing_kind).and_modify(|e| *e = match e {
+							self.transaction_status.lock().unwrap().entry(outgoing_kind).and_modify(|e| *e = match e {
 								TransactionStatus::Running => TransactionStatus::Failed(1, Instant::now()),
 								TransactionStatus::Retrying(n) => TransactionStatus::Failed(*n+1, Instant::now()),
 								TransactionStatus::Failed(_, _) => {
@@ -346,7 +489,7 @@ impl Service {
 					if let Ok(Some(events)) = self.select_events(
 						&outgoing_kind,
 						vec![(event, key)],
-						&mut current_transaction_status,
+						&mut self.transaction_status.lock().unwrap(),
 					) {
 						futures.push(handle_events(outgoing_kind, events));
 					}
@@ -10,8 +10,8 @@ use http::{header::AUTHORIZATION, HeaderValue};
 use ipaddress::IPAddress;
 use ruma::{
 	api::{
-		client::error::Error as RumaError, EndpointError, IncomingResponse, MatrixVersion, OutgoingRequest,
-		SendAccessToken,
+		client::error::{Error as RumaError, ErrorKind},
+		EndpointError, IncomingResponse, MatrixVersion, OutgoingRequest, SendAccessToken,
 	},
 	OwnedServerName, ServerName,
 };
@@ -58,6 +58,14 @@ where
 		return Err(Error::bad_config("Won't send federation request to ourselves"));
 	}
 
+	if !services().server_policy.is_federation_allowed(destination) {
+		debug!("Not sending federation request to denied server {destination}");
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Federation with this server is forbidden.",
+		));
+	}
+
 	if destination.is_ip_literal() || IPAddress::is_valid(destination.host()) {
 		debug!(
 			"Destination {} is an IP literal, checking against IP range denylist.",
@@ -68,7 +76,7 @@ where
 			Error::BadServerResponse("Invalid IP address")
 		})?;
 
-		let cidr_ranges_s = services().globals.ip_range_denylist().to_vec();
+		let cidr_ranges_s = services().globals.ip_range_denylist();
 		let mut cidr_ranges: Vec<IPAddress> = Vec::new();
 
 		for cidr in cidr_ranges_s {
@@ -188,7 +196,7 @@ where
 	if let Some(url_host) = url.host_str() {
 		trace!("Checking request URL for IP");
 		if let Ok(ip) = IPAddress::parse(url_host) {
-			let cidr_ranges_s = services().globals.ip_range_denylist().to_vec();
+			let cidr_ranges_s = services().globals.ip_range_denylist();
 			let mut cidr_ranges: Vec<IPAddress> = Vec::new();
 
 			for cidr in cidr_ranges_s {
@@ -214,7 +222,7 @@ where
 			trace!("Checking response destination's IP");
 			if let Some(remote_addr) = response.remote_addr() {
 				if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
-					let cidr_ranges_s = services().globals.ip_range_denylist().to_vec();
+					let cidr_ranges_s = services().globals.ip_range_denylist();
 					let mut cidr_ranges: Vec<IPAddress> = Vec::new();
 
 					for cidr in cidr_ranges_s {
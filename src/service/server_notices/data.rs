@@ -0,0 +1,10 @@
+use ruma::{OwnedRoomId, RoomId, UserId};
+
+use crate::Result;
+
+pub trait Data: Send + Sync {
+	/// Returns the server notices room of a local user.
+	fn notice_room(&self, user_id: &UserId) -> Result<Option<OwnedRoomId>>;
+
+	fn set_notice_room(&self, user_id: &UserId, room_id: &RoomId) -> Result<()>;
+}
@@ -0,0 +1,359 @@
+mod data;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+pub use data::Data;
+use ruma::{
+	api::client::error::ErrorKind,
+	events::{
+		room::{
+			create::RoomCreateEventContent,
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+			message::{MessageType, RoomMessageEventContent, ServerNoticeMessageEventContent, ServerNoticeType},
+			name::RoomNameEventContent,
+			power_levels::RoomPowerLevelsEventContent,
+		},
+		tag::{TagEvent, TagEventContent, TagInfo, TagName},
+		RoomAccountDataEventType, TimelineEventType,
+	},
+	OwnedRoomId, OwnedUserId, RoomId, RoomVersionId, UserId,
+};
+use serde_json::value::to_raw_value;
+use tracing::{info, warn};
+
+use crate::{
+	service::{admin::jobs, pdu::PduBuilder},
+	services, Error, Result,
+};
+
+/// `server_notice_type` of notices that are just a message to the user, like
+/// the ones admins send
+const MESSAGE_NOTICE_TYPE: &str = "org.conduwuit.message";
+
+pub struct Service {
+	pub db: &'static dyn Data,
+}
+
+impl Service {
+	/// Creates the account server notices are sent from, if it doesn't exist
+	/// yet.
+	pub async fn create_notices_user(&self) -> Result<()> {
+		let notices_user = notices_user();
+		if services().users.exists(&notices_user)? {
+			return check_notices_user(&notices_user);
+		}
+
+		// Without a password the account can't be logged into, like the server user
+		services().users.create(&notices_user, None)?;
+		services()
+			.users
+			.set_displayname(&notices_user, Some("Server Notices".to_owned()))
+			.await?;
+
+		info!("Created server notices user {notices_user}");
+
+		Ok(())
+	}
+
+	/// Sends a notice from the server to a local user in their server notices
+	/// room. The room is created, and the user joined to it, if they are not
+	/// in it already.
+	pub async fn send_notice(&self, user_id: &UserId, notice: ServerNoticeMessageEventContent) -> Result<()> {
+		let notices_user = notices_user();
+		check_notices_user(&notices_user)?;
+
+		let room_id = match self.db.notice_room(user_id)? {
+			// A room of a previously configured notices user can't be used anymore
+			Some(room_id)
+				if services().rooms.state_cache.is_joined(user_id, &room_id)?
+					&& services()
+						.rooms
+						.state_cache
+						.is_joined(&notices_user, &room_id)? =>
+			{
+				room_id
+			},
+			_ => {
+				let room_id = self.create_notice_room(user_id).await?;
+				self.db.set_notice_room(user_id, &room_id)?;
+				room_id
+			},
+		};
+
+		let mutex_state = Arc::clone(
+			services()
+				.globals
+				.roomid_mutex_state
+				.write()
+				.await
+				.entry(room_id.clone())
+				.or_default(),
+		);
+		let state_lock = mutex_state.lock().await;
+
+		let content = RoomMessageEventContent::new(MessageType::ServerNotice(notice));
+		services()
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					event_type: TimelineEventType::RoomMessage,
+					content: to_raw_value(&content).expect("event is valid, we just created it"),
+					unsigned: None,
+					state_key: None,
+					redacts: None,
+				},
+				&notices_user,
+				&room_id,
+				&state_lock,
+			)
+			.await?;
+
+		Ok(())
+	}
+
+	/// Sends a notice to every local user, returning how many users it was
+	/// sent to and the users it couldn't be sent to.
+	pub async fn send_notice_to_all(
+		&self, notice: ServerNoticeMessageEventContent,
+	) -> Result<(usize, Vec<(OwnedUserId, Error)>)> {
+		check_notices_user(&notices_user())?;
+
+		let user_ids = services()
+			.users
+			.list_local_users()?
+			.into_iter()
+			.filter_map(|user_id| UserId::parse(user_id).ok())
+			.filter(|user_id| user_id.server_name() == services().globals.server_name())
+			.collect::<Vec<_>>();
+
+		let mut sent = 0;
+		let mut failed = Vec::new();
+		let total = user_ids.len();
+		for user_id in user_ids {
+			match self.send_notice(&user_id, notice.clone()).await {
+				Ok(()) => sent += 1,
+				Err(e) => {
+					warn!("Failed to send server notice to {user_id}: {e}");
+					failed.push((user_id, e));
+				},
+			}
+			jobs::set_progress(format!("sent the notice to {sent} of {total} users, {} failed", failed.len()));
+		}
+
+		Ok((sent, failed))
+	}
+
+	/// Creates a room only the notices user can send events to, joins the user
+	/// to it and tags it as `m.server_notice` for them.
+	async fn create_notice_room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+		let room_id = self
+			.create_server_room(&notices_user(), "Server Notices", &[user_id.to_owned()])
+			.await?;
+
+		let mut tags = BTreeMap::new();
+		tags.insert(TagName::ServerNotice, TagInfo::new());
+		services().account_data.update(
+			Some(&room_id),
+			user_id,
+			RoomAccountDataEventType::Tag,
+			&serde_json::to_value(TagEvent {
+				content: TagEventContent {
+					tags,
+				},
+			})
+			.expect("to json value always works"),
+		)?;
+
+		info!("Created server notices room {room_id} for {user_id}");
+
+		Ok(room_id)
+	}
+
+	/// Creates an unfederated room only the given server account can send
+	/// events to and joins the local users to it.
+	pub(crate) async fn create_server_room(
+		&self, server_user: &UserId, name: &str, user_ids: &[OwnedUserId],
+	) -> Result<OwnedRoomId> {
+		let server_user = server_user.to_owned();
+		let room_id = RoomId::new(services().globals.server_name());
+
+		services().rooms.short.get_or_create_shortroomid(&room_id)?;
+
+		let mutex_state = Arc::clone(
+			services()
+				.globals
+				.roomid_mutex_state
+				.write()
+				.await
+				.entry(room_id.clone())
+				.or_default(),
+		);
+		let state_lock = mutex_state.lock().await;
+
+		let room_version = services().globals.default_room_version();
+		let mut create_content = match room_version {
+			RoomVersionId::V1
+			| RoomVersionId::V2
+			| RoomVersionId::V3
+			| RoomVersionId::V4
+			| RoomVersionId::V5
+			| RoomVersionId::V6
+			| RoomVersionId::V7
+			| RoomVersionId::V8
+			| RoomVersionId::V9
+			| RoomVersionId::V10 => RoomCreateEventContent::new_v1(server_user.clone()),
+			RoomVersionId::V11 => RoomCreateEventContent::new_v11(),
+			_ => {
+				warn!("Unexpected or unsupported room version {}", room_version);
+				return Err(Error::BadRequest(
+					ErrorKind::BadJson,
+					"Unexpected or unsupported room version found",
+				));
+			},
+		};
+		create_content.federate = false;
+		create_content.room_version = room_version;
+
+		let member_content = |membership| RoomMemberEventContent {
+			membership,
+			displayname: None,
+			avatar_url: None,
+			is_direct: None,
+			third_party_invite: None,
+			blurhash: None,
+			reason: None,
+			join_authorized_via_users_server: None,
+		};
+
+		let mut users = BTreeMap::new();
+		users.insert(server_user.clone(), 100.into());
+
+		let mut events = vec![
+			(
+				&server_user,
+				TimelineEventType::RoomCreate,
+				to_raw_value(&create_content),
+				String::new(),
+			),
+			(
+				&server_user,
+				TimelineEventType::RoomMember,
+				to_raw_value(&member_content(MembershipState::Join)),
+				server_user.to_string(),
+			),
+			(
+				&server_user,
+				TimelineEventType::RoomPowerLevels,
+				to_raw_value(&RoomPowerLevelsEventContent {
+					users,
+					events_default: 100.into(),
+					..Default::default()
+				}),
+				String::new(),
+			),
+			(
+				&server_user,
+				TimelineEventType::RoomJoinRules,
+				to_raw_value(&RoomJoinRulesEventContent::new(JoinRule::Invite)),
+				String::new(),
+			),
+			(
+				&server_user,
+				TimelineEventType::RoomHistoryVisibility,
+				to_raw_value(&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Joined)),
+				String::new(),
+			),
+			(
+				&server_user,
+				TimelineEventType::RoomName,
+				to_raw_value(&RoomNameEventContent::new(name.to_owned())),
+				String::new(),
+			),
+		];
+
+		for user_id in user_ids {
+			events.push((
+				&server_user,
+				TimelineEventType::RoomMember,
+				to_raw_value(&member_content(MembershipState::Invite)),
+				user_id.to_string(),
+			));
+			events.push((
+				user_id,
+				TimelineEventType::RoomMember,
+				to_raw_value(&member_content(MembershipState::Join)),
+				user_id.to_string(),
+			));
+		}
+
+		for (sender, event_type, content, state_key) in events {
+			services()
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder {
+						event_type,
+						content: content.expect("event is valid, we just created it"),
+						unsigned: None,
+						state_key: Some(state_key),
+						redacts: None,
+					},
+					sender,
+					&room_id,
+					&state_lock,
+				)
+				.await?;
+		}
+
+		Ok(room_id)
+	}
+}
+
+/// A notice that is just a message to the user.
+pub fn message_notice(body: impl Into<String>) -> ServerNoticeMessageEventContent {
+	ServerNoticeMessageEventContent::new(body.into(), ServerNoticeType::from(MESSAGE_NOTICE_TYPE))
+}
+
+/// The server user, which runs the admin room and owns the rooms the server
+/// creates for its admins
+pub fn server_user() -> OwnedUserId {
+	UserId::parse_with_server_name("conduit", services().globals.server_name()).expect("@conduit:server_name is valid")
+}
+
+/// The user server notices are sent from, `@server` unless configured
+/// otherwise
+pub fn notices_user() -> OwnedUserId {
+	UserId::parse_with_server_name(
+		services()
+			.globals
+			.config
+			.get()
+			.server_notices_localpart
+			.as_str(),
+		services().globals.server_name(),
+	)
+	.expect("server_notices_localpart is checked on startup")
+}
+
+/// Fails if the notices user is an account someone can log into, as it would
+/// be joined to the notice rooms of all users.
+fn check_notices_user(notices_user: &UserId) -> Result<()> {
+	if services()
+		.users
+		.password_hash(notices_user)?
+		.is_some_and(|hash| !hash.is_empty())
+	{
+		return Err(Error::bad_config(
+			"The server notices user is a regular user account. Please set \"server_notices_localpart\" to an unused \
+			 username.",
+		));
+	}
+
+	Ok(())
+}
+
+/// Whether the user is one of the accounts the server acts as
+pub fn is_server_account(user_id: &UserId) -> bool { *user_id == *server_user() || *user_id == *notices_user() }
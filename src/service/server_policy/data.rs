@@ -0,0 +1,13 @@
+use super::PolicyList;
+use crate::Result;
+
+pub trait Data: Send + Sync {
+	/// Adds a server name pattern to a policy list.
+	fn add_server_policy(&self, list: PolicyList, pattern: &str) -> Result<()>;
+
+	/// Removes a server name pattern from a policy list.
+	fn remove_server_policy(&self, list: PolicyList, pattern: &str) -> Result<()>;
+
+	/// Returns an iterator over all server name patterns of all policy lists.
+	fn server_policies<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(PolicyList, String)>> + 'a>;
+}
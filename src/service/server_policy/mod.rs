@@ -0,0 +1,280 @@
+mod data;
+
+use std::sync::RwLock;
+
+pub use data::Data;
+use regex::RegexSet;
+use ruma::ServerName;
+use tracing::info;
+
+use crate::{services, Error, Result};
+
+pub struct Service {
+	pub db: &'static dyn Data,
+
+	/// Compiled patterns of the policy lists, updated along with the database
+	lists: RwLock<PolicyLists>,
+}
+
+/// The server policy lists admins manage at runtime, on top of the
+/// `forbidden_remote_server_names` style config options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PolicyList {
+	/// Servers we don't federate with at all
+	Deny,
+	/// Servers we federate with if `federation_allowlist_only` is enabled, in
+	/// addition to the `trusted_servers`
+	Allow,
+	/// Servers we don't download remote media from
+	Media,
+}
+
+impl PolicyList {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Deny => "deny",
+			Self::Allow => "allow",
+			Self::Media => "media",
+		}
+	}
+}
+
+#[derive(Default)]
+struct PolicyLists {
+	deny: Vec<String>,
+	allow: Vec<String>,
+	media: Vec<String>,
+	deny_set: RegexSet,
+	allow_set: RegexSet,
+	media_set: RegexSet,
+}
+
+impl PolicyLists {
+	fn patterns(&self, list: PolicyList) -> &Vec<String> {
+		match list {
+			PolicyList::Deny => &self.deny,
+			PolicyList::Allow => &self.allow,
+			PolicyList::Media => &self.media,
+		}
+	}
+
+	fn patterns_mut(&mut self, list: PolicyList) -> &mut Vec<String> {
+		match list {
+			PolicyList::Deny => &mut self.deny,
+			PolicyList::Allow => &mut self.allow,
+			PolicyList::Media => &mut self.media,
+		}
+	}
+
+	fn compile(&mut self) {
+		self.deny_set = glob_set(&self.deny);
+		self.allow_set = glob_set(&self.allow);
+		self.media_set = glob_set(&self.media);
+	}
+}
+
+impl Service {
+	pub fn build(db: &'static dyn Data) -> Result<Self> {
+		let mut lists = PolicyLists::default();
+		for policy in db.server_policies() {
+			let (list, pattern) = policy?;
+			lists.patterns_mut(list).push(pattern);
+		}
+		lists.compile();
+
+		Ok(Self {
+			db,
+			lists: RwLock::new(lists),
+		})
+	}
+
+	/// Whether we accept federation requests from and send them to a server,
+	/// taking both the config options and the policy lists into account.
+	pub fn is_federation_allowed(&self, server: &ServerName) -> bool {
+		if server == services().globals.server_name() {
+			return true;
+		}
+
+		if services()
+			.globals
+			.forbidden_remote_server_names()
+			.iter()
+			.any(|forbidden| forbidden == server)
+		{
+			return false;
+		}
+
+		let lists = self.lists.read().unwrap();
+		if matches(&lists.deny_set, server) {
+			return false;
+		}
+
+		// Keys of other servers are fetched from the trusted servers
+		!services().globals.config.get().federation_allowlist_only
+			|| matches(&lists.allow_set, server)
+			|| services()
+				.globals
+				.trusted_servers()
+				.iter()
+				.any(|trusted| trusted == server)
+	}
+
+	/// Whether we download remote media from a server.
+	pub fn is_media_allowed(&self, server: &ServerName) -> bool {
+		self.is_federation_allowed(server)
+			&& !services()
+				.globals
+				.prevent_media_downloads_from()
+				.iter()
+				.any(|forbidden| forbidden == server)
+			&& !matches(&self.lists.read().unwrap().media_set, server)
+	}
+
+	/// Whether our users can query the room directory of a server.
+	pub fn is_room_directory_allowed(&self, server: &ServerName) -> bool {
+		self.is_federation_allowed(server)
+			&& !services()
+				.globals
+				.forbidden_remote_room_directory_server_names()
+				.iter()
+				.any(|forbidden| forbidden == server)
+	}
+
+	/// Returns the patterns of a policy list.
+	pub fn list(&self, list: PolicyList) -> Vec<String> { self.lists.read().unwrap().patterns(list).clone() }
+
+	/// Adds a server name pattern to a policy list, taking effect immediately.
+	/// Returns false if the list already contains it.
+	///
+	/// Patterns can't contain a port, as they match server names without it.
+	pub fn add(&self, list: PolicyList, pattern: &str) -> Result<bool> {
+		let pattern = pattern.to_lowercase();
+		if pattern.is_empty()
+			|| !pattern
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']' | '*' | '?'))
+		{
+			return Err(Error::Error(format!(
+				"{pattern} is not a valid server name pattern, only server names with * and ? wildcards are allowed."
+			)));
+		}
+
+		if has_port(&pattern) {
+			return Err(Error::Error(format!(
+				"{pattern} contains a port. Like room server ACLs, patterns match server names without their port, so \
+				 it would never match."
+			)));
+		}
+
+		let mut lists = self.lists.write().unwrap();
+		if lists.patterns(list).contains(&pattern) {
+			return Ok(false);
+		}
+
+		self.db.add_server_policy(list, &pattern)?;
+		lists.patterns_mut(list).push(pattern);
+		lists.compile();
+		drop(lists);
+
+		if list == PolicyList::Deny {
+			purge_denied_destinations()?;
+		}
+
+		Ok(true)
+	}
+
+	/// Removes a server name pattern from a policy list, taking effect
+	/// immediately. Returns false if the list does not contain it.
+	pub fn remove(&self, list: PolicyList, pattern: &str) -> Result<bool> {
+		let pattern = pattern.to_lowercase();
+
+		let mut lists = self.lists.write().unwrap();
+		let patterns = lists.patterns_mut(list);
+		let Some(index) = patterns.iter().position(|p| *p == pattern) else {
+			return Ok(false);
+		};
+
+		self.db.remove_server_policy(list, &pattern)?;
+		patterns.remove(index);
+		lists.compile();
+		drop(lists);
+
+		if list == PolicyList::Allow && services().globals.config.get().federation_allowlist_only {
+			purge_denied_destinations()?;
+		}
+
+		Ok(true)
+	}
+}
+
+/// Drops what is queued for servers a policy change stopped us federating
+/// with, so it isn't retried forever.
+fn purge_denied_destinations() -> Result<()> {
+	let purged = services().sending.purge_denied_destinations()?;
+	if purged > 0 {
+		info!("Dropped {purged} queued event(s) for servers we don't federate with anymore");
+	}
+
+	Ok(())
+}
+
+/// Whether a server name matches a set of patterns. Like room server ACLs, the
+/// port is ignored.
+fn matches(set: &RegexSet, server: &ServerName) -> bool { set.is_match(server.host()) }
+
+/// Whether a pattern has a port, the part after the `:` that isn't inside the
+/// brackets of an IPv6 address
+fn has_port(pattern: &str) -> bool {
+	let host_end = pattern.rfind(']').unwrap_or(0);
+	pattern[host_end..].contains(':')
+}
+
+/// Compiles glob patterns where `*` matches any number of characters and `?`
+/// a single one, like in room server ACLs, into a case insensitive set.
+fn glob_set(patterns: &[String]) -> RegexSet {
+	RegexSet::new(patterns.iter().map(|pattern| {
+		format!(
+			"(?i)^{}$",
+			regex::escape(pattern)
+				.replace(r"\*", ".*")
+				.replace(r"\?", ".")
+		)
+	}))
+	.expect("escaped patterns are valid regexes")
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::server_name;
+
+	use super::{glob_set, has_port, matches};
+
+	#[test]
+	fn glob_patterns_match_server_names() {
+		let set = glob_set(&["*.evil.example".to_owned(), "spam?.example".to_owned()]);
+
+		assert!(set.is_match("a.evil.example"));
+		assert!(set.is_match("A.B.EVIL.example"));
+		assert!(!set.is_match("evil.example"));
+		assert!(!set.is_match("notevil.example"));
+		assert!(set.is_match("spam1.example"));
+		assert!(!set.is_match("spam12.example"));
+		assert!(!set.is_match("spam.example"));
+	}
+
+	#[test]
+	fn ports_are_ignored() {
+		let set = glob_set(&["evil.example".to_owned(), "*.evil.example".to_owned()]);
+
+		assert!(matches(&set, server_name!("evil.example")));
+		assert!(matches(&set, server_name!("evil.example:8448")));
+		assert!(matches(&set, server_name!("matrix.evil.example:443")));
+		assert!(!matches(&set, server_name!("good.example:8448")));
+
+		assert!(has_port("evil.example:8448"));
+		assert!(has_port("[::1]:8448"));
+		assert!(!has_port("evil.example"));
+		assert!(!has_port("[::1]"));
+		assert!(!has_port("*.evil.example"));
+	}
+}
@@ -0,0 +1,11 @@
+use ruma::{OwnedUserId, UserId};
+
+use crate::Result;
+
+pub trait Data: Send + Sync {
+	/// Returns the local user a subject of an identity provider is linked to.
+	fn subject_user(&self, idp_id: &str, subject: &str) -> Result<Option<OwnedUserId>>;
+
+	/// Links a subject of an identity provider to a local user.
+	fn set_subject_user(&self, idp_id: &str, subject: &str, user_id: &UserId) -> Result<()>;
+}
@@ -0,0 +1,725 @@
+mod data;
+
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose, Engine as _};
+pub use data::Data;
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use ruma::{
+	api::client::{error::ErrorKind, uiaa::AuthType},
+	events::room::message::RoomMessageEventContent,
+	OwnedDeviceId, OwnedUserId, UserId,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::{config::OidcProviderConfig, services, utils, Error, Result};
+
+/// Path of the redirect URI identity providers send users back to
+pub const CALLBACK_PATH: &str = "/_conduwuit/oidc/callback";
+
+/// How long a user has to complete the login at the identity provider
+const PENDING_AUTH_LIFETIME: Duration = Duration::from_secs(60 * 10);
+
+/// How many logins may be waiting for the identity provider at once
+const MAX_PENDING_AUTH: usize = 10_000;
+
+pub struct Service {
+	pub db: &'static dyn Data,
+	pub pending_auth: Mutex<HashMap<String, PendingAuth>>,
+	pub provider_metadata: Mutex<HashMap<String, ProviderMetadata>>,
+}
+
+/// An authorization request waiting for the identity provider to send the user
+/// back, keyed by its `state` parameter.
+pub struct PendingAuth {
+	idp_id: String,
+	code_verifier: String,
+	nonce: String,
+	purpose: SsoPurpose,
+	expires_at: Instant,
+}
+
+/// Why a user is sent to an identity provider.
+pub enum SsoPurpose {
+	/// Logging in with `m.login.sso`, redirecting the user to `redirect_url`
+	/// with a login token afterwards
+	Login {
+		redirect_url: Url,
+	},
+
+	/// Completing the `m.login.sso` stage of a user-interactive authentication
+	/// session of an existing user
+	Uiaa {
+		user_id: OwnedUserId,
+		device_id: OwnedDeviceId,
+		session: String,
+	},
+}
+
+/// The parts of the OpenID Connect discovery document we use.
+#[derive(Clone, Deserialize)]
+pub struct ProviderMetadata {
+	issuer: String,
+	authorization_endpoint: Url,
+	token_endpoint: Url,
+	userinfo_endpoint: Option<Url>,
+	jwks_uri: Url,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	access_token: String,
+	id_token: Option<String>,
+}
+
+/// An authentication completed at an identity provider.
+pub enum SsoCompletion {
+	Login {
+		user_id: OwnedUserId,
+
+		/// Where to send the user with their login token
+		redirect_url: Url,
+	},
+
+	/// The `m.login.sso` stage of the user-interactive authentication session
+	/// was completed
+	Uiaa,
+}
+
+impl Service {
+	/// Starts a login at an identity provider and returns the URL to send the
+	/// user to. If `idp_id` is None, the first configured provider is used.
+	pub async fn authorization_url(&self, idp_id: Option<&str>, redirect_url: &str) -> Result<Url> {
+		let redirect_url = Url::parse(redirect_url)
+			.ok()
+			.filter(|url| !matches!(url.scheme(), "javascript" | "data" | "vbscript"))
+			.ok_or(Error::BadRequest(ErrorKind::InvalidParam, "Invalid redirect URL."))?;
+
+		self.start_authorization(
+			idp_id,
+			SsoPurpose::Login {
+				redirect_url,
+			},
+		)
+		.await
+	}
+
+	/// Starts re-authenticating the user of a user-interactive authentication
+	/// session at the first configured identity provider and returns the URL
+	/// to send the user to.
+	pub async fn uiaa_authorization_url(&self, session: &str) -> Result<Url> {
+		let (user_id, device_id) = services()
+			.uiaa
+			.find_session(session, &AuthType::Sso)?
+			.ok_or(Error::BadRequest(ErrorKind::forbidden(), "UIAA session does not exist."))?;
+
+		self.start_authorization(
+			None,
+			SsoPurpose::Uiaa {
+				user_id,
+				device_id,
+				session: session.to_owned(),
+			},
+		)
+		.await
+	}
+
+	async fn start_authorization(&self, idp_id: Option<&str>, purpose: SsoPurpose) -> Result<Url> {
+		let provider = provider(idp_id)?;
+		let metadata = self.provider_metadata(&provider).await?;
+
+		let state = utils::random_string(32);
+		let code_verifier = utils::random_string(64);
+		let nonce = utils::random_string(32);
+
+		let mut scopes = provider.scopes.clone();
+		if !scopes.iter().any(|scope| scope == "openid") {
+			scopes.insert(0, "openid".to_owned());
+		}
+
+		let mut url = metadata.authorization_endpoint;
+		url.query_pairs_mut()
+			.append_pair("response_type", "code")
+			.append_pair("client_id", &provider.client_id)
+			.append_pair("redirect_uri", callback_url()?.as_str())
+			.append_pair("scope", &scopes.join(" "))
+			.append_pair("state", &state)
+			.append_pair("nonce", &nonce)
+			.append_pair("code_challenge", &pkce_challenge(&code_verifier))
+			.append_pair("code_challenge_method", "S256");
+
+		let now = Instant::now();
+		let mut pending_auth = self.pending_auth.lock().unwrap();
+		pending_auth.retain(|_, auth| auth.expires_at > now);
+		if pending_auth.len() >= MAX_PENDING_AUTH {
+			warn!("Too many pending SSO logins, refusing to start another one");
+			return Err(Error::BadRequest(
+				ErrorKind::LimitExceeded {
+					retry_after: None,
+				},
+				"Too many pending SSO logins, try again later.",
+			));
+		}
+		pending_auth.insert(
+			state,
+			PendingAuth {
+				idp_id: provider.id.clone(),
+				code_verifier,
+				nonce,
+				purpose,
+				expires_at: now + PENDING_AUTH_LIFETIME,
+			},
+		);
+
+		Ok(url)
+	}
+
+	/// Completes a login when the identity provider sends the user back with an
+	/// authorization code.
+	///
+	/// - Exchanges the code for an ID token using the PKCE code verifier
+	/// - Validates the ID token signature, audience, issuer and nonce
+	/// - Maps the subject to a local user, provisioning or linking one if the
+	///   provider allows it, or checks that it is linked to the user of the
+	///   user-interactive authentication session
+	pub async fn complete_authorization(&self, state: &str, code: &str) -> Result<SsoCompletion> {
+		let pending = self
+			.pending_auth
+			.lock()
+			.unwrap()
+			.remove(state)
+			.filter(|auth| auth.expires_at > Instant::now())
+			.ok_or(Error::BadRequest(ErrorKind::forbidden(), "Unknown or expired SSO session."))?;
+
+		let provider = provider(Some(&pending.idp_id))?;
+		let metadata = self.provider_metadata(&provider).await?;
+		let redirect_uri = callback_url()?;
+
+		let mut form = vec![
+			("grant_type", "authorization_code"),
+			("code", code),
+			("redirect_uri", redirect_uri.as_str()),
+			("client_id", provider.client_id.as_str()),
+			("code_verifier", pending.code_verifier.as_str()),
+		];
+		if let Some(client_secret) = &provider.client_secret {
+			form.push(("client_secret", client_secret.as_str()));
+		}
+
+		let response = services()
+			.globals
+			.client
+			.default
+			.post(metadata.token_endpoint.clone())
+			.form(&form)
+			.send()
+			.await?;
+
+		if !response.status().is_success() {
+			warn!(
+				"Token request to identity provider {} failed with status {}",
+				provider.id,
+				response.status()
+			);
+			return Err(Error::BadServerResponse("Identity provider rejected the authorization code."));
+		}
+
+		let token_response: TokenResponse = serde_json::from_slice(&response.bytes().await?).map_err(|e| {
+			warn!("Invalid token response from identity provider {}: {e}", provider.id);
+			Error::BadServerResponse("Invalid token response from identity provider.")
+		})?;
+
+		let id_token = token_response
+			.id_token
+			.ok_or(Error::BadServerResponse("Identity provider did not return an ID token."))?;
+
+		let mut claims = validate_id_token(&provider, &metadata, &id_token, &pending.nonce).await?;
+
+		// Claims in the ID token take precedence over the userinfo endpoint
+		if let Some(userinfo_endpoint) = &metadata.userinfo_endpoint {
+			match fetch_json::<JsonMap<String, JsonValue>>(
+				userinfo_endpoint.clone(),
+				Some(&token_response.access_token),
+			)
+			.await
+			{
+				Ok(userinfo) => {
+					for (claim, value) in userinfo {
+						claims.entry(claim).or_insert(value);
+					}
+				},
+				Err(e) => warn!("Failed to fetch userinfo from identity provider {}: {e}", provider.id),
+			}
+		}
+
+		check_required_claims(&provider, &claims)?;
+
+		let subject = claims
+			.get("sub")
+			.and_then(JsonValue::as_str)
+			.ok_or(Error::BadServerResponse("ID token has no subject."))?;
+
+		match pending.purpose {
+			SsoPurpose::Login {
+				redirect_url,
+			} => Ok(SsoCompletion::Login {
+				user_id: self.map_user(&provider, subject, &claims).await?,
+				redirect_url,
+			}),
+			SsoPurpose::Uiaa {
+				user_id,
+				device_id,
+				session,
+			} => {
+				// Never link or provision accounts here, the subject has to be
+				// the user that is authenticating
+				if self.db.subject_user(&provider.id, subject)?.as_ref() != Some(&user_id) {
+					return Err(Error::BadRequest(
+						ErrorKind::forbidden(),
+						"This identity provider account does not belong to you.",
+					));
+				}
+
+				services()
+					.uiaa
+					.complete_stage(&user_id, &device_id, &session, AuthType::Sso)?;
+
+				Ok(SsoCompletion::Uiaa)
+			},
+		}
+	}
+
+	/// Returns the local user a subject of an identity provider logs in as,
+	/// linking or provisioning one on first login.
+	async fn map_user(
+		&self, provider: &OidcProviderConfig, subject: &str, claims: &JsonMap<String, JsonValue>,
+	) -> Result<OwnedUserId> {
+		if let Some(user_id) = self.db.subject_user(&provider.id, subject)? {
+			if services().users.is_deactivated(&user_id)? {
+				return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+			}
+
+			return Ok(user_id);
+		}
+
+		let localpart = claims
+			.get(&provider.localpart_claim)
+			.and_then(JsonValue::as_str)
+			.map(sanitize_localpart)
+			.filter(|localpart| !localpart.is_empty())
+			.ok_or(Error::BadRequest(
+				ErrorKind::forbidden(),
+				"Identity provider did not provide a usable username.",
+			))?;
+
+		let user_id = UserId::parse_with_server_name(localpart, services().globals.server_name())
+			.ok()
+			.filter(|user_id| !user_id.is_historical())
+			.ok_or(Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
+
+		if services().appservice.is_exclusive_user_id(&user_id).await {
+			return Err(Error::BadRequest(ErrorKind::Exclusive, "User ID reserved by appservice."));
+		}
+
+		if services().users.exists(&user_id)? {
+			if !provider.allow_existing_users {
+				return Err(Error::BadRequest(
+					ErrorKind::UserInUse,
+					"An account with this username already exists and is not linked to this identity provider.",
+				));
+			}
+
+			if services().users.is_deactivated(&user_id)? {
+				return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+			}
+
+			info!(
+				"Linking existing user {user_id} to subject {subject} of identity provider {}",
+				provider.id
+			);
+		} else {
+			if !provider.allow_registration {
+				return Err(Error::BadRequest(
+					ErrorKind::forbidden(),
+					"Registration via this identity provider is disabled.",
+				));
+			}
+
+			if services()
+				.globals
+				.forbidden_usernames()
+				.is_match(user_id.localpart())
+			{
+				return Err(Error::BadRequest(ErrorKind::Unknown, "Username is forbidden."));
+			}
+
+			let displayname = claims
+				.get(&provider.displayname_claim)
+				.and_then(JsonValue::as_str)
+				.map(ToOwned::to_owned);
+
+			services().users.provision(&user_id, displayname).await?;
+
+			info!(
+				"New user \"{user_id}\" registered on this server via identity provider {}.",
+				provider.id
+			);
+			services()
+				.admin
+				.send_message(RoomMessageEventContent::notice_plain(format!(
+					"New user \"{user_id}\" registered on this server via identity provider {}.",
+					provider.id
+				)));
+		}
+
+		self.db.set_subject_user(&provider.id, subject, &user_id)?;
+
+		Ok(user_id)
+	}
+
+	/// Fetches and caches the discovery document of an identity provider.
+	async fn provider_metadata(&self, provider: &OidcProviderConfig) -> Result<ProviderMetadata> {
+		if let Some(metadata) = self.provider_metadata.lock().unwrap().get(&provider.id) {
+			return Ok(metadata.clone());
+		}
+
+		let mut url = provider.issuer.clone();
+		url.path_segments_mut()
+			.map_err(|()| Error::bad_config("OIDC issuer URL cannot be a base URL."))?
+			.pop_if_empty()
+			.extend([".well-known", "openid-configuration"]);
+
+		let metadata: ProviderMetadata = fetch_json(url, None).await?;
+
+		if metadata.issuer.trim_end_matches('/') != provider.issuer.as_str().trim_end_matches('/') {
+			warn!(
+				"Issuer {} in discovery document of identity provider {} does not match the configured issuer",
+				metadata.issuer, provider.id
+			);
+			return Err(Error::BadServerResponse("Identity provider issuer mismatch."));
+		}
+
+		self.provider_metadata
+			.lock()
+			.unwrap()
+			.insert(provider.id.clone(), metadata.clone());
+
+		Ok(metadata)
+	}
+}
+
+/// Returns the configured identity provider with the given ID, or the first
+/// one if `idp_id` is None.
+pub fn provider(idp_id: Option<&str>) -> Result<OidcProviderConfig> {
+	let config = services().globals.config.get();
+	let providers = &config.oidc_providers;
+
+	match idp_id {
+		Some(idp_id) => providers.iter().find(|provider| provider.id == idp_id),
+		None => providers.first(),
+	}
+	.cloned()
+	.ok_or(Error::BadRequest(ErrorKind::NotFound, "Unknown identity provider."))
+}
+
+/// The redirect URI identity providers send users back to
+pub fn callback_url() -> Result<Url> {
+	services()
+		.globals
+		.well_known_client()
+		.as_ref()
+		.ok_or_else(|| Error::bad_config("\"well_known.client\" must be set to use SSO."))?
+		.join(CALLBACK_PATH)
+		.map_err(|_| Error::bad_config("\"well_known.client\" is not a valid base URL."))
+}
+
+async fn validate_id_token(
+	provider: &OidcProviderConfig, metadata: &ProviderMetadata, id_token: &str, nonce: &str,
+) -> Result<JsonMap<String, JsonValue>> {
+	let header = jsonwebtoken::decode_header(id_token).map_err(|e| {
+		warn!("Invalid ID token from identity provider {}: {e}", provider.id);
+		Error::BadServerResponse("Invalid ID token from identity provider.")
+	})?;
+
+	let key = match header.alg {
+		Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+			let client_secret = provider
+				.client_secret
+				.as_ref()
+				.ok_or(Error::BadServerResponse(
+					"ID token is signed with the client secret, but none is configured.",
+				))?;
+
+			DecodingKey::from_secret(client_secret.as_bytes())
+		},
+		_ => {
+			let jwks: JwkSet = fetch_json(metadata.jwks_uri.clone(), None).await?;
+			let jwk = match &header.kid {
+				Some(kid) => jwks.find(kid),
+				None => jwks.keys.first(),
+			}
+			.ok_or(Error::BadServerResponse("No matching key found for ID token."))?;
+
+			DecodingKey::from_jwk(jwk).map_err(|e| {
+				warn!("Invalid JWK from identity provider {}: {e}", provider.id);
+				Error::BadServerResponse("Invalid key from identity provider.")
+			})?
+		},
+	};
+
+	decode_id_token(provider, &metadata.issuer, id_token, &key, nonce)
+}
+
+/// Checks the signature, algorithm, audience, issuer, expiry and nonce of an
+/// ID token and returns its claims.
+fn decode_id_token(
+	provider: &OidcProviderConfig, issuer: &str, id_token: &str, key: &DecodingKey, nonce: &str,
+) -> Result<JsonMap<String, JsonValue>> {
+	// The token must not pick its own algorithm, e.g. HMAC with a public key
+	let alg = jsonwebtoken::decode_header(id_token)
+		.ok()
+		.map(|header| header.alg)
+		.filter(|alg| provider.id_token_signing_algs.contains(alg))
+		.ok_or_else(|| {
+			warn!(
+				"ID token from identity provider {} is signed with an algorithm that is not allowed",
+				provider.id
+			);
+			Error::BadRequest(ErrorKind::forbidden(), "ID token is invalid.")
+		})?;
+
+	let mut validation = Validation::new(alg);
+	validation.set_audience(&[&provider.client_id]);
+	validation.set_issuer(&[issuer]);
+
+	let claims = jsonwebtoken::decode::<JsonMap<String, JsonValue>>(id_token, key, &validation)
+		.map_err(|e| {
+			warn!("Failed to validate ID token from identity provider {}: {e}", provider.id);
+			Error::BadRequest(ErrorKind::forbidden(), "ID token is invalid.")
+		})?
+		.claims;
+
+	if claims.get("nonce").and_then(JsonValue::as_str) != Some(nonce) {
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "ID token nonce does not match."));
+	}
+
+	Ok(claims)
+}
+
+fn check_required_claims(provider: &OidcProviderConfig, claims: &JsonMap<String, JsonValue>) -> Result<()> {
+	for (claim, required) in &provider.required_claims {
+		let matches = match claims.get(claim) {
+			Some(JsonValue::String(value)) => value == required,
+			Some(JsonValue::Array(values)) => values
+				.iter()
+				.any(|value| value.as_str() == Some(required.as_str())),
+			Some(JsonValue::Bool(value)) => value.to_string() == *required,
+			_ => false,
+		};
+
+		if !matches {
+			debug!("Required claim {claim} not satisfied for identity provider {}", provider.id);
+			return Err(Error::BadRequest(
+				ErrorKind::forbidden(),
+				"You are not allowed to log in with this identity provider.",
+			));
+		}
+	}
+
+	Ok(())
+}
+
+async fn fetch_json<T: DeserializeOwned>(url: Url, bearer_token: Option<&str>) -> Result<T> {
+	let mut request = services().globals.client.default.get(url.clone());
+	if let Some(bearer_token) = bearer_token {
+		request = request.bearer_auth(bearer_token);
+	}
+
+	let response = request.send().await?;
+	if !response.status().is_success() {
+		warn!("Request to identity provider at {url} failed with status {}", response.status());
+		return Err(Error::BadServerResponse("Request to identity provider failed."));
+	}
+
+	serde_json::from_slice(&response.bytes().await?).map_err(|e| {
+		warn!("Invalid response from identity provider at {url}: {e}");
+		Error::BadServerResponse("Invalid response from identity provider.")
+	})
+}
+
+/// Whether users can be sent back to a client with their login token without
+/// confirming it first. The scheme, host and port have to match an allowlisted
+/// URL exactly, and the path has to be below its path.
+pub fn is_trusted_client(redirect_url: &Url, allowlist: &[Url]) -> bool {
+	allowlist.iter().any(|trusted| {
+		redirect_url.scheme() == trusted.scheme()
+			&& redirect_url.host() == trusted.host()
+			&& redirect_url.port_or_known_default() == trusted.port_or_known_default()
+			&& is_below_path(redirect_url.path(), trusted.path())
+	})
+}
+
+/// Whether `path` is `base` or a path below it, so `/app` doesn't include
+/// `/application`
+fn is_below_path(path: &str, base: &str) -> bool {
+	(base.ends_with('/') && path.starts_with(base))
+		|| path
+			.strip_prefix(base)
+			.map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Code challenge for the `S256` PKCE method (RFC 7636)
+fn pkce_challenge(code_verifier: &str) -> String {
+	general_purpose::URL_SAFE_NO_PAD.encode(utils::calculate_hash(&[code_verifier.as_bytes()]))
+}
+
+/// Turns a claim into a localpart by lowercasing it and replacing characters
+/// not allowed in user IDs.
+fn sanitize_localpart(claim: &str) -> String {
+	claim
+		.to_lowercase()
+		.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '=' | '-' | '/') {
+				c
+			} else {
+				'_'
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use jsonwebtoken::{DecodingKey, EncodingKey, Header};
+	use serde_json::{json, Value as JsonValue};
+	use url::Url;
+
+	use super::{
+		check_required_claims, decode_id_token, is_trusted_client, pkce_challenge, sanitize_localpart,
+		OidcProviderConfig,
+	};
+
+	const ISSUER: &str = "https://idp.example.com";
+	const SECRET: &str = "mock idp secret";
+
+	fn provider(extra: JsonValue) -> OidcProviderConfig {
+		let mut provider = json!({
+			"id": "mock",
+			"name": "Mock IdP",
+			"issuer": ISSUER,
+			"client_id": "conduwuit",
+			"client_secret": SECRET,
+		});
+		provider
+			.as_object_mut()
+			.unwrap()
+			.extend(extra.as_object().unwrap().clone());
+
+		serde_json::from_value(provider).unwrap()
+	}
+
+	/// Issues an ID token the way an identity provider would
+	fn id_token(header: &Header, claims: &JsonValue) -> String {
+		jsonwebtoken::encode(header, claims, &EncodingKey::from_secret(SECRET.as_bytes())).unwrap()
+	}
+
+	fn claims(nonce: &str) -> JsonValue {
+		json!({
+			"iss": ISSUER,
+			"aud": "conduwuit",
+			"sub": "subject",
+			"exp": u64::MAX / 2,
+			"nonce": nonce,
+		})
+	}
+
+	#[test]
+	fn pkce_s256_challenge() {
+		assert_eq!(
+			pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r7cB2BhBfYRrT9zNXRtTT9lx1N0ll3T6P0"),
+			"TylrjEfeAvb6kDTycVFxPptLN6dEM3CBqgIX1kBbwtg"
+		);
+	}
+
+	#[test]
+	fn localpart_from_claim() {
+		assert_eq!(sanitize_localpart("Jane.Doe"), "jane.doe");
+		assert_eq!(sanitize_localpart("jane doe@corp"), "jane_doe_corp");
+	}
+
+	#[test]
+	fn id_token_validation() {
+		let provider = provider(json!({ "id_token_signing_algs": ["HS256"] }));
+		let key = DecodingKey::from_secret(SECRET.as_bytes());
+		let token = id_token(&Header::default(), &claims("nonce"));
+
+		let decoded = decode_id_token(&provider, ISSUER, &token, &key, "nonce").unwrap();
+		assert_eq!(decoded["sub"], "subject");
+
+		assert!(decode_id_token(&provider, ISSUER, &token, &key, "other nonce").is_err());
+		assert!(decode_id_token(&provider, "https://evil.example.com", &token, &key, "nonce").is_err());
+		assert!(
+			decode_id_token(&provider, ISSUER, &token, &DecodingKey::from_secret(b"wrong secret"), "nonce").is_err()
+		);
+
+		let mut wrong_audience = claims("nonce");
+		wrong_audience["aud"] = json!("someone else");
+		let token = id_token(&Header::default(), &wrong_audience);
+		assert!(decode_id_token(&provider, ISSUER, &token, &key, "nonce").is_err());
+	}
+
+	#[test]
+	fn id_token_algorithm_is_pinned() {
+		// The default only allows RS256, an HMAC token must not be accepted
+		let provider = provider(json!({}));
+		let key = DecodingKey::from_secret(SECRET.as_bytes());
+		let token = id_token(&Header::default(), &claims("nonce"));
+
+		assert!(decode_id_token(&provider, ISSUER, &token, &key, "nonce").is_err());
+	}
+
+	#[test]
+	fn required_claims() {
+		let provider = provider(json!({ "required_claims": { "groups": "matrix-users" } }));
+
+		let member = json!({ "groups": ["staff", "matrix-users"] });
+		assert!(check_required_claims(&provider, member.as_object().unwrap()).is_ok());
+
+		let other = json!({ "groups": ["staff"] });
+		assert!(check_required_claims(&provider, other.as_object().unwrap()).is_err());
+		assert!(check_required_claims(&provider, &serde_json::Map::new()).is_err());
+	}
+
+	#[test]
+	fn trusted_clients() {
+		let allowlist = vec![
+			Url::parse("https://app.element.io").unwrap(),
+			Url::parse("https://example.com/app").unwrap(),
+		];
+		let trusted = |url: &str| is_trusted_client(&Url::parse(url).unwrap(), &allowlist);
+
+		assert!(trusted("https://app.element.io/#/login"));
+		assert!(trusted("https://app.element.io:443/"));
+		assert!(trusted("https://example.com/app"));
+		assert!(trusted("https://example.com/app/#/login"));
+
+		// Lookalike hosts
+		assert!(!trusted("https://app.element.io.evil.example.com/"));
+		assert!(!trusted("https://app.element.io@evil.example.com/"));
+		// Other schemes, ports and paths
+		assert!(!trusted("http://app.element.io/"));
+		assert!(!trusted("https://app.element.io:8443/"));
+		assert!(!trusted("https://example.com/application"));
+		assert!(!trusted("https://example.com/"));
+
+		assert!(!is_trusted_client(&Url::parse("https://evil.example.com/").unwrap(), &[]));
+	}
+}
@@ -0,0 +1,33 @@
+use ruma::{
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	ClientSecret, OwnedSessionId, OwnedUserId, UserId,
+};
+
+use super::ValidationSession;
+use crate::Result;
+
+pub trait Data: Send + Sync {
+	/// Returns the user a 3PID is bound to.
+	fn threepid_user(&self, medium: &Medium, address: &str) -> Result<Option<OwnedUserId>>;
+
+	/// Binds a validated 3PID to a user.
+	fn add_threepid(&self, user_id: &UserId, threepid: &ThirdPartyIdentifier) -> Result<()>;
+
+	/// Unbinds a 3PID from a user.
+	fn remove_threepid(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<()>;
+
+	/// Returns an iterator over the 3PIDs bound to a user.
+	fn user_threepids<'a>(&'a self, user_id: &UserId) -> Box<dyn Iterator<Item = Result<ThirdPartyIdentifier>> + 'a>;
+
+	fn validation_session(&self, sid: &str) -> Result<Option<ValidationSession>>;
+
+	/// Returns the validation session started with a client secret for an
+	/// address.
+	fn validation_session_id(
+		&self, client_secret: &ClientSecret, medium: &Medium, address: &str,
+	) -> Result<Option<OwnedSessionId>>;
+
+	fn set_validation_session(&self, sid: &str, session: &ValidationSession) -> Result<()>;
+
+	fn remove_validation_session(&self, sid: &str) -> Result<()>;
+}
@@ -0,0 +1,547 @@
+mod data;
+
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+pub use data::Data;
+use lettre::{
+	message::{header::ContentType, Mailbox},
+	transport::smtp::authentication::Credentials,
+	Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use ruma::{
+	api::client::{
+		error::{ErrorKind, RetryAfter},
+		uiaa::ThirdpartyIdCredentials,
+	},
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	ClientSecret, MilliSecondsSinceUnixEpoch, OwnedClientSecret, OwnedSessionId, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+use url::Url;
+
+use crate::{
+	api::client_server::{SESSION_ID_LENGTH, TOKEN_LENGTH},
+	config::{EmailConfig, SmtpTls},
+	services, utils, Config, Error, Result,
+};
+
+/// Path of the link in validation emails, and of the `submit_url` clients can
+/// submit tokens to
+pub const SUBMIT_TOKEN_PATH: &str = "/_conduwuit/email/submit_token";
+
+/// How long to wait before sending another email to the same address
+const MIN_EMAIL_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Service {
+	pub db: &'static dyn Data,
+	mailer: Option<Mailer>,
+
+	/// When the last email was sent to an address
+	last_sent: Mutex<HashMap<String, Instant>>,
+}
+
+struct Mailer {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+
+/// What an email address is being validated for, which decides the wording of
+/// the email and which addresses are accepted.
+#[derive(Clone, Copy)]
+pub enum ValidationPurpose {
+	/// Adding an address that is not bound to any account yet
+	AddThreepid,
+
+	/// Resetting the password of the account the address is bound to
+	ResetPassword,
+}
+
+/// The validation of a 3PID, identified by its session ID (`sid`).
+#[derive(Deserialize, Serialize)]
+pub struct ValidationSession {
+	pub client_secret: OwnedClientSecret,
+	pub medium: Medium,
+	pub address: String,
+	token: String,
+	send_attempt: u64,
+	next_link: Option<String>,
+
+	/// Timestamp in milliseconds the token was submitted at
+	validated_at: Option<u64>,
+	expires_at: u64,
+}
+
+impl Service {
+	pub fn build(db: &'static dyn Data, config: &Config) -> Result<Self> {
+		Ok(Self {
+			db,
+			mailer: config.email.as_ref().map(Mailer::new).transpose()?,
+			last_sent: Mutex::new(HashMap::new()),
+		})
+	}
+
+	pub fn email_enabled(&self) -> bool { self.mailer.is_some() }
+
+	/// Starts validating an email address by sending a token to it, and
+	/// returns the session ID.
+	///
+	/// Requests with the same client secret and a send attempt that is not
+	/// higher than the previous one return the existing session without
+	/// sending another email.
+	pub async fn request_email_token(
+		&self, email: &str, client_secret: &ClientSecret, send_attempt: u64, next_link: Option<String>,
+		purpose: ValidationPurpose,
+	) -> Result<OwnedSessionId> {
+		let Some(mailer) = &self.mailer else {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidDenied,
+				"Email is not supported on this server.",
+			));
+		};
+
+		let address = normalize_email(email)?;
+		let medium = Medium::Email;
+
+		match purpose {
+			ValidationPurpose::AddThreepid => {
+				if self.db.threepid_user(&medium, address.as_ref())?.is_some() {
+					return Err(Error::BadRequest(ErrorKind::ThreepidInUse, "Email address is already in use."));
+				}
+			},
+			ValidationPurpose::ResetPassword => {
+				if !services()
+					.globals
+					.config
+					.get()
+					.email
+					.as_ref()
+					.is_some_and(|email| email.allow_password_reset)
+				{
+					return Err(Error::BadRequest(
+						ErrorKind::ThreepidDenied,
+						"Password reset is disabled on this server.",
+					));
+				}
+
+				if self.db.threepid_user(&medium, address.as_ref())?.is_none() {
+					return Err(Error::BadRequest(
+						ErrorKind::ThreepidNotFound,
+						"Email address is not bound to any account.",
+					));
+				}
+			},
+		}
+
+		let config = services().globals.config.get();
+		let next_link_domains = config
+			.email
+			.as_ref()
+			.map_or(&[][..], |email| &email.next_link_domains);
+		let next_link = next_link.filter(|next_link| {
+			let allowed = is_allowed_next_link(next_link, next_link_domains);
+			if !allowed {
+				debug!("Ignoring next_link {next_link}, its host is not in next_link_domains");
+			}
+			allowed
+		});
+
+		let now = utils::millis_since_unix_epoch();
+
+		let existing = match self
+			.db
+			.validation_session_id(client_secret, &medium, address.as_ref())?
+		{
+			Some(sid) => match self.db.validation_session(sid.as_str())? {
+				Some(session) if session.expires_at > now => Some((sid, session)),
+				_ => {
+					self.db.remove_validation_session(sid.as_str())?;
+					None
+				},
+			},
+			None => None,
+		};
+
+		let (sid, mut session) = match existing {
+			Some((sid, session)) if session.send_attempt >= send_attempt => return Ok(sid),
+			Some(existing) => existing,
+			None => {
+				let lifetime = services()
+					.globals
+					.config
+					.get()
+					.email
+					.as_ref()
+					.map_or(0, |email| email.validation_token_lifetime);
+
+				let sid = OwnedSessionId::try_from(utils::random_string(SESSION_ID_LENGTH))
+					.expect("random string is a valid session ID");
+
+				(
+					sid,
+					ValidationSession {
+						client_secret: client_secret.to_owned(),
+						medium,
+						address: address.to_string(),
+						token: utils::random_string(TOKEN_LENGTH),
+						send_attempt,
+						next_link: None,
+						validated_at: None,
+						expires_at: now.saturating_add(lifetime.saturating_mul(1000)),
+					},
+				)
+			},
+		};
+
+		session.send_attempt = send_attempt;
+		session.next_link = next_link;
+
+		let retry_after = email_retry_after(&mut self.last_sent.lock().unwrap(), address.as_ref(), Instant::now());
+		if let Some(retry_after) = retry_after {
+			return Err(Error::BadRequest(
+				ErrorKind::LimitExceeded {
+					retry_after: Some(RetryAfter::Delay(retry_after)),
+				},
+				"An email was sent to this address recently, try again later.",
+			));
+		}
+
+		let mut link = submit_url()?;
+		link.query_pairs_mut()
+			.append_pair("sid", sid.as_str())
+			.append_pair("client_secret", client_secret.as_str())
+			.append_pair("token", &session.token);
+
+		mailer
+			.send(address, purpose, &link, session.expires_at.saturating_sub(now))
+			.await?;
+		self.db.set_validation_session(sid.as_str(), &session)?;
+
+		info!("Sent validation email for session {sid}");
+
+		Ok(sid)
+	}
+
+	/// Validates a session with the token sent to its address. Returns the
+	/// link the client asked the user to be sent to afterwards.
+	pub fn submit_token(&self, sid: &str, client_secret: &str, token: &str) -> Result<Option<String>> {
+		let now = utils::millis_since_unix_epoch();
+
+		let Some(mut session) = self.db.validation_session(sid)?.filter(|session| {
+			utils::constant_time_eq(session.client_secret.as_str().as_bytes(), client_secret.as_bytes())
+				&& session.expires_at > now
+		}) else {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidAuthFailed,
+				"Unknown or expired validation session.",
+			));
+		};
+
+		if !utils::constant_time_eq(session.token.as_bytes(), token.as_bytes()) {
+			return Err(Error::BadRequest(ErrorKind::ThreepidAuthFailed, "Invalid validation token."));
+		}
+
+		if session.validated_at.is_none() {
+			session.validated_at = Some(now);
+			self.db.set_validation_session(sid, &session)?;
+		}
+
+		Ok(session.next_link)
+	}
+
+	/// Returns a session if its token has been submitted and it has not
+	/// expired yet.
+	pub fn validated_session(&self, sid: &str, client_secret: &str) -> Result<Option<ValidationSession>> {
+		let now = utils::millis_since_unix_epoch();
+
+		Ok(self.db.validation_session(sid)?.filter(|session| {
+			utils::constant_time_eq(session.client_secret.as_str().as_bytes(), client_secret.as_bytes())
+				&& session.validated_at.is_some()
+				&& session.expires_at > now
+		}))
+	}
+
+	/// Returns the user the 3PID validated in a session is bound to, without
+	/// consuming the session.
+	pub fn validated_user(&self, creds: &ThirdpartyIdCredentials) -> Result<Option<OwnedUserId>> {
+		let Some(session) = self.validated_session(creds.sid.as_str(), creds.client_secret.as_str())? else {
+			return Ok(None);
+		};
+
+		self.db.threepid_user(&session.medium, &session.address)
+	}
+
+	/// Returns the user the 3PID validated in a `m.login.email.identity` UIAA
+	/// stage is bound to. Consumes the session.
+	pub fn authenticate(&self, creds: &ThirdpartyIdCredentials) -> Result<Option<OwnedUserId>> {
+		let Some(session) = self.validated_session(creds.sid.as_str(), creds.client_secret.as_str())? else {
+			return Ok(None);
+		};
+
+		self.db.remove_validation_session(creds.sid.as_str())?;
+		self.db.threepid_user(&session.medium, &session.address)
+	}
+
+	/// Binds the 3PID validated in a session to a user. Consumes the session.
+	pub fn bind(&self, user_id: &UserId, sid: &str, client_secret: &str) -> Result<()> {
+		let Some(session) = self.validated_session(sid, client_secret)? else {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidAuthFailed,
+				"The third party identifier has not been validated.",
+			));
+		};
+
+		match self.db.threepid_user(&session.medium, &session.address)? {
+			Some(owner) if owner != user_id => {
+				return Err(Error::BadRequest(
+					ErrorKind::ThreepidInUse,
+					"Third party identifier is already in use.",
+				));
+			},
+			Some(_) => {},
+			None => {
+				let validated_at = session.validated_at.unwrap_or_default();
+
+				self.db.add_threepid(
+					user_id,
+					&ThirdPartyIdentifier {
+						address: session.address.clone(),
+						medium: session.medium.clone(),
+						validated_at: MilliSecondsSinceUnixEpoch(validated_at.try_into().unwrap_or_default()),
+						added_at: MilliSecondsSinceUnixEpoch::now(),
+					},
+				)?;
+
+				info!("{user_id} added the {} {}", session.medium, session.address);
+			},
+		}
+
+		self.db.remove_validation_session(sid)
+	}
+
+	/// Unbinds a 3PID from a user. Returns false if it wasn't bound to them.
+	pub fn unbind(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<bool> {
+		let address = match medium {
+			Medium::Email => normalize_email(address)
+				.map(|address| address.to_string())
+				.unwrap_or_else(|_| address.to_owned()),
+			_ => address.to_owned(),
+		};
+
+		if self.db.threepid_user(medium, &address)?.as_deref() != Some(user_id) {
+			return Ok(false);
+		}
+
+		self.db.remove_threepid(user_id, medium, &address)?;
+
+		info!("{user_id} removed the {medium} {address}");
+
+		Ok(true)
+	}
+
+	/// Unbinds all 3PIDs of a user, e.g. when they are deactivated.
+	pub fn unbind_all(&self, user_id: &UserId) -> Result<()> {
+		let threepids = self.user_threepids(user_id).collect::<Result<Vec<_>>>()?;
+		for threepid in threepids {
+			self.db
+				.remove_threepid(user_id, &threepid.medium, &threepid.address)?;
+		}
+
+		Ok(())
+	}
+
+	pub fn user_threepids<'a>(&'a self, user_id: &UserId) -> impl Iterator<Item = Result<ThirdPartyIdentifier>> + 'a {
+		self.db.user_threepids(user_id)
+	}
+
+	/// Whether the user has an email address to authenticate with via
+	/// `m.login.email.identity`.
+	pub fn has_email(&self, user_id: &UserId) -> Result<bool> {
+		if !self.email_enabled() {
+			return Ok(false);
+		}
+
+		for threepid in self.user_threepids(user_id) {
+			if threepid?.medium == Medium::Email {
+				return Ok(true);
+			}
+		}
+
+		Ok(false)
+	}
+}
+
+impl Mailer {
+	fn new(config: &EmailConfig) -> Result<Self> {
+		let mut builder = match config.smtp_tls {
+			SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host),
+			SmtpTls::StartTls => {
+				AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host).map_err(|e| {
+					error!("Failed to set up SMTP transport: {e}");
+					Error::bad_config("Failed to set up the SMTP transport.")
+				})?
+			},
+			SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host).map_err(|e| {
+				error!("Failed to set up SMTP transport: {e}");
+				Error::bad_config("Failed to set up the SMTP transport.")
+			})?,
+		};
+
+		if let Some(port) = config.smtp_port {
+			builder = builder.port(port);
+		}
+		if let Some(username) = &config.smtp_username {
+			builder = builder.credentials(Credentials::new(
+				username.clone(),
+				config.smtp_password.clone().unwrap_or_default(),
+			));
+		}
+
+		Ok(Self {
+			transport: builder.build(),
+			from: config
+				.from
+				.parse()
+				.map_err(|_| Error::bad_config("The \"from\" address of the email config is not a valid mailbox."))?,
+		})
+	}
+
+	async fn send(&self, to: Address, purpose: ValidationPurpose, link: &Url, valid_for_ms: u64) -> Result<()> {
+		let server_name = services().globals.server_name();
+		let minutes = valid_for_ms / 1000 / 60;
+
+		let (subject, body) = match purpose {
+			ValidationPurpose::AddThreepid => (
+				format!("Validate your email address on {server_name}"),
+				format!(
+					"Someone, hopefully you, asked to add this email address to a Matrix account on \
+					 {server_name}.\n\nTo confirm, open this link:\n\n{link}\n\nIf this wasn't you, you can safely \
+					 ignore this email. The link expires in {minutes} minutes.\n"
+				),
+			),
+			ValidationPurpose::ResetPassword => (
+				format!("Reset your password on {server_name}"),
+				format!(
+					"Someone, hopefully you, asked to reset the password of the Matrix account on {server_name} this \
+					 email address belongs to.\n\nTo continue, open this link and return to your \
+					 client:\n\n{link}\n\nIf this wasn't you, you can safely ignore this email, your password will \
+					 not be changed. The link expires in {minutes} minutes.\n"
+				),
+			),
+		};
+
+		let message = Message::builder()
+			.from(self.from.clone())
+			.to(Mailbox::new(None, to))
+			.subject(subject)
+			.header(ContentType::TEXT_PLAIN)
+			.body(body)
+			.map_err(|e| {
+				error!("Failed to build email: {e}");
+				Error::BadServerResponse("Failed to build validation email.")
+			})?;
+
+		self.transport.send(message).await.map_err(|e| {
+			error!("Failed to send email: {e}");
+			Error::BadServerResponse("Failed to send validation email.")
+		})?;
+
+		Ok(())
+	}
+}
+
+/// Where users submit validation tokens, either by following the link in the
+/// email or through their client.
+pub fn submit_url() -> Result<Url> {
+	services()
+		.globals
+		.well_known_client()
+		.as_ref()
+		.ok_or_else(|| Error::bad_config("\"well_known.client\" must be set to use email."))?
+		.join(SUBMIT_TOKEN_PATH)
+		.map_err(|_| Error::bad_config("\"well_known.client\" is not a valid base URL."))
+}
+
+/// Whether users can be sent to a `next_link` after validating their email
+/// address
+fn is_allowed_next_link(next_link: &str, domains: &[String]) -> bool {
+	Url::parse(next_link).is_ok_and(|url| {
+		matches!(url.scheme(), "http" | "https")
+			&& url.host_str().is_some_and(|host| {
+				domains
+					.iter()
+					.any(|domain| domain.eq_ignore_ascii_case(host))
+			})
+	})
+}
+
+/// Returns how long to wait until another email can be sent to an address,
+/// or records that one is sent now.
+fn email_retry_after(last_sent: &mut HashMap<String, Instant>, address: &str, now: Instant) -> Option<Duration> {
+	last_sent.retain(|_, sent_at| now.duration_since(*sent_at) < MIN_EMAIL_INTERVAL);
+
+	if let Some(sent_at) = last_sent.get(address) {
+		return Some(MIN_EMAIL_INTERVAL.saturating_sub(now.duration_since(*sent_at)));
+	}
+
+	last_sent.insert(address.to_owned(), now);
+
+	None
+}
+
+fn normalize_email(email: &str) -> Result<Address> {
+	email
+		.trim()
+		.to_lowercase()
+		.parse()
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid email address."))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		collections::HashMap,
+		time::{Duration, Instant},
+	};
+
+	use super::{email_retry_after, is_allowed_next_link, normalize_email, MIN_EMAIL_INTERVAL};
+
+	#[test]
+	fn normalizes_email() {
+		assert_eq!(normalize_email(" Alice@Example.ORG ").unwrap().to_string(), "alice@example.org");
+		assert!(normalize_email("not an email").is_err());
+	}
+
+	#[test]
+	fn next_link_hosts() {
+		let domains = vec!["app.element.io".to_owned()];
+
+		assert!(is_allowed_next_link("https://app.element.io/#/login", &domains));
+		assert!(!is_allowed_next_link("https://evil.example.com/", &domains));
+		assert!(!is_allowed_next_link("https://app.element.io.evil.example.com/", &domains));
+		assert!(!is_allowed_next_link("javascript://app.element.io/%0aalert(1)", &domains));
+		assert!(!is_allowed_next_link("https://app.element.io/", &[]));
+	}
+
+	#[test]
+	fn emails_to_an_address_are_throttled() {
+		let now = Instant::now();
+		let mut last_sent = HashMap::new();
+
+		assert_eq!(email_retry_after(&mut last_sent, "alice@example.org", now), None);
+		assert_eq!(email_retry_after(&mut last_sent, "bob@example.org", now), None);
+
+		let later = now + Duration::from_secs(10);
+		assert_eq!(
+			email_retry_after(&mut last_sent, "alice@example.org", later),
+			Some(MIN_EMAIL_INTERVAL - Duration::from_secs(10))
+		);
+
+		let much_later = now + MIN_EMAIL_INTERVAL;
+		assert_eq!(email_retry_after(&mut last_sent, "alice@example.org", much_later), None);
+	}
+}
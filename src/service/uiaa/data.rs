@@ -1,4 +1,4 @@
-use ruma::{api::client::uiaa::UiaaInfo, CanonicalJsonValue, DeviceId, UserId};
+use ruma::{api::client::uiaa::UiaaInfo, CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId};
 
 use crate::Result;
 
@@ -9,6 +9,9 @@ pub trait Data: Send + Sync {
 
 	fn get_uiaa_request(&self, user_id: &UserId, device_id: &DeviceId, session: &str) -> Option<CanonicalJsonValue>;
 
+	/// Returns the user and device a UIAA session was started by.
+	fn find_uiaa_session(&self, session: &str) -> Option<(OwnedUserId, OwnedDeviceId)>;
+
 	fn update_uiaa_session(
 		&self, user_id: &UserId, device_id: &DeviceId, session: &str, uiaainfo: Option<&UiaaInfo>,
 	) -> Result<()>;
@@ -1,13 +1,14 @@
 mod data;
 
-use argon2::{PasswordHash, PasswordVerifier};
+use std::net::IpAddr;
+
 pub use data::Data;
 use ruma::{
 	api::client::{
 		error::ErrorKind,
-		uiaa::{AuthData, AuthType, Password, UiaaInfo, UserIdentifier},
+		uiaa::{AuthData, AuthFlow, AuthType, EmailIdentity, Password, UiaaInfo, UserIdentifier},
 	},
-	CanonicalJsonValue, DeviceId, UserId,
+	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId,
 };
 use tracing::error;
 
@@ -37,8 +38,64 @@ impl Service {
 		)
 	}
 
-	pub fn try_auth(
-		&self, user_id: &UserId, device_id: &DeviceId, auth: &AuthData, uiaainfo: &UiaaInfo,
+	/// Flows a logged in user can confirm their identity with: their password,
+	/// a bound email address, or an identity provider.
+	pub fn user_flows(&self, user_id: &UserId) -> Result<Vec<AuthFlow>> {
+		let mut flows = vec![AuthFlow {
+			stages: vec![AuthType::Password],
+		}];
+
+		if services().threepid.has_email(user_id)? {
+			flows.push(AuthFlow {
+				stages: vec![AuthType::EmailIdentity],
+			});
+		}
+
+		if !services().globals.config.get().oidc_providers.is_empty() {
+			flows.push(AuthFlow {
+				stages: vec![AuthType::Sso],
+			});
+		}
+
+		Ok(flows)
+	}
+
+	/// Returns the user and device of a UIAA session if one of its flows has
+	/// the given stage.
+	pub fn find_session(&self, session: &str, stage: &AuthType) -> Result<Option<(OwnedUserId, OwnedDeviceId)>> {
+		let Some((user_id, device_id)) = self.db.find_uiaa_session(session) else {
+			return Ok(None);
+		};
+
+		let uiaainfo = self.db.get_uiaa_session(&user_id, &device_id, session)?;
+		if !uiaainfo
+			.flows
+			.iter()
+			.any(|flow| flow.stages.contains(stage))
+		{
+			return Ok(None);
+		}
+
+		Ok(Some((user_id, device_id)))
+	}
+
+	/// Marks a stage completed out of band, e.g. through its fallback page. The
+	/// client then acknowledges it with the session ID.
+	pub fn complete_stage(&self, user_id: &UserId, device_id: &DeviceId, session: &str, stage: AuthType) -> Result<()> {
+		let mut uiaainfo = self.db.get_uiaa_session(user_id, device_id, session)?;
+
+		if !uiaainfo.completed.contains(&stage) {
+			uiaainfo.completed.push(stage);
+		}
+
+		self.db
+			.update_uiaa_session(user_id, device_id, session, Some(&uiaainfo))
+	}
+
+	/// Completes a stage of a UIAA session. Password attempts count towards
+	/// the login lockout of the user and the IP address they come from.
+	pub async fn try_auth(
+		&self, user_id: &UserId, device_id: &DeviceId, auth: &AuthData, uiaainfo: &UiaaInfo, client_ip: Option<IpAddr>,
 	) -> Result<(bool, UiaaInfo)> {
 		let mut uiaainfo = auth.session().map_or_else(
 			|| Ok(uiaainfo.clone()),
@@ -60,34 +117,40 @@ impl Service {
 					return Err(Error::BadRequest(ErrorKind::Unrecognized, "Identifier type not recognized."));
 				};
 
-				let user_id = UserId::parse_with_server_name(username.clone(), services().globals.server_name())
-					.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "User ID is invalid."))?;
+				let identifier_user =
+					UserId::parse_with_server_name(username.clone(), services().globals.server_name())
+						.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "User ID is invalid."))?;
+
+				// Users can only confirm their own identity
+				if *identifier_user != *user_id {
+					return Err(Error::BadRequest(
+						ErrorKind::forbidden(),
+						"User ID does not match the authenticated user.",
+					));
+				}
+
+				services().login_lockout.check(user_id, client_ip)?;
 
 				// Check if password is correct
-				if let Some(hash) = services().users.password_hash(&user_id)? {
-					let hash_matches = services()
-						.globals
-						.argon
-						.verify_password(
-							password.as_bytes(),
-							&PasswordHash::new(&hash).expect("valid hash in database"),
-						)
-						.is_ok();
-
-					if !hash_matches {
-						uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
-							kind: ErrorKind::forbidden(),
-							message: "Invalid username or password.".to_owned(),
-						});
-						return Ok((false, uiaainfo));
-					}
+				if !services().auth.check_password(user_id, password).await? {
+					services().login_lockout.record_failure(user_id, client_ip);
+					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+						kind: ErrorKind::forbidden(),
+						message: "Invalid username or password.".to_owned(),
+					});
+					return Ok((false, uiaainfo));
 				}
 
+				services().login_lockout.record_success(user_id);
+
 				// Password was correct! Let's add it to `completed`
 				uiaainfo.completed.push(AuthType::Password);
 			},
 			AuthData::RegistrationToken(t) => {
-				if Some(t.token.trim()) == services().globals.config.registration_token.as_deref() {
+				if services()
+					.registration_tokens
+					.use_token(t.token.trim(), uiaainfo.session.as_deref().expect("session is always set"))?
+				{
 					uiaainfo.completed.push(AuthType::RegistrationToken);
 				} else {
 					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
@@ -97,9 +160,34 @@ impl Service {
 					return Ok((false, uiaainfo));
 				}
 			},
+			AuthData::EmailIdentity(EmailIdentity {
+				thirdparty_id_creds,
+				..
+			}) => {
+				if services()
+					.threepid
+					.authenticate(thirdparty_id_creds)?
+					.as_deref() == Some(user_id)
+				{
+					uiaainfo.completed.push(AuthType::EmailIdentity);
+				} else {
+					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+						kind: ErrorKind::ThreepidAuthFailed,
+						message: "Email address has not been validated.".to_owned(),
+					});
+					return Ok((false, uiaainfo));
+				}
+			},
+			AuthData::Terms(_) => {
+				uiaainfo.completed.push(AuthType::Terms);
+			},
 			AuthData::Dummy(_) => {
 				uiaainfo.completed.push(AuthType::Dummy);
 			},
+			AuthData::FallbackAcknowledgement(_) => {
+				// Stages completed through their fallback page are already in
+				// the session
+			},
 			k => error!("type not supported: {:?}", k),
 		}
 
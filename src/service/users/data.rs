@@ -17,6 +17,19 @@ pub trait Data: Send + Sync {
 	/// Check if account is deactivated
 	fn is_deactivated(&self, user_id: &UserId) -> Result<bool>;
 
+	/// Returns when the account was locked, if it is locked.
+	fn locked_at(&self, user_id: &UserId) -> Result<Option<u64>>;
+
+	/// Locks an account at the given time, or unlocks it if None.
+	fn set_locked_at(&self, user_id: &UserId, locked_at: Option<u64>) -> Result<()>;
+
+	/// Returns the auth provider that manages the password of an account, if
+	/// it isn't a local one.
+	fn auth_provider(&self, user_id: &UserId) -> Result<Option<String>>;
+
+	/// Records the auth provider that manages the password of an account.
+	fn set_auth_provider(&self, user_id: &UserId, provider: &str) -> Result<()>;
+
 	/// Returns the number of users registered on this server.
 	fn count(&self) -> Result<usize>;
 
@@ -71,6 +84,32 @@ pub trait Data: Send + Sync {
 	/// Replaces the access token of one device.
 	fn set_token(&self, user_id: &UserId, device_id: &DeviceId, token: &str) -> Result<()>;
 
+	/// Returns when the access token of one device expires, in milliseconds
+	/// since the unix epoch.
+	fn token_expires_at(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Option<u64>>;
+
+	/// Sets or removes the expiry of the access token of one device.
+	fn set_token_expires_at(&self, user_id: &UserId, device_id: &DeviceId, expires_at: Option<u64>) -> Result<()>;
+
+	/// Find out which user and device a refresh token belongs to.
+	fn find_from_refresh_token(&self, refresh_token: &str) -> Result<Option<(OwnedUserId, String)>>;
+
+	/// Replaces or removes the refresh token of one device. `previous` stays
+	/// valid until the device uses its new access token.
+	fn set_refresh_token(
+		&self, user_id: &UserId, device_id: &DeviceId, refresh_token: Option<&str>, previous: Option<&str>,
+	) -> Result<()>;
+
+	/// Invalidates the refresh token a device refreshed its access token with.
+	fn remove_previous_refresh_token(&self, user_id: &UserId, device_id: &DeviceId) -> Result<()>;
+
+	/// Stores a single-use login token for a user, removing expired ones.
+	fn create_login_token(&self, user_id: &UserId, token: &str, expires_at: u64) -> Result<()>;
+
+	/// Removes a login token and returns the user it belongs to and when it
+	/// expires, in milliseconds since the unix epoch.
+	fn take_login_token(&self, token: &str) -> Result<Option<(OwnedUserId, u64)>>;
+
 	fn add_one_time_key(
 		&self, user_id: &UserId, device_id: &DeviceId, one_time_key_key: &DeviceKeyId,
 		one_time_key_value: &Raw<OneTimeKey>,
@@ -132,6 +171,10 @@ pub trait Data: Send + Sync {
 
 	fn update_device_metadata(&self, user_id: &UserId, device_id: &DeviceId, device: &Device) -> Result<()>;
 
+	/// Stores where and when a device was last used, without notifying other
+	/// users of a device change.
+	fn update_device_last_seen(&self, user_id: &UserId, device_id: &DeviceId, device: &Device) -> Result<()>;
+
 	/// Get device metadata.
 	fn get_device_metadata(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Option<Device>>;
 
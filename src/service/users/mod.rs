@@ -2,7 +2,9 @@ mod data;
 use std::{
 	collections::{BTreeMap, BTreeSet},
 	mem,
+	net::IpAddr,
 	sync::{Arc, Mutex},
+	time::Duration,
 };
 
 pub use data::Data;
@@ -17,13 +19,14 @@ use ruma::{
 		},
 	},
 	encryption::{CrossSigningKey, DeviceKeys, OneTimeKey},
-	events::AnyToDeviceEvent,
+	events::{AnyToDeviceEvent, GlobalAccountDataEventType},
+	push,
 	serde::Raw,
-	DeviceId, DeviceKeyAlgorithm, DeviceKeyId, OwnedDeviceId, OwnedDeviceKeyId, OwnedMxcUri, OwnedRoomId, OwnedUserId,
-	RoomAliasId, UInt, UserId,
+	DeviceId, DeviceKeyAlgorithm, DeviceKeyId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedDeviceKeyId,
+	OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomAliasId, UInt, UserId,
 };
 
-use crate::{services, Error, Result};
+use crate::{api::client_server::AUTO_GEN_PASSWORD_LENGTH, services, utils, Error, Result};
 
 pub struct SlidingSyncCache {
 	lists: BTreeMap<String, SyncRequestList>,
@@ -32,6 +35,9 @@ pub struct SlidingSyncCache {
 	extensions: ExtensionsConfig,
 }
 
+/// How often the last seen timestamp of a device is updated at most
+const LAST_SEEN_UPDATE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
 type DbConnections = Mutex<BTreeMap<(OwnedUserId, OwnedDeviceId, String), Arc<Mutex<SlidingSyncCache>>>>;
 
 pub struct Service {
@@ -232,6 +238,19 @@ impl Service {
 	/// Check if account is deactivated
 	pub fn is_deactivated(&self, user_id: &UserId) -> Result<bool> { self.db.is_deactivated(user_id) }
 
+	/// Check if an admin locked the account. Locked users can't log in or use
+	/// their access tokens, but keep their devices and rooms.
+	pub fn is_locked(&self, user_id: &UserId) -> Result<bool> { Ok(self.db.locked_at(user_id)?.is_some()) }
+
+	/// Returns when the account was locked, in milliseconds since the epoch
+	pub fn locked_at(&self, user_id: &UserId) -> Result<Option<u64>> { self.db.locked_at(user_id) }
+
+	/// Locks or unlocks an account
+	pub fn set_locked(&self, user_id: &UserId, locked: bool) -> Result<()> {
+		self.db
+			.set_locked_at(user_id, locked.then(utils::millis_since_unix_epoch))
+	}
+
 	/// Check if a user is an admin
 	pub fn is_admin(&self, user_id: &UserId) -> Result<bool> {
 		let admin_room_alias_id = RoomAliasId::parse(format!("#admins:{}", services().globals.server_name()))
@@ -254,6 +273,39 @@ impl Service {
 		Ok(())
 	}
 
+	/// Creates an account for a user authenticated by an external provider
+	/// (SSO, LDAP) with a display name and the default push rules.
+	pub async fn provision(&self, user_id: &UserId, displayname: Option<String>) -> Result<()> {
+		// These users never learn this password, an empty one would mark the account
+		// as deactivated
+		self.create(user_id, Some(utils::random_string(AUTO_GEN_PASSWORD_LENGTH).as_str()))?;
+
+		let mut displayname = displayname.unwrap_or_else(|| user_id.localpart().to_owned());
+
+		// If `new_user_displayname_suffix` is set, registration will push whatever
+		// content is set to the user's display name with a space before it
+		if !services().globals.new_user_displayname_suffix().is_empty() {
+			displayname.push_str(&(" ".to_owned() + &services().globals.new_user_displayname_suffix()));
+		}
+
+		self.set_displayname(user_id, Some(displayname)).await?;
+
+		// Initial account data
+		services().account_data.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
+				content: ruma::events::push_rules::PushRulesEventContent {
+					global: push::Ruleset::server_default(user_id),
+				},
+			})
+			.expect("to json always works"),
+		)?;
+
+		Ok(())
+	}
+
 	/// Returns the number of users registered on this server.
 	pub fn count(&self) -> Result<usize> { self.db.count() }
 
@@ -275,10 +327,43 @@ impl Service {
 	pub fn password_hash(&self, user_id: &UserId) -> Result<Option<String>> { self.db.password_hash(user_id) }
 
 	/// Hash and set the user's password to the Argon2 hash
+	///
+	/// Fails for accounts whose password is managed by an auth provider like
+	/// LDAP, as the local password would let them log in without it.
 	pub fn set_password(&self, user_id: &UserId, password: Option<&str>) -> Result<()> {
+		if password.is_some() && self.auth_provider(user_id)?.is_some() {
+			return Err(Error::BadRequest(
+				ErrorKind::forbidden(),
+				"The password of this account is managed by an external directory.",
+			));
+		}
+
 		self.db.set_password(user_id, password)
 	}
 
+	/// Returns the auth provider that manages the password of an account, if
+	/// it isn't a local one.
+	pub fn auth_provider(&self, user_id: &UserId) -> Result<Option<String>> { self.db.auth_provider(user_id) }
+
+	/// Records that an auth provider manages the password of an account, so it
+	/// can't be logged into or changed locally anymore.
+	pub fn set_auth_provider(&self, user_id: &UserId, provider: &str) -> Result<()> {
+		self.db.set_auth_provider(user_id, provider)
+	}
+
+	/// Fails with `M_WEAK_PASSWORD` if a password chosen for the user doesn't
+	/// meet the configured password policy.
+	pub fn check_password_policy(&self, user_id: &UserId, password: &str) -> Result<()> {
+		let config = services().globals.config.get();
+		let Some(policy) = &config.password_policy else {
+			return Ok(());
+		};
+
+		policy
+			.check(user_id.localpart(), password)
+			.map_err(|message| Error::BadRequest(ErrorKind::WeakPassword, message))
+	}
+
 	/// Returns the displayname of a user on this homeserver.
 	pub fn displayname(&self, user_id: &UserId) -> Result<Option<String>> { self.db.displayname(user_id) }
 
@@ -327,6 +412,67 @@ impl Service {
 		self.db.set_token(user_id, device_id, token)
 	}
 
+	/// Sets the access token of one device to expire after `expires_in`, or to
+	/// never expire if `expires_in` is None.
+	pub fn set_token_expiry(&self, user_id: &UserId, device_id: &DeviceId, expires_in: Option<Duration>) -> Result<()> {
+		let expires_at = expires_in.map(|expires_in| {
+			utils::millis_since_unix_epoch().saturating_add(u64::try_from(expires_in.as_millis()).unwrap_or(u64::MAX))
+		});
+
+		self.db.set_token_expires_at(user_id, device_id, expires_at)
+	}
+
+	/// Check if the access token of one device has expired.
+	pub fn is_token_expired(&self, user_id: &UserId, device_id: &DeviceId) -> Result<bool> {
+		Ok(self
+			.db
+			.token_expires_at(user_id, device_id)?
+			.is_some_and(|expires_at| expires_at <= utils::millis_since_unix_epoch()))
+	}
+
+	/// Find out which user and device a refresh token belongs to.
+	pub fn find_from_refresh_token(&self, refresh_token: &str) -> Result<Option<(OwnedUserId, String)>> {
+		self.db.find_from_refresh_token(refresh_token)
+	}
+
+	/// Replaces the refresh token of one device, or removes it if
+	/// `refresh_token` is None.
+	///
+	/// The refresh token the client used to refresh, if any, stays valid until
+	/// the new access token is first used, in case the client never received
+	/// the response.
+	pub fn set_refresh_token(
+		&self, user_id: &UserId, device_id: &DeviceId, refresh_token: Option<&str>, previous: Option<&str>,
+	) -> Result<()> {
+		self.db
+			.set_refresh_token(user_id, device_id, refresh_token, previous)
+	}
+
+	/// Invalidates the refresh token a device refreshed its access token with,
+	/// now that the new access token has been used.
+	pub fn remove_previous_refresh_token(&self, user_id: &UserId, device_id: &DeviceId) -> Result<()> {
+		self.db.remove_previous_refresh_token(user_id, device_id)
+	}
+
+	/// Creates a single-use login token for a user which expires after
+	/// `expires_in`.
+	pub fn create_login_token(&self, user_id: &UserId, token: &str, expires_in: Duration) -> Result<()> {
+		let expires_at =
+			utils::millis_since_unix_epoch().saturating_add(u64::try_from(expires_in.as_millis()).unwrap_or(u64::MAX));
+
+		self.db.create_login_token(user_id, token, expires_at)
+	}
+
+	/// Find out which user a login token belongs to. The token is consumed, and
+	/// expired tokens are treated as unknown.
+	pub fn find_from_login_token(&self, token: &str) -> Result<Option<OwnedUserId>> {
+		Ok(self
+			.db
+			.take_login_token(token)?
+			.filter(|(_, expires_at)| *expires_at > utils::millis_since_unix_epoch())
+			.map(|(user_id, _)| user_id))
+	}
+
 	pub fn add_one_time_key(
 		&self, user_id: &UserId, device_id: &DeviceId, one_time_key_key: &DeviceKeyId,
 		one_time_key_value: &Raw<OneTimeKey>,
@@ -432,6 +578,28 @@ impl Service {
 		self.db.update_device_metadata(user_id, device_id, device)
 	}
 
+	/// Records the IP address a device was used from and when. The timestamp
+	/// is only updated every `LAST_SEEN_UPDATE_INTERVAL` unless the address
+	/// changes, to not write to the database on every request.
+	pub fn update_last_seen(&self, user_id: &UserId, device_id: &DeviceId, ip: Option<IpAddr>) -> Result<()> {
+		let Some(mut device) = self.db.get_device_metadata(user_id, device_id)? else {
+			return Ok(());
+		};
+
+		let now = MilliSecondsSinceUnixEpoch::now();
+		let ip = ip.map(|ip| ip.to_string());
+		if !last_seen_outdated(&device, ip.as_deref(), now) {
+			return Ok(());
+		}
+
+		if ip.is_some() {
+			device.last_seen_ip = ip;
+		}
+		device.last_seen_ts = Some(now);
+
+		self.db.update_device_last_seen(user_id, device_id, &device)
+	}
+
 	/// Get device metadata.
 	pub fn get_device_metadata(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Option<Device>> {
 		self.db.get_device_metadata(user_id, device_id)
@@ -458,8 +626,7 @@ impl Service {
 		// account is deactivated.
 		self.db.set_password(user_id, None)?;
 
-		// TODO: Unhook 3PID
-		Ok(())
+		services().threepid.unbind_all(user_id)
 	}
 
 	/// Creates a new sync filter. Returns the filter id.
@@ -472,6 +639,17 @@ impl Service {
 	}
 }
 
+/// Whether the last seen address or timestamp of a device has to be updated
+fn last_seen_outdated(device: &Device, ip: Option<&str>, now: MilliSecondsSinceUnixEpoch) -> bool {
+	if ip.is_some() && ip != device.last_seen_ip.as_deref() {
+		return true;
+	}
+
+	device.last_seen_ts.map_or(true, |last_seen| {
+		u128::from(u64::from(now.get().saturating_sub(last_seen.get()))) >= LAST_SEEN_UPDATE_INTERVAL.as_millis()
+	})
+}
+
 /// Ensure that a user only sees signatures from themselves and the target user
 pub(crate) fn clean_signatures<F: Fn(&UserId) -> bool>(
 	cross_signing_key: &mut serde_json::Value, sender_user: Option<&UserId>, user_id: &UserId, allowed_signatures: F,
@@ -494,3 +672,30 @@ pub(crate) fn clean_signatures<F: Fn(&UserId) -> bool>(
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use ruma::{api::client::device::Device, device_id, MilliSecondsSinceUnixEpoch, UInt};
+
+	use super::{last_seen_outdated, LAST_SEEN_UPDATE_INTERVAL};
+
+	#[test]
+	fn last_seen_updates() {
+		let seen_at = MilliSecondsSinceUnixEpoch(UInt::new(1_000_000).unwrap());
+		let mut device = Device::new(device_id!("ABCDEFG").to_owned());
+		device.last_seen_ip = Some("192.0.2.1".to_owned());
+		device.last_seen_ts = Some(seen_at);
+
+		let soon = MilliSecondsSinceUnixEpoch(UInt::new(1_000_000 + 1_000).unwrap());
+		assert!(!last_seen_outdated(&device, Some("192.0.2.1"), soon));
+		assert!(!last_seen_outdated(&device, None, soon));
+		assert!(last_seen_outdated(&device, Some("192.0.2.2"), soon));
+
+		let later =
+			MilliSecondsSinceUnixEpoch(UInt::new(1_000_000 + LAST_SEEN_UPDATE_INTERVAL.as_secs() * 1000).unwrap());
+		assert!(last_seen_outdated(&device, Some("192.0.2.1"), later));
+
+		device.last_seen_ts = None;
+		assert!(last_seen_outdated(&device, None, soon));
+	}
+}
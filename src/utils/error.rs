@@ -19,6 +19,8 @@ use crate::RumaResponse;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+const USER_LOCKED: &str = "M_USER_LOCKED";
+
 #[derive(Error)]
 pub enum Error {
 	#[cfg(feature = "sqlite")]
@@ -33,6 +35,12 @@ pub enum Error {
 		#[from]
 		source: rust_rocksdb::Error,
 	},
+	#[cfg(feature = "ldap")]
+	#[error("There was a problem with the connection to the LDAP server: {source}")]
+	LdapError {
+		#[from]
+		source: ldap3::LdapError,
+	},
 	#[error("Could not generate an image.")]
 	ImageError {
 		#[from]
@@ -92,6 +100,15 @@ impl Error {
 		error!("BadConfig: {}", message);
 		Self::BadConfig(message.to_owned())
 	}
+
+	/// `M_USER_LOCKED`, built from its error code as our ruma version might
+	/// not have a variant for it yet.
+	pub fn user_locked() -> Self {
+		let kind = serde_json::from_value(serde_json::json!({ "errcode": USER_LOCKED }))
+			.expect("error kinds with any error code deserialize");
+
+		Self::BadRequest(kind, "This account has been locked by a server admin.")
+	}
 }
 
 impl Error {
@@ -134,6 +151,7 @@ impl Error {
 						..
 					} => StatusCode::TOO_MANY_REQUESTS,
 					TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+					_ if kind.to_string() == USER_LOCKED => StatusCode::UNAUTHORIZED,
 					_ => StatusCode::BAD_REQUEST,
 				},
 			),
@@ -176,6 +194,10 @@ impl Error {
 			Self::RocksDbError {
 				..
 			} => db_error,
+			#[cfg(feature = "ldap")]
+			Self::LdapError {
+				..
+			} => String::from("Authentication backend error occurred."),
 			Self::IoError {
 				..
 			} => db_error,
@@ -17,6 +17,12 @@ use crate::{services, Error, Result};
 
 pub(crate) fn clamp<T: Ord>(val: T, min: T, max: T) -> T { cmp::min(cmp::max(val, min), max) }
 
+/// Compares secrets in constant time, so how long the comparison takes does
+/// not reveal how much of a guess was correct.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
 pub(crate) fn millis_since_unix_epoch() -> u64 {
 	SystemTime::now()
 		.duration_since(UNIX_EPOCH)